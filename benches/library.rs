@@ -0,0 +1,108 @@
+//! Benchmarks of the code paths that grow with the size of the library.
+//! Run with `cargo bench`, optionally followed by a part of a benchmark name.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use musikbox::grouping;
+use musikbox::index::SearchIndex;
+use musikbox::player::file_uri;
+use musikbox::queue::{Shuffle, ShuffleWeights};
+use musikbox::search;
+use musikbox::stats::Stats;
+use musikbox::tags::Tags;
+
+use std::hint::black_box;
+use std::path::PathBuf;
+
+/// Number of files in the generated library.
+const FILES: usize = 50_000;
+
+fn library_benchmarks(c: &mut Criterion) {
+    let files = library();
+    let stats = Stats::default();
+
+    c.bench_function("file uris", |b| {
+        b.iter(|| {
+            for file in &files {
+                black_box(file_uri(file));
+            }
+        })
+    });
+
+    c.bench_function("group by directory", |b| {
+        b.iter(|| {
+            black_box(grouping::groups(&files, |file| {
+                file.parent()
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_default()
+            }))
+        })
+    });
+
+    c.bench_function("group by letter", |b| {
+        b.iter(|| {
+            black_box(grouping::groups(&files, |file| {
+                grouping::letter(&file.file_name().unwrap_or_default().to_string_lossy())
+            }))
+        })
+    });
+
+    c.bench_function("regex search", |b| {
+        let regex = search::pattern(r"^track 0*4\d+ - (live|remix)").unwrap();
+        b.iter(|| {
+            for file in &files {
+                black_box(regex.is_match(&file.to_string_lossy()));
+            }
+        })
+    });
+
+    c.bench_function("index search", |b| {
+        let mut index = SearchIndex::new().unwrap();
+        for file in &files {
+            index.set(&file_uri(file), file, &Tags::default()).unwrap();
+        }
+        // The first search commits the files.
+        index.search("").unwrap();
+
+        // Words typed to find an album, one with a typo.
+        b.iter(|| black_box(index.search("album 4023 titel").unwrap()))
+    });
+
+    c.bench_function("weighted shuffle", |b| {
+        let weights = ShuffleWeights {
+            plays: 1.0,
+            recency: 1.0,
+        };
+        b.iter(|| {
+            let mut shuffle = Shuffle::default();
+            shuffle.reshuffle(&files, None, |file| {
+                weights.weight(stats.get(&file_uri(file)), 0)
+            });
+            black_box(shuffle)
+        })
+    });
+}
+
+/// Generate the paths of a library sorted like a directory listing.
+fn library() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = (0..FILES)
+        .map(|i| {
+            PathBuf::from(format!(
+                "/music/Artist {}/Album {}/Track {i:05} - Title #{}.flac",
+                i / 500,
+                i / 12,
+                i % 7
+            ))
+        })
+        .collect();
+
+    files.sort();
+    files
+}
+
+criterion_group! {
+    name = benches;
+    // Every iteration goes through the entire library, so fewer samples suffice.
+    config = Criterion::default().sample_size(20);
+    targets = library_benchmarks
+}
+criterion_main!(benches);
@@ -0,0 +1,441 @@
+use crate::eq;
+use crate::fade::Fade;
+use crate::player::{PlayState, PlaybackEvent, ReplayGain};
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "gstreamer")]
+mod gst;
+#[cfg(feature = "rodio")]
+mod rodio;
+
+#[cfg(feature = "gstreamer")]
+pub use self::gst::GstBackend;
+#[cfg(feature = "rodio")]
+pub use self::rodio::RodioBackend;
+
+/// Callback for the events of a backend.
+/// It may be called from any thread.
+pub type EventHandler = Arc<dyn Fn(PlaybackEvent) + Send + Sync>;
+
+/// Average loudness in dBFS of the tracks as measured while they played,
+/// `None` if too little of a track was played to tell.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Loudness {
+    pub previous: Option<f64>,
+    pub current: Option<f64>,
+}
+
+/// Decodes and outputs audio. The player engine drives a backend
+/// and doesn't care how it is implemented.
+/// Backends are shared with the task ramping the volume.
+pub trait PlaybackBackend: Send + Sync {
+    fn set_uri(&self, uri: Option<&str>);
+    /// Get the URI set by `set_uri`.
+    fn uri(&self) -> Option<String>;
+    fn play(&self);
+    fn pause(&self);
+    fn stop(&self);
+    fn seek(&self, position: Duration);
+    fn position(&self) -> Option<Duration>;
+    fn duration(&self) -> Option<Duration>;
+    /// Get the volume from 0.0 to 1.0.
+    fn volume(&self) -> f64;
+    fn set_volume(&self, volume: f64);
+    /// Call a function for every playback event.
+    fn subscribe(&self, handler: EventHandler);
+
+    /// Get the URI of the track that is currently playing.
+    /// Differs from `uri` after a gapless transition.
+    fn current_uri(&self) -> Option<String> {
+        self.uri()
+    }
+
+    /// Set the URI to switch to seamlessly when the current track is about to end.
+    /// Backends without gapless playback ignore it and end the stream as usual.
+    fn set_next_uri(&self, _uri: Option<String>) {}
+
+    /// Use the album gain instead of the track gain.
+    /// Backends without ReplayGain ignore it.
+    fn set_album_gain(&self, _album: bool) {}
+
+    /// Enable or disable the fade-out of the current track.
+    /// It is enabled again for every new track.
+    fn set_fade_out(&self, _enabled: bool) {}
+
+    /// Start the next track without a fade-in.
+    fn skip_fade_in(&self) {}
+
+    /// Bypass fades, the EQ and any other processing that changes the sound of the signal,
+    /// which passes through them untouched. Only stages setting its level still apply:
+    /// the volume, ReplayGain and a gain keeping the loudness about the same.
+    fn set_passthrough(&self, _passthrough: bool) {}
+
+    /// Set the equalizer gains in dB, `None` for a flat response.
+    /// Backends without an equalizer ignore it.
+    fn set_eq(&self, _gains: Option<[f64; eq::BANDS]>) {}
+
+    /// Get the loudness of the previous and the current track with ReplayGain and the EQ,
+    /// but before fades and the gain set by `set_gain`.
+    /// Backends that can't measure it report it as unknown.
+    fn loudness(&self) -> Loudness {
+        Loudness::default()
+    }
+
+    /// Amplify every track by a gain in dB, e.g. to match the loudness of tracks.
+    /// Backends that can't amplify ignore it.
+    fn set_gain(&self, _gain: f64) {}
+}
+
+impl<B: PlaybackBackend + ?Sized> PlaybackBackend for Box<B> {
+    fn set_uri(&self, uri: Option<&str>) {
+        (**self).set_uri(uri)
+    }
+
+    fn uri(&self) -> Option<String> {
+        (**self).uri()
+    }
+
+    fn play(&self) {
+        (**self).play()
+    }
+
+    fn pause(&self) {
+        (**self).pause()
+    }
+
+    fn stop(&self) {
+        (**self).stop()
+    }
+
+    fn seek(&self, position: Duration) {
+        (**self).seek(position)
+    }
+
+    fn position(&self) -> Option<Duration> {
+        (**self).position()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        (**self).duration()
+    }
+
+    fn volume(&self) -> f64 {
+        (**self).volume()
+    }
+
+    fn set_volume(&self, volume: f64) {
+        (**self).set_volume(volume)
+    }
+
+    fn subscribe(&self, handler: EventHandler) {
+        (**self).subscribe(handler)
+    }
+
+    fn current_uri(&self) -> Option<String> {
+        (**self).current_uri()
+    }
+
+    fn set_next_uri(&self, uri: Option<String>) {
+        (**self).set_next_uri(uri)
+    }
+
+    fn set_album_gain(&self, album: bool) {
+        (**self).set_album_gain(album)
+    }
+
+    fn set_fade_out(&self, enabled: bool) {
+        (**self).set_fade_out(enabled)
+    }
+
+    fn skip_fade_in(&self) {
+        (**self).skip_fade_in()
+    }
+
+    fn set_passthrough(&self, passthrough: bool) {
+        (**self).set_passthrough(passthrough)
+    }
+
+    fn set_eq(&self, gains: Option<[f64; eq::BANDS]>) {
+        (**self).set_eq(gains)
+    }
+
+    fn loudness(&self) -> Loudness {
+        (**self).loudness()
+    }
+
+    fn set_gain(&self, gain: f64) {
+        (**self).set_gain(gain)
+    }
+}
+
+/// Where a backend plays audio.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Output {
+    /// The default output device.
+    #[default]
+    Default,
+    /// Output device of PulseAudio or PipeWire, as named by `pactl list short sinks`.
+    Device(String),
+    /// Pipe source of a Snapcast server, e.g. `snapcast:/tmp/snapfifo`.
+    SnapcastPipe(PathBuf),
+    /// TCP source of a Snapcast server in server mode, e.g. `snapcast://host:4953`.
+    SnapcastTcp { host: String, port: u16 },
+    /// RTP stream of uncompressed audio, e.g. `rtp://239.0.0.1:5004` for multicast.
+    Rtp { host: String, port: u16 },
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = |address: &str| {
+            let (host, port) = address
+                .rsplit_once(':')
+                .ok_or_else(|| format!("expected HOST:PORT in {s}"))?;
+            let port = port
+                .parse()
+                .map_err(|_| format!("invalid port {port:?} in {s}"))?;
+
+            Ok::<_, String>((host.to_string(), port))
+        };
+
+        if let Some(rest) = s.strip_prefix("snapcast://") {
+            let (host, port) = address(rest)?;
+            Ok(Self::SnapcastTcp { host, port })
+        } else if let Some(path) = s.strip_prefix("snapcast:") {
+            Ok(Self::SnapcastPipe(PathBuf::from(path)))
+        } else if let Some(rest) = s.strip_prefix("rtp://") {
+            let (host, port) = address(rest)?;
+            Ok(Self::Rtp { host, port })
+        } else if s.is_empty() || s == "default" {
+            Ok(Self::Default)
+        } else {
+            Ok(Self::Device(s.to_string()))
+        }
+    }
+}
+
+/// Backend selectable at runtime.
+/// Only the backends enabled at build time are available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Gstreamer,
+    Rodio,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(feature = "gstreamer") {
+            Self::Gstreamer
+        } else {
+            Self::Rodio
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gstreamer" => Ok(Self::Gstreamer),
+            "rodio" => Ok(Self::Rodio),
+            _ => Err(format!("unknown backend {s}, expected gstreamer or rodio")),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Gstreamer => write!(f, "gstreamer"),
+            Self::Rodio => write!(f, "rodio"),
+        }
+    }
+}
+
+impl Backend {
+    /// Create the backend playing on an output, with an equalizer if `eq` is set.
+    /// Fails if it wasn't enabled at build time.
+    pub fn open(
+        self,
+        replaygain: ReplayGain,
+        fade: Fade,
+        eq: bool,
+        output: &Output,
+    ) -> anyhow::Result<Box<dyn PlaybackBackend>> {
+        match self {
+            #[cfg(feature = "gstreamer")]
+            Self::Gstreamer => Ok(Box::new(GstBackend::new(replaygain, fade, eq, output)?)),
+            #[cfg(feature = "rodio")]
+            Self::Rodio => {
+                if replaygain != ReplayGain::Off {
+                    return Err(anyhow::anyhow!(
+                        "the rodio backend doesn't support ReplayGain"
+                    ));
+                }
+
+                if eq {
+                    return Err(anyhow::anyhow!(
+                        "the rodio backend doesn't support EQ profiles"
+                    ));
+                }
+
+                if *output != Output::Default {
+                    return Err(anyhow::anyhow!(
+                        "the rodio backend only plays on the default output device"
+                    ));
+                }
+
+                Ok(Box::new(RodioBackend::new(fade)?))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(anyhow::anyhow!(
+                "musikbox was built without the {self} backend, enable its cargo feature"
+            )),
+        }
+    }
+}
+
+/// Backend that plays nothing and reports whatever it is told.
+/// Drives the player engine without audio output, e.g. in tests.
+#[derive(Default)]
+pub struct MockBackend {
+    state: Mutex<MockState>,
+    handlers: Mutex<Vec<EventHandler>>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    uri: Option<String>,
+    play_state: PlayState,
+    position: Duration,
+    duration: Option<Duration>,
+    volume: f64,
+    /// Track playing after a gapless transition, `uri` is still the one that was set.
+    current: Option<String>,
+    next_uri: Option<String>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            uri: None,
+            play_state: PlayState::Stopped,
+            position: Duration::ZERO,
+            duration: None,
+            volume: 1.0,
+            current: None,
+            next_uri: None,
+        }
+    }
+}
+
+impl MockBackend {
+    /// Set the duration reported for the current track.
+    pub fn set_duration(&self, duration: Option<Duration>) {
+        self.state.lock().unwrap().duration = duration;
+    }
+
+    pub fn play_state(&self) -> PlayState {
+        self.state.lock().unwrap().play_state
+    }
+
+    /// Play the current track to its end: switch to the next URI seamlessly if one was set,
+    /// like a gapless transition, otherwise end the stream.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        match state.next_uri.take() {
+            Some(next) => {
+                state.current = Some(next);
+                state.position = Duration::ZERO;
+            }
+            None => {
+                drop(state);
+                self.emit(PlaybackEvent::EndOfStream);
+            }
+        }
+    }
+
+    /// Send an event to all subscribers, e.g. `EndOfStream` to simulate a finished track.
+    pub fn emit(&self, event: PlaybackEvent) {
+        let handlers = self.handlers.lock().unwrap().clone();
+        for handler in handlers {
+            handler(event.clone());
+        }
+    }
+
+    fn set_play_state(&self, play_state: PlayState) {
+        self.state.lock().unwrap().play_state = play_state;
+        self.emit(PlaybackEvent::StateChanged(play_state));
+    }
+}
+
+impl PlaybackBackend for MockBackend {
+    fn set_uri(&self, uri: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state.uri = uri.map(String::from);
+        state.current = None;
+        state.position = Duration::ZERO;
+    }
+
+    fn uri(&self) -> Option<String> {
+        self.state.lock().unwrap().uri.clone()
+    }
+
+    fn play(&self) {
+        self.set_play_state(PlayState::Playing);
+    }
+
+    fn pause(&self) {
+        self.set_play_state(PlayState::Paused);
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().position = Duration::ZERO;
+        self.set_play_state(PlayState::Stopped);
+    }
+
+    fn seek(&self, position: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.position = match state.duration {
+            Some(duration) => position.min(duration),
+            None => position,
+        };
+    }
+
+    fn position(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.uri.as_ref().map(|_| state.position)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.state.lock().unwrap().duration
+    }
+
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume
+    }
+
+    fn set_volume(&self, volume: f64) {
+        self.state.lock().unwrap().volume = volume;
+    }
+
+    fn subscribe(&self, handler: EventHandler) {
+        self.handlers.lock().unwrap().push(handler);
+    }
+
+    fn current_uri(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.current.clone().or_else(|| state.uri.clone())
+    }
+
+    fn set_next_uri(&self, uri: Option<String>) {
+        self.state.lock().unwrap().next_uri = uri;
+    }
+}
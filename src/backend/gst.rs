@@ -0,0 +1,493 @@
+use super::{EventHandler, Loudness, Output, PlaybackBackend};
+use crate::eq;
+use crate::fade::Fade;
+use crate::player::{PlayState, PlaybackEvent, ReplayGain};
+
+use gstreamer::prelude::*;
+use gstreamer::{ClockTime, PadProbeData, PadProbeReturn, PadProbeType};
+use gstreamer_play::{Play, PlaySignalAdapter, PlayVideoRenderer};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Audio of a track that has to be measured before its loudness is known.
+const MIN_MEASURED: Duration = Duration::from_secs(5);
+
+/// Backend using a GStreamer playbin.
+pub struct GstBackend {
+    play: Play,
+    signals: PlaySignalAdapter,
+    /// Applies the ReplayGain tags, `None` if ReplayGain is disabled.
+    rgvolume: Option<gstreamer::Element>,
+    /// Applies the EQ profile, `None` if there are no profiles.
+    equalizer: Option<gstreamer::Element>,
+    /// Gains of the EQ profile, kept to restore them after passthrough.
+    eq_gains: Mutex<Option<[f64; eq::BANDS]>>,
+    next_uri: Arc<Mutex<Option<String>>>,
+    /// Whether a gapless transition is in progress,
+    /// i.e. the next track was set but hasn't started playing yet.
+    switching: Arc<AtomicBool>,
+    /// Whether the EQ and fades are bypassed.
+    passthrough: Arc<AtomicBool>,
+    /// Whether the current track fades out, reset for every track.
+    fade_out: Arc<AtomicBool>,
+    /// Whether the next track to start skips its fade-in.
+    skip_fade_in: Arc<AtomicBool>,
+    meter: Arc<Mutex<Meter>>,
+    /// Applies the gain set by `set_gain`.
+    gain: gstreamer::Element,
+    /// Gain set by `set_gain` in dB, kept to restore it after passthrough.
+    level_gain: Mutex<f64>,
+}
+
+/// Measurement of the loudness of the current track as its samples pass.
+#[derive(Debug, Default)]
+struct Meter {
+    /// Sum of the squares of the samples and their number, over all channels.
+    squares: f64,
+    samples: u64,
+    /// Duration of the measured audio.
+    measured: Duration,
+    /// Loudness of the previous track, if enough of it was measured.
+    previous: Option<f64>,
+}
+
+impl Meter {
+    /// Get the loudness in dBFS, the mean square of the samples.
+    fn loudness(&self) -> Option<f64> {
+        if self.measured < MIN_MEASURED || self.samples == 0 {
+            return None;
+        }
+
+        let mean = self.squares / self.samples as f64;
+        Some(10.0 * mean.max(1e-10).log10())
+    }
+}
+
+impl GstBackend {
+    pub fn new(
+        replaygain: ReplayGain,
+        fade: Fade,
+        eq: bool,
+        output: &Output,
+    ) -> anyhow::Result<Self> {
+        let play = Play::new(PlayVideoRenderer::NONE);
+        let signals = PlaySignalAdapter::new_sync_emit(&play);
+        let next_uri = Arc::new(Mutex::new(None));
+        let switching = Arc::new(AtomicBool::new(false));
+        let passthrough = Arc::new(AtomicBool::new(false));
+        let fade_out = Arc::new(AtomicBool::new(true));
+        let skip_fade_in = Arc::new(AtomicBool::new(false));
+
+        // Encoder delay and padding (e.g. the LAME gap info) are trimmed by the parsers,
+        // so switching the URI shortly before the end yields a seamless transition.
+        let next = Arc::clone(&next_uri);
+        let switched = Arc::clone(&switching);
+        play.pipeline()
+            .connect("about-to-finish", false, move |values| {
+                let playbin = values[0].get::<gstreamer::Element>().unwrap();
+                if let Some(uri) = next.lock().unwrap().take() {
+                    playbin.set_property("uri", uri);
+                    switched.store(true, Ordering::SeqCst);
+                }
+
+                None
+            });
+
+        if let Some(sink) = sink(output)? {
+            play.pipeline().set_property("audio-sink", &sink);
+        }
+
+        // ReplayGain only changes the level, which passthrough keeps.
+        let rgvolume = if replaygain == ReplayGain::Off {
+            None
+        } else {
+            Some(
+                gstreamer::ElementFactory::make("rgvolume")
+                    .property("album-mode", replaygain != ReplayGain::Track)
+                    .build()?,
+            )
+        };
+
+        let equalizer = if eq {
+            Some(gstreamer::ElementFactory::make("equalizer-10bands").build()?)
+        } else {
+            None
+        };
+
+        let fader = if fade.is_enabled() {
+            Some(fader(
+                fade,
+                Arc::clone(&next_uri),
+                Arc::clone(&switching),
+                Arc::clone(&passthrough),
+                Arc::clone(&fade_out),
+                Arc::clone(&skip_fade_in),
+            )?)
+        } else {
+            None
+        };
+
+        let meter = Arc::new(Mutex::new(Meter::default()));
+        let gain = gstreamer::ElementFactory::make("volume").build()?;
+
+        // The loudness is measured as heard, except for the fades and the gain.
+        let [convert, measure] = measuring(Arc::clone(&meter))?;
+        let filters: Vec<&gstreamer::Element> = rgvolume
+            .iter()
+            .chain(equalizer.iter())
+            .chain([&convert, &measure])
+            .chain(fader.iter())
+            .chain([&gain])
+            .collect();
+
+        let bin = gstreamer::Bin::new(None);
+        bin.add_many(&filters)?;
+        gstreamer::Element::link_many(&filters)?;
+
+        let sink = filters[0].static_pad("sink").unwrap();
+        let src = filters[filters.len() - 1].static_pad("src").unwrap();
+        bin.add_pad(&gstreamer::GhostPad::with_target(Some("sink"), &sink)?)?;
+        bin.add_pad(&gstreamer::GhostPad::with_target(Some("src"), &src)?)?;
+
+        play.pipeline().set_property("audio-filter", &bin);
+
+        Ok(Self {
+            play,
+            signals,
+            rgvolume,
+            equalizer,
+            eq_gains: Mutex::new(None),
+            next_uri,
+            switching,
+            passthrough,
+            fade_out,
+            skip_fade_in,
+            meter,
+            gain,
+            level_gain: Mutex::new(0.0),
+        })
+    }
+}
+
+/// Create the sink of an output, `None` to let playbin pick the default device.
+/// Streams are synchronized to the clock, since their receivers don't pace them.
+fn sink(output: &Output) -> anyhow::Result<Option<gstreamer::Element>> {
+    // Snapcast expects its default sample format unless configured otherwise.
+    const SNAPCAST_FORMAT: &str =
+        "audioconvert ! audioresample ! audio/x-raw,format=S16LE,rate=48000,channels=2";
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let description = match output {
+        Output::Default => return Ok(None),
+        // Devices are named as by pactl, which works with PulseAudio and PipeWire.
+        Output::Device(device) => {
+            let sink = gstreamer::ElementFactory::make("pulsesink")
+                .property("device", device)
+                .build()?;
+            return Ok(Some(sink));
+        }
+        Output::SnapcastPipe(path) => format!(
+            "{SNAPCAST_FORMAT} ! filesink location={} sync=true",
+            quote(&path.to_string_lossy())
+        ),
+        Output::SnapcastTcp { host, port } => format!(
+            "{SNAPCAST_FORMAT} ! tcpclientsink host={} port={port} sync=true",
+            quote(host)
+        ),
+        Output::Rtp { host, port } => format!(
+            "audioconvert ! audioresample ! audio/x-raw,rate=48000,channels=2 ! rtpL16pay ! udpsink host={} port={port} sync=true",
+            quote(host)
+        ),
+    };
+
+    let bin = gstreamer::parse_bin_from_description(&description, true)?;
+    Ok(Some(bin.upcast()))
+}
+
+/// Create elements converting to 32-bit float samples and measuring their loudness.
+/// The measurement starts over with every track.
+fn measuring(meter: Arc<Mutex<Meter>>) -> anyhow::Result<[gstreamer::Element; 2]> {
+    let convert = gstreamer::ElementFactory::make("audioconvert").build()?;
+    let caps = gstreamer::Caps::builder("audio/x-raw")
+        .field("format", "F32LE")
+        .build();
+    let filter = gstreamer::ElementFactory::make("capsfilter")
+        .property("caps", &caps)
+        .build()?;
+
+    filter.static_pad("src").unwrap().add_probe(
+        PadProbeType::BUFFER | PadProbeType::EVENT_DOWNSTREAM,
+        move |_, info| {
+            match &info.data {
+                Some(PadProbeData::Event(event)) => {
+                    if let gstreamer::EventView::StreamStart(_) = event.view() {
+                        let mut meter = meter.lock().unwrap();
+                        *meter = Meter {
+                            previous: meter.loudness(),
+                            ..Default::default()
+                        };
+                    }
+                }
+                Some(PadProbeData::Buffer(buffer)) => {
+                    if let Ok(map) = buffer.map_readable() {
+                        let squares: f64 = map
+                            .chunks_exact(4)
+                            .map(|bytes| {
+                                let sample = f32::from_le_bytes(bytes.try_into().unwrap());
+                                f64::from(sample) * f64::from(sample)
+                            })
+                            .sum();
+
+                        let mut meter = meter.lock().unwrap();
+                        meter.squares += squares;
+                        meter.samples += map.len() as u64 / 4;
+                        meter.measured += buffer.duration().map(Duration::from).unwrap_or_default();
+                    }
+                }
+                _ => {}
+            }
+
+            PadProbeReturn::Ok
+        },
+    );
+
+    Ok([convert, filter])
+}
+
+/// Set the bands of an equalizer-10bands element, all to 0 dB for `None`.
+fn set_bands(equalizer: &gstreamer::Element, gains: Option<[f64; eq::BANDS]>) {
+    let gains = gains.unwrap_or_default();
+    for (i, gain) in gains.into_iter().enumerate() {
+        equalizer.set_property(&format!("band{i}"), gain);
+    }
+}
+
+/// Create a volume element applying the fades to every buffer passing through.
+/// Gapless transitions neither fade out nor in.
+fn fader(
+    fade: Fade,
+    next_uri: Arc<Mutex<Option<String>>>,
+    switching: Arc<AtomicBool>,
+    passthrough: Arc<AtomicBool>,
+    fade_out: Arc<AtomicBool>,
+    skip_fade_in: Arc<AtomicBool>,
+) -> anyhow::Result<gstreamer::Element> {
+    let volume = gstreamer::ElementFactory::make("volume").build()?;
+    let weak = volume.downgrade();
+
+    // Whether the current track started with a gapless transition.
+    let seamless = AtomicBool::new(false);
+    // Whether the current track was started without a fade-in.
+    let no_fade_in = AtomicBool::new(false);
+
+    volume.static_pad("sink").unwrap().add_probe(
+        PadProbeType::BUFFER | PadProbeType::EVENT_DOWNSTREAM,
+        move |pad, info| {
+            let buffer = match &info.data {
+                Some(PadProbeData::Buffer(buffer)) => buffer,
+                Some(PadProbeData::Event(event)) => {
+                    if let gstreamer::EventView::StreamStart(_) = event.view() {
+                        seamless.store(switching.swap(false, Ordering::SeqCst), Ordering::SeqCst);
+                        no_fade_in
+                            .store(skip_fade_in.swap(false, Ordering::SeqCst), Ordering::SeqCst);
+                    }
+
+                    return PadProbeReturn::Ok;
+                }
+                _ => return PadProbeReturn::Ok,
+            };
+
+            if let (Some(volume), Some(pts)) = (weak.upgrade(), buffer.pts()) {
+                // Buffer timestamps are relative to the segment, e.g. after seeking.
+                let position = pad
+                    .sticky_event::<gstreamer::event::Segment>(0)
+                    .and_then(|event| {
+                        event
+                            .segment()
+                            .downcast_ref::<ClockTime>()
+                            .and_then(|segment| segment.to_stream_time(pts))
+                    })
+                    .unwrap_or(pts);
+                let duration = pad.peer_query_duration::<ClockTime>();
+
+                let bypass = passthrough.load(Ordering::SeqCst);
+                let mut gain = 1.0;
+                if !bypass && !seamless.load(Ordering::SeqCst) && !no_fade_in.load(Ordering::SeqCst)
+                {
+                    gain *= fade.fade_in_gain(position.into());
+                }
+                if !bypass
+                    && fade_out.load(Ordering::SeqCst)
+                    && next_uri.lock().unwrap().is_none()
+                    && !switching.load(Ordering::SeqCst)
+                {
+                    gain *= fade.fade_out_gain(position.into(), duration.map(Duration::from));
+                }
+
+                volume.set_property("volume", gain);
+            }
+
+            PadProbeReturn::Ok
+        },
+    );
+
+    Ok(volume)
+}
+
+impl PlaybackBackend for GstBackend {
+    fn set_uri(&self, uri: Option<&str>) {
+        self.switching.store(false, Ordering::SeqCst);
+        self.fade_out.store(true, Ordering::SeqCst);
+        self.play.set_uri(uri);
+    }
+
+    fn uri(&self) -> Option<String> {
+        self.play.uri().map(String::from)
+    }
+
+    fn play(&self) {
+        self.play.play();
+    }
+
+    fn pause(&self) {
+        self.play.pause();
+    }
+
+    fn stop(&self) {
+        self.play.stop();
+    }
+
+    fn seek(&self, position: Duration) {
+        self.play
+            .seek(ClockTime::from_nseconds(position.as_nanos() as u64));
+    }
+
+    fn position(&self) -> Option<Duration> {
+        self.play.position().map(Duration::from)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.play.duration().map(Duration::from)
+    }
+
+    fn volume(&self) -> f64 {
+        self.play.volume()
+    }
+
+    fn set_volume(&self, volume: f64) {
+        self.play.set_volume(volume);
+    }
+
+    fn subscribe(&self, handler: EventHandler) {
+        let f = Arc::clone(&handler);
+        self.signals
+            .connect_end_of_stream(move |_| f(PlaybackEvent::EndOfStream));
+        let f = Arc::clone(&handler);
+        self.signals.connect_error(move |_, err, _| {
+            f(PlaybackEvent::Error(err.to_string()));
+        });
+        let f = Arc::clone(&handler);
+        self.signals.connect_warning(move |_, err, _| {
+            f(PlaybackEvent::Warning(err.to_string()));
+        });
+        let f = Arc::clone(&handler);
+        self.signals.connect_buffering(move |_, percent| {
+            f(PlaybackEvent::Buffering(percent));
+        });
+        let f = handler;
+        self.signals.connect_state_changed(move |_, state| {
+            let state = match state {
+                gstreamer_play::PlayState::Buffering => PlayState::Buffering,
+                gstreamer_play::PlayState::Paused => PlayState::Paused,
+                gstreamer_play::PlayState::Playing => PlayState::Playing,
+                _ => PlayState::Stopped,
+            };
+
+            f(PlaybackEvent::StateChanged(state));
+        });
+    }
+
+    fn current_uri(&self) -> Option<String> {
+        self.play
+            .pipeline()
+            .property::<Option<String>>("current-uri")
+            .or_else(|| self.uri())
+    }
+
+    fn set_next_uri(&self, uri: Option<String>) {
+        *self.next_uri.lock().unwrap() = uri;
+    }
+
+    fn set_album_gain(&self, album: bool) {
+        if let Some(rgvolume) = &self.rgvolume {
+            rgvolume.set_property("album-mode", album);
+        }
+    }
+
+    fn set_fade_out(&self, enabled: bool) {
+        self.fade_out.store(enabled, Ordering::SeqCst);
+    }
+
+    fn skip_fade_in(&self) {
+        self.skip_fade_in.store(true, Ordering::SeqCst);
+    }
+
+    fn set_passthrough(&self, passthrough: bool) {
+        self.passthrough.store(passthrough, Ordering::SeqCst);
+
+        // An equalizer with all bands at 0 dB passes the samples through untouched.
+        if let Some(equalizer) = &self.equalizer {
+            let gains = match passthrough {
+                true => None,
+                false => *self.eq_gains.lock().unwrap(),
+            };
+
+            set_bands(equalizer, gains);
+        }
+
+        self.apply_gain();
+    }
+
+    fn set_eq(&self, gains: Option<[f64; eq::BANDS]>) {
+        *self.eq_gains.lock().unwrap() = gains;
+
+        if let Some(equalizer) = &self.equalizer {
+            if !self.passthrough.load(Ordering::SeqCst) {
+                set_bands(equalizer, gains);
+            }
+        }
+        self.apply_gain();
+    }
+
+    fn loudness(&self) -> Loudness {
+        let meter = self.meter.lock().unwrap();
+
+        Loudness {
+            previous: meter.previous,
+            current: meter.loudness(),
+        }
+    }
+
+    fn set_gain(&self, gain: f64) {
+        *self.level_gain.lock().unwrap() = gain;
+        self.apply_gain();
+    }
+}
+
+impl GstBackend {
+    /// Apply the gain set by `set_gain`. While bypassing the EQ its average gain
+    /// is added, so that the comparison doesn't favour the louder version.
+    fn apply_gain(&self) {
+        let mut gain = *self.level_gain.lock().unwrap();
+        if self.passthrough.load(Ordering::SeqCst) {
+            if let Some(gains) = &*self.eq_gains.lock().unwrap() {
+                gain += eq::average_gain(gains);
+            }
+        }
+
+        // The volume element amplifies by up to 10, i.e. 20 dB.
+        let factor = 10f64.powf(gain / 20.0).min(10.0);
+        self.gain.set_property("volume", factor);
+    }
+}
@@ -0,0 +1,263 @@
+use super::{EventHandler, PlaybackBackend};
+use crate::fade::Fade;
+use crate::player::{uri_to_path, PlayState, PlaybackEvent};
+
+use rodio::{Decoder, OutputStream, Sink, Source};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// Interval in which the end of a track is detected.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Interval in which the gain of a fade is updated.
+const FADE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Backend decoding local files with symphonia and playing them through cpal.
+/// Only `file://` URIs are supported.
+pub struct RodioBackend {
+    /// Keeps the output device open on its own thread, as it can't be shared.
+    /// Playback stops when this is dropped.
+    _stream: Sender<()>,
+    shared: Arc<Shared>,
+}
+
+/// State shared with the thread detecting the end of a track.
+struct Shared {
+    sink: Sink,
+    fade: Fade,
+    /// Whether fades are bypassed.
+    passthrough: AtomicBool,
+    /// Whether the current track fades out, reset for every track.
+    fade_out: AtomicBool,
+    /// Whether the next track to be loaded skips its fade-in.
+    skip_fade_in: AtomicBool,
+    track: Mutex<Track>,
+    handlers: Mutex<Vec<EventHandler>>,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    uri: Option<String>,
+    duration: Option<Duration>,
+    /// Whether the track has been started and hasn't ended since.
+    playing: bool,
+}
+
+impl Shared {
+    fn emit(&self, event: PlaybackEvent) {
+        let handlers = self.handlers.lock().unwrap().clone();
+        for handler in handlers {
+            handler(event.clone());
+        }
+    }
+
+    /// Decode a URI and queue it, paused.
+    /// Returns false if it can't be played.
+    fn load(self: &Arc<Self>, uri: &str) -> bool {
+        self.sink.clear();
+
+        let source = uri_to_path(uri)
+            .ok_or_else(|| anyhow::anyhow!("unsupported URI {uri}"))
+            .and_then(|path| Ok(File::open(path)?))
+            .and_then(|file| Ok(Decoder::new(BufReader::new(file))?));
+
+        match source {
+            Ok(source) => {
+                let duration = source.total_duration();
+                self.track.lock().unwrap().duration = duration;
+
+                let fade = self.fade;
+                let fade_in = !self.skip_fade_in.swap(false, Ordering::SeqCst);
+                let shared = Arc::downgrade(self);
+                let source = source
+                    .amplify(1.0)
+                    .periodic_access(FADE_INTERVAL, move |source| {
+                        if let Some(shared) = shared.upgrade() {
+                            let position = shared.sink.get_pos();
+                            let mut gain = 1.0;
+                            if !shared.passthrough.load(Ordering::SeqCst) {
+                                if fade_in {
+                                    gain *= fade.fade_in_gain(position);
+                                }
+                                if shared.fade_out.load(Ordering::SeqCst) {
+                                    gain *= fade.fade_out_gain(position, duration);
+                                }
+                            }
+                            source.set_factor(gain as f32);
+                        }
+                    });
+
+                self.sink.append(source);
+
+                true
+            }
+            Err(err) => {
+                self.emit(PlaybackEvent::Error(err.to_string()));
+                false
+            }
+        }
+    }
+
+    /// Report the end of a track once the sink ran out of audio.
+    fn poll(&self) {
+        let ended = {
+            let mut track = self.track.lock().unwrap();
+            let ended = track.playing && self.sink.empty();
+            if ended {
+                track.playing = false;
+            }
+
+            ended
+        };
+
+        if ended {
+            self.emit(PlaybackEvent::StateChanged(PlayState::Stopped));
+            self.emit(PlaybackEvent::EndOfStream);
+        }
+    }
+}
+
+impl RodioBackend {
+    pub fn new(fade: Fade) -> anyhow::Result<Self> {
+        let (stream_tx, stream_rx) = mpsc::channel::<()>();
+        let (handle_tx, handle_rx) = mpsc::channel();
+        thread::spawn(move || match OutputStream::try_default() {
+            Ok((stream, handle)) => {
+                let _ = handle_tx.send(Ok(handle));
+                // Returns once the backend and with it the sender is dropped.
+                let _ = stream_rx.recv();
+                drop(stream);
+            }
+            Err(e) => {
+                let _ = handle_tx.send(Err(anyhow::Error::from(e)));
+            }
+        });
+
+        let handle = handle_rx.recv()??;
+        let sink = Sink::try_new(&handle)?;
+        sink.pause();
+
+        let shared = Arc::new(Shared {
+            sink,
+            fade,
+            passthrough: AtomicBool::new(false),
+            fade_out: AtomicBool::new(true),
+            skip_fade_in: AtomicBool::new(false),
+            track: Mutex::new(Track::default()),
+            handlers: Mutex::new(Vec::new()),
+        });
+
+        let weak: Weak<Shared> = Arc::downgrade(&shared);
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+
+            match weak.upgrade() {
+                Some(shared) => shared.poll(),
+                None => break,
+            }
+        });
+
+        Ok(Self {
+            _stream: stream_tx,
+            shared,
+        })
+    }
+}
+
+impl PlaybackBackend for RodioBackend {
+    fn set_uri(&self, uri: Option<&str>) {
+        {
+            let mut track = self.shared.track.lock().unwrap();
+            track.uri = uri.map(String::from);
+            track.duration = None;
+            track.playing = false;
+        }
+
+        self.shared.fade_out.store(true, Ordering::SeqCst);
+
+        match uri {
+            Some(uri) => {
+                self.shared.load(uri);
+            }
+            None => self.shared.sink.clear(),
+        }
+    }
+
+    fn uri(&self) -> Option<String> {
+        self.shared.track.lock().unwrap().uri.clone()
+    }
+
+    fn play(&self) {
+        let uri = self.uri();
+
+        // The track has to be decoded again after it ended or was stopped.
+        if self.shared.sink.empty() {
+            match &uri {
+                Some(uri) if self.shared.load(uri) => {}
+                _ => return,
+            }
+        }
+
+        self.shared.sink.play();
+        self.shared.track.lock().unwrap().playing = true;
+        self.shared
+            .emit(PlaybackEvent::StateChanged(PlayState::Playing));
+    }
+
+    fn pause(&self) {
+        self.shared.sink.pause();
+        self.shared
+            .emit(PlaybackEvent::StateChanged(PlayState::Paused));
+    }
+
+    fn stop(&self) {
+        self.shared.track.lock().unwrap().playing = false;
+        self.shared.sink.clear();
+        self.shared
+            .emit(PlaybackEvent::StateChanged(PlayState::Stopped));
+    }
+
+    fn seek(&self, position: Duration) {
+        // Not every format supports seeking, playback continues in that case.
+        if let Err(err) = self.shared.sink.try_seek(position) {
+            self.shared.emit(PlaybackEvent::Warning(err.to_string()));
+        }
+    }
+
+    fn position(&self) -> Option<Duration> {
+        self.uri().map(|_| self.shared.sink.get_pos())
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.shared.track.lock().unwrap().duration
+    }
+
+    fn volume(&self) -> f64 {
+        self.shared.sink.volume() as f64
+    }
+
+    fn set_volume(&self, volume: f64) {
+        self.shared.sink.set_volume(volume as f32);
+    }
+
+    fn subscribe(&self, handler: EventHandler) {
+        self.shared.handlers.lock().unwrap().push(handler);
+    }
+
+    fn set_fade_out(&self, enabled: bool) {
+        self.shared.fade_out.store(enabled, Ordering::SeqCst);
+    }
+
+    fn skip_fade_in(&self) {
+        self.shared.skip_fade_in.store(true, Ordering::SeqCst);
+    }
+
+    fn set_passthrough(&self, passthrough: bool) {
+        self.shared.passthrough.store(passthrough, Ordering::SeqCst);
+    }
+}
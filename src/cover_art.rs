@@ -0,0 +1,216 @@
+use crate::tags::Tags;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SEARCH_URL: &str = "https://musicbrainz.org/ws/2/release/";
+const COVER_URL: &str = "https://coverartarchive.org/release";
+
+/// MusicBrainz requires a meaningful user agent and blocks anonymous clients.
+const USER_AGENT: &str = concat!(
+    "musikbox/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/HimbeerserverDE/musikbox )"
+);
+
+/// MusicBrainz allows one request per second, the Cover Art Archive is treated the same.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(1100);
+
+/// curl's exit code for HTTP errors such as 404.
+const CURL_HTTP_ERROR: i32 = 22;
+
+/// Cover art fetched from the Cover Art Archive for tracks without embedded art.
+/// Covers are cached on disk, including the albums that don't have one,
+/// so every album is looked up at most once.
+pub struct CoverArt {
+    /// Time of the last request to either service, shared by all threads.
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl CoverArt {
+    pub fn new() -> Self {
+        Self {
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Get the cached cover of a track's album without fetching it.
+    pub fn cached(&self, tags: &Tags) -> Option<PathBuf> {
+        let path = cache_dir()?.join(key(tags)? + ".jpg");
+        path.exists().then_some(path)
+    }
+
+    /// Get the cover of a track's album, downloading it if it isn't cached.
+    /// Blocks while waiting for the rate limit, so it belongs in a thread.
+    /// Returns `None` if the album has no cover or isn't known to MusicBrainz.
+    pub fn fetch(&self, tags: &Tags) -> anyhow::Result<Option<PathBuf>> {
+        let key = match key(tags) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let dir = cache_dir().ok_or_else(|| anyhow::anyhow!("can't determine cache directory"))?;
+        let path = dir.join(format!("{key}.jpg"));
+        let missing = dir.join(format!("{key}.missing"));
+
+        if path.exists() {
+            return Ok(Some(path));
+        } else if missing.exists() {
+            return Ok(None);
+        }
+
+        fs::create_dir_all(&dir)?;
+
+        let release = match &tags.musicbrainz_album_id {
+            Some(release) => Some(release.clone()),
+            None => self.search(tags)?,
+        };
+
+        let release = match release {
+            Some(release) => release,
+            None => {
+                fs::write(missing, "")?;
+                return Ok(None);
+            }
+        };
+
+        let tmp = dir.join(format!(".{key}.tmp"));
+        self.wait();
+
+        let status = Command::new("curl")
+            .args(["-fsSL", "--max-time", "30", "-A", USER_AGENT, "-o"])
+            .arg(&tmp)
+            .arg(format!("{COVER_URL}/{release}/front-500"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+
+        match status.code() {
+            Some(0) => {
+                fs::rename(tmp, &path)?;
+                tracing::info!("fetched cover of release {release}");
+                Ok(Some(path))
+            }
+            Some(CURL_HTTP_ERROR) => {
+                let _ = fs::remove_file(tmp);
+                fs::write(missing, "")?;
+                Ok(None)
+            }
+            _ => {
+                let _ = fs::remove_file(tmp);
+                Err(anyhow::anyhow!("curl {status}"))
+            }
+        }
+    }
+
+    /// Look up the MusicBrainz release ID of an album by its artist and title.
+    fn search(&self, tags: &Tags) -> anyhow::Result<Option<String>> {
+        let (artist, album) = match (
+            tags.album_artist.as_ref().or(tags.artist.as_ref()),
+            &tags.album,
+        ) {
+            (Some(artist), Some(album)) => (artist, album),
+            _ => return Ok(None),
+        };
+
+        let query = format!(
+            "release:\"{}\" AND artist:\"{}\"",
+            lucene_escape(album),
+            lucene_escape(artist)
+        );
+
+        self.wait();
+
+        let output = Command::new("curl")
+            .args(["-fsS", "--max-time", "10", "-A", USER_AGENT, "-G"])
+            .arg("--data-urlencode")
+            .arg(format!("query={query}"))
+            .args(["--data", "limit=1", "--data", "fmt=json"])
+            .arg(SEARCH_URL)
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("{}", err.trim()));
+        }
+
+        // Only the ID of the best match is needed, it's the first one in the list.
+        let body = String::from_utf8_lossy(&output.stdout);
+        let release = body
+            .split_once("\"releases\":[{\"id\":\"")
+            .and_then(|(_, rest)| rest.split_once('"').map(|(release, _)| release.to_string()));
+
+        Ok(release)
+    }
+
+    /// Sleep until the next request is allowed.
+    fn wait(&self) {
+        let mut last_request = self.last_request.lock().unwrap();
+        if let Some(last_request) = *last_request {
+            thread::sleep(REQUEST_INTERVAL.saturating_sub(last_request.elapsed()));
+        }
+
+        *last_request = Some(Instant::now());
+    }
+}
+
+impl Default for CoverArt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the directory covers are cached in.
+/// This is `$XDG_CACHE_HOME/musikbox/covers` or `~/.cache/musikbox/covers`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(cache_home) if !cache_home.is_empty() => PathBuf::from(cache_home),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(base.join("musikbox/covers"))
+}
+
+/// Get the cache file name of an album: its release ID,
+/// or a hash of the artist and album title if it isn't tagged.
+fn key(tags: &Tags) -> Option<String> {
+    if let Some(release) = &tags.musicbrainz_album_id {
+        // The ID ends up in a path, so it has to be a UUID.
+        let valid = release.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+        return valid.then(|| release.clone());
+    }
+
+    let artist = tags.album_artist.as_ref().or(tags.artist.as_ref())?;
+    let album = tags.album_title()?;
+
+    // FNV-1a, which unlike the standard library's hasher is stable across releases.
+    let hash = format!("{}\0{album}", artist.to_lowercase())
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+
+    Some(format!("{hash:016x}"))
+}
+
+/// Escape the special characters of a Lucene query term.
+fn lucene_escape(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if "+-&|!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
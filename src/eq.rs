@@ -0,0 +1,163 @@
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Number of equalizer bands, centered at 29, 59, 119, 237, 474 and 947 Hz
+/// and 1.9, 3.8, 7.5 and 15 kHz.
+pub const BANDS: usize = 10;
+
+/// Range of the gain of a band in dB.
+pub const MIN_GAIN: f64 = -24.0;
+pub const MAX_GAIN: f64 = 12.0;
+
+/// Interval in which the default output device is checked for changes.
+const SINK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Named equalizer settings as NAME=GAINS, where GAINS is either ten gains in dB
+/// separated by commas, e.g. `laptop=6,5,4,2,0,0,0,0,0,0`, or a preset:
+/// flat, bass-boost or treble-boost.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EqProfile {
+    pub name: String,
+    pub gains: [f64; BANDS],
+}
+
+impl FromStr for EqProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, gains) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected NAME=GAINS"))?;
+
+        let gains = match preset(gains.trim()) {
+            Some(gains) => gains,
+            None => {
+                let gains: Vec<f64> = gains
+                    .split(',')
+                    .map(|gain| {
+                        gain.trim()
+                            .parse()
+                            .map_err(|_| format!("invalid gain {gain:?}"))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                gains.try_into().map_err(|_| {
+                    format!("expected {BANDS} gains or a preset: flat, bass-boost, treble-boost")
+                })?
+            }
+        };
+
+        if let Some(gain) = gains
+            .iter()
+            .find(|gain| !(MIN_GAIN..=MAX_GAIN).contains(*gain))
+        {
+            return Err(format!(
+                "gain {gain} dB is out of range, expected {MIN_GAIN} to {MAX_GAIN}"
+            ));
+        }
+
+        Ok(Self {
+            name: name.trim().to_string(),
+            gains,
+        })
+    }
+}
+
+/// Get the gains of a built-in profile.
+fn preset(name: &str) -> Option<[f64; BANDS]> {
+    match name {
+        "flat" => Some([0.0; BANDS]),
+        "bass-boost" => Some([6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        "treble-boost" => Some([0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+        _ => None,
+    }
+}
+
+/// Estimate the change in loudness in dB caused by gains,
+/// as the gain of the average power over all bands.
+pub fn average_gain(gains: &[f64; BANDS]) -> f64 {
+    let power = gains
+        .iter()
+        .map(|gain| 10f64.powf(gain / 10.0))
+        .sum::<f64>();
+
+    10.0 * (power / BANDS as f64).log10()
+}
+
+/// Assignment of a profile to the output devices whose name contains SINK,
+/// given as SINK=PROFILE. `*` matches any device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EqOutput {
+    pub sink: String,
+    pub profile: String,
+}
+
+impl FromStr for EqOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sink, profile) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected SINK=PROFILE"))?;
+
+        Ok(Self {
+            sink: sink.trim().to_string(),
+            profile: profile.trim().to_string(),
+        })
+    }
+}
+
+/// Get the profile of an output device. The first matching assignment wins.
+pub fn select<'a>(
+    profiles: &'a [EqProfile],
+    outputs: &[EqOutput],
+    sink: &str,
+) -> Option<&'a EqProfile> {
+    let output = outputs
+        .iter()
+        .find(|output| output.sink == "*" || sink.contains(&output.sink))?;
+
+    profiles
+        .iter()
+        .find(|profile| profile.name == output.profile)
+}
+
+/// Watch the default output device of PulseAudio or PipeWire in the background.
+/// Calls a function with its name on startup and whenever it changes.
+/// Gives up if `pactl` isn't available.
+pub fn watch_sink<F>(f: F)
+where
+    F: Fn(String) + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut current = None;
+
+        loop {
+            let output = Command::new("pactl")
+                .arg("get-default-sink")
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output();
+
+            match output {
+                Ok(output) if output.status.success() => {
+                    let sink = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                    if current.as_ref() != Some(&sink) {
+                        tracing::info!("default output device is {sink}");
+                        current = Some(sink.clone());
+                        f(sink);
+                    }
+                }
+                Ok(output) => tracing::debug!("pactl get-default-sink failed: {}", output.status),
+                Err(err) => {
+                    tracing::warn!("can't determine output device, EQ profiles disabled: {err}");
+                    return;
+                }
+            }
+
+            thread::sleep(SINK_POLL_INTERVAL);
+        }
+    });
+}
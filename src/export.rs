@@ -0,0 +1,275 @@
+use crate::json;
+use crate::stats::Stats;
+use crate::tags::{self, Tags};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Output format of an export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format {s}, expected csv or json")),
+        }
+    }
+}
+
+/// What the rows of an export are aggregated by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Grouping {
+    #[default]
+    Track,
+    Artist,
+    Album,
+    Day,
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "track" => Ok(Self::Track),
+            "artist" => Ok(Self::Artist),
+            "album" => Ok(Self::Album),
+            "day" => Ok(Self::Day),
+            _ => Err(format!(
+                "unknown grouping {s}, expected track, artist, album or day"
+            )),
+        }
+    }
+}
+
+/// A value of an exported row.
+enum Field {
+    Text(String),
+    Number(f64),
+    /// Missing value, empty in CSV and `null` in JSON.
+    None,
+}
+
+impl From<Option<String>> for Field {
+    fn from(value: Option<String>) -> Self {
+        value.map_or(Self::None, Self::Text)
+    }
+}
+
+/// Accumulated statistics of a group.
+#[derive(Default)]
+struct Totals {
+    plays: u64,
+    skips: u64,
+    listened: f64,
+}
+
+/// Artist and album title, without disc suffixes.
+type AlbumKey = (Option<String>, Option<String>);
+
+/// Write the listening statistics, aggregated by a grouping.
+/// Tracks are grouped by artist or album using their tags,
+/// so tracks that can't be read anymore are counted as unknown.
+pub fn export<W: Write>(
+    stats: &Stats,
+    grouping: Grouping,
+    format: Format,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut tags: HashMap<&str, Tags> = HashMap::new();
+    if matches!(
+        grouping,
+        Grouping::Track | Grouping::Artist | Grouping::Album
+    ) {
+        for (uri, _) in stats.tracks() {
+            tags.insert(uri, tags::read(uri).unwrap_or_default());
+        }
+    }
+
+    let (header, rows): (&[&str], Vec<Vec<Field>>) = match grouping {
+        Grouping::Track => {
+            let mut tracks: Vec<_> = stats.tracks().collect();
+            tracks.sort_by(|a, b| a.0.cmp(b.0));
+
+            let rows = tracks
+                .into_iter()
+                .map(|(uri, track)| {
+                    let tags = &tags[uri];
+                    vec![
+                        Field::Text(uri.to_string()),
+                        tags.title.clone().into(),
+                        tags.artist.clone().into(),
+                        tags.album.clone().into(),
+                        Field::Number(track.plays as f64),
+                        Field::Number(track.skips as f64),
+                        Field::Number(track.listened.round()),
+                        track
+                            .last_played
+                            .map_or(Field::None, |t| Field::Number(t as f64)),
+                    ]
+                })
+                .collect();
+
+            (
+                &[
+                    "uri",
+                    "title",
+                    "artist",
+                    "album",
+                    "plays",
+                    "skips",
+                    "listened",
+                    "last_played",
+                ],
+                rows,
+            )
+        }
+        Grouping::Artist => {
+            let mut groups: BTreeMap<Option<String>, Totals> = BTreeMap::new();
+            for (uri, track) in stats.tracks() {
+                let totals = groups.entry(tags[uri].artist.clone()).or_default();
+                totals.plays += track.plays;
+                totals.skips += track.skips;
+                totals.listened += track.listened;
+            }
+
+            let rows = groups
+                .into_iter()
+                .map(|(artist, totals)| {
+                    vec![
+                        artist.into(),
+                        Field::Number(totals.plays as f64),
+                        Field::Number(totals.skips as f64),
+                        Field::Number(totals.listened.round()),
+                    ]
+                })
+                .collect();
+
+            (&["artist", "plays", "skips", "listened"], rows)
+        }
+        Grouping::Album => {
+            // Discs of the same album are counted together.
+            // Keyed by artist and album title, the album name of the first track is shown.
+            let mut groups: BTreeMap<AlbumKey, (Option<String>, Totals)> = BTreeMap::new();
+            for (uri, track) in stats.tracks() {
+                let tags = &tags[uri];
+                let artist = tags.album_artist.clone().or_else(|| tags.artist.clone());
+                let (album, totals) = groups
+                    .entry((artist, tags.album_title()))
+                    .or_insert_with(|| (tags.album.clone(), Totals::default()));
+
+                if album.is_none() {
+                    *album = tags.album.clone();
+                }
+
+                totals.plays += track.plays;
+                totals.skips += track.skips;
+                totals.listened += track.listened;
+            }
+
+            let rows = groups
+                .into_iter()
+                .map(|((artist, _), (album, totals))| {
+                    vec![
+                        artist.into(),
+                        album.into(),
+                        Field::Number(totals.plays as f64),
+                        Field::Number(totals.skips as f64),
+                        Field::Number(totals.listened.round()),
+                    ]
+                })
+                .collect();
+
+            (&["artist", "album", "plays", "skips", "listened"], rows)
+        }
+        Grouping::Day => {
+            let mut groups: BTreeMap<&str, Totals> = BTreeMap::new();
+            for (day, _, stats) in stats.days() {
+                let totals = groups.entry(day).or_default();
+                totals.plays += stats.plays;
+                totals.listened += stats.listened;
+            }
+
+            let rows = groups
+                .into_iter()
+                .map(|(day, totals)| {
+                    vec![
+                        Field::Text(day.to_string()),
+                        Field::Number(totals.plays as f64),
+                        Field::Number(totals.listened.round()),
+                    ]
+                })
+                .collect();
+
+            (&["day", "plays", "listened"], rows)
+        }
+    };
+
+    match format {
+        Format::Csv => write_csv(out, header, &rows),
+        Format::Json => write_json(out, header, &rows),
+    }
+}
+
+fn write_csv<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<Field>]) -> io::Result<()> {
+    writeln!(out, "{}", header.join(","))?;
+
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|field| match field {
+                Field::Text(text) => csv_escape(text),
+                Field::Number(n) => n.to_string(),
+                Field::None => String::new(),
+            })
+            .collect();
+
+        writeln!(out, "{}", fields.join(","))?;
+    }
+
+    Ok(())
+}
+
+fn write_json<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<Field>]) -> io::Result<()> {
+    writeln!(out, "[")?;
+
+    for (i, row) in rows.iter().enumerate() {
+        let fields: Vec<String> = header
+            .iter()
+            .zip(row)
+            .map(|(name, field)| {
+                let value = match field {
+                    Field::Text(text) => format!("\"{}\"", json::escape(text)),
+                    Field::Number(n) => n.to_string(),
+                    Field::None => String::from("null"),
+                };
+
+                format!("\"{name}\":{value}")
+            })
+            .collect();
+
+        let separator = if i + 1 < rows.len() { "," } else { "" };
+        writeln!(out, "  {{{}}}{separator}", fields.join(","))?;
+    }
+
+    writeln!(out, "]")
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
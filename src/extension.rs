@@ -0,0 +1,80 @@
+use crate::json;
+use crate::player::uri_name;
+use crate::tags::Tags;
+use crate::task::Progress;
+
+use std::time::Duration;
+
+/// Optional subsystem that follows playback, e.g. webhooks.
+/// Subsystems with heavy dependencies are enabled by cargo features
+/// and registered with the player at startup if they are configured.
+pub trait Extension: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Handle a playback event. Must not block, slow work belongs in a thread.
+    fn notify(&self, notification: &Notification);
+
+    /// Handle a position update, sent about once a second while a track plays.
+    /// Most extensions only need the events. Must not block either.
+    fn position(&self, _notification: &Notification) {}
+
+    /// Get a short description of the extension's state to show in the status bar.
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Playback event and the state of the player at the time it happened.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    /// One of start, pause, resume, stop and queue-empty,
+    /// task-progress and task-done for background tasks,
+    /// or position for the updates sent while a track plays.
+    pub event: String,
+    /// URI of the current track, empty if there is none.
+    pub uri: String,
+    pub position: Option<Duration>,
+    pub duration: Option<Duration>,
+    pub volume: f64,
+    /// Progress of the background task the event is about.
+    pub task: Option<Progress>,
+}
+
+impl Notification {
+    /// Get the file name of the current track.
+    pub fn file(&self) -> String {
+        uri_name(&self.uri)
+    }
+
+    /// Serialize the notification as a JSON object with the title, artist and album
+    /// from the tags of the current track, null if unknown.
+    /// Position and duration are in seconds, task events have the task's progress.
+    pub fn to_json(&self, tags: &Tags) -> String {
+        let text = |value: Option<&str>| match value {
+            Some(value) => format!("\"{}\"", json::escape(value)),
+            None => String::from("null"),
+        };
+        let seconds = |duration: Option<Duration>| match duration {
+            Some(duration) => duration.as_secs().to_string(),
+            None => String::from("null"),
+        };
+
+        let task = match &self.task {
+            Some(progress) => format!(",\"task\":{}", progress.to_json()),
+            None => String::new(),
+        };
+
+        format!(
+            "{{\"event\":\"{}\",\"uri\":\"{}\",\"file\":\"{}\",\"title\":{},\"artist\":{},\"album\":{},\"position\":{},\"duration\":{},\"volume\":{}{task}}}",
+            json::escape(&self.event),
+            json::escape(&self.uri),
+            json::escape(&self.file()),
+            text(tags.title.as_deref()),
+            text(tags.artist.as_deref()),
+            text(tags.album.as_deref()),
+            seconds(self.position),
+            seconds(self.duration),
+            self.volume,
+        )
+    }
+}
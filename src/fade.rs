@@ -0,0 +1,107 @@
+use std::f64::consts::FRAC_PI_2;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Shape of the volume curve of a fade.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Keeps the perceived loudness constant when two fades overlap.
+    EqualPower,
+    /// Starts and ends slowly, changing fastest in the middle.
+    SCurve,
+}
+
+impl FadeCurve {
+    /// Get the gain at a point of a fade-in from 0.0 to 1.0.
+    /// Fade-outs use the same curve backwards.
+    pub fn gain(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => t,
+            Self::EqualPower => (t * FRAC_PI_2).sin(),
+            Self::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl FromStr for FadeCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "equal-power" => Ok(Self::EqualPower),
+            "s-curve" => Ok(Self::SCurve),
+            _ => Err(format!(
+                "unknown fade curve {s}, expected linear, equal-power or s-curve"
+            )),
+        }
+    }
+}
+
+/// Which transitions between tracks fade.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FadePolicy {
+    Always,
+    /// Tracks of the same album follow each other without fades, e.g. on live albums.
+    #[default]
+    BetweenAlbums,
+}
+
+impl FromStr for FadePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Self::Always),
+            "between-albums" => Ok(Self::BetweenAlbums),
+            _ => Err(format!(
+                "unknown fade policy {s}, expected always or between-albums"
+            )),
+        }
+    }
+}
+
+/// Volume fades at the start and end of every track.
+/// A zero duration disables the respective fade.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fade {
+    pub curve: FadeCurve,
+    pub fade_in: Duration,
+    pub fade_out: Duration,
+}
+
+impl Fade {
+    pub fn is_enabled(&self) -> bool {
+        !self.fade_in.is_zero() || !self.fade_out.is_zero()
+    }
+
+    /// Get the gain of the fade-in at a position of a track.
+    pub fn fade_in_gain(&self, position: Duration) -> f64 {
+        if position >= self.fade_in {
+            return 1.0;
+        }
+
+        self.curve
+            .gain(position.as_secs_f64() / self.fade_in.as_secs_f64())
+    }
+
+    /// Get the gain of the fade-out at a position of a track.
+    /// Tracks of unknown duration don't fade out.
+    pub fn fade_out_gain(&self, position: Duration, duration: Option<Duration>) -> f64 {
+        let remaining = match duration {
+            Some(duration) => duration.saturating_sub(position),
+            None => return 1.0,
+        };
+
+        if remaining >= self.fade_out {
+            return 1.0;
+        }
+
+        self.curve
+            .gain(remaining.as_secs_f64() / self.fade_out.as_secs_f64())
+    }
+}
@@ -0,0 +1,147 @@
+use std::env;
+
+/// Symbol of the control bar, the indicators or the listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symbol {
+    RepeatList,
+    Repeat,
+    Previous,
+    Back,
+    Pause,
+    Play,
+    Forward,
+    Next,
+    Sequential,
+    Shuffle,
+    Consume,
+    StopAfterCurrent,
+    Muted,
+    /// Bypass that stays on for the following tracks.
+    Pinned,
+    /// File of the listing marked for a bulk action.
+    Marked,
+    CollapsedGroup,
+    ExpandedGroup,
+}
+
+/// How symbols are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Glyphs {
+    #[default]
+    Emoji,
+    /// Words, e.g. for screen readers and braille displays.
+    Labels,
+    /// Narrow ASCII symbols for terminals without emoji, which would misalign the bar.
+    Ascii,
+}
+
+impl Glyphs {
+    /// Guess whether the terminal can show emoji. The Linux console can't
+    /// and anything but a UTF-8 locale suggests that the terminal can't either.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        let utf8 = locale.contains("utf-8") || locale.contains("utf8");
+
+        let console = matches!(
+            env::var("TERM").as_deref(),
+            Ok("linux" | "vt100" | "vt220" | "dumb")
+        );
+
+        if utf8 && !console {
+            Self::Emoji
+        } else {
+            Self::Ascii
+        }
+    }
+
+    pub fn get(self, symbol: Symbol) -> &'static str {
+        match self {
+            Self::Emoji => match symbol {
+                Symbol::RepeatList => "🔁",
+                Symbol::Repeat => "🔂",
+                Symbol::Previous => "⏮",
+                Symbol::Back => "◀",
+                Symbol::Pause => "⏸",
+                Symbol::Play | Symbol::Forward => "▶",
+                Symbol::Next => "⏭",
+                Symbol::Sequential => "⏬",
+                Symbol::Shuffle => "🔀",
+                Symbol::Consume => "✂",
+                Symbol::StopAfterCurrent => "⏹",
+                Symbol::Muted => "🔇",
+                Symbol::Pinned => "📌",
+                Symbol::Marked => "✔",
+                Symbol::CollapsedGroup => "▸",
+                Symbol::ExpandedGroup => "▾",
+            },
+            Self::Labels => match symbol {
+                Symbol::RepeatList => "repeat list",
+                Symbol::Repeat => "repeat",
+                Symbol::Previous => "previous",
+                Symbol::Back => "back",
+                Symbol::Pause => "pause",
+                Symbol::Play => "play",
+                Symbol::Forward => "forward",
+                Symbol::Next => "next",
+                Symbol::Sequential => "sequential",
+                Symbol::Shuffle => "shuffle",
+                Symbol::Consume => "consume",
+                Symbol::StopAfterCurrent => "stop after current",
+                Symbol::Muted => "muted",
+                Symbol::Pinned => "pinned",
+                Symbol::Marked => "marked",
+                Symbol::CollapsedGroup => "+",
+                Symbol::ExpandedGroup => "-",
+            },
+            Self::Ascii => match symbol {
+                Symbol::RepeatList => "RA",
+                Symbol::Repeat => "R1",
+                Symbol::Previous => "|<",
+                Symbol::Back => "<<",
+                Symbol::Pause => "||",
+                Symbol::Play => ">",
+                Symbol::Forward => ">>",
+                Symbol::Next => ">|",
+                Symbol::Sequential => "SEQ",
+                Symbol::Shuffle => "SHUF",
+                Symbol::Consume => "CON",
+                Symbol::StopAfterCurrent => "STOP",
+                Symbol::Muted => "MUTE",
+                Symbol::Pinned => "PIN",
+                Symbol::Marked => "*",
+                Symbol::CollapsedGroup => "+",
+                Symbol::ExpandedGroup => "-",
+            },
+        }
+    }
+
+    /// Get the buttons of the control bar, with play instead of pause while paused.
+    pub fn controls(self, paused: bool) -> String {
+        let buttons = [
+            Symbol::RepeatList,
+            Symbol::Repeat,
+            Symbol::Previous,
+            Symbol::Back,
+            if paused { Symbol::Play } else { Symbol::Pause },
+            Symbol::Forward,
+            Symbol::Next,
+            Symbol::Sequential,
+            Symbol::Shuffle,
+        ];
+        let separator = match self {
+            Self::Emoji | Self::Ascii => "   ",
+            Self::Labels => " ",
+        };
+
+        buttons
+            .iter()
+            .map(|&symbol| format!("[ {} ]", self.get(symbol)))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
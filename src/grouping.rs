@@ -0,0 +1,95 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Property by which consecutive files of a list are grouped under headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    Directory,
+    Album,
+    /// First letter of the file name, with digits and symbols grouped under #.
+    Letter,
+}
+
+impl Grouping {
+    /// Get the grouping after this one, `None` after the last one.
+    pub fn next(grouping: Option<Self>) -> Option<Self> {
+        match grouping {
+            None => Some(Self::Directory),
+            Some(Self::Directory) => Some(Self::Album),
+            Some(Self::Album) => Some(Self::Letter),
+            Some(Self::Letter) => None,
+        }
+    }
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Directory => write!(f, "directory"),
+            Self::Album => write!(f, "album"),
+            Self::Letter => write!(f, "letter"),
+        }
+    }
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "directory" => Ok(Self::Directory),
+            "album" => Ok(Self::Album),
+            "letter" => Ok(Self::Letter),
+            _ => Err(format!(
+                "unknown grouping {s}, expected directory, album or letter"
+            )),
+        }
+    }
+}
+
+/// Run of consecutive files with the same header.
+/// A file that sorts apart from its group starts a new one with the same title.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub title: String,
+    /// Index of the first file in the list.
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Group {
+    pub fn contains(&self, i: usize) -> bool {
+        (self.start..self.start + self.len).contains(&i)
+    }
+}
+
+/// Split a list into groups of consecutive files with the same title.
+pub fn groups<F>(files: &[PathBuf], mut title: F) -> Vec<Group>
+where
+    F: FnMut(&PathBuf) -> String,
+{
+    let mut groups: Vec<Group> = Vec::new();
+
+    for (i, file) in files.iter().enumerate() {
+        let title = title(file);
+        match groups.last_mut() {
+            Some(group) if group.title == title => group.len += 1,
+            _ => groups.push(Group {
+                title,
+                start: i,
+                len: 1,
+            }),
+        }
+    }
+
+    groups
+}
+
+/// Get the letter a file name is grouped under.
+pub fn letter(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => String::from("#"),
+    }
+}
@@ -0,0 +1,104 @@
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+
+/// Built-in manual, shown with F1.
+const MANUAL: &str = include_str!("../docs/manual.md");
+
+/// State of the manual viewer.
+#[derive(Debug, Default)]
+pub struct Help {
+    /// Index of the topmost visible line.
+    pub scroll: usize,
+    pub search: String,
+}
+
+impl Help {
+    pub fn lines() -> impl Iterator<Item = &'static str> {
+        MANUAL.lines()
+    }
+
+    pub fn len() -> usize {
+        Self::lines().count()
+    }
+
+    /// Scroll to the next line containing the search term, wrapping around.
+    /// The current line is included, so typing refines the match in place.
+    pub fn find(&mut self, skip_current: bool) {
+        if self.search.is_empty() {
+            return;
+        }
+
+        let search = self.search.to_lowercase();
+        let len = Self::len();
+        let start = self.scroll + usize::from(skip_current);
+
+        if let Some(i) = (start..start + len).map(|i| i % len).find(|&i| {
+            Self::lines()
+                .nth(i)
+                .unwrap()
+                .to_lowercase()
+                .contains(&search)
+        }) {
+            self.scroll = i;
+        }
+    }
+
+    pub fn scroll_by(&mut self, lines: isize) {
+        let max = Self::len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(lines).min(max);
+    }
+
+    /// Render the visible part of the manual.
+    /// Headings are bold, matches of the search term are reversed.
+    pub fn text(&self, style: Style) -> Vec<Spans<'static>> {
+        Self::lines()
+            .skip(self.scroll)
+            .map(|line| {
+                let heading = line.trim_start_matches('#');
+                if heading.len() < line.len() {
+                    Spans::from(Span::styled(
+                        heading.trim().to_string(),
+                        style.add_modifier(Modifier::BOLD),
+                    ))
+                } else {
+                    let line = match line.strip_prefix("- ") {
+                        Some(item) => format!("  • {item}"),
+                        None => line.to_string(),
+                    };
+
+                    highlight(line, &self.search, style)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Split a line into spans with the occurrences of a search term reversed.
+fn highlight(line: String, search: &str, style: Style) -> Spans<'static> {
+    if search.is_empty() {
+        return Spans::from(Span::styled(line, style));
+    }
+
+    let lower = line.to_lowercase();
+    let search = search.to_lowercase();
+
+    // Lowercasing may change byte offsets of non-ASCII text, don't highlight in that case.
+    if lower.len() != line.len() {
+        return Spans::from(Span::styled(line, style));
+    }
+
+    let mut spans = Vec::new();
+    let mut rest = 0;
+
+    for (i, _) in lower.match_indices(&search) {
+        spans.push(Span::styled(line[rest..i].to_string(), style));
+        spans.push(Span::styled(
+            line[i..i + search.len()].to_string(),
+            style.add_modifier(Modifier::REVERSED),
+        ));
+        rest = i + search.len();
+    }
+
+    spans.push(Span::styled(line[rest..].to_string(), style));
+    Spans::from(spans)
+}
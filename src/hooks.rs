@@ -0,0 +1,91 @@
+use crate::extension::{Extension, Notification};
+use crate::player::uri_to_path;
+use crate::tags;
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Shell commands to run on playback events.
+#[derive(Clone, Debug, Default)]
+pub struct HookCommands {
+    pub track_change: Option<String>,
+    pub pause: Option<String>,
+    pub stop: Option<String>,
+    pub queue_empty: Option<String>,
+}
+
+/// Extension running a shell command for every event that has one,
+/// e.g. to update a status bar or scrobble to a custom service.
+/// The track is described by `MUSIKBOX_*` environment variables.
+pub struct Hooks {
+    commands: HookCommands,
+}
+
+impl Hooks {
+    pub fn new(commands: HookCommands) -> Self {
+        Self { commands }
+    }
+}
+
+impl Extension for Hooks {
+    fn name(&self) -> &'static str {
+        "hooks"
+    }
+
+    fn notify(&self, notification: &Notification) {
+        let command = match notification.event.as_str() {
+            "start" => &self.commands.track_change,
+            "pause" => &self.commands.pause,
+            "stop" => &self.commands.stop,
+            "queue-empty" => &self.commands.queue_empty,
+            _ => return,
+        };
+
+        if let Some(command) = command {
+            run(command.clone(), notification.clone());
+        }
+    }
+}
+
+/// Run a command through `sh` in the background.
+/// Failures are only logged, a broken hook must never interrupt playback.
+fn run(command: String, notification: Notification) {
+    thread::spawn(move || {
+        let seconds = |duration: Option<Duration>| {
+            duration
+                .map(|duration| duration.as_secs().to_string())
+                .unwrap_or_default()
+        };
+
+        // Reading the tags prerolls the file, which is why it's done here.
+        let tags = match notification.uri.as_str() {
+            "" => Default::default(),
+            uri => tags::read(uri).unwrap_or_default(),
+        };
+        let path = uri_to_path(&notification.uri).unwrap_or_default();
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .env("MUSIKBOX_EVENT", &notification.event)
+            .env("MUSIKBOX_URI", &notification.uri)
+            .env("MUSIKBOX_PATH", path)
+            .env("MUSIKBOX_TITLE", tags.title.unwrap_or_default())
+            .env("MUSIKBOX_ARTIST", tags.artist.unwrap_or_default())
+            .env("MUSIKBOX_ALBUM", tags.album.unwrap_or_default())
+            .env("MUSIKBOX_POSITION", seconds(notification.position))
+            .env("MUSIKBOX_DURATION", seconds(notification.duration))
+            .env("MUSIKBOX_VOLUME", notification.volume.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => tracing::warn!("{} hook failed: {status}", notification.event),
+            Err(err) => tracing::warn!("{} hook failed: {err}", notification.event),
+        }
+    });
+}
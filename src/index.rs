@@ -0,0 +1,278 @@
+use crate::tags::Tags;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::columnar::Column;
+use tantivy::query::{AllQuery, BooleanQuery, FuzzyTermQuery, Occur, Query};
+use tantivy::schema::{Field, Schema, FAST, INDEXED, TEXT};
+use tantivy::{
+    DocId, Index, IndexReader, IndexWriter, ReloadPolicy, Score, SegmentOrdinal, SegmentReader,
+    TantivyDocument, Term,
+};
+
+/// Memory the writer may use before flushing a segment, the least tantivy accepts.
+const WRITER_MEMORY: usize = 15_000_000;
+
+/// Full-text index of the paths and tags of the library, kept in memory.
+/// Every word of a search has to match the start of a word in any field,
+/// with a typo allowed in longer words, so "thundr" finds "Thunderstruck".
+pub struct SearchIndex {
+    index: Index,
+    writer: IndexWriter,
+    reader: IndexReader,
+    /// Number of a file in `uris`, which is much quicker to get for many results
+    /// than a stored URI.
+    id: Field,
+    ids: HashMap<String, u64>,
+    /// URIs of the indexed files.
+    uris: Vec<String>,
+    /// Searched fields: the path, title, artist, album and genre.
+    text: [Field; 5],
+    /// Whether files were changed since the searcher was last reloaded.
+    dirty: bool,
+}
+
+impl SearchIndex {
+    pub fn new() -> tantivy::Result<Self> {
+        let mut schema = Schema::builder();
+        let id = schema.add_u64_field("id", INDEXED | FAST);
+        let text = ["path", "title", "artist", "album", "genre"]
+            .map(|name| schema.add_text_field(name, TEXT));
+
+        let index = Index::create_in_ram(schema.build());
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        Ok(Self {
+            index,
+            writer,
+            reader,
+            id,
+            ids: HashMap::new(),
+            uris: Vec::new(),
+            text,
+            dirty: false,
+        })
+    }
+
+    /// Add a file, or replace it once its tags are known.
+    pub fn set(&mut self, uri: &str, path: &Path, tags: &Tags) -> tantivy::Result<()> {
+        let id = match self.ids.get(uri) {
+            Some(&id) => {
+                self.writer.delete_term(Term::from_field_u64(self.id, id));
+                id
+            }
+            None => {
+                let id = self.uris.len() as u64;
+                self.ids.insert(uri.to_string(), id);
+                self.uris.push(uri.to_string());
+                id
+            }
+        };
+
+        let mut doc = TantivyDocument::default();
+        doc.add_u64(self.id, id);
+
+        let values = [
+            Some(path.to_string_lossy().into_owned()),
+            tags.title.clone(),
+            tags.artist.clone(),
+            tags.album.clone(),
+            tags.genre.clone(),
+        ];
+        for (field, value) in self.text.into_iter().zip(values) {
+            if let Some(value) = value {
+                doc.add_text(field, value);
+            }
+        }
+
+        self.writer.add_document(doc)?;
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn contains(&self, uri: &str) -> bool {
+        self.ids.contains_key(uri)
+    }
+
+    /// Get the URIs of the files matching a search. Changes since the last search
+    /// are committed first, which is why this needs a mutable reference.
+    pub fn search(&mut self, search: &str) -> tantivy::Result<HashSet<String>> {
+        if self.dirty {
+            self.writer.commit()?;
+            self.reader.reload()?;
+            self.dirty = false;
+        }
+
+        let mut tokenizer = self.index.tokenizer_for_field(self.text[0])?;
+        let mut words = Vec::new();
+        tokenizer
+            .token_stream(search)
+            .process(&mut |token| words.push(token.text.clone()));
+
+        let query: Box<dyn Query> = match words.is_empty() {
+            true => Box::new(AllQuery),
+            false => Box::new(BooleanQuery::new(
+                words
+                    .iter()
+                    .map(|word| (Occur::Must, self.word_query(word)))
+                    .collect(),
+            )),
+        };
+
+        let ids = self.reader.searcher().search(&query, &IdCollector)?;
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| self.uris.get(id as usize).cloned())
+            .collect())
+    }
+
+    /// Match a word of a search as the prefix of a word in any field.
+    fn word_query(&self, word: &str) -> Box<dyn Query> {
+        // Short words would match almost anything with a typo.
+        let distance = match word.chars().count() {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+
+        Box::new(BooleanQuery::new(
+            self.text
+                .iter()
+                .map(|&field| {
+                    let term = Term::from_field_text(field, word);
+                    let query: Box<dyn Query> =
+                        Box::new(FuzzyTermQuery::new_prefix(term, distance, true));
+                    (Occur::Should, query)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Collects the ids of the matching files.
+struct IdCollector;
+
+impl Collector for IdCollector {
+    type Fruit = Vec<u64>;
+    type Child = SegmentIdCollector;
+
+    fn for_segment(
+        &self,
+        _segment: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> tantivy::Result<SegmentIdCollector> {
+        Ok(SegmentIdCollector {
+            column: reader.fast_fields().u64("id")?,
+            ids: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Vec<u64>>) -> tantivy::Result<Vec<u64>> {
+        Ok(fruits.concat())
+    }
+}
+
+struct SegmentIdCollector {
+    column: Column<u64>,
+    ids: Vec<u64>,
+}
+
+impl SegmentCollector for SegmentIdCollector {
+    type Fruit = Vec<u64>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        self.ids.extend(self.column.first(doc));
+    }
+
+    fn harvest(self) -> Vec<u64> {
+        self.ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::new().unwrap();
+        let tracks = [
+            ("/music/acdc/01.flac", "Thunderstruck", "AC/DC", "Rock"),
+            ("/music/abba/02.flac", "Dancing Queen", "ABBA", "Pop"),
+            ("/music/queen/03.flac", "Bohemian Rhapsody", "Queen", "Rock"),
+        ];
+
+        for (path, title, artist, genre) in tracks {
+            let tags = Tags {
+                title: Some(title.to_string()),
+                artist: Some(artist.to_string()),
+                genre: Some(genre.to_string()),
+                ..Tags::default()
+            };
+            index
+                .set(&format!("file://{path}"), Path::new(path), &tags)
+                .unwrap();
+        }
+
+        index
+    }
+
+    fn search(index: &mut SearchIndex, search: &str) -> Vec<String> {
+        let mut uris: Vec<String> = index.search(search).unwrap().into_iter().collect();
+        uris.sort();
+        uris
+    }
+
+    #[test]
+    fn fuzzy_prefixes() {
+        let mut index = index();
+        assert_eq!(search(&mut index, "thundr"), ["file:///music/acdc/01.flac"]);
+        assert_eq!(search(&mut index, "BOHEM"), ["file:///music/queen/03.flac"]);
+        assert_eq!(search(&mut index, "ac dc"), ["file:///music/acdc/01.flac"]);
+        assert!(search(&mut index, "jazz").is_empty());
+    }
+
+    #[test]
+    fn every_word_matches() {
+        let mut index = index();
+        assert_eq!(
+            search(&mut index, "queen"),
+            ["file:///music/abba/02.flac", "file:///music/queen/03.flac"]
+        );
+        assert_eq!(
+            search(&mut index, "queen rock"),
+            ["file:///music/queen/03.flac"]
+        );
+        assert_eq!(
+            search(&mut index, "abba 02"),
+            ["file:///music/abba/02.flac"]
+        );
+        assert_eq!(search(&mut index, "").len(), 3);
+    }
+
+    #[test]
+    fn replaces_files() {
+        let mut index = index();
+        let uri = "file:///music/acdc/01.flac";
+        let tags = Tags {
+            title: Some(String::from("Highway to Hell")),
+            ..Tags::default()
+        };
+        index
+            .set(uri, Path::new("/music/acdc/01.flac"), &tags)
+            .unwrap();
+
+        assert!(search(&mut index, "thunderstruck").is_empty());
+        assert_eq!(search(&mut index, "highway"), [uri]);
+        assert_eq!(search(&mut index, "").len(), 3);
+    }
+}
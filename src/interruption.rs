@@ -0,0 +1,159 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+const ROUTE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Words in the names of output devices and ports that only the listener hears.
+const PRIVATE_OUTPUTS: [&str; 4] = ["headphone", "headset", "bluez", "earpiece"];
+
+/// Event that should pause playback, or allows it to resume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interruption {
+    /// The system is about to suspend.
+    Suspend,
+    /// The system woke up again.
+    Wake,
+    /// Output moved from headphones to other devices, e.g. because they were unplugged.
+    HeadphonesRemoved,
+    /// Output moved back to headphones.
+    HeadphonesConnected,
+}
+
+/// Watch for suspends and wakeups announced by logind in the background.
+/// Gives up if `gdbus` isn't available.
+pub fn watch_suspend<F>(f: F)
+where
+    F: Fn(Interruption) + Send + 'static,
+{
+    let child = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            "/org/freedesktop/login1",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::warn!("can't watch for suspends: {err}");
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => return,
+        };
+
+        // e.g. /org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if !line.contains(".PrepareForSleep ") {
+                continue;
+            }
+
+            if line.contains("(true,)") {
+                tracing::info!("system is suspending");
+                f(Interruption::Suspend);
+            } else if line.contains("(false,)") {
+                tracing::info!("system woke up");
+                f(Interruption::Wake);
+            }
+        }
+
+        let _ = child.wait();
+        tracing::warn!("stopped watching for suspends, gdbus exited");
+    });
+}
+
+/// Watch the active port of the default output device of PulseAudio or PipeWire
+/// in the background and report when it moves away from or back to headphones.
+/// Gives up if `pactl` isn't available.
+pub fn watch_headphones<F>(f: F)
+where
+    F: Fn(Interruption) + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut private = None;
+
+        loop {
+            match output_route() {
+                Ok(Some(route)) => {
+                    let is_private = is_private(&route);
+                    match private {
+                        Some(true) if !is_private => {
+                            tracing::info!("output moved from headphones to {route}");
+                            f(Interruption::HeadphonesRemoved);
+                        }
+                        Some(false) if is_private => {
+                            tracing::info!("output moved to headphones {route}");
+                            f(Interruption::HeadphonesConnected);
+                        }
+                        _ => {}
+                    }
+
+                    private = Some(is_private);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!("can't watch for headphones: {err}");
+                    return;
+                }
+            }
+
+            thread::sleep(ROUTE_POLL_INTERVAL);
+        }
+    });
+}
+
+/// Get the name of the default output device and its active port, e.g.
+/// `alsa_output.pci-0000_00_1f.3.analog-stereo:analog-output-headphones`.
+/// `None` if pactl failed, e.g. because the sound server isn't running.
+fn output_route() -> std::io::Result<Option<String>> {
+    let output = Command::new("pactl")
+        .arg("get-default-sink")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let sink = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = Command::new("pactl")
+        .args(["list", "sinks"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let list = String::from_utf8_lossy(&output.stdout);
+
+    // Sinks are listed as blocks of indented lines,
+    // the active port comes after the name.
+    let mut in_sink = false;
+    for line in list.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("Name: ") {
+            in_sink = name == sink;
+        } else if let Some(port) = line.strip_prefix("Active Port: ").filter(|_| in_sink) {
+            return Ok(Some(format!("{sink}:{port}")));
+        }
+    }
+
+    Ok(Some(sink))
+}
+
+fn is_private(route: &str) -> bool {
+    let route = route.to_lowercase();
+    PRIVATE_OUTPUTS.iter().any(|word| route.contains(word))
+}
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::widgets::{Block, Borders};
+
+/// Terminals narrower than this stack the listing above the other panes.
+const NARROW_WIDTH: u16 = 100;
+/// The control bar is left out if the status pane is shorter than this.
+const CONTROLS_MIN_HEIGHT: u16 = 24;
+
+/// Pane that can be hidden with --hide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pane {
+    Volume,
+    Progress,
+    Controls,
+    Search,
+    Queue,
+    Playlists,
+    History,
+}
+
+impl FromStr for Pane {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "volume" => Ok(Self::Volume),
+            "progress" => Ok(Self::Progress),
+            "controls" => Ok(Self::Controls),
+            "search" => Ok(Self::Search),
+            "queue" => Ok(Self::Queue),
+            "playlists" => Ok(Self::Playlists),
+            "history" => Ok(Self::History),
+            _ => Err(format!(
+                "unknown pane {s}, expected volume, progress, controls, search, queue, playlists or history"
+            )),
+        }
+    }
+}
+
+/// Areas of the panes for a terminal size, `None` for the hidden ones.
+#[derive(Clone, Debug, Default)]
+pub struct Areas {
+    pub listing: Rect,
+    /// Border around the other panes, with the status in its title.
+    pub status: Rect,
+    pub volume: Option<Rect>,
+    pub progress: Option<Rect>,
+    pub controls: Option<Rect>,
+    pub search: Option<Rect>,
+    pub queue: Option<Rect>,
+    pub playlists: Option<Rect>,
+    pub timers: Option<Rect>,
+    pub history: Option<Rect>,
+    pub chapters: Option<Rect>,
+}
+
+impl Areas {
+    /// Lay out the panes. The listing takes up `listing` percent of the width,
+    /// or of the height on narrow terminals where it is stacked above the others.
+    /// The timers and chapters panes are only shown if there are any.
+    pub fn new(size: Rect, listing: u16, hidden: &[Pane], timers: bool, chapters: bool) -> Self {
+        let visible = |pane| !hidden.contains(&pane);
+
+        let direction = if size.width < NARROW_WIDTH {
+            Direction::Vertical
+        } else {
+            Direction::Horizontal
+        };
+        let split = Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Percentage(listing.min(100)), Constraint::Min(0)])
+            .split(size);
+        let status = split[1];
+        let inner = Block::default().borders(Borders::ALL).inner(status);
+
+        // Gauges and the search field need a line between their borders,
+        // the control bar one for the buttons and one for the indicators.
+        let controls = visible(Pane::Controls) && inner.height >= CONTROLS_MIN_HEIGHT;
+        let heights = [
+            (visible(Pane::Volume), 3),
+            (visible(Pane::Progress), 3),
+            (controls, 5),
+            (visible(Pane::Search), 3),
+        ];
+        let mut constraints: Vec<Constraint> = heights
+            .iter()
+            .filter(|(shown, _)| *shown)
+            .map(|(_, height)| Constraint::Length(*height))
+            .collect();
+        constraints.push(Constraint::Min(0));
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(inner);
+        let mut rows = rows.into_iter();
+        let mut next_row = |shown: bool| if shown { rows.next() } else { None };
+
+        let volume = next_row(visible(Pane::Volume));
+        let progress = next_row(visible(Pane::Progress));
+        let controls = next_row(controls);
+        let search = next_row(visible(Pane::Search));
+        let lists = next_row(true).unwrap_or_default();
+
+        // Queue, playlists and timers side by side above the chapters and the history.
+        let top = [visible(Pane::Queue), visible(Pane::Playlists), timers];
+        let columns = top.iter().filter(|shown| **shown).count() as u32;
+        let stacked = [columns > 0, chapters, visible(Pane::History)];
+        let lines = stacked.iter().filter(|shown| **shown).count() as u32;
+
+        let mut stacked_areas = split_evenly(lists, Direction::Vertical, lines).into_iter();
+        let mut next_line = |shown: bool| if shown { stacked_areas.next() } else { None };
+        let top_area = next_line(columns > 0);
+        let chapters = next_line(chapters);
+        let history = next_line(visible(Pane::History));
+
+        let mut columns = match top_area {
+            Some(area) => split_evenly(area, Direction::Horizontal, columns),
+            None => Vec::new(),
+        }
+        .into_iter();
+        let mut next_column = |shown: bool| if shown { columns.next() } else { None };
+
+        Self {
+            listing: split[0],
+            status,
+            volume,
+            progress,
+            controls,
+            search,
+            queue: next_column(top[0]),
+            playlists: next_column(top[1]),
+            timers: next_column(top[2]),
+            history,
+            chapters,
+        }
+    }
+}
+
+/// Split an area into equal parts.
+fn split_evenly(area: Rect, direction: Direction, parts: u32) -> Vec<Rect> {
+    if parts == 0 {
+        return Vec::new();
+    }
+
+    Layout::default()
+        .direction(direction)
+        .constraints(vec![Constraint::Ratio(1, parts); parts as usize])
+        .split(area)
+}
@@ -0,0 +1,53 @@
+//! Player engine and model types of musikbox.
+//! The terminal interface in `main.rs` is a frontend to this library.
+
+#[cfg(not(any(feature = "gstreamer", feature = "rodio")))]
+compile_error!("enable at least one playback backend: the gstreamer or rodio feature");
+
+pub mod backend;
+#[cfg(feature = "cover-art")]
+pub mod cover_art;
+pub mod eq;
+pub mod export;
+pub mod extension;
+pub mod fade;
+pub mod grouping;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+pub mod index;
+pub mod interruption;
+pub mod json;
+pub mod library;
+pub mod line_mode;
+#[cfg(feature = "listen-along")]
+pub mod listen_along;
+#[cfg(feature = "listenbrainz")]
+pub mod listenbrainz;
+#[cfg(feature = "media-keys")]
+pub mod media_keys;
+pub mod naming;
+#[cfg(feature = "now-playing")]
+pub mod now_playing;
+pub mod player;
+pub mod playlist;
+pub mod profile;
+pub mod queue;
+pub mod ratings;
+pub mod remote;
+pub mod resolve;
+pub mod rules;
+pub mod schedule;
+pub mod search;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod tag_journal;
+pub mod tags;
+pub mod task;
+pub mod verify;
+pub mod watch;
+#[cfg(feature = "web")]
+pub mod web;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+pub mod zone;
@@ -0,0 +1,400 @@
+use crate::index::SearchIndex;
+use crate::player::{file_uri, uri_to_path};
+use crate::remote::{Mounts, Source};
+use crate::tags::{self, Tags};
+use crate::task::{Progress, Task};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Files of the music directory, or of the tracks given as arguments,
+/// and what is known about them. Sizes and tags are read by a background scan.
+pub struct Library {
+    files: Vec<PathBuf>,
+    sizes: HashMap<PathBuf, u64>,
+    sources: HashMap<PathBuf, Source>,
+    /// Tags by URI, including those of streams and files outside of the library.
+    tags: HashMap<String, Tags>,
+    /// Full-text index of the paths and tags for the search.
+    index: SearchIndex,
+    scan: Option<Receiver<(PathBuf, u64, Tags)>>,
+    /// Files the scan reads next because they are visible, the last one first.
+    scan_first: Arc<Mutex<Vec<PathBuf>>>,
+    /// Files whose tags are needed but not cached, e.g. of the playing track.
+    /// The scan reads them before any other file.
+    tag_requests: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+/// Results of the background scan collected by `Library::poll_scan`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanUpdate {
+    /// Number of files that were read since the last poll.
+    pub scanned: usize,
+    /// Whether the scan read the last file.
+    pub finished: bool,
+}
+
+impl Library {
+    pub fn new() -> tantivy::Result<Self> {
+        Ok(Self {
+            files: Vec::new(),
+            sizes: HashMap::new(),
+            sources: HashMap::new(),
+            tags: HashMap::new(),
+            index: SearchIndex::new()?,
+            scan: None,
+            scan_first: Arc::default(),
+            tag_requests: Arc::default(),
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Replace the files with the ones that were read again, forgetting
+    /// the sizes of removed files and finding out where new ones are stored.
+    /// Returns the removed files.
+    pub fn set_files(&mut self, files: Vec<PathBuf>, mounts: &Mounts) -> Vec<PathBuf> {
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        let removed = self
+            .files
+            .iter()
+            .filter(|file| !present.contains(file))
+            .cloned()
+            .collect();
+
+        self.sizes.retain(|file, _| present.contains(file));
+        self.sources.retain(|file, _| present.contains(file));
+
+        for file in &files {
+            if !self.sources.contains_key(file) {
+                self.sources.insert(file.clone(), mounts.source(file));
+            }
+        }
+
+        self.files = files;
+        removed
+    }
+
+    /// Get the size of a file, once it has been scanned.
+    pub fn size(&self, file: &Path) -> Option<u64> {
+        self.sizes.get(file).copied()
+    }
+
+    pub fn source(&self, file: &Path) -> Option<Source> {
+        self.sources.get(file).copied()
+    }
+
+    /// Whether the library contains files from more than one source.
+    pub fn has_mixed_sources(&self) -> bool {
+        let mut sources = self.sources.values();
+        match sources.next() {
+            Some(first) => sources.any(|source| source != first),
+            None => false,
+        }
+    }
+
+    /// Get the cached tags of a URI without blocking.
+    /// Files that haven't been read yet are queued for the background scan,
+    /// which `poll_scan` starts if needed, and have no tags until then,
+    /// just like unreadable files.
+    pub fn tags(&self, uri: &str) -> Tags {
+        if let Some(tags) = self.tags.get(uri) {
+            return tags.clone();
+        }
+
+        // Remote streams would block while prerolling, their tags are only used once known.
+        if let Some(path) = uri_to_path(uri) {
+            let mut requests = self.tag_requests.lock().unwrap();
+            if !requests.contains(&path) {
+                requests.push(path);
+            }
+        }
+
+        Tags::default()
+    }
+
+    /// Get the tags of a URI if they are cached, without requesting them.
+    pub fn cached_tags(&self, uri: &str) -> Option<&Tags> {
+        self.tags.get(uri)
+    }
+
+    /// Get all cached tags by URI.
+    pub fn tag_cache(&self) -> &HashMap<String, Tags> {
+        &self.tags
+    }
+
+    /// Get the tags of a URI, reading them if they aren't cached yet.
+    /// This blocks, so it's only for actions that need the tags right away.
+    pub fn read_tags(&mut self, uri: &str) -> &Tags {
+        if !self.tags.contains_key(uri) {
+            self.cache_tags(uri.to_string(), tags::read(uri).unwrap_or_default());
+        }
+
+        &self.tags[uri]
+    }
+
+    /// Cache the tags of a URI, and index them for the search if it's a local file.
+    pub fn cache_tags(&mut self, uri: String, tags: Tags) {
+        if let Some(path) = uri_to_path(&uri) {
+            if let Err(e) = self.index.set(&uri, &path, &tags) {
+                tracing::warn!("can't index {}: {e}", path.display());
+            }
+        }
+
+        self.tags.insert(uri, tags);
+    }
+
+    /// Drop the cached tags of a URI, so that they are read again when needed.
+    pub fn forget_tags(&mut self, uri: &str) {
+        self.tags.remove(uri);
+    }
+
+    /// Get the URIs of the files matching a plain search.
+    /// Files whose tags haven't been read yet are found by their path until then.
+    pub fn search(&mut self, files: &[PathBuf], search: &str) -> tantivy::Result<HashSet<String>> {
+        for file in files {
+            let uri = file_uri(file);
+            if !self.index.contains(&uri) {
+                let tags = self.tags(&uri);
+                self.index.set(&uri, file, &tags)?;
+            }
+        }
+
+        self.index.search(search)
+    }
+
+    /// Read the size and tags of the files that haven't been scanned yet
+    /// in the background, starting with the visible ones set with `scan_first`.
+    pub fn start_scan(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        let files: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|file| !self.sizes.contains_key(*file))
+            .cloned()
+            .collect();
+        let first = Arc::clone(&self.scan_first);
+        let requests = Arc::clone(&self.tag_requests);
+
+        thread::spawn(move || {
+            let mut pending: HashSet<PathBuf> = files.iter().cloned().collect();
+            let mut files = files.into_iter();
+
+            loop {
+                // Requested files are read even if they were scanned before,
+                // their tags were dropped from the cache to read them again.
+                let requested = requests.lock().unwrap().pop();
+                let file = match requested {
+                    Some(file) => {
+                        pending.remove(&file);
+                        file
+                    }
+                    None => {
+                        let file = match first.lock().unwrap().pop() {
+                            Some(file) => file,
+                            None => match files.next() {
+                                Some(file) => file,
+                                None => break,
+                            },
+                        };
+                        if !pending.remove(&file) {
+                            continue;
+                        }
+
+                        file
+                    }
+                };
+
+                let size = fs::metadata(&file).map(|m| m.len()).unwrap_or_default();
+                let tags = tags::read(&file_uri(&file)).unwrap_or_default();
+
+                if tx.send((file, size, tags)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.scan = Some(rx);
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        self.scan.is_some()
+    }
+
+    /// Have the scan read these files next, the last one first, e.g. the visible ones.
+    pub fn scan_first(&self, files: impl Iterator<Item = PathBuf>) {
+        *self.scan_first.lock().unwrap() = files
+            .filter(|file| !self.sizes.contains_key(file))
+            .collect();
+    }
+
+    /// Collect the results the background scan produced so far,
+    /// starting it again if tags were requested after it finished.
+    pub fn poll_scan(&mut self) -> ScanUpdate {
+        let mut update = ScanUpdate::default();
+
+        loop {
+            let received = match &self.scan {
+                Some(rx) => rx.try_recv(),
+                None => {
+                    if !self.tag_requests.lock().unwrap().is_empty() {
+                        self.start_scan();
+                    }
+
+                    return update;
+                }
+            };
+
+            match received {
+                Ok((file, size, tags)) => {
+                    self.cache_tags(file_uri(&file), tags);
+                    self.sizes.insert(file, size);
+                    update.scanned += 1;
+                }
+                Err(mpsc::TryRecvError::Empty) => return update,
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+
+        self.scan = None;
+        update.finished = true;
+        update
+    }
+
+    pub fn scan_progress(&self) -> Progress {
+        Progress {
+            task: Task::Scan,
+            done: self.sizes.len().min(self.files.len()),
+            total: self.files.len(),
+        }
+    }
+
+    /// Forget the sizes of all files, so that the next scan reads every file again.
+    pub fn forget_sizes(&mut self) {
+        self.sizes.clear();
+    }
+}
+
+/// Get the entries of a directory, sorted by name.
+pub fn read_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Create a directory with files that aren't audio, so their tags are empty.
+    fn dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("musikbox-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), file.as_bytes()).unwrap();
+        }
+
+        dir
+    }
+
+    fn scan(library: &mut Library) {
+        let start = Instant::now();
+        while !library.poll_scan().finished {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "scan didn't finish"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn reads_sorted_entries() {
+        let dir = dir("read", &["b.flac", "a.mp3", "c.ogg"]);
+        let names: Vec<String> = read_dir(&dir)
+            .unwrap()
+            .iter()
+            .map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+
+        assert_eq!(names, ["a.mp3", "b.flac", "c.ogg"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scans_and_forgets_removed_files() {
+        let dir = dir("scan", &["a.flac", "bb.flac"]);
+        let mut library = Library::new().unwrap();
+        assert!(library
+            .set_files(read_dir(&dir).unwrap(), &Mounts::default())
+            .is_empty());
+
+        library.start_scan();
+        scan(&mut library);
+        assert_eq!(library.size(&dir.join("bb.flac")), Some(7));
+        assert!(library.scan_progress().is_done());
+        assert_eq!(library.source(&dir.join("a.flac")), Some(Source::Local));
+        assert!(!library.has_mixed_sources());
+
+        let removed = library.set_files(vec![dir.join("a.flac")], &Mounts::default());
+        assert_eq!(removed, [dir.join("bb.flac")]);
+        assert_eq!(library.size(&dir.join("bb.flac")), None);
+        assert_eq!(library.source(&dir.join("bb.flac")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_requested_tags() {
+        let dir = dir("requests", &["a.flac"]);
+        let uri = file_uri(&dir.join("a.flac"));
+        let mut library = Library::new().unwrap();
+
+        // Unknown tags are requested and read by a scan that is started for them.
+        assert_eq!(library.tags(&uri), Tags::default());
+        assert!(library.cached_tags(&uri).is_none());
+        assert_eq!(library.poll_scan(), ScanUpdate::default());
+        assert!(library.is_scanning());
+        scan(&mut library);
+        assert!(library.cached_tags(&uri).is_some());
+
+        library.forget_tags(&uri);
+        assert!(library.tag_cache().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn searches_unscanned_files_by_path() {
+        let mut library = Library::new().unwrap();
+        let files = [
+            PathBuf::from("/music/Queen - Bohemian Rhapsody.flac"),
+            PathBuf::from("/music/ABBA - Waterloo.flac"),
+        ];
+        library.set_files(files.to_vec(), &Mounts::default());
+
+        let uris = library.search(&files, "bohemian").unwrap();
+        assert_eq!(uris, HashSet::from([file_uri(&files[0])]));
+
+        library.cache_tags(
+            file_uri(&files[1]),
+            Tags {
+                genre: Some(String::from("Pop")),
+                ..Tags::default()
+            },
+        );
+        let uris = library.search(&files, "pop").unwrap();
+        assert_eq!(uris, HashSet::from([file_uri(&files[1])]));
+    }
+}
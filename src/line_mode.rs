@@ -0,0 +1,316 @@
+use crate::player::AutoplayState;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Commands of the --no-tui prompt.
+pub const HELP: &str = "\
+play            resume playback
+play NAME       play the first file whose name contains NAME
+play NUMBER     play a file by its number in the list
+pause, stop     pause or stop playback
+next, previous  play the next or previous track
+seek POSITION   seek to a position, e.g. 12:34, 90 (seconds) or 45%
+volume [PCT]    show the volume or set it in percent
+mute            mute or unmute
+list [NAME]     list the files, or those whose name contains NAME
+status          describe the current track, the volume and the autoplay modes
+repeat, repeat-list, sequential, shuffle, consume
+                toggle an autoplay mode
+help            show this help
+quit            stop playback and exit";
+
+/// Command typed at the --no-tui prompt.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Resume,
+    Play(Track),
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    Seek(SeekTarget),
+    /// Show the volume, after setting it to a percentage if one is given.
+    Volume(Option<f64>),
+    Mute,
+    /// List the files whose name contains a text, all of them if it's empty.
+    List(String),
+    Status,
+    Toggle(Mode),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a line typed at the prompt, `None` if it's empty.
+    /// Fails with the message to print for unknown commands and invalid arguments.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let command = match command {
+            "" => return Ok(None),
+            "play" if argument.is_empty() => Self::Resume,
+            // A number refers to the list printed by the list command.
+            "play" => Self::Play(match argument.parse() {
+                Ok(n) => Track::Number(n),
+                Err(_) => Track::Name(argument.to_string()),
+            }),
+            "pause" => Self::Pause,
+            "stop" => Self::Stop,
+            "next" => Self::Next,
+            "previous" => Self::Previous,
+            "seek" => Self::Seek(
+                argument
+                    .parse()
+                    .map_err(|_| format!("Invalid position {argument}"))?,
+            ),
+            "volume" if argument.is_empty() => Self::Volume(None),
+            "volume" => match argument.trim_end_matches('%').parse() {
+                Ok(percent) => Self::Volume(Some(percent)),
+                Err(_) => return Err(format!("Invalid volume {argument}")),
+            },
+            "mute" => Self::Mute,
+            "list" => Self::List(argument.to_string()),
+            "status" => Self::Status,
+            "repeat" => Self::Toggle(Mode::Repeat),
+            "repeat-list" => Self::Toggle(Mode::RepeatList),
+            "sequential" => Self::Toggle(Mode::Sequential),
+            "shuffle" => Self::Toggle(Mode::Shuffle),
+            "consume" => Self::Toggle(Mode::Consume),
+            "help" => Self::Help,
+            "quit" | "exit" => Self::Quit,
+            _ => {
+                return Err(format!(
+                    "Unknown command {command}, type help for the commands"
+                ))
+            }
+        };
+
+        Ok(Some(command))
+    }
+}
+
+/// File to play, by its number in the list or a part of its name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Track {
+    /// Number starting at 1, as printed by the list command.
+    Number(usize),
+    Name(String),
+}
+
+impl Track {
+    /// Find the file among the listed ones. Names match regardless of case.
+    pub fn find<'a>(&self, files: &'a [PathBuf]) -> Option<&'a PathBuf> {
+        match self {
+            Self::Number(n) => files.get(n.checked_sub(1)?),
+            Self::Name(name) => list(files, name).next().map(|(_, file)| file),
+        }
+    }
+}
+
+/// Get the files whose name contains a text regardless of case,
+/// with their numbers starting at 1.
+pub fn list<'a>(files: &'a [PathBuf], name: &str) -> impl Iterator<Item = (usize, &'a PathBuf)> {
+    let name = name.to_lowercase();
+
+    files.iter().enumerate().filter_map(move |(i, file)| {
+        let file_name = file.file_name().unwrap_or(file.as_os_str());
+        file_name
+            .to_string_lossy()
+            .to_lowercase()
+            .contains(&name)
+            .then_some((i + 1, file))
+    })
+}
+
+/// Autoplay mode toggled at the prompt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Repeat,
+    RepeatList,
+    Sequential,
+    Shuffle,
+    Consume,
+}
+
+impl Mode {
+    /// Turn the mode on or off, returning whether it's on now.
+    pub fn toggle(self, autoplay: &mut AutoplayState) -> bool {
+        let mode = match self {
+            Self::Repeat => &mut autoplay.repeat,
+            Self::RepeatList => &mut autoplay.repeat_list,
+            Self::Sequential => &mut autoplay.sequential,
+            Self::Shuffle => &mut autoplay.shuffle,
+            Self::Consume => &mut autoplay.consume,
+        };
+        *mode = !*mode;
+
+        *mode
+    }
+}
+
+/// Position to seek to, like `12:34`, `1:02:03`, `90` (seconds) or `45%`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeekTarget {
+    Time(Duration),
+    Percent(f64),
+}
+
+impl FromStr for SeekTarget {
+    type Err = ();
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        let target = target.trim();
+
+        if let Some(percent) = target.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse().map_err(|_| ())?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(());
+            }
+
+            return Ok(Self::Percent(percent));
+        }
+
+        let mut seconds = 0.0;
+        for (i, part) in target.split(':').enumerate() {
+            if i > 2 {
+                return Err(());
+            }
+
+            let part: f64 = part.parse().map_err(|_| ())?;
+            if !part.is_finite() || part < 0.0 {
+                return Err(());
+            }
+
+            seconds = seconds * 60.0 + part;
+        }
+
+        Ok(Self::Time(Duration::from_secs_f64(seconds)))
+    }
+}
+
+impl SeekTarget {
+    /// Get the position in a track. Percentages require its duration.
+    pub fn position(self, duration: Option<Duration>) -> Option<Duration> {
+        match self {
+            Self::Time(position) => Some(position),
+            Self::Percent(percent) => Some(duration?.mul_f64(percent / 100.0)),
+        }
+    }
+}
+
+/// Parse a position like `12:34`, `1:02:03`, `90` (seconds) or `45%`.
+/// Percentages require the duration of the track.
+pub fn parse_seek_target(target: &str, duration: Option<Duration>) -> Option<Duration> {
+    target.parse::<SeekTarget>().ok()?.position(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let parse = |line| Command::parse(line).unwrap();
+
+        assert_eq!(parse("  "), None);
+        assert_eq!(parse("play"), Some(Command::Resume));
+        assert_eq!(parse("play 3"), Some(Command::Play(Track::Number(3))));
+        assert_eq!(
+            parse("play  live at wembley "),
+            Some(Command::Play(Track::Name(String::from("live at wembley"))))
+        );
+        assert_eq!(
+            parse("seek 1:30"),
+            Some(Command::Seek(SeekTarget::Time(Duration::from_secs(90))))
+        );
+        assert_eq!(parse("volume"), Some(Command::Volume(None)));
+        assert_eq!(parse("volume 40%"), Some(Command::Volume(Some(40.0))));
+        assert_eq!(parse("list"), Some(Command::List(String::new())));
+        assert_eq!(
+            parse("repeat-list"),
+            Some(Command::Toggle(Mode::RepeatList))
+        );
+        assert_eq!(parse("exit"), Some(Command::Quit));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        assert_eq!(
+            Command::parse("seek soon"),
+            Err(String::from("Invalid position soon"))
+        );
+        assert_eq!(
+            Command::parse("volume loud"),
+            Err(String::from("Invalid volume loud"))
+        );
+        assert_eq!(
+            Command::parse("dance"),
+            Err(String::from(
+                "Unknown command dance, type help for the commands"
+            ))
+        );
+    }
+
+    #[test]
+    fn seek_targets() {
+        let minute = Some(Duration::from_secs(60));
+
+        assert_eq!(parse_seek_target("90", None), Some(Duration::from_secs(90)));
+        assert_eq!(
+            parse_seek_target("12:34", None),
+            Some(Duration::from_secs(754))
+        );
+        assert_eq!(
+            parse_seek_target("1:02:03", None),
+            Some(Duration::from_secs(3723))
+        );
+        assert_eq!(
+            parse_seek_target("1.5", None),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            parse_seek_target("50%", minute),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_seek_target("50%", None), None);
+        assert_eq!(parse_seek_target("101%", minute), None);
+        assert_eq!(parse_seek_target("1:2:3:4", None), None);
+        assert_eq!(parse_seek_target("-5", None), None);
+        assert_eq!(parse_seek_target("inf", None), None);
+        assert_eq!(parse_seek_target("", None), None);
+    }
+
+    #[test]
+    fn finds_tracks() {
+        let files = [
+            PathBuf::from("/music/Queen/Bohemian Rhapsody.flac"),
+            PathBuf::from("/music/Queen/Radio Ga Ga.flac"),
+        ];
+
+        assert_eq!(Track::Number(2).find(&files), Some(&files[1]));
+        assert_eq!(Track::Number(0).find(&files), None);
+        assert_eq!(Track::Number(3).find(&files), None);
+        assert_eq!(
+            Track::Name(String::from("RADIO")).find(&files),
+            Some(&files[1])
+        );
+        // Directories aren't part of the name.
+        assert_eq!(Track::Name(String::from("queen")).find(&files), None);
+
+        let listed: Vec<usize> = list(&files, "a").map(|(n, _)| n).collect();
+        assert_eq!(listed, [1, 2]);
+    }
+
+    #[test]
+    fn toggles_modes() {
+        let mut autoplay = AutoplayState::default();
+        assert!(Mode::Shuffle.toggle(&mut autoplay));
+        assert!(autoplay.shuffle);
+        assert!(!Mode::Shuffle.toggle(&mut autoplay));
+        assert!(!autoplay.shuffle);
+    }
+}
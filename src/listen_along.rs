@@ -0,0 +1,137 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval in which the host sends its state to the peers.
+const BROADCAST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time to wait before reconnecting to a host that went away.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Playback state of the host, sent to the peers as a tab-separated line
+/// of the state, the position in milliseconds and the URI.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncState {
+    /// URI of the current track, empty if playback is stopped.
+    pub uri: String,
+    pub position: Duration,
+    pub playing: bool,
+}
+
+impl SyncState {
+    fn to_line(&self) -> String {
+        let state = match (self.uri.is_empty(), self.playing) {
+            (true, _) => "stopped",
+            (false, true) => "playing",
+            (false, false) => "paused",
+        };
+
+        format!("{state}\t{}\t{}", self.position.as_millis(), self.uri)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let state = fields.next()?;
+        let position = Duration::from_millis(fields.next()?.parse().ok()?);
+        let uri = fields.next()?.to_string();
+
+        match state {
+            "stopped" => Some(Self::default()),
+            "playing" | "paused" if !uri.is_empty() => Some(Self {
+                uri,
+                position,
+                playing: state == "playing",
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Host of a listen-along session, broadcasting its state to every connected peer.
+pub struct Host {
+    /// Latest state and when it was published, to extrapolate the position.
+    state: Arc<Mutex<(SyncState, Instant)>>,
+}
+
+impl Host {
+    /// Accept peers on an address in the background.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let state = Arc::new(Mutex::new((SyncState::default(), Instant::now())));
+
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&peers);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let peer = stream.peer_addr().map(|addr| addr.to_string());
+                tracing::info!("listen-along peer {} joined", peer.unwrap_or_default());
+
+                // A peer that stops reading must not hold up the others.
+                if stream.set_write_timeout(Some(BROADCAST_INTERVAL)).is_ok() {
+                    accepted.lock().unwrap().push(stream);
+                }
+            }
+        });
+
+        let current = Arc::clone(&state);
+        thread::spawn(move || loop {
+            let line = {
+                let (state, published) = &*current.lock().unwrap();
+                let mut state = state.clone();
+                if state.playing {
+                    state.position += published.elapsed();
+                }
+
+                state.to_line()
+            };
+
+            // Peers that can't be written to have left.
+            peers
+                .lock()
+                .unwrap()
+                .retain_mut(|peer: &mut TcpStream| writeln!(peer, "{line}").is_ok());
+
+            thread::sleep(BROADCAST_INTERVAL);
+        });
+
+        Ok(Self { state })
+    }
+
+    /// Update the state sent to the peers.
+    pub fn publish(&self, state: SyncState) {
+        *self.state.lock().unwrap() = (state, Instant::now());
+    }
+}
+
+/// Follow the state of a host in the background, reconnecting if the connection is lost.
+pub fn follow<F>(addr: SocketAddr, f: F)
+where
+    F: Fn(SyncState) + Send + 'static,
+{
+    thread::spawn(move || loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                tracing::info!("joined listen-along session of {addr}");
+
+                for line in BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+
+                    match SyncState::from_line(&line) {
+                        Some(state) => f(state),
+                        None => tracing::debug!("invalid listen-along state {line:?}"),
+                    }
+                }
+
+                tracing::warn!("lost connection to listen-along host {addr}");
+            }
+            Err(err) => tracing::warn!("can't join listen-along session of {addr}: {err}"),
+        }
+
+        thread::sleep(RECONNECT_INTERVAL);
+    });
+}
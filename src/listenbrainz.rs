@@ -0,0 +1,169 @@
+use crate::extension::{Extension, Notification};
+use crate::json;
+use crate::tags;
+
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const SUBMIT_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+/// Time a track has to keep playing before it is reported,
+/// so that skipping through a list doesn't send a request per track.
+const DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// State of the last "playing now" submission.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Status {
+    Idle,
+    /// Waiting for the debounce time to pass.
+    Queued,
+    Sent,
+    Failed(String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Idle => write!(f, "idle"),
+            Self::Queued => write!(f, "queued"),
+            Self::Sent => write!(f, "sent"),
+            Self::Failed(err) => write!(f, "failed ({err})"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    /// Incremented on every track change to cancel pending submissions.
+    generation: u64,
+    status: Status,
+}
+
+/// Extension reporting the current track to ListenBrainz as "playing now".
+/// These updates are not listens, they only show up on the profile while playing.
+pub struct ListenBrainz {
+    token: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl ListenBrainz {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            state: Arc::new(Mutex::new(State {
+                generation: 0,
+                status: Status::Idle,
+            })),
+        }
+    }
+}
+
+impl Extension for ListenBrainz {
+    fn name(&self) -> &'static str {
+        "listenbrainz"
+    }
+
+    fn notify(&self, notification: &Notification) {
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            match notification.event.as_str() {
+                "start" if !notification.uri.is_empty() => {
+                    state.generation += 1;
+                    state.status = Status::Queued;
+                }
+                "stop" => {
+                    state.generation += 1;
+                    if state.status == Status::Queued {
+                        state.status = Status::Idle;
+                    }
+
+                    return;
+                }
+                _ => return,
+            }
+
+            state.generation
+        };
+
+        let token = self.token.clone();
+        let state = Arc::clone(&self.state);
+        let notification = notification.clone();
+
+        thread::spawn(move || {
+            thread::sleep(DEBOUNCE);
+            if state.lock().unwrap().generation != generation {
+                return;
+            }
+
+            let status = match submit(&token, &notification) {
+                Ok(()) => Status::Sent,
+                Err(err) => {
+                    tracing::warn!("ListenBrainz submission failed: {err}");
+                    Status::Failed(err.to_string())
+                }
+            };
+
+            let mut state = state.lock().unwrap();
+            if state.generation == generation {
+                state.status = status;
+            }
+        });
+    }
+
+    fn status(&self) -> Option<String> {
+        match &self.state.lock().unwrap().status {
+            Status::Idle => None,
+            status => Some(format!("ListenBrainz: {status}")),
+        }
+    }
+}
+
+/// Send a "playing now" update. ListenBrainz requires an artist and a title.
+fn submit(token: &str, notification: &Notification) -> anyhow::Result<()> {
+    let tags = tags::read(&notification.uri)?;
+    let artist = tags
+        .artist
+        .ok_or_else(|| anyhow::anyhow!("no artist tag"))?;
+    let title = tags.title.unwrap_or_else(|| notification.file());
+
+    let mut metadata = format!(
+        "\"artist_name\":\"{}\",\"track_name\":\"{}\"",
+        json::escape(&artist),
+        json::escape(&title)
+    );
+    if let Some(album) = tags.album {
+        metadata += &format!(",\"release_name\":\"{}\"", json::escape(&album));
+    }
+
+    let body = format!(
+        "{{\"listen_type\":\"playing_now\",\"payload\":[{{\"track_metadata\":{{{metadata}}}}}]}}"
+    );
+
+    // The token is passed on stdin to keep it out of the process list.
+    let mut child = Command::new("curl")
+        .args(["-fsS", "--max-time", "10", "-X", "POST"])
+        .args(["-H", "Content-Type: application/json", "-H", "@-"])
+        .arg("--data-binary")
+        .arg(body)
+        .arg(SUBMIT_URL)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "Authorization: Token {token}")?;
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let err = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow::anyhow!("{}", err.trim()))
+    }
+}
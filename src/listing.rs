@@ -0,0 +1,144 @@
+use musikbox::grouping::Group;
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Row of the listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// Header of a group, by index of the group.
+    Header(usize),
+    /// File, by index in the list.
+    File(usize),
+}
+
+/// State of the listing kept between frames. Only the visible rows are rendered,
+/// everything that takes a pass over all files is cached until the files,
+/// their metadata or the grouping change.
+#[derive(Debug, Default)]
+pub struct Listing {
+    cache: Option<Cache>,
+    /// Index of the topmost visible row.
+    offset: usize,
+}
+
+/// Parts of the listing that take a pass over all files.
+#[derive(Debug)]
+pub struct Cache {
+    pub groups: Vec<Group>,
+    /// Whether the files of each group are hidden.
+    collapsed: Vec<bool>,
+    /// Index of the header row of each group.
+    group_rows: Vec<usize>,
+    rows: usize,
+    /// Number of tracks, size and duration of the list.
+    pub summary: String,
+    /// Whether files are marked with their source.
+    pub badges: bool,
+}
+
+impl Cache {
+    pub fn new(
+        files: usize,
+        groups: Vec<Group>,
+        collapsed: &HashSet<String>,
+        summary: String,
+        badges: bool,
+    ) -> Self {
+        let collapsed: Vec<bool> = groups
+            .iter()
+            .map(|group| collapsed.contains(&group.title))
+            .collect();
+
+        let mut group_rows = Vec::with_capacity(groups.len());
+        let mut rows = 0;
+        for (group, collapsed) in groups.iter().zip(&collapsed) {
+            group_rows.push(rows);
+            rows += if *collapsed { 1 } else { 1 + group.len };
+        }
+
+        if groups.is_empty() {
+            rows = files;
+        }
+
+        Self {
+            groups,
+            collapsed,
+            group_rows,
+            rows,
+            summary,
+            badges,
+        }
+    }
+
+    /// Get the number of rows including the group headers.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_collapsed(&self, group: usize) -> bool {
+        self.collapsed[group]
+    }
+
+    /// Get what is shown in a row.
+    pub fn row(&self, row: usize) -> Row {
+        if self.groups.is_empty() {
+            return Row::File(row);
+        }
+
+        let group = self.group_rows.partition_point(|&start| start <= row) - 1;
+        match row - self.group_rows[group] {
+            0 => Row::Header(group),
+            n => Row::File(self.groups[group].start + n - 1),
+        }
+    }
+
+    /// Get the row of a file, which is the header of its group if that is collapsed.
+    pub fn row_of(&self, file: usize) -> usize {
+        if self.groups.is_empty() {
+            return file;
+        }
+
+        let group = self
+            .groups
+            .partition_point(|group| group.start <= file)
+            .saturating_sub(1);
+        if self.collapsed[group] {
+            self.group_rows[group]
+        } else {
+            self.group_rows[group] + 1 + file - self.groups[group].start
+        }
+    }
+}
+
+impl Listing {
+    /// Build the cache again on its next use, e.g. after the files changed.
+    pub fn invalidate(&mut self) {
+        self.cache = None;
+    }
+
+    /// Take the cache out, e.g. to use it alongside other state. It's put back with `set`.
+    pub fn take(&mut self) -> Option<Cache> {
+        self.cache.take()
+    }
+
+    pub fn set(&mut self, cache: Cache) {
+        self.cache = Some(cache);
+    }
+
+    /// Scroll as little as possible to show the selected row
+    /// and get the rows that fit into a height.
+    pub fn scroll(&mut self, selected: Option<usize>, rows: usize, height: usize) -> Range<usize> {
+        if let Some(selected) = selected {
+            if selected < self.offset {
+                self.offset = selected;
+            } else if selected >= self.offset + height {
+                self.offset = selected + 1 - height;
+            }
+        }
+
+        // Lists that became shorter don't leave empty rows at the bottom.
+        self.offset = self.offset.min(rows.saturating_sub(height));
+        self.offset..(self.offset + height).min(rows)
+    }
+}
@@ -0,0 +1,18 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::filter::LevelFilter;
+
+/// Log events up to a level to a file, appending to it if it exists.
+/// The terminal is in raw mode while the TUI runs, so nothing is printed.
+/// Lines are `TIMESTAMP LEVEL TARGET: MESSAGE`.
+pub fn init(path: &Path, level: LevelFilter) -> anyhow::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(level)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!(e))
+}
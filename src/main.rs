@@ -1,31 +1,113 @@
-use clap::Parser;
-use crossterm::event::{self, Event, KeyCode};
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use gstreamer::ClockTime;
-use gstreamer_play::{Play, PlayVideoRenderer};
+use clap::{Parser, Subcommand};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use regex::Regex;
 use signal_hook::consts::signal::*;
-use std::fmt;
+use signal_hook::iterator::Signals;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Once};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::MetadataExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "cover-art")]
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing_subscriber::filter::LevelFilter;
+use tui::backend::{Backend, CrosstermBackend};
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
-use tui::style::{Color, Style};
-use tui::widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph};
-use tui::{backend::CrosstermBackend, Terminal};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph};
+use tui::{Frame, Terminal};
+
+mod glyphs;
+mod help;
+mod layout;
+mod listing;
+mod logger;
+mod notice;
+mod scrollbar;
+mod terminal;
+mod theme;
+
+use glyphs::{Glyphs, Symbol};
+use help::Help;
+use layout::{Areas, Pane};
+use listing::{Listing, Row};
+use musikbox::backend::{self, Output, PlaybackBackend};
+#[cfg(feature = "cover-art")]
+use musikbox::cover_art::CoverArt;
+use musikbox::eq::{self, EqOutput, EqProfile};
+use musikbox::export;
+use musikbox::fade::{Fade, FadeCurve, FadePolicy};
+use musikbox::grouping::{self, Group, Grouping};
+#[cfg(feature = "hooks")]
+use musikbox::hooks::{HookCommands, Hooks};
+use musikbox::interruption::{self, Interruption};
+use musikbox::library::{self, Library};
+use musikbox::line_mode::{self, parse_seek_target, Mode};
+#[cfg(feature = "listen-along")]
+use musikbox::listen_along::{self, SyncState};
+#[cfg(feature = "listenbrainz")]
+use musikbox::listenbrainz::ListenBrainz;
+#[cfg(feature = "media-keys")]
+use musikbox::media_keys::{self, MediaKey};
+use musikbox::naming::NamingTemplate;
+#[cfg(feature = "now-playing")]
+use musikbox::now_playing::NowPlaying;
+use musikbox::player::{
+    file_uri, uri_name, uri_to_path, AutoplayState, PlayState, PlaybackEvent, Player, ReplayGain,
+    VolumeScale,
+};
+use musikbox::playlist::{self, Playlist};
+use musikbox::profile::Profile;
+use musikbox::queue::{Advance, History, Previous, Queue, ShuffleWeights};
+use musikbox::ratings::{self, Ratings};
+use musikbox::remote::{self, LocalPaths, Mounts, Source};
+use musikbox::resolve;
+use musikbox::rules::{Context, SmartPlaylist};
+use musikbox::schedule::{self, Schedule, Timer};
+use musikbox::search;
+use musikbox::snapshot::{Snapshot, Snapshots};
+use musikbox::state;
+use musikbox::stats::Stats;
+use musikbox::tag_journal::{self, FileEdit, TagJournal};
+use musikbox::tags::{self, Chapter, Tags};
+use musikbox::task::{Progress, Task};
+use musikbox::verify::{self, Verifications};
+use musikbox::watch;
+#[cfg(feature = "webhook")]
+use musikbox::webhook::Webhooks;
+use musikbox::zone::{Zone, ZoneOutput};
+#[cfg(feature = "web")]
+use musikbox::{json, web};
+use notice::{Level, Notice};
+use scrollbar::Scrollbar;
+use terminal::RawMode;
+use theme::GenreColor;
 
 #[derive(Debug, Parser)]
 #[command(author = "Himbeer", version = "v0.1.0", about = "A custom music player for the command line, written in Rust.", long_about = None)]
 struct Args {
-    /// Playlist directory. Defaults to current directory.
+    /// Playlist directory, or a directory on a network share such as smb://nas/music.
+    /// Defaults to current directory.
     #[arg(short = 'd', long = "dir")]
-    dir: Option<String>,
-    /// Play file on startup. Overrides --random.
+    dir: Option<PathBuf>,
+    /// Play file or URI on startup. Overrides --random.
     #[arg(short = 'p', long = "play")]
-    play: Option<String>,
+    play: Option<PathBuf>,
+    /// Files or URLs to list and play in this order instead of the directory.
+    #[arg(value_name = "TRACK", conflicts_with = "no_listing")]
+    tracks: Vec<PathBuf>,
+    /// Read the tracks from standard input, one path or URL per line,
+    /// e.g. `find ~/Music -name '*.flac' | musikbox --stdin`.
+    #[arg(long = "stdin", conflicts_with = "no_listing")]
+    stdin: bool,
     /// Exit when there are no songs left to play. Useful in scripts.
     #[arg(short = 'e', long = "no-remain")]
     no_remain: bool,
@@ -33,7 +115,7 @@ struct Args {
     #[arg(short = 'r', long = "random")]
     random: bool,
     /// Initial volume (float from 0.0 to 1.0).
-    /// By default the last selected volume is restored when a file is played.
+    /// By default the volume of the previous session is restored when a file is played.
     #[arg(short = 'v', long = "volume")]
     volume: Option<f64>,
     /// Repeat the entire sequential list. Can be toggled from the TUI.
@@ -48,9 +130,282 @@ struct Args {
     /// Play the list (directory) randomly and indefinitely. Can be toggled from the TUI.
     #[arg(short = 's', long = "shuffle")]
     shuffle: bool,
+    /// Shuffle towards tracks that were played rarely and long ago, given as PLAYS,RECENCY,
+    /// e.g. "1,1". Higher values bias more strongly against tracks that were played
+    /// often or recently, 0 ignores the play count or the recency.
+    #[arg(long = "shuffle-weights", value_name = "PLAYS,RECENCY")]
+    shuffle_weights: Option<ShuffleWeights>,
+    /// Remove tracks from the list once they have been played, e.g. for a party.
+    /// Can be toggled from the TUI.
+    #[arg(long = "consume")]
+    consume: bool,
+    /// Group the listing under headers by directory, album or letter.
+    /// Can be cycled through with G in the listing.
+    #[arg(long = "group-by")]
+    group_by: Option<Grouping>,
     /// Don't create a directory listing.
     #[arg(short = 'n', long = "no-listing")]
     no_listing: bool,
+    /// POST a JSON notification to this URL on playback events
+    /// (start, pause, resume, stop). Can be specified multiple times.
+    #[arg(short = 'w', long = "webhook")]
+    webhooks: Vec<String>,
+    /// Run a shell command when a track starts. The track is described by the
+    /// MUSIKBOX_URI, _PATH, _TITLE, _ARTIST, _ALBUM, _POSITION and _DURATION
+    /// environment variables.
+    #[arg(long = "on-track-change", value_name = "COMMAND")]
+    on_track_change: Option<String>,
+    /// Run a shell command when playback is paused.
+    #[arg(long = "on-pause", value_name = "COMMAND")]
+    on_pause: Option<String>,
+    /// Run a shell command when playback stops.
+    #[arg(long = "on-stop", value_name = "COMMAND")]
+    on_stop: Option<String>,
+    /// Run a shell command when the autoplay mode has nothing left to play.
+    #[arg(long = "on-queue-empty", value_name = "COMMAND")]
+    on_queue_empty: Option<String>,
+    /// Keep this file up to date with the current track and position,
+    /// e.g. for status bars and stream overlays.
+    #[arg(long = "now-playing", value_name = "PATH")]
+    now_playing: Option<PathBuf>,
+    /// Contents of the --now-playing file: json, or a template such as
+    /// "{artist} - {title} [{position}/{duration}]", see the manual for the placeholders.
+    #[arg(
+        long = "now-playing-format",
+        value_name = "FORMAT",
+        default_value = "{artist} - {title}"
+    )]
+    now_playing_format: String,
+    /// Smart playlist as NAME=RULE, e.g. "recent=ext = flac AND modified < 30d".
+    /// Fields: name, ext, size, modified, genre, rating, lastplayed.
+    /// Can be specified multiple times,
+    /// cycle through the lists and statistics views with 'v' in the listing.
+    #[arg(long = "smart")]
+    smart_playlists: Vec<SmartPlaylist>,
+    /// Equalizer profile as NAME=GAINS, with ten gains in dB from 29 Hz to 15 kHz
+    /// separated by commas, or a preset: flat, bass-boost, treble-boost.
+    /// Can be specified multiple times. Requires the GStreamer backend.
+    #[arg(long = "eq-profile", value_name = "NAME=GAINS")]
+    eq_profiles: Vec<EqProfile>,
+    /// Apply an EQ profile while the default output device's name contains SINK,
+    /// given as SINK=PROFILE. * matches any device. Can be specified multiple times,
+    /// the first match wins. Devices are detected with pactl.
+    #[arg(long = "eq-output", value_name = "SINK=PROFILE")]
+    eq_outputs: Vec<EqOutput>,
+    /// Restore the selected file of every list from the previous session.
+    #[arg(long = "remember-selection")]
+    remember_selection: bool,
+    /// Play consecutive tracks of the same album without gaps in sequential mode.
+    #[arg(short = 'g', long = "gapless")]
+    gapless: bool,
+    /// Save the playback history on exit and restore it on startup.
+    #[arg(long = "persist-history")]
+    persist_history: bool,
+    /// Keep saving the current track, the queue and the autoplay modes while playing
+    /// and continue from them on startup, e.g. after a crash or a reboot.
+    #[arg(long = "restore-queue")]
+    restore_queue: bool,
+    /// Pause when the system suspends or the output moves away from headphones,
+    /// e.g. because they were unplugged.
+    #[arg(long = "auto-pause")]
+    auto_pause: bool,
+    /// Resume after waking up or when the headphones are back,
+    /// if playback was paused by --auto-pause.
+    #[arg(long = "auto-resume", requires = "auto_pause")]
+    auto_resume: bool,
+    /// Accent color while playing a genre as GENRE=COLOR, e.g. "metal=red" or "jazz=#ffbf00".
+    /// Can be specified multiple times, the first matching genre wins.
+    #[arg(long = "genre-color")]
+    genre_colors: Vec<GenreColor>,
+    /// Rename files after their tags when saving them in the tag editor, e.g. "{track} {title}".
+    /// Fields: title, artist, album, albumartist, genre, track, disc.
+    #[arg(long = "rename-template", value_name = "TEMPLATE")]
+    rename_template: Option<NamingTemplate>,
+    /// Apply ReplayGain tags: off, track, album or auto.
+    /// Auto uses the album gain while the neighbouring tracks are from the same album.
+    #[arg(long = "replaygain", default_value = "off")]
+    replaygain: ReplayGain,
+    /// Playback backend: gstreamer or rodio, if enabled at build time.
+    /// Defaults to gstreamer if it is available.
+    #[arg(long = "backend", default_value_t)]
+    backend: backend::Backend,
+    /// Fade in the first SECONDS of every track.
+    #[arg(long = "fade-in", value_name = "SECONDS", default_value_t = 0.0)]
+    fade_in: f64,
+    /// Fade out the last SECONDS of every track.
+    /// Gapless transitions don't fade.
+    #[arg(long = "fade-out", value_name = "SECONDS", default_value_t = 0.0)]
+    fade_out: f64,
+    /// Shape of the fades: linear, equal-power or s-curve.
+    #[arg(long = "fade-curve", default_value = "linear")]
+    fade_curve: FadeCurve,
+    /// Which transitions fade: always, or between-albums to play consecutive
+    /// tracks of the same album without fades, e.g. live albums.
+    #[arg(long = "fade-policy", default_value = "between-albums")]
+    fade_policy: FadePolicy,
+    /// Fade to silence over the final SECONDS before the sleep timer
+    /// or the end of the queue stops playback.
+    #[arg(long = "sleep-fade", value_name = "SECONDS", default_value_t = 30.0)]
+    sleep_fade: f64,
+    /// Print status lines instead of drawing the TUI. Control playback with signals.
+    /// Enabled automatically if stdout isn't a terminal, e.g. when piped or run from cron.
+    #[arg(long = "headless")]
+    headless: bool,
+    /// Read commands from a prompt and print state changes as lines of text
+    /// instead of drawing the TUI, e.g. for screen readers. Type help for the commands.
+    #[arg(long = "no-tui")]
+    no_tui: bool,
+    /// Share of the width the listing takes up in percent,
+    /// or of the height in terminals narrower than 100 columns.
+    #[arg(long = "listing-size", value_name = "PERCENT", default_value_t = 50)]
+    listing_size: u16,
+    /// Hide a pane: volume, progress, controls, search, queue, playlists or history.
+    /// Can be specified multiple times.
+    #[arg(long = "hide", value_name = "PANE")]
+    hidden_panes: Vec<Pane>,
+    /// Show words instead of symbols in the control bar and indicators,
+    /// e.g. for screen readers and braille displays.
+    #[arg(long = "labels", conflicts_with_all = ["ascii", "emoji"])]
+    labels: bool,
+    /// Show ASCII symbols instead of emoji, e.g. for the Linux console or terminals
+    /// without an emoji font. Chosen automatically for non-UTF-8 locales and the console.
+    #[arg(long = "ascii", conflicts_with = "emoji")]
+    ascii: bool,
+    /// Show emoji even if the terminal doesn't seem to support them.
+    #[arg(long = "emoji")]
+    emoji: bool,
+    /// Append a log of playback events, key presses and GStreamer warnings to this file.
+    #[arg(long = "log-file")]
+    log_file: Option<PathBuf>,
+    /// Most verbose messages to log: off, error, warn, info, debug or trace.
+    #[arg(long = "log-level", default_value = "info")]
+    log_level: LevelFilter,
+    /// Quit once the first frame is drawn and all tags are loaded, and print how long
+    /// the directory scan, tag loading, opening the databases and drawing took.
+    #[arg(long = "profile-startup")]
+    profile_startup: bool,
+    /// Serve a remote control page and JSON API on this address, e.g. 0.0.0.0:8080.
+    /// Anyone who can reach the address can control playback.
+    #[arg(long = "web", value_name = "ADDR")]
+    web: Option<SocketAddr>,
+    /// Ramp the volume over MILLISECONDS when pausing, resuming, seeking and quitting
+    /// to avoid clicks. 0 switches immediately.
+    #[arg(long = "ramp", value_name = "MILLISECONDS", default_value_t = 80)]
+    ramp: u64,
+    /// Seek by SECONDS with Left and Right in the controls pane.
+    /// Holding Shift seeks ten times as far.
+    #[arg(long = "seek-step", value_name = "SECONDS", default_value_t = 1.0)]
+    seek_step: f64,
+    /// Seek by SECONDS with Down and Up in the controls pane.
+    #[arg(
+        long = "seek-step-large",
+        value_name = "SECONDS",
+        default_value_t = 15.0
+    )]
+    seek_step_large: f64,
+    /// Change the volume by PERCENT with Left and Right in the volume pane.
+    /// Holding Shift changes it ten times as much.
+    #[arg(long = "volume-step", value_name = "PERCENT", default_value_t = 1.0)]
+    volume_step: f64,
+    /// Change the volume by PERCENT with Down and Up in the volume pane.
+    #[arg(
+        long = "volume-step-large",
+        value_name = "PERCENT",
+        default_value_t = 5.0
+    )]
+    volume_step_large: f64,
+    /// Scale of the volume gauge and steps: linear or cubic.
+    /// Cubic steps sound more even, like those of most system mixers.
+    #[arg(long = "volume-scale", default_value = "linear")]
+    volume_scale: VolumeScale,
+    /// Show the volume in decibels as well as in percent.
+    #[arg(long = "volume-db")]
+    volume_db: bool,
+    /// Host a listen-along session on this address, e.g. 0.0.0.0:7070.
+    /// Peers joining with --listen-along follow the track, position and pauses.
+    #[arg(
+        long = "listen-along-host",
+        value_name = "ADDR",
+        conflicts_with = "listen_along"
+    )]
+    listen_along_host: Option<SocketAddr>,
+    /// Join the listen-along session of the host at this address.
+    /// Tracks are played from the library if a file of the same name exists,
+    /// otherwise from the host's URI, e.g. a shared network location.
+    #[arg(long = "listen-along", value_name = "ADDR")]
+    listen_along: Option<SocketAddr>,
+    /// Seek when the position differs from the host's by more than MILLISECONDS.
+    #[arg(
+        long = "listen-along-tolerance",
+        value_name = "MILLISECONDS",
+        default_value_t = 500
+    )]
+    listen_along_tolerance: u64,
+    /// Fetch album covers from the Cover Art Archive for tracks without embedded art.
+    /// Albums are looked up on MusicBrainz by their release ID, or by artist and title.
+    #[arg(long = "cover-art")]
+    cover_art: bool,
+    /// Handle the play, pause, stop, next and previous media keys
+    /// even if the terminal isn't focused. Requires read access to /dev/input.
+    #[arg(long = "media-keys")]
+    media_keys: bool,
+    /// Send "playing now" updates to ListenBrainz using this user token.
+    #[arg(
+        long = "listenbrainz-token",
+        env = "LISTENBRAINZ_TOKEN",
+        hide_env_values = true
+    )]
+    listenbrainz_token: Option<String>,
+    /// Resolve SoundCloud URLs opened with o using this API client ID.
+    #[arg(
+        long = "soundcloud-client-id",
+        env = "SOUNDCLOUD_CLIENT_ID",
+        hide_env_values = true
+    )]
+    soundcloud_client_id: Option<String>,
+    /// Play on this output instead of the default device: a device as listed by
+    /// "pactl list short sinks", the pipe source of a Snapcast server
+    /// ("snapcast:/tmp/snapfifo"), its TCP source ("snapcast://HOST:PORT")
+    /// or an RTP stream ("rtp://HOST:PORT"). Requires the GStreamer backend.
+    #[arg(long = "output", default_value = "default")]
+    output: Output,
+    /// Add a playback zone with its own queue on an output as NAME=OUTPUT,
+    /// e.g. "kitchen=alsa_output.usb-speaker", see --output.
+    /// Can be specified multiple times, switch between the zones with Z.
+    /// Requires the GStreamer backend.
+    #[arg(long = "zone", value_name = "NAME=OUTPUT")]
+    zones: Vec<ZoneOutput>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Work with the listening statistics.
+    Stats {
+        #[command(subcommand)]
+        action: StatsCommand,
+    },
+    /// Decode every file of the directory completely to find damaged or truncated ones.
+    /// Damaged files are marked in the listing.
+    Verify {
+        /// Also check files that were verified before and haven't changed since.
+        #[arg(short = 'a', long = "all")]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum StatsCommand {
+    /// Print the statistics for spreadsheets or scripts.
+    Export {
+        /// Output format: csv or json.
+        #[arg(short = 'f', long = "format", default_value = "csv")]
+        format: export::Format,
+        /// Aggregate the statistics by track, artist, album or day.
+        #[arg(short = 'b', long = "by", default_value = "track")]
+        by: export::Grouping,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -59,6 +414,10 @@ enum CursorState {
     Volume,
     Control,
     Search,
+    History,
+    Chapters,
+    Playlists,
+    Timers,
 }
 
 impl CursorState {
@@ -67,7 +426,11 @@ impl CursorState {
             Self::MusicList => Self::Volume,
             Self::Volume => Self::Control,
             Self::Control => Self::Search,
-            Self::Search => Self::MusicList,
+            Self::Search => Self::History,
+            Self::History => Self::Chapters,
+            Self::Chapters => Self::Playlists,
+            Self::Playlists => Self::Timers,
+            Self::Timers => Self::MusicList,
         };
     }
 }
@@ -78,521 +441,5581 @@ impl Default for CursorState {
     }
 }
 
+/// Username and password entry for a network share that requires authentication.
 #[derive(Debug, Default)]
-struct AutoplayState {
-    repeat_list: bool,
-    repeat: bool,
-    sequential: bool,
-    shuffle: bool,
+struct CredentialPrompt {
+    uri: String,
+    username: String,
+    password: String,
+    /// Focused field: 0 is the username, 1 the password and 2 the keyring toggle.
+    field: usize,
+    save: bool,
+}
+
+/// Files matching the search in the listing.
+enum SearchMatcher {
+    /// The search is empty.
+    All,
+    /// URIs of the files the index found for a plain search.
+    Uris(HashSet<String>),
+    Regex(Regex),
+}
+
+/// Labels of the fields of the tag editor.
+const TAG_FIELDS: [&str; 5] = ["Title", "Artist", "Album", "Track", "Genre"];
+
+/// Edit view for the tags of a file.
+#[derive(Debug)]
+struct TagEditor {
+    /// Files to save the tags to, fields left empty keep the tags of each file.
+    paths: Vec<PathBuf>,
+    /// Tags of each file when the editor was opened, to preview their new names.
+    current: Vec<Tags>,
+    /// Values of the fields in the order of `TAG_FIELDS`.
+    fields: [String; 5],
+    /// Focused field.
+    field: usize,
+}
+
+impl TagEditor {
+    /// Get the tags to save, fields left empty are `None` to be kept.
+    fn tags(&self) -> Result<Tags, String> {
+        let text = |i: usize| {
+            let value = self.fields[i].trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+
+        let track_number = match text(3).map(|n| n.parse::<u32>()) {
+            Some(Ok(n)) => Some(n),
+            Some(Err(_)) => return Err(format!("invalid track number {}", self.fields[3])),
+            None => None,
+        };
+
+        Ok(Tags {
+            title: text(0),
+            artist: text(1),
+            album: text(2),
+            track_number,
+            genre: text(4),
+            ..Default::default()
+        })
+    }
+
+    /// Get the name a file of the editor gets under a naming template once it's saved.
+    fn file_name(&self, i: usize, template: &NamingTemplate) -> Result<String, String> {
+        let tags = self.tags()?;
+        let current = &self.current[i];
+        let saved = Tags {
+            title: tags.title.or_else(|| current.title.clone()),
+            artist: tags.artist.or_else(|| current.artist.clone()),
+            album: tags.album.or_else(|| current.album.clone()),
+            track_number: tags.track_number.or(current.track_number),
+            genre: tags.genre.or_else(|| current.genre.clone()),
+            ..current.clone()
+        };
+
+        template.file_name(&saved, &self.paths[i])
+    }
+
+    /// Describe how the first file would be renamed, and how many others are.
+    fn rename_preview(&self, template: &NamingTemplate) -> String {
+        let others = match self.paths.len() {
+            0 | 1 => String::new(),
+            n => format!(" (and {} more)", n - 1),
+        };
+
+        match self.file_name(0, template) {
+            Ok(name)
+                if Some(name.as_str()) == self.paths[0].file_name().and_then(|n| n.to_str()) =>
+            {
+                format!("Name: {name}, unchanged{others}")
+            }
+            Ok(name) => format!("Rename to: {name}{others}"),
+            Err(e) => format!("Name: kept, {e}{others}"),
+        }
+    }
+}
+
+/// Overlay for saving the player state as a snapshot or restoring one.
+#[derive(Debug)]
+enum SnapshotDialog {
+    Save { name: String },
+    Restore { state: ListState },
+}
+
+/// Name entry for creating a playlist, renaming the selected one
+/// or saving the mix of the picked ones.
+#[derive(Debug)]
+enum PlaylistDialog {
+    Create { name: String },
+    Rename { name: String },
+    Mix { name: String },
+}
+
+/// Confirmation for deleting the marked files.
+#[derive(Debug)]
+struct DeleteDialog {
+    files: Vec<PathBuf>,
+}
+
+/// Confirmation for rating every track that matches the search.
+#[derive(Debug)]
+struct RatingDialog {
+    files: Vec<PathBuf>,
+    /// Rating to apply, 0 removes the ratings.
+    rating: u8,
+}
+
+/// Input for the main loop.
+#[derive(Debug)]
+enum Message {
+    Input(Event),
+    Playback(PlaybackEvent),
+    Signal(i32),
+    /// The default output device changed.
+    Sink(String),
+    /// Suspend, wakeup or headphone change for --auto-pause.
+    Interruption(Interruption),
+    #[cfg(feature = "media-keys")]
+    MediaKey(MediaKey),
+    /// State of the listen-along host being followed.
+    #[cfg(feature = "listen-along")]
+    ListenAlong(SyncState),
+    /// Request from the web remote and where to send the response.
+    #[cfg(feature = "web")]
+    Web(web::Request, mpsc::Sender<web::Response>),
+    /// Event of the player of a zone, by its index in `Instance::zones`.
+    ZonePlayback(usize, PlaybackEvent),
+    /// Files were added to, removed from or renamed in the directory.
+    DirChanged,
+    /// Command typed at the --no-tui prompt.
+    Line(String),
+    /// Periodic redraw to keep the progress up to date.
+    Tick,
+}
+
+/// List shown in the listing pane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum View {
+    /// The entire directory.
+    #[default]
+    All,
+    Smart(usize),
+    MostPlayed,
+    RecentlyAdded,
+    NeverPlayed,
+    /// A saved playlist or M3U file.
+    Playlist(usize),
+}
+
+/// Remembered cursor position of a list.
+#[derive(Clone, Debug, Default)]
+struct Selection {
+    state: ListState,
+    file: Option<PathBuf>,
 }
 
 struct Instance {
     args: Args,
     cursor_state: CursorState,
-    autoplay_state: AutoplayState,
-    play: Play,
-    files: Vec<PathBuf>,
+    player: Player<Box<dyn PlaybackBackend>>,
+    /// Files of the directory with their sizes, sources and tags, read by the background scan.
+    library: Library,
+    /// List of the current view, which autoplay goes through, and the autoplay modes.
+    queue: Queue,
+    view: View,
     list_state: ListState,
+    grouping: Option<Grouping>,
+    /// Titles of the groups whose files are hidden.
+    collapsed: HashSet<String>,
+    /// Visible rows of the listing and what takes a pass over all files to draw it.
+    listing: Listing,
+    selections: HashMap<String, Selection>,
     search: String,
-    volume_once: Once,
+    /// Whether the search is a regular expression over file names and tags.
+    search_regex: bool,
+    track_uri: Option<String>,
+    history: History,
+    history_state: ListState,
+    chapter_state: ListState,
+    playlists: Vec<Playlist>,
+    playlist_state: ListState,
+    playlist_dialog: Option<PlaylistDialog>,
+    /// Playlists picked for mixing and how many entries to take from each per round.
+    mix: Vec<(PathBuf, usize)>,
+    schedule: Schedule,
+    timer_state: ListState,
+    /// Track and time of day being entered to schedule it.
+    timer_prompt: Option<(PathBuf, String)>,
+    /// Whether autoplay stops while timers are pending, so the next one starts after silence.
+    hold_for_timers: bool,
+    /// Whether playback stops when the current track ends instead of continuing.
+    stop_after_current: bool,
+    /// Step of the sleep timer in `SLEEP_STEPS` and when it stops playback.
+    sleep: Option<(usize, Instant)>,
+    /// Volume before fading to silence, restored once playback stopped.
+    silence_volume: Option<f64>,
+    /// Volume before muting with m.
+    muted: Option<f64>,
+    /// Whether moving through the listing previews the selected file.
+    preview_mode: bool,
+    /// File that was selected when previews were last checked for.
+    preview_selected: Option<PathBuf>,
+    /// Whether playback was paused by --auto-pause and may be resumed.
+    auto_paused: bool,
+    stats: Stats,
+    listened: f64,
+    counted: bool,
+    /// Tracks that were left before they counted as played and the positions
+    /// they were left at, most recent last.
+    skipped: Vec<(String, Duration)>,
+    /// Whether the current track is left to return to a skipped one.
+    undoing_skip: bool,
+    last_tick: Instant,
+    /// Only list files from this source.
+    source_filter: Option<Source>,
+    /// URL being entered to play it.
+    url_prompt: Option<String>,
+    /// Page URL that is being resolved into a stream in the background.
+    resolving: Option<Receiver<anyhow::Result<resolve::Stream>>>,
+    play_state: PlayState,
+    buffering: Option<i32>,
+    notice: Option<Notice>,
+    credential_prompt: Option<CredentialPrompt>,
+    help: Option<Help>,
+    /// Whether the statistics of the current track are shown.
+    track_stats: bool,
+    /// Position of the current track at the last update, where it was left if it's skipped.
+    track_position: Option<Duration>,
+    watchdog_position: Option<Duration>,
+    watchdog_since: Instant,
+    /// Number of stalls since the position last advanced.
+    watchdog_retries: u32,
+    /// Whether the EQ and fades are bypassed for the current track.
+    passthrough: bool,
+    /// Keep the bypass for the following tracks.
+    passthrough_pinned: bool,
+    snapshots: Snapshots,
+    snapshot_dialog: Option<SnapshotDialog>,
+    ratings: Ratings,
+    /// URIs of the files that failed verification.
+    damaged: HashSet<String>,
+    /// URIs of the tracks that failed to play in this session.
+    failed: HashSet<String>,
+    /// Number of tracks that failed to play in a row, to stop skipping at some point.
+    failed_in_row: usize,
+    /// Results of the background verification as they come in, and its progress.
+    verification: Option<(Receiver<(String, bool)>, Progress)>,
+    /// When the progress of the running tasks was last sent to the extensions.
+    tasks_reported: Instant,
+    rating_dialog: Option<RatingDialog>,
+    delete_dialog: Option<DeleteDialog>,
+    /// Files of the listing marked for bulk actions.
+    marked: HashSet<PathBuf>,
+    /// Inode and modification time of the current track's file,
+    /// to notice it being replaced during playback.
+    track_file: Option<(u64, SystemTime)>,
+    /// Current track whose file changed, offered to be reloaded.
+    reload_prompt: Option<String>,
+    /// Previous ratings of the tracks rated last, to undo it.
+    rating_undo: Vec<(String, Option<u8>)>,
+    /// Time or percentage typed to seek to.
+    seek_prompt: Option<String>,
+    tag_editor: Option<TagEditor>,
+    /// Saved tag edits, to undo and redo them or revert files to their original tags.
+    tag_journal: TagJournal,
+    #[cfg(feature = "cover-art")]
+    cover_art: Option<Arc<CoverArt>>,
+    #[cfg(feature = "listen-along")]
+    listen_along_host: Option<listen_along::Host>,
+    /// Host URI that couldn't be found in the library, to only warn once.
+    #[cfg(feature = "listen-along")]
+    listen_along_missing: Option<String>,
+    zones: Vec<Zone>,
+    /// Zone the playback keys control, 0 for the main one and otherwise `zones[zone - 1]`.
+    zone: usize,
+    /// Durations of the startup phases, printed on exit with --profile-startup.
+    startup: Profile,
+    /// Glyphs the terminal seems to support.
+    detected_glyphs: Glyphs,
+    /// Areas of the panes as last drawn.
+    areas: Areas,
+    /// Track, autoplay modes and number of upcoming tracks as last saved for --restore-queue.
+    saved_queue: Option<(Option<String>, AutoplayState, usize)>,
+    queue_saved: Instant,
 }
 
 impl Instance {
-    fn dir(&self) -> String {
-        self.args.dir.clone().unwrap_or_else(|| String::from("."))
+    fn dir(&self) -> PathBuf {
+        self.args.dir.clone().unwrap_or_else(|| PathBuf::from("."))
     }
 
-    fn is_paused(&self) -> bool {
-        match self.play.position() {
-            Some(position) => match self.play.position() {
-                Some(new_pos) => position.nseconds() == new_pos.nseconds(),
-                None => true,
-            },
-            None => true,
+    /// Play the track that was played before the current one
+    /// without recording it in the history again.
+    /// Returns false if there is no previous track.
+    fn play_previous(&mut self) -> bool {
+        match self.history.previous() {
+            Some(uri) => {
+                self.player.play_uri(&uri);
+                self.track_uri = Some(uri);
+
+                true
+            }
+            None => false,
         }
     }
 
-    fn play_path<T: fmt::Display>(&self, path: T) {
-        let uri = format!("file://{path}");
+    /// Download the album cover of a track in the background unless it has one.
+    #[cfg(feature = "cover-art")]
+    fn fetch_cover(&mut self, uri: &str) {
+        let cover_art = match &self.cover_art {
+            Some(cover_art) => Arc::clone(cover_art),
+            None => return,
+        };
 
-        self.play.set_uri(Some(&uri));
-        self.play.play();
+        // The tags are read in the background if the scan hasn't reached the track yet.
+        let tags = self.library.cached_tags(uri).cloned();
+        let uri = uri.to_string();
 
-        if let Some(init_volume) = self.args.volume {
-            thread::sleep(Duration::from_millis(500));
+        thread::spawn(move || {
+            let tags = tags.unwrap_or_else(|| tags::read(&uri).unwrap_or_default());
+            if tags.embedded_art || cover_art.cached(&tags).is_some() {
+                return;
+            }
 
-            self.volume_once.call_once(|| {
-                self.play.set_volume(init_volume);
-            });
-        }
+            match cover_art.fetch(&tags) {
+                Ok(Some(path)) => tracing::debug!("cover cached as {}", path.display()),
+                Ok(None) => tracing::debug!("no cover for {:?}", tags.album),
+                Err(e) => tracing::warn!("can't fetch cover of {:?}: {e}", tags.album),
+            }
+        });
     }
 
-    /// Get the progress ratio of the current song.
-    /// Returns 0.0 if no song is selected.
-    fn current_progress(&self) -> f64 {
-        if let Some(position) = self.play.position() {
-            if let Some(duration) = self.play.duration() {
-                if duration.seconds() > 0 {
-                    position.seconds() as f64 / duration.seconds() as f64
-                } else {
-                    position.mseconds() as f64 / duration.mseconds() as f64
-                }
-            } else {
-                0.0
+    /// Check whether a track is played as part of an album,
+    /// i.e. a neighbouring track in the list is from the same album.
+    fn in_album(&self, uri: &str) -> bool {
+        let i = match self.queue.index_of(uri) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        let current = self.library.tags(uri);
+        let neighbours: Vec<String> = [i.checked_sub(1), Some(i + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.queue.files().get(i))
+            .map(|file| file_uri(file))
+            .collect();
+
+        neighbours
+            .iter()
+            .any(|neighbour| current.same_album(&self.library.tags(neighbour)))
+    }
+
+    /// Queue the next sequential track for a gapless transition
+    /// if it belongs to the same album as the current one.
+    fn prepare_gapless(&mut self) {
+        let next = match &self.track_uri {
+            Some(uri) if self.args.gapless => {
+                self.queue
+                    .gapless_next(uri, self.stops_after_track(), |uri| self.library.tags(uri))
             }
-        } else {
-            0.0
+            _ => None,
+        };
+
+        self.player.set_gapless_next(next);
+    }
+
+    /// Don't fade out at the end of the track if the next one continues the album.
+    fn prepare_fades(&mut self) {
+        let continues = match self.upcoming_track() {
+            Some(next) => self.continues_album(&next),
+            None => false,
+        };
+
+        self.player.prepare_fades(continues);
+    }
+
+    /// Get the track autoplay will continue with, if it's known in advance.
+    fn upcoming_track(&self) -> Option<PathBuf> {
+        self.queue
+            .upcoming(self.track_uri.as_deref(), self.stops_after_track())
+    }
+
+    /// Check whether a track continues the album of the current track.
+    /// Tags are only read if the fades between tracks of an album are left out.
+    fn continues_album(&self, next: &Path) -> bool {
+        if !self.player.joins_albums() {
+            return false;
+        }
+
+        let uri = match &self.track_uri {
+            Some(uri) => uri,
+            None => return false,
+        };
+
+        self.library
+            .tags(uri)
+            .same_album(&self.library.tags(&file_uri(next)))
+    }
+
+    /// Get the name of the current list.
+    /// The full directory listing is unnamed.
+    fn playlist_name(&self) -> &str {
+        match self.view {
+            View::All => "",
+            View::Smart(i) => &self.args.smart_playlists[i].name,
+            View::MostPlayed => "Most played",
+            View::RecentlyAdded => "Recently added",
+            View::NeverPlayed => "Never played",
+            View::Playlist(i) => &self.playlists[i].name,
         }
     }
 
-    fn new() -> anyhow::Result<Self> {
-        let mut instance = Self {
-            args: Args::parse(),
-            cursor_state: CursorState::default(),
-            autoplay_state: AutoplayState::default(),
-            play: Play::new(PlayVideoRenderer::NONE),
-            files: Vec::new(),
-            list_state: ListState::default(),
-            search: String::new(),
-            volume_once: Once::new(),
+    /// Remember the cursor position of the current list.
+    fn store_selection(&mut self) {
+        let selection = Selection {
+            state: self.list_state.clone(),
+            file: self
+                .list_state
+                .selected()
+                .and_then(|i| self.queue.files().get(i))
+                .cloned(),
         };
 
-        if !instance.args.no_listing {
-            instance.files = fs::read_dir(instance.dir())?
-                .map(|e| e.unwrap().path())
-                .collect();
-            instance.files.sort();
+        self.selections
+            .insert(self.playlist_name().to_string(), selection);
+    }
+
+    /// Restore the remembered cursor position of the current list.
+    /// If the remembered file moved, it is selected at its new index.
+    fn restore_selection(&mut self) {
+        let selection = self
+            .selections
+            .get(self.playlist_name())
+            .cloned()
+            .unwrap_or_default();
+
+        self.list_state = selection.state;
+
+        let selected = self
+            .list_state
+            .selected()
+            .and_then(|i| self.queue.files().get(i));
+        // Saved selections are absolute while the list may hold relative paths,
+        // so both are compared as URIs.
+        if let Some(uri) = selection.file.as_deref().map(file_uri) {
+            if selected.map(|file| file_uri(file)).as_ref() != Some(&uri) {
+                self.list_state.select(self.queue.index_of(&uri));
+            }
         }
 
-        instance.list_state.select(Some(0));
+        match self.list_state.selected() {
+            Some(i) if i < self.queue.files().len() => {}
+            _ if self.queue.files().is_empty() => self.list_state.select(None),
+            _ => self.list_state.select(Some(0)),
+        }
+    }
 
-        instance.autoplay_state.repeat_list = instance.args.repeat_list;
-        instance.autoplay_state.repeat = instance.args.repeat;
-        instance.autoplay_state.sequential = instance.args.sequential;
-        instance.autoplay_state.shuffle = instance.args.shuffle;
+    /// Load the selections of the previous session for the current directory.
+    fn load_selections(&mut self) {
+        let dir = selection_key(&self.dir());
 
-        Ok(instance)
+        for row in state::read("selections") {
+            if let [row_dir, name, file] = row.as_slice() {
+                if *row_dir == dir {
+                    let selection = Selection {
+                        state: ListState::default(),
+                        file: Some(uri_to_path(file).unwrap_or_else(|| PathBuf::from(file))),
+                    };
+
+                    self.selections.insert(name.clone(), selection);
+                }
+            }
+        }
     }
 
-    fn run(&mut self) -> anyhow::Result<()> {
-        enable_raw_mode()?;
+    /// Save the selections of all lists of the current directory,
+    /// keeping those of other directories.
+    fn save_selections(&mut self) -> anyhow::Result<()> {
+        self.store_selection();
 
-        self.play = Play::new(PlayVideoRenderer::NONE);
-        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let dir = selection_key(&self.dir());
+        let mut rows: Vec<Vec<String>> = state::read("selections")
+            .into_iter()
+            .filter(|row| row.first() != Some(&dir))
+            .collect();
 
-        if let Some(initial) = &self.args.play {
-            self.play_path(initial);
-        } else if self.args.random {
-            let track = rand::random::<usize>() % self.files.len();
-            self.play_path(self.files[track].display());
+        for (name, selection) in &self.selections {
+            if let Some(file) = &selection.file {
+                rows.push(vec![dir.clone(), name.clone(), file_uri(file)]);
+            }
         }
 
-        let usr1 = Arc::new(AtomicBool::new(false));
-        let hup = Arc::new(AtomicBool::new(false));
+        state::write("selections", &rows)
+    }
 
-        signal_hook::flag::register(SIGUSR1, Arc::clone(&usr1))?;
-        signal_hook::flag::register(SIGHUP, Arc::clone(&hup))?;
+    /// Switch the listing to the next smart playlist or statistics view,
+    /// wrapping around to the full directory listing.
+    fn next_playlist(&mut self) {
+        self.store_selection();
 
-        loop {
-            terminal.draw(|f| {
-                let main_style = Style::default().bg(Color::Reset).fg(Color::Magenta);
-                let focused_style = main_style.fg(Color::Cyan);
+        let n = self.args.smart_playlists.len();
+        self.view = match self.view {
+            View::All if n > 0 => View::Smart(0),
+            View::Smart(i) if i + 1 < n => View::Smart(i + 1),
+            View::All | View::Smart(_) => View::MostPlayed,
+            View::MostPlayed => View::RecentlyAdded,
+            View::RecentlyAdded => View::NeverPlayed,
+            View::NeverPlayed if !self.playlists.is_empty() => View::Playlist(0),
+            View::Playlist(i) if i + 1 < self.playlists.len() => View::Playlist(i + 1),
+            View::NeverPlayed | View::Playlist(_) => View::All,
+        };
 
-                let sizes = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([Constraint::Length(f.size().width / 2), Constraint::Min(0)])
-                    .split(f.size());
+        self.refresh_files();
+        self.restore_selection();
+        self.queue.shuffle.clear();
+    }
 
-                let listing_size = sizes[0];
-                let status_size = sizes[1];
+    /// Show a playlist in the listing.
+    fn open_playlist(&mut self, i: usize) {
+        self.store_selection();
+        self.view = View::Playlist(i);
+        self.refresh_files();
+        self.restore_selection();
+        self.queue.shuffle.clear();
+    }
 
-                let files: Vec<ListItem> = self.files
-                    .iter()
-                    .map(|e| ListItem::new(e.file_name().unwrap().to_str().unwrap()))
-                    .collect();
+    /// Write a changed playlist and update the listing if it is shown.
+    fn save_playlist(&mut self, i: usize) {
+        if let Err(e) = self.playlists[i].save() {
+            self.show(Level::Warning, format!("can't save playlist: {e}"));
+        }
 
-                let highlight_base_style = match self.cursor_state {
-                    CursorState::MusicList => focused_style,
-                    _ => main_style,
-                };
+        if self.view == View::Playlist(i) {
+            self.refresh_files();
+        }
+    }
 
-                let block = Block::default().title("Select music").borders(Borders::ALL);
-                let listing = List::new(files)
-                    .block(block)
-                    .style(match self.cursor_state {
-                        CursorState::MusicList => focused_style,
-                        _ => main_style,
-                    })
-                    .highlight_style(
-                        highlight_base_style
-                            .bg(highlight_base_style.fg.unwrap())
-                            .fg(Color::Black),
-                    )
-                    .highlight_symbol("> ");
+    /// Append the marked files of the listing, or the selected one if none are marked,
+    /// to the playlist selected in the playlists pane.
+    fn add_to_playlist(&mut self) {
+        let files = self.marked_or_selected();
+        if files.is_empty() {
+            return;
+        }
 
-                let status_title = match self.play.uri() {
-                    Some(uri) => {
-                        String::from("Now playing: ") + uri.as_str().split('/').next_back().unwrap()
-                    }
-                    None => String::from("Idle"),
-                };
+        let i = match self.playlist_state.selected() {
+            Some(i) if i < self.playlists.len() => i,
+            _ => {
+                self.show(Level::Info, "Select a playlist in the playlists pane first");
+                return;
+            }
+        };
 
-                let status_block = Block::default()
-                    .title(status_title)
-                    .borders(Borders::ALL)
-                    .style(main_style);
-                let status_sizes = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Length(status_size.height / 8),
-                        Constraint::Min(0),
-                    ])
-                    .margin(1)
-                    .split(status_size)[0];
-
-                let volume_size = subsize(status_sizes, 0);
-                let progress_size = subsize(status_sizes, 1);
-                let control_size = subsize(status_sizes, 2);
-                let search_size = subsize(status_sizes, 3);
-
-                let block = Block::default().title("Volume").borders(Borders::ALL);
-                let volume_gauge = Gauge::default()
-                    .block(block)
-                    .style(match self.cursor_state {
-                        CursorState::Volume => focused_style,
-                        _ => main_style,
-                    })
-                    .gauge_style(main_style.fg(Color::Blue))
-                    .ratio(self.play.volume());
-
-                let progress_label = match self.play.position() {
-                    Some(position) => match self.play.duration() {
-                        Some(duration) => {
-                            let pos_m = position.minutes();
-                            let pos_s = position.seconds() - pos_m * 60;
-                            let total_m = duration.minutes();
-                            let total_s = duration.seconds() - total_m * 60;
-
-                            format!("{pos_m}:{pos_s:0>2} / {total_m}:{total_s:0>2}")
-                        }
-                        None => String::from("-:-- / -:--"),
-                    }
-                    None => String::from("-:-- / -:--"),
-                };
+        let added = match files.as_slice() {
+            [file] => display_name(file),
+            files => format!("{} tracks", files.len()),
+        };
+        self.show(
+            Level::Info,
+            format!("Added {added} to {}", self.playlists[i].name),
+        );
+        self.playlists[i].entries.extend(files);
+        self.marked.clear();
+        self.save_playlist(i);
+    }
 
-                let block = Block::default().borders(Borders::ALL);
-                let progress_gauge = Gauge::default()
-                    .block(block)
-                    .style(main_style)
-                    .label(progress_label)
-                    .gauge_style(main_style.fg(Color::Blue))
-                    .ratio(self.current_progress());
-
-                let control_buttons = if self.is_paused() {
-                    String::from(
-                        "[ 🔁 ]   [ 🔂 ]   [ ⏮ ]   [ ◀ ]   [ ▶ ]   [ ▶ ]   [ ⏭ ]   [ ⏬ ]   [ 🔀 ]\n\n",
-                    )
-                } else {
-                    String::from(
-                        "[ 🔁 ]   [ 🔂 ]   [ ⏮ ]   [ ◀ ]   [ ⏸ ]   [ ▶ ]   [ ⏭ ]   [ ⏬ ]   [ 🔀 ]\n\n",
-                    )
-                };
+    /// Mark or unmark the selected file for bulk actions and move on to the next one.
+    fn toggle_mark(&mut self) {
+        let file = match self.selected_file() {
+            Some(file) => file,
+            None => return,
+        };
+
+        if !self.marked.remove(&file) {
+            self.marked.insert(file);
+        }
+        self.move_selection(1);
+    }
+
+    /// Get the marked files in list order, or the selected one if none are marked.
+    fn marked_or_selected(&self) -> Vec<PathBuf> {
+        let marked: Vec<PathBuf> = self
+            .queue
+            .files()
+            .iter()
+            .filter(|file| self.marked.contains(*file))
+            .cloned()
+            .collect();
+
+        if marked.is_empty() {
+            self.selected_file().into_iter().collect()
+        } else {
+            marked
+        }
+    }
+
+    /// Queue the marked files, or the selected one, to play after the current track.
+    fn enqueue(&mut self) {
+        let files = self.marked_or_selected();
+        let queued = match files.as_slice() {
+            [] => return,
+            [file] => display_name(file),
+            files => format!("{} tracks", files.len()),
+        };
+
+        self.queue.up_next.extend(files);
+        self.marked.clear();
+        self.show(Level::Info, format!("Queued {queued}"));
+    }
+
+    /// Remove the marked entries from the shown playlist,
+    /// or ask whether to delete the marked files outside of playlists.
+    /// Without marks only the selected playlist entry is removed.
+    fn delete_marked(&mut self) {
+        if self.marked.is_empty() {
+            self.remove_playlist_entry();
+            return;
+        }
 
-                let mut control_indicators = String::new();
+        let files = self.marked_or_selected();
+        match self.view {
+            View::Playlist(i) => {
+                self.playlists[i]
+                    .entries
+                    .retain(|entry| !self.marked.contains(entry));
+                self.marked.clear();
+                self.save_playlist(i);
 
-                if self.autoplay_state.repeat_list {
-                    control_indicators += " 🔁 ";
+                match self.queue.files().len() {
+                    0 => self.list_state.select(None),
+                    n => self
+                        .list_state
+                        .select(self.list_state.selected().map(|i| i.min(n - 1))),
                 }
-                if self.autoplay_state.repeat {
-                    control_indicators += " 🔂 ";
+            }
+            _ if files.is_empty() => {}
+            _ => self.delete_dialog = Some(DeleteDialog { files }),
+        }
+    }
+
+    /// Handle a key press while the delete confirmation is open.
+    fn delete_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.delete_dialog = None,
+            KeyCode::Enter => {
+                if let Some(dialog) = self.delete_dialog.take() {
+                    self.delete_files(&dialog.files);
                 }
-                if self.autoplay_state.sequential {
-                    control_indicators += " ⏬ ";
+            }
+            _ => {}
+        }
+    }
+
+    /// Delete files from the disk and the listing.
+    /// The current track is kept, it's still being read.
+    fn delete_files(&mut self, files: &[PathBuf]) {
+        let current = self.player.current_uri();
+        let (playing, files): (Vec<&PathBuf>, Vec<&PathBuf>) = files
+            .iter()
+            .partition(|file| current.as_deref() == Some(file_uri(file).as_str()));
+
+        let mut deleted = 0;
+        for file in files {
+            match fs::remove_file(file) {
+                Ok(()) => {
+                    tracing::info!("deleted {}", file.display());
+                    self.marked.remove(file);
+                    self.queue.up_next.retain(|queued| queued != file);
+                    deleted += 1;
                 }
-                if self.autoplay_state.shuffle {
-                    control_indicators += " 🔀 ";
+                Err(e) => {
+                    let text = format!("can't delete {}: {e}", display_name(file));
+                    self.show(Level::Warning, text);
+                    self.reload_library();
+                    return;
                 }
+            }
+        }
 
-                let block = Block::default().borders(Borders::ALL);
-                let control_paragraph = Paragraph::new(control_buttons + &control_indicators)
-                    .block(block)
-                    .alignment(Alignment::Center)
-                    .style(match self.cursor_state {
-                        CursorState::Control => focused_style,
-                        _ => main_style,
-                    });
+        self.reload_library();
+        match playing.first() {
+            Some(file) => self.show(
+                Level::Info,
+                format!(
+                    "Deleted {deleted} files, kept {} because it's playing",
+                    display_name(file)
+                ),
+            ),
+            None => self.show(Level::Info, format!("Deleted {deleted} files")),
+        }
+    }
 
-                let block = Block::default().borders(Borders::ALL);
-                let search_paragraph = Paragraph::new(format!("Search: {}", self.search))
-                    .block(block)
-                    .alignment(Alignment::Left)
-                    .style(match self.cursor_state {
-                        CursorState::Search => focused_style,
-                        _ => main_style,
-                    });
+    /// Move the selected entry of the shown playlist up or down.
+    fn move_playlist_entry(&mut self, up: bool) {
+        let (i, from) = match (self.view, self.list_state.selected()) {
+            (View::Playlist(i), Some(from)) if self.source_filter.is_none() => (i, from),
+            (View::Playlist(_), Some(_)) => {
+                self.show(
+                    Level::Info,
+                    "Clear the source filter to reorder the playlist",
+                );
+                return;
+            }
+            _ => return,
+        };
 
-                f.render_stateful_widget(listing, listing_size, &mut self.list_state);
-                f.render_widget(status_block, status_size);
-                f.render_widget(volume_gauge, volume_size);
-                f.render_widget(progress_gauge, progress_size);
-                f.render_widget(control_paragraph, control_size);
-                f.render_widget(search_paragraph, search_size);
-            })?;
+        let to = if up {
+            match from.checked_sub(1) {
+                Some(to) => to,
+                None => return,
+            }
+        } else if from + 1 < self.playlists[i].entries.len() {
+            from + 1
+        } else {
+            return;
+        };
 
-            if self.current_progress() == 1.0 {
-                if self.autoplay_state.repeat {
-                    self.play.play();
-                } else if self.autoplay_state.sequential {
-                    let mut track = self
-                        .files
-                        .iter()
-                        .enumerate()
-                        .find(|(_, file)| {
-                            format!("file://{}", file.display()) == self.play.uri().unwrap()
-                        })
-                        .unwrap()
-                        .0
-                        + 1;
+        self.playlists[i].move_entry(from, to);
+        self.save_playlist(i);
+        self.list_state.select(Some(to));
+    }
 
-                    if track >= self.files.len() && self.autoplay_state.repeat_list {
-                        track = 0
-                    }
+    /// Remove the selected entry from the shown playlist.
+    fn remove_playlist_entry(&mut self) {
+        let (i, entry) = match (self.view, self.list_state.selected()) {
+            (View::Playlist(i), Some(entry)) if self.source_filter.is_none() => (i, entry),
+            _ => return,
+        };
 
-                    if track < self.files.len() {
-                        self.play_path(self.files[track].display());
-                    }
-                } else if self.autoplay_state.shuffle {
-                    let track = rand::random::<usize>() % self.files.len();
-                    self.play_path(self.files[track].display());
-                } else if self.args.no_remain {
-                    break;
-                } else {
-                    self.play.stop();
-                }
+        if entry < self.playlists[i].entries.len() {
+            self.playlists[i].entries.remove(entry);
+            self.save_playlist(i);
+
+            match self.queue.files().len() {
+                0 => self.list_state.select(None),
+                n => self.list_state.select(Some(entry.min(n - 1))),
             }
+        }
+    }
+
+    /// Delete the playlist selected in the playlists pane and its file.
+    fn delete_playlist(&mut self) {
+        let i = match self.playlist_state.selected() {
+            Some(i) if i < self.playlists.len() => i,
+            _ => return,
+        };
+
+        if let Err(e) = self.playlists[i].delete() {
+            self.show(Level::Warning, format!("can't delete playlist: {e}"));
+            return;
+        }
 
-            // Lower priority than pausing.
-            if usr1.load(Ordering::Relaxed) {
-                // SIGUSR1: play
-                self.play.play();
+        let playlist = self.playlists.remove(i);
+        self.show(Level::Info, format!("Deleted playlist {}", playlist.name));
+        self.playlist_state
+            .select(self.playlists.len().checked_sub(1).map(|n| i.min(n)));
 
-                usr1.store(false, Ordering::Relaxed);
+        match self.view {
+            View::Playlist(shown) if shown == i => {
+                self.view = View::All;
+                self.refresh_files();
+                self.restore_selection();
+                self.queue.shuffle.clear();
             }
+            View::Playlist(shown) if shown > i => self.view = View::Playlist(shown - 1),
+            _ => {}
+        }
+    }
 
-            if hup.load(Ordering::Relaxed) {
-                // SIGHUP: pause
-                self.play.pause();
+    /// Handle a key press while the playlist name entry is open.
+    fn playlist_dialog_key(&mut self, code: KeyCode) {
+        let name = match &mut self.playlist_dialog {
+            Some(
+                PlaylistDialog::Create { name }
+                | PlaylistDialog::Rename { name }
+                | PlaylistDialog::Mix { name },
+            ) => name,
+            None => return,
+        };
 
-                hup.store(false, Ordering::Relaxed);
+        match code {
+            KeyCode::Esc => self.playlist_dialog = None,
+            KeyCode::Char(c) => name.push(c),
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Enter if !name.trim().is_empty() => {
+                let name = name.trim().to_string();
+                match self.playlist_dialog.take() {
+                    Some(PlaylistDialog::Create { .. }) => {
+                        self.create_playlist(&name);
+                    }
+                    Some(PlaylistDialog::Rename { .. }) => self.rename_playlist(&name),
+                    Some(PlaylistDialog::Mix { .. }) => self.mix_playlists(&name),
+                    None => {}
+                }
             }
+            _ => {}
+        }
+    }
 
-            if !event::poll(Duration::from_secs(1))? {
-                continue;
+    /// Create an empty playlist and select it in the playlists pane.
+    /// Returns its index, or `None` if it can't be created.
+    fn create_playlist(&mut self, name: &str) -> Option<usize> {
+        let playlist = match playlist::create(name) {
+            Ok(playlist) => playlist,
+            Err(e) => {
+                self.show(Level::Warning, format!("can't create playlist: {e}"));
+                return None;
             }
+        };
 
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Esc => {
-                        break;
-                    }
-                    KeyCode::Char('q') if self.cursor_state != CursorState::Search => {
-                        break;
-                    }
-                    KeyCode::Tab => {
-                        self.cursor_state.overflowing_next();
-                    }
-                    KeyCode::Char(' ') if self.cursor_state != CursorState::Search => {
-                        if self.is_paused() {
-                            self.play.play();
-                        } else {
-                            self.play.pause();
-                        }
-                    }
-                    _ => match self.cursor_state {
-                        CursorState::MusicList => match key.code {
-                            KeyCode::Down => match self.list_state.selected() {
-                                Some(i) => {
-                                    if self.files.len() > 1 {
-                                        self.list_state.select(Some((i + 1) % self.files.len()));
-                                    }
-                                }
-                                None => self.list_state.select(Some(0)),
-                            },
-                            KeyCode::Up => match self.list_state.selected() {
-                                Some(i) => {
-                                    let n = self.files.len();
-                                    if n > 1 {
-                                        self.list_state.select(Some((i + n - 1) % n))
-                                    }
-                                }
-                                None => self.list_state.select(Some(self.files.len() - 1)),
-                            },
-                            KeyCode::Left => match self.list_state.selected() {
-                                Some(i) => {
-                                    let n = self.files.len();
-                                    if n > 5 {
-                                        self.list_state.select(Some((i + n - 5) % n))
-                                    }
-                                }
-                                None => self.list_state.select(Some(self.files.len() - 1)),
-                            },
-                            KeyCode::Right => match self.list_state.selected() {
-                                Some(i) => {
-                                    if self.files.len() > 5 {
-                                        self.list_state.select(Some((i + 5) % self.files.len()));
-                                    }
-                                }
-                                None => self.list_state.select(Some(0)),
-                            },
-                            KeyCode::Home => self.list_state.select(Some(0)),
-                            KeyCode::End => self.list_state.select(Some(self.files.len() - 1)),
-                            KeyCode::Char('r') => {
-                                let track = rand::random::<usize>() % self.files.len();
-                                self.list_state.select(Some(track));
-                            }
-                            KeyCode::Char('R') => {
-                                let track = rand::random::<usize>() % self.files.len();
-                                self.list_state.select(Some(track));
+        // Keep the shown playlist when the indices shift.
+        let shown = match self.view {
+            View::Playlist(i) => Some(self.playlists[i].path.clone()),
+            _ => None,
+        };
 
-                                self.play_path(self.files[track].display());
-                            }
-                            KeyCode::Enter => {
-                                let track = match self.list_state.selected() {
-                                    Some(i) => i,
-                                    None => {
-                                        continue;
-                                    }
-                                };
-
-                                self.play_path(self.files[track].display());
-                            }
-                            _ => {}
-                        },
-                        CursorState::Volume => match key.code {
-                            KeyCode::Left => {
-                                self.play.set_volume(0.0_f64.max(self.play.volume() - 0.01))
-                            }
-                            KeyCode::Right => {
-                                self.play.set_volume(1.0_f64.min(self.play.volume() + 0.01))
-                            }
-                            KeyCode::Home => self.play.set_volume(0.0),
-                            KeyCode::End => self.play.set_volume(1.0),
-                            KeyCode::Down => {
-                                self.play.set_volume(0.0_f64.max(self.play.volume() - 0.05))
-                            }
-                            KeyCode::Up => {
-                                self.play.set_volume(1.0_f64.min(self.play.volume() + 0.05))
-                            }
-                            _ => {}
-                        },
-                        CursorState::Control => match key.code {
-                            KeyCode::Left => {
-                                if let Some(position) = self.play.position() {
-                                    self.play.seek(ClockTime::from_seconds(
-                                        0_u64.max(position.seconds().saturating_sub(1)),
-                                    ));
-                                }
-                            }
-                            KeyCode::Right => {
-                                if let Some(position) = self.play.position() {
-                                    if let Some(duration) = self.play.duration() {
-                                        self.play.seek(ClockTime::from_seconds(
-                                            duration
-                                                .seconds()
-                                                .min(position.seconds().saturating_add(1)),
-                                        ));
-                                    }
-                                }
-                            }
-                            KeyCode::Down => {
-                                if let Some(position) = self.play.position() {
-                                    self.play.seek(ClockTime::from_seconds(
-                                        0_u64.max(position.seconds().saturating_sub(15)),
-                                    ));
-                                }
-                            }
-                            KeyCode::Up => {
-                                if let Some(position) = self.play.position() {
-                                    if let Some(duration) = self.play.duration() {
-                                        self.play.seek(ClockTime::from_seconds(
-                                            duration
-                                                .seconds()
-                                                .min(position.seconds().saturating_add(15)),
-                                        ));
-                                    }
-                                }
-                            }
-                            KeyCode::Home => {
-                                self.play.seek(ClockTime::ZERO);
-                            }
-                            KeyCode::End => {
-                                if let Some(duration) = self.play.duration() {
-                                    self.play.seek(duration);
-                                }
-                            }
-                            KeyCode::Char('r') => {
-                                self.autoplay_state.repeat = !self.autoplay_state.repeat;
-                            }
-                            KeyCode::Char('s') => {
-                                self.autoplay_state.shuffle = !self.autoplay_state.shuffle;
-                            }
-                            KeyCode::Char('l') => {
-                                self.autoplay_state.sequential = !self.autoplay_state.sequential;
-                            }
-                            KeyCode::Char('i') => {
-                                self.autoplay_state.repeat_list = !self.autoplay_state.repeat_list;
-                            }
-                            _ => {}
-                        },
-                        CursorState::Search => match key.code {
-                            KeyCode::Char(c) => self.search.push(c),
-                            KeyCode::Backspace => {
-                                self.search.pop();
-                            }
-                            KeyCode::Delete => self.search.clear(),
-                            KeyCode::Enter => {
-                                if let Some(selected) = self.list_state.selected() {
-                                    if let Some(fmatch) =
-                                        std::iter::repeat(self.files.iter().enumerate())
-                                            .take(2)
-                                            .flatten()
-                                            .skip(selected + 1)
-                                            .find(|(_, file)| {
-                                                file.to_str()
-                                                    .unwrap()
-                                                    .to_lowercase()
-                                                    .contains(&self.search.to_lowercase())
-                                            })
-                                    {
-                                        self.list_state.select(Some(fmatch.0));
-                                    }
-                                }
-                            }
-                            _ => {}
-                        },
-                    },
-                }
+        self.playlists.push(playlist);
+        self.sort_playlists(shown);
+
+        let i = self.playlists.iter().position(|p| p.name == name);
+        self.playlist_state.select(i);
+        i
+    }
+
+    /// Pick the playlist selected in the playlists pane for mixing,
+    /// taking this many entries from it per round, or drop it from the mix.
+    fn pick_for_mix(&mut self, weight: Option<usize>) {
+        let path = match self
+            .playlist_state
+            .selected()
+            .and_then(|i| self.playlists.get(i))
+        {
+            Some(playlist) => playlist.path.clone(),
+            None => return,
+        };
+
+        let picked = self.mix.iter().position(|(other, _)| *other == path);
+        match (picked, weight) {
+            (Some(i), Some(weight)) => self.mix[i].1 = weight,
+            (Some(i), None) => {
+                self.mix.remove(i);
             }
+            (None, weight) => self.mix.push((path, weight.unwrap_or(1))),
         }
+    }
 
-        disable_raw_mode()?;
-        terminal.clear()?;
-        terminal.set_cursor(0, 0)?;
+    /// Save the interleaved entries of the picked playlists as a new playlist.
+    fn mix_playlists(&mut self, name: &str) {
+        let entries = {
+            let lists: Vec<(&[PathBuf], usize)> = self
+                .mix
+                .iter()
+                .filter_map(|(path, weight)| {
+                    let playlist = self.playlists.iter().find(|p| p.path == *path)?;
+                    Some((playlist.entries.as_slice(), *weight))
+                })
+                .collect();
 
-        Ok(())
+            playlist::interleave(&lists)
+        };
+
+        let i = match self.create_playlist(name) {
+            Some(i) => i,
+            None => return,
+        };
+
+        self.show(
+            Level::Info,
+            format!("Mixed {} tracks into {name}", entries.len()),
+        );
+        self.playlists[i].entries = entries;
+        self.save_playlist(i);
+        self.mix.clear();
     }
-}
 
-fn subsize(area: Rect, i: u16) -> Rect {
-    let mut new_area = area;
-    new_area.y += i * area.height;
+    /// Rename the playlist selected in the playlists pane.
+    fn rename_playlist(&mut self, name: &str) {
+        let i = match self.playlist_state.selected() {
+            Some(i) if i < self.playlists.len() => i,
+            _ => return,
+        };
 
-    new_area
-}
+        let old_name = self.playlists[i].name.clone();
+        let old_path = self.playlists[i].path.clone();
+        if let Err(e) = self.playlists[i].rename(name) {
+            self.show(Level::Warning, format!("can't rename playlist: {e}"));
+            return;
+        }
 
-fn main() -> anyhow::Result<()> {
-    gstreamer::init()?;
-    Instance::new()?.run()?;
+        // The remembered cursor position moves along.
+        if let Some(selection) = self.selections.remove(&old_name) {
+            self.selections.insert(name.to_string(), selection);
+        }
+
+        let shown = match self.view {
+            View::Playlist(shown) => Some(self.playlists[shown].path.clone()),
+            _ => None,
+        };
+        let path = self.playlists[i].path.clone();
+
+        for (picked, _) in &mut self.mix {
+            if *picked == old_path {
+                *picked = path.clone();
+            }
+        }
+
+        self.sort_playlists(shown);
+        let i = self.playlists.iter().position(|p| p.path == path);
+        self.playlist_state.select(i);
+    }
+
+    /// Sort the playlists by name, keeping the shown one in the listing.
+    fn sort_playlists(&mut self, shown: Option<PathBuf>) {
+        self.playlists
+            .sort_by_key(|playlist| playlist.name.to_lowercase());
+
+        if let Some(shown) = shown {
+            if let Some(i) = self.playlists.iter().position(|p| p.path == shown) {
+                self.view = View::Playlist(i);
+            }
+        }
+    }
+
+    /// Cycle the source filter through local files, network files and all files.
+    fn next_source_filter(&mut self) {
+        self.source_filter = match self.source_filter {
+            None => Some(Source::Local),
+            Some(Source::Local) => Some(Source::Network),
+            Some(Source::Network) => None,
+        };
+
+        self.refresh_files();
+        self.list_state.select(if self.queue.files().is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+        self.queue.shuffle.clear();
+    }
+
+    /// Take the cached parts of the listing, building them if anything changed since.
+    /// They are put back with `self.listing.set`.
+    fn take_listing(&mut self) -> listing::Cache {
+        self.listing.take().unwrap_or_else(|| {
+            listing::Cache::new(
+                self.queue.files().len(),
+                self.groups(),
+                &self.collapsed,
+                self.list_summary(),
+                self.library.has_mixed_sources(),
+            )
+        })
+    }
+
+    /// Get the text of a file in the listing, with its source if `badges` is set.
+    fn file_label(&self, file: &Path, badges: bool) -> String {
+        let name = match self.library.source(file) {
+            Some(Source::Local) if badges => format!("[local] {}", display_name(file)),
+            Some(Source::Network) if badges => format!("[NAS]   {}", display_name(file)),
+            _ => display_name(file),
+        };
+        let uri = file_uri(file);
+        let name = if self.damaged.contains(&uri) {
+            format!("{name} [damaged]")
+        } else if self.failed.contains(&uri) {
+            format!("{name} [failed]")
+        } else {
+            name
+        };
+        let name = match self.ratings.get(&uri) {
+            Some(rating) => format!("{name} {}", ratings::stars(rating)),
+            None => name,
+        };
+
+        match self.stats.get(&uri) {
+            Some(stats) if self.view == View::MostPlayed => format!(
+                "{name} ({} plays, {} min)",
+                stats.plays,
+                (stats.listened / 60.0).round()
+            ),
+            _ => name,
+        }
+    }
+
+    /// Rebuild the list of the current view, applying the source filter.
+    fn refresh_files(&mut self) {
+        let mut files = match self.view {
+            View::All => self.library.files().to_vec(),
+            View::Smart(i) => {
+                let rule = &self.args.smart_playlists[i].rule;
+                let context = Context {
+                    tags: self.library.tag_cache(),
+                    ratings: &self.ratings,
+                    stats: &self.stats,
+                };
+
+                self.library
+                    .files()
+                    .iter()
+                    .filter(|file| rule.matches(file, &context))
+                    .cloned()
+                    .collect()
+            }
+            View::MostPlayed => {
+                let mut files: Vec<PathBuf> = self
+                    .library
+                    .files()
+                    .iter()
+                    .filter(|file| self.stats.plays(&file_uri(file)) > 0)
+                    .cloned()
+                    .collect();
+
+                files.sort_by_cached_key(|file| Reverse(self.stats.plays(&file_uri(file))));
+                files
+            }
+            View::RecentlyAdded => {
+                let mut files = self.library.files().to_vec();
+                files.sort_by_cached_key(|file| {
+                    Reverse(
+                        fs::metadata(file)
+                            .and_then(|metadata| metadata.created().or(metadata.modified()))
+                            .ok(),
+                    )
+                });
+
+                files
+            }
+            View::Playlist(i) => self.playlists[i].entries.clone(),
+            View::NeverPlayed => self
+                .library
+                .files()
+                .iter()
+                .filter(|file| self.stats.plays(&file_uri(file)) == 0)
+                .cloned()
+                .collect(),
+        };
+
+        if let Some(filter) = self.source_filter {
+            files.retain(|file| self.library.source(file) == Some(filter));
+        }
+
+        self.queue.set_files(files);
+        self.listing.invalidate();
+    }
+
+    /// Get the groups of the list, none unless it is grouped.
+    /// Albums are known once the tags of their files have been read.
+    fn groups(&self) -> Vec<Group> {
+        let grouping = match self.grouping {
+            Some(grouping) => grouping,
+            None => return Vec::new(),
+        };
+        let dir = self.dir();
+
+        grouping::groups(self.queue.files(), |file| match grouping {
+            Grouping::Directory => {
+                let parent = file.parent().unwrap_or(&dir);
+                match parent.strip_prefix(&dir).unwrap_or(parent) {
+                    relative if relative.as_os_str().is_empty() => String::from("."),
+                    relative => relative.to_string_lossy().into_owned(),
+                }
+            }
+            Grouping::Album => self
+                .library
+                .cached_tags(&file_uri(file))
+                .and_then(|tags| tags.album.clone())
+                .unwrap_or_else(|| String::from("Unknown album")),
+            Grouping::Letter => grouping::letter(&display_name(file)),
+        })
+    }
+
+    /// Move the selection by a number of entries, wrapping around.
+    /// Lists that are too short to move that far don't move.
+    /// Collapsed groups count as their first file.
+    fn move_selection(&mut self, delta: isize) {
+        let n = self.queue.files().len();
+        let i = match self.list_state.selected() {
+            Some(i) => i,
+            None if n == 0 => return,
+            None => {
+                self.list_state
+                    .select(Some(if delta > 0 { 0 } else { n - 1 }));
+                return;
+            }
+        };
+
+        if n <= delta.unsigned_abs() {
+            return;
+        }
+
+        let cache = self.take_listing();
+        let hidden =
+            |j: usize| {
+                cache.groups.iter().enumerate().any(|(k, group)| {
+                    group.contains(j) && j != group.start && cache.is_collapsed(k)
+                })
+            };
+
+        let step = if delta > 0 { 1 } else { n - 1 };
+        let mut j = i;
+        for _ in 0..delta.unsigned_abs() {
+            j = (j + step) % n;
+            // Moving up into a collapsed group ends up at its first file.
+            while hidden(j) && j != i {
+                j = (j + step) % n;
+            }
+        }
+
+        self.listing.set(cache);
+        self.list_state.select(Some(j));
+    }
+
+    /// Select the next file whose name starts with a character, ignoring case
+    /// and wrapping around, so that pressing it again cycles through the matches.
+    fn jump_to_letter(&mut self, c: char) {
+        let n = self.queue.files().len();
+        let start = self.list_state.selected().map_or(0, |i| i + 1);
+        let lowercase = |c: char| c.to_lowercase().collect::<String>();
+
+        let found = (0..n).map(|k| (start + k) % n).find(|&i| {
+            display_name(&self.queue.files()[i])
+                .chars()
+                .next()
+                .is_some_and(|first| lowercase(first) == lowercase(c))
+        });
+
+        match found {
+            Some(i) => self.list_state.select(Some(i)),
+            None => self.show(Level::Info, format!("No file starts with {c}")),
+        }
+    }
+
+    /// Collapse the group of the selected file, or expand it if it's collapsed.
+    fn toggle_group(&mut self) {
+        let i = match self.list_state.selected() {
+            Some(i) => i,
+            None => return,
+        };
+
+        if let Some(group) = self.groups().into_iter().find(|group| group.contains(i)) {
+            if !self.collapsed.remove(&group.title) {
+                self.collapsed.insert(group.title);
+                self.list_state.select(Some(group.start));
+            }
+            self.listing.invalidate();
+        }
+    }
+
+    /// Take the next track of the shuffled order.
+    fn next_shuffled(&mut self) -> Option<PathBuf> {
+        let weights = self.args.shuffle_weights;
+        self.queue.next_shuffled(self.track_uri.as_deref(), |file| {
+            shuffle_weight(weights, &self.stats, file)
+        })
+    }
+
+    /// Skip to the next track according to the autoplay mode.
+    fn skip_next(&mut self) {
+        let weights = self.args.shuffle_weights;
+        let current = self.player.current_uri();
+        let track = self.queue.skip_next(current.as_deref(), |file| {
+            shuffle_weight(weights, &self.stats, file)
+        });
+
+        if let Some(track) = track {
+            self.player.play_path(&track);
+        }
+    }
+
+    /// Return to the most recently skipped track at the position it was left at.
+    /// The track that is left for it can't be returned to.
+    fn undo_skip(&mut self) {
+        match self.skipped.pop() {
+            Some((uri, position)) => {
+                self.undoing_skip = true;
+                self.player.play_uri_at(&uri, position);
+            }
+            None => self.show(Level::Info, "No skipped track to return to"),
+        }
+    }
+
+    /// Go back to the previous track according to the autoplay mode.
+    /// Shuffled playback goes back in the history.
+    fn skip_previous(&mut self) {
+        let current = self.player.current_uri();
+        match self.queue.skip_previous(current.as_deref()) {
+            Previous::Track(track) => self.player.play_path(&track),
+            Previous::History => {
+                self.play_previous();
+            }
+            Previous::Restart => self.player.backend.seek(Duration::ZERO),
+        }
+    }
+
+    /// Get the accent color for the genre of the current track.
+    fn accent_color(&mut self) -> Color {
+        let uri = match &self.track_uri {
+            Some(uri) if !self.args.genre_colors.is_empty() => uri.clone(),
+            _ => return Color::Magenta,
+        };
+
+        let genre = match self.library.tags(&uri).genre {
+            Some(genre) => genre,
+            None => return Color::Magenta,
+        };
+
+        self.args
+            .genre_colors
+            .iter()
+            .find(|genre_color| genre_color.matches(&genre))
+            .map_or(Color::Magenta, |genre_color| genre_color.color)
+    }
+
+    /// Show a message in the notice line.
+    /// Errors aren't replaced by less severe messages until they are resolved.
+    fn show(&mut self, level: Level, text: impl Into<String>) {
+        let text = text.into();
+        match level {
+            Level::Info => tracing::info!("{text}"),
+            Level::Warning => tracing::warn!("{text}"),
+            Level::Error => tracing::error!("{text}"),
+        }
+
+        if let Some(notice) = &self.notice {
+            if notice.level > level && !notice.is_expired() {
+                return;
+            }
+        }
+
+        self.notice = Some(Notice::new(level, text));
+    }
+
+    /// Check whether a pane can be focused, i.e. it's on the screen
+    /// and wasn't hidden with --hide or left out by the layout.
+    fn is_shown(&self, pane: &CursorState) -> bool {
+        match pane {
+            CursorState::MusicList => true,
+            CursorState::Volume => self.areas.volume.is_some(),
+            CursorState::Control => self.areas.controls.is_some(),
+            CursorState::Search => self.areas.search.is_some(),
+            CursorState::History => self.areas.history.is_some(),
+            CursorState::Chapters => self.areas.chapters.is_some(),
+            CursorState::Playlists => self.areas.playlists.is_some(),
+            CursorState::Timers => self.areas.timers.is_some(),
+        }
+    }
+
+    /// Get the symbols of the enabled autoplay modes.
+    fn modes(&self) -> impl Iterator<Item = Symbol> {
+        [
+            (self.queue.autoplay.repeat_list, Symbol::RepeatList),
+            (self.queue.autoplay.repeat, Symbol::Repeat),
+            (self.queue.autoplay.sequential, Symbol::Sequential),
+            (self.queue.autoplay.shuffle, Symbol::Shuffle),
+            (self.queue.autoplay.consume, Symbol::Consume),
+            (self.stop_after_current, Symbol::StopAfterCurrent),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, symbol)| enabled.then_some(symbol))
+    }
+
+    fn glyphs(&self) -> Glyphs {
+        if self.args.labels {
+            Glyphs::Labels
+        } else if self.args.ascii {
+            Glyphs::Ascii
+        } else if self.args.emoji {
+            Glyphs::Emoji
+        } else {
+            self.detected_glyphs
+        }
+    }
+
+    /// Whether nothing is playing, as last reported by the backend.
+    /// Buffering counts as playing, it resumes on its own.
+    fn is_paused(&self) -> bool {
+        matches!(self.play_state, PlayState::Stopped | PlayState::Paused)
+    }
+
+    /// Get the name of the playback state for the status outputs.
+    /// Stopped with no track loaded is idle.
+    #[cfg(feature = "web")]
+    fn state_name(&self) -> &'static str {
+        match self.play_state {
+            PlayState::Stopped if self.track_uri.is_none() => "idle",
+            PlayState::Stopped => "stopped",
+            PlayState::Buffering => "buffering",
+            PlayState::Paused => "paused",
+            PlayState::Playing => "playing",
+        }
+    }
+
+    /// Get the chapters of the current track.
+    fn chapters(&mut self) -> Vec<Chapter> {
+        match self.track_uri.clone() {
+            Some(uri) => self.library.tags(&uri).chapters,
+            None => Vec::new(),
+        }
+    }
+
+    /// Seek to the start of the next or previous chapter.
+    /// Going back more than a few seconds into a chapter restarts it instead.
+    fn seek_chapter(&mut self, forward: bool) {
+        let chapters = self.chapters();
+        let position = match self.player.backend.position() {
+            Some(position) if !chapters.is_empty() => position,
+            _ => return,
+        };
+
+        let current = current_chapter(&chapters, position);
+        let target = if forward {
+            match chapters.get(current.map_or(0, |i| i + 1)) {
+                Some(chapter) => chapter.start,
+                None => {
+                    self.show(Level::Info, "This is the last chapter");
+                    return;
+                }
+            }
+        } else {
+            match current {
+                Some(i) if position.saturating_sub(chapters[i].start) > CHAPTER_RESTART => {
+                    chapters[i].start
+                }
+                Some(i) if i > 0 => chapters[i - 1].start,
+                _ => Duration::ZERO,
+            }
+        };
+
+        self.player.seek(target);
+    }
+
+    /// Seek relative to the current position, staying within the track.
+    fn seek_by(&self, seconds: f64) {
+        let position = match self.player.backend.position() {
+            Some(position) => position.as_secs_f64(),
+            None => return,
+        };
+
+        let mut target = (position + seconds).max(0.0);
+        if seconds > 0.0 {
+            match self.player.backend.duration() {
+                Some(duration) => target = target.min(duration.as_secs_f64()),
+                None => return,
+            }
+        }
+
+        self.player.seek(Duration::from_secs_f64(target));
+    }
+
+    /// Handle a key press while the seek prompt is open.
+    fn seek_prompt_key(&mut self, code: KeyCode) {
+        let target = match &mut self.seek_prompt {
+            Some(target) => target,
+            None => return,
+        };
+
+        match code {
+            KeyCode::Esc => self.seek_prompt = None,
+            KeyCode::Char(c) => target.push(c),
+            KeyCode::Backspace => {
+                target.pop();
+            }
+            KeyCode::Enter => {
+                let target = self.seek_prompt.take().unwrap_or_default();
+                let duration = self.player.backend.duration();
+
+                match parse_seek_target(&target, duration) {
+                    Some(position) => match duration {
+                        Some(duration) => self.player.seek(position.min(duration)),
+                        None => self.player.seek(position),
+                    },
+                    None => self.show(Level::Warning, format!("invalid position {target}")),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Change the volume by a step of the gauge.
+    /// Muted players start from the volume before muting.
+    fn change_volume(&mut self, percent: f64) {
+        let scale = self.args.volume_scale;
+        let volume = self.muted.take().unwrap_or_else(|| self.player.volume());
+        let position = scale.position(volume) + percent / 100.0;
+        self.player
+            .set_volume(scale.amplitude(position.clamp(0.0, 1.0)));
+    }
+
+    /// Mute, or restore the volume from before muting.
+    fn toggle_mute(&mut self) {
+        match self.muted.take() {
+            Some(volume) => self.player.set_volume(volume),
+            None => {
+                self.muted = Some(self.player.volume());
+                self.player.set_volume(0.0);
+            }
+        }
+    }
+
+    /// Get the volume as shown, in percent of the gauge and optionally in decibels.
+    fn volume_label(&self, volume: f64) -> String {
+        let percent = self.args.volume_scale.position(volume) * 100.0;
+        if !self.args.volume_db {
+            return format!("{percent:.0}%");
+        }
+
+        if volume > 0.0 {
+            format!("{percent:.0}% · {:.1} dB", 20.0 * volume.log10())
+        } else {
+            format!("{percent:.0}% · -inf dB")
+        }
+    }
+
+    /// Amplify the current track to the loudness of the previous one as both were heard,
+    /// and keep the gain for the following tracks.
+    fn match_loudness(&mut self) {
+        match self.player.match_loudness() {
+            Some(gain) => self.show(
+                Level::Info,
+                format!("Matched the previous track, {gain:+.1} dB"),
+            ),
+            None => self.show(
+                Level::Info,
+                "Play this and the previous track for a few seconds to match them",
+            ),
+        }
+    }
+
+    /// Switch the playback keys to the next zone, after the last one to the main zone.
+    fn next_zone(&mut self) {
+        if self.zones.is_empty() {
+            self.show(Level::Info, "No zones, add them with --zone");
+            return;
+        }
+
+        self.zone = (self.zone + 1) % (self.zones.len() + 1);
+
+        let text = match self.zone {
+            0 => String::from("Controlling the main zone"),
+            i => format!("Controlling zone {}", self.zones[i - 1].name),
+        };
+        self.show(Level::Info, text);
+    }
+
+    /// Handle the playback keys while a zone is controlled.
+    /// Returns false for keys that work the same in every zone.
+    fn zone_key(&mut self, code: KeyCode, multiplier: f64) -> bool {
+        let scale = self.args.volume_scale;
+        let zone = &mut self.zones[self.zone - 1];
+        let volume_step = |step: f64| {
+            let position = scale.position(zone.player.volume()) + step * multiplier / 100.0;
+            zone.player
+                .set_volume(scale.amplitude(position.clamp(0.0, 1.0)));
+        };
+
+        match (&self.cursor_state, code) {
+            (CursorState::Search, _) => return false,
+            (_, KeyCode::Char(' ')) => zone.toggle_pause(),
+            (_, KeyCode::Char('n')) => zone.advance(),
+            (CursorState::MusicList, KeyCode::Enter) => {
+                let i = match self.list_state.selected() {
+                    Some(i) if i < self.queue.files().len() => i,
+                    _ => return true,
+                };
+
+                // The rest of the list plays after it, e.g. the rest of an album.
+                zone.play(
+                    self.queue.files()[i].clone(),
+                    self.queue.files()[i + 1..].iter().cloned(),
+                );
+            }
+            (CursorState::Volume, KeyCode::Left) => volume_step(-self.args.volume_step),
+            (CursorState::Volume, KeyCode::Right) => volume_step(self.args.volume_step),
+            (CursorState::Volume, KeyCode::Down) => volume_step(-self.args.volume_step_large),
+            (CursorState::Volume, KeyCode::Up) => volume_step(self.args.volume_step_large),
+            (CursorState::Volume, KeyCode::Home) => zone.player.set_volume(0.0),
+            (CursorState::Volume, KeyCode::End) => zone.player.set_volume(1.0),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Mount the network share of a URI that failed to play and retry.
+    /// Credentials are taken from the keyring if possible,
+    /// otherwise the user is prompted for them.
+    fn request_credentials(&mut self, uri: String) {
+        if let Some((username, password)) = remote::lookup_credentials(&uri) {
+            if remote::mount(&uri, &username, &password).is_ok() {
+                self.player.play_uri(&uri);
+                return;
+            }
+        }
+
+        self.credential_prompt = Some(CredentialPrompt {
+            uri,
+            ..Default::default()
+        });
+    }
+
+    /// Handle a key press while the credential prompt is open.
+    fn credential_key(&mut self, code: KeyCode) {
+        let prompt = match &mut self.credential_prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        match code {
+            KeyCode::Esc => {
+                let text = format!("Not mounted: {}", remote::share(&prompt.uri));
+                self.show(Level::Error, text);
+                self.credential_prompt = None;
+            }
+            KeyCode::Tab | KeyCode::Down => prompt.field = (prompt.field + 1) % 3,
+            KeyCode::BackTab | KeyCode::Up => prompt.field = (prompt.field + 2) % 3,
+            KeyCode::Char(' ') if prompt.field == 2 => prompt.save = !prompt.save,
+            KeyCode::Char(c) if prompt.field == 0 => prompt.username.push(c),
+            KeyCode::Char(c) if prompt.field == 1 => prompt.password.push(c),
+            KeyCode::Backspace if prompt.field == 0 => {
+                prompt.username.pop();
+            }
+            KeyCode::Backspace if prompt.field == 1 => {
+                prompt.password.pop();
+            }
+            KeyCode::Enter => {
+                match remote::mount(&prompt.uri, &prompt.username, &prompt.password) {
+                    Ok(_) => {
+                        let stored = if prompt.save {
+                            remote::store_credentials(
+                                &prompt.uri,
+                                &prompt.username,
+                                &prompt.password,
+                            )
+                        } else {
+                            Ok(())
+                        };
+
+                        let uri = prompt.uri.clone();
+                        self.credential_prompt = None;
+
+                        match stored {
+                            Ok(()) => {
+                                self.show(Level::Info, format!("Mounted {}", remote::share(&uri)))
+                            }
+                            Err(e) => self.show(Level::Warning, e.to_string()),
+                        }
+
+                        self.player.play_uri(&uri);
+                    }
+                    Err(e) => {
+                        prompt.password.clear();
+                        self.show(Level::Error, e.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Continue after the current track ended according to the autoplay mode.
+    /// Returns false if there are no songs left to play and the player should exit.
+    fn autoplay(&mut self) -> bool {
+        let weights = self.args.shuffle_weights;
+        let current = self.player.current_uri();
+        let advance = self
+            .queue
+            .advance(current.as_deref(), self.stops_after_track(), |file| {
+                shuffle_weight(weights, &self.stats, file)
+            });
+
+        self.follow(advance)
+    }
+
+    /// Go on after the current track failed to play as if it had ended,
+    /// except that it isn't repeated.
+    fn skip_failed(&mut self) -> bool {
+        let weights = self.args.shuffle_weights;
+        let current = self.player.current_uri();
+        let advance =
+            self.queue
+                .skip_failed(current.as_deref(), self.stops_after_track(), |file| {
+                    shuffle_weight(weights, &self.stats, file)
+                });
+
+        self.follow(advance)
+    }
+
+    /// Carry out what autoplay decided.
+    /// Returns false if there are no songs left to play and the player should exit.
+    fn follow(&mut self, advance: Advance) -> bool {
+        let continues = match &advance {
+            Advance::Stop => {
+                self.stop_after_current = false;
+                false
+            }
+            Advance::Repeat => {
+                self.listened = 0.0;
+                self.counted = false;
+                false
+            }
+            Advance::Play(track) => self.continues_album(track),
+            Advance::Idle if self.args.no_remain => {
+                self.player.notify("queue-empty");
+                return false;
+            }
+            Advance::Exhausted | Advance::Idle => false,
+        };
+
+        self.player.advance(&advance, continues);
+        true
+    }
+
+    /// Turn previews of the selected file on or off.
+    fn toggle_preview_mode(&mut self) {
+        self.preview_mode = !self.preview_mode;
+        self.preview_selected = self.selected_file();
+
+        if self.preview_mode {
+            self.show(Level::Info, "Previewing the selected file when it changes");
+        } else {
+            self.player.end_preview(true);
+            self.show(Level::Info, "Previews off");
+        }
+    }
+
+    fn selected_file(&self) -> Option<PathBuf> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.queue.files().get(i))
+            .cloned()
+    }
+
+    /// Preview the selected file if it changed and end previews that have played long enough.
+    fn update_preview(&mut self) {
+        if !self.preview_mode {
+            return;
+        }
+
+        self.player.update_preview();
+
+        let selected = self.selected_file();
+        if selected == self.preview_selected {
+            return;
+        }
+        self.preview_selected = selected.clone();
+
+        if let Some(file) = selected {
+            let uri = file_uri(&file);
+            let duration = self
+                .library
+                .cached_tags(&uri)
+                .and_then(|tags| tags.duration);
+            self.player.preview(&uri, duration);
+        }
+    }
+
+    /// Pause on a suspend or when the headphones are gone,
+    /// and resume afterwards with --auto-resume.
+    fn interrupt(&mut self, interruption: Interruption) {
+        match interruption {
+            Interruption::Suspend | Interruption::HeadphonesRemoved => {
+                if self.play_state == PlayState::Playing {
+                    // Waiting for the ramp could outlast the time before the suspend.
+                    self.player.backend.pause();
+                    self.player.notify("pause");
+                    self.auto_paused = true;
+
+                    if interruption == Interruption::HeadphonesRemoved {
+                        self.show(Level::Info, "Paused, the headphones were disconnected");
+                    }
+                }
+            }
+            Interruption::Wake | Interruption::HeadphonesConnected => {
+                if self.args.auto_resume && self.auto_paused && self.play_state == PlayState::Paused
+                {
+                    self.player.resume();
+                    self.player.notify("resume");
+                }
+                self.auto_paused = false;
+            }
+        }
+    }
+
+    /// Apply the EQ profile of an output device, or a flat response if it has none.
+    fn apply_eq(&mut self, sink: &str) {
+        match eq::select(&self.args.eq_profiles, &self.args.eq_outputs, sink) {
+            Some(profile) => {
+                self.player.set_eq(Some(profile.gains));
+                self.show(
+                    Level::Info,
+                    format!("EQ profile {} for {sink}", profile.name),
+                );
+            }
+            None => {
+                self.player.set_eq(None);
+                self.show(Level::Info, format!("No EQ profile for {sink}"));
+            }
+        }
+    }
+
+    /// Keep the selection on the same file after the one at index `i`
+    /// was removed from the list, e.g. by consume mode.
+    fn keep_selection(&mut self, i: usize) {
+        self.listing.invalidate();
+
+        let n = self.queue.files().len();
+        match self.list_state.selected() {
+            _ if n == 0 => self.list_state.select(None),
+            Some(selected) if selected > i || selected == n => {
+                self.list_state.select(Some(selected - 1));
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether playback holds silence after the current track until the next timer.
+    fn holding(&self) -> bool {
+        self.hold_for_timers && !self.schedule.is_empty()
+    }
+
+    /// Whether autoplay stops at the end of the current track.
+    fn stops_after_track(&self) -> bool {
+        self.stop_after_current || self.holding()
+    }
+
+    /// Cycle the sleep timer through `SLEEP_STEPS` and off.
+    fn next_sleep_step(&mut self) {
+        let step = match self.sleep {
+            None => 0,
+            Some((step, _)) => step + 1,
+        };
+
+        match SLEEP_STEPS.get(step) {
+            Some(&minutes) => {
+                self.sleep = Some((step, Instant::now() + Duration::from_secs(minutes * 60)));
+                self.show(Level::Info, format!("Sleeping in {minutes} min"));
+            }
+            None => {
+                self.sleep = None;
+                self.show(Level::Info, "Sleep timer off");
+            }
+        }
+    }
+
+    /// Get the time until the sleep timer or the end of the queue stops playback.
+    fn silence_in(&self) -> Option<Duration> {
+        let sleep = self
+            .sleep
+            .map(|(_, at)| at.saturating_duration_since(Instant::now()));
+
+        let end_of_queue = match self.play_state {
+            PlayState::Playing if self.upcoming_track().is_none() => {
+                let duration = self.player.backend.duration()?;
+                let position = self.player.backend.position()?;
+                Some(duration.saturating_sub(position))
+            }
+            _ => None,
+        };
+
+        match (sleep, end_of_queue) {
+            (Some(sleep), Some(end)) => Some(sleep.min(end)),
+            (sleep, end) => sleep.or(end),
+        }
+    }
+
+    /// Fade the volume out over the final seconds before playback stops,
+    /// and stop playback once the sleep timer expires.
+    fn fade_to_silence(&mut self) {
+        let fade = Duration::try_from_secs_f64(self.args.sleep_fade).unwrap_or_default();
+
+        match self.silence_in() {
+            Some(remaining) if remaining < fade && self.play_state == PlayState::Playing => {
+                let volume = *self
+                    .silence_volume
+                    .get_or_insert_with(|| self.player.volume());
+                let ratio = remaining.as_secs_f64() / fade.as_secs_f64();
+
+                self.player.backend.set_volume(volume * ratio);
+            }
+            _ => {
+                if let Some(volume) = self.silence_volume.take() {
+                    self.player.backend.set_volume(volume);
+                }
+            }
+        }
+
+        if let Some((_, at)) = self.sleep {
+            if Instant::now() >= at {
+                tracing::info!("sleep timer expired");
+                self.sleep = None;
+                self.player.backend.stop();
+                self.player.notify("stop");
+
+                if let Some(volume) = self.silence_volume.take() {
+                    self.player.backend.set_volume(volume);
+                }
+            }
+        }
+    }
+
+    /// Start the track of a timer that is due.
+    fn run_timers(&mut self) {
+        if let Some(timer) = self.schedule.due(SystemTime::now()) {
+            tracing::info!("starting scheduled track {}", timer.path.display());
+            self.player.play_path(&timer.path);
+        }
+    }
+
+    /// Handle a key press while a time is being entered for a timer.
+    fn timer_prompt_key(&mut self, code: KeyCode) {
+        let (path, time) = match &mut self.timer_prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        match code {
+            KeyCode::Esc => self.timer_prompt = None,
+            KeyCode::Char(c) => time.push(c),
+            KeyCode::Backspace => {
+                time.pop();
+            }
+            KeyCode::Enter => match schedule::next_occurrence(time, SystemTime::now()) {
+                Ok(at) => {
+                    let path = path.clone();
+                    self.timer_prompt = None;
+                    self.show(
+                        Level::Info,
+                        format!(
+                            "{} starts at {}",
+                            display_name(&path),
+                            schedule::format_time(at)
+                        ),
+                    );
+                    self.schedule.add(Timer { at, path });
+                }
+                Err(e) => self.show(Level::Warning, e),
+            },
+            _ => {}
+        }
+    }
+
+    /// Handle a key press while a URL is being entered.
+    fn url_prompt_key(&mut self, code: KeyCode) {
+        let url = match &mut self.url_prompt {
+            Some(url) => url,
+            None => return,
+        };
+
+        match code {
+            KeyCode::Esc => self.url_prompt = None,
+            KeyCode::Char(c) => url.push(c),
+            KeyCode::Backspace => {
+                url.pop();
+            }
+            KeyCode::Enter => {
+                let url = url.trim().to_string();
+                if url.contains("://") {
+                    self.url_prompt = None;
+                    self.open_url(url);
+                } else {
+                    self.show(Level::Warning, format!("{url:?} isn't a URL"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Play a URL. Pages of supported sites such as Bandcamp or YouTube are resolved
+    /// into their audio stream in the background first.
+    fn open_url(&mut self, url: String) {
+        let site = match resolve::Site::of(&url) {
+            Some(site) => site,
+            None => {
+                self.player.play_uri(&url);
+                return;
+            }
+        };
+
+        tracing::info!("resolving {site:?} URL {url}");
+        self.show(Level::Info, format!("Resolving {url}"));
+
+        let (tx, rx) = mpsc::channel();
+        let client_id = self.args.soundcloud_client_id.clone();
+        thread::spawn(move || {
+            let _ = tx.send(resolve::resolve(&url, client_id.as_deref()));
+        });
+
+        self.resolving = Some(rx);
+    }
+
+    /// Play the stream of a resolved page once it's available.
+    fn poll_resolve(&mut self) {
+        let result = match self.resolving.as_ref().map(Receiver::try_recv) {
+            Some(Ok(result)) => result,
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                self.resolving = None;
+                return;
+            }
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+
+        self.resolving = None;
+        match result {
+            Ok(stream) => {
+                self.notice = None;
+                // The site's metadata is all there is, streams rarely carry tags.
+                self.library.cache_tags(stream.uri.clone(), stream.tags);
+                self.player.play_uri(&stream.uri);
+            }
+            Err(e) => self.show(Level::Error, format!("Can't open URL: {e}")),
+        }
+    }
+
+    /// Reload the current track at the last known position
+    /// if the position hasn't advanced for a while during playback.
+    /// Buffering doesn't count as playback.
+    fn watchdog(&mut self) {
+        let position = self.player.backend.position();
+
+        if self.play_state != PlayState::Playing || position != self.watchdog_position {
+            if self.play_state == PlayState::Playing {
+                self.watchdog_retries = 0;
+            }
+            self.watchdog_position = position;
+            self.watchdog_since = Instant::now();
+            return;
+        }
+
+        if self.watchdog_since.elapsed() < WATCHDOG_TIMEOUT
+            || self.watchdog_retries >= WATCHDOG_RETRIES
+        {
+            return;
+        }
+
+        if let Some(uri) = self.track_uri.clone() {
+            let position = position.unwrap_or_default();
+            self.watchdog_retries += 1;
+
+            if self.watchdog_retries == WATCHDOG_RETRIES {
+                tracing::warn!(
+                    "playback of {uri} stalled at {}s, giving up after {WATCHDOG_RETRIES} reloads",
+                    position.as_secs()
+                );
+                self.show(
+                    Level::Error,
+                    format!("Playback stalled at {}s", position.as_secs()),
+                );
+            } else {
+                tracing::warn!(
+                    "playback of {uri} stalled at {}s, reloading",
+                    position.as_secs()
+                );
+                self.show(
+                    Level::Warning,
+                    format!("Playback stalled at {}s, reloading", position.as_secs()),
+                );
+
+                self.player.backend.stop();
+                self.player.play_uri_at(&uri, position);
+            }
+        }
+
+        self.watchdog_since = Instant::now();
+    }
+
+    /// Read the directory, or the tracks given as arguments, again after files were
+    /// added, removed or renamed, keeping the selected file and scanning the new ones.
+    /// Returns false if the directory can't be read.
+    fn reload_library(&mut self) -> bool {
+        if self.args.no_listing {
+            return false;
+        }
+
+        let library = if self.args.tracks.is_empty() {
+            match library::read_dir(&self.dir()) {
+                Ok(library) => library,
+                Err(e) => {
+                    self.show(Level::Warning, format!("can't read directory: {e}"));
+                    return false;
+                }
+            }
+        } else {
+            self.args.tracks.clone()
+        };
+
+        let selected = self
+            .list_state
+            .selected()
+            .and_then(|i| self.queue.files().get(i))
+            .cloned();
+
+        tracing::info!(
+            "directory changed, {} files instead of {}",
+            library.len(),
+            self.library.files().len()
+        );
+
+        for file in self.library.set_files(library, &Mounts::load()) {
+            self.queue.shuffle.remove(&file);
+        }
+        self.refresh_files();
+
+        match selected.and_then(|file| self.queue.files().iter().position(|f| *f == file)) {
+            Some(i) => self.list_state.select(Some(i)),
+            None if self.queue.files().is_empty() => self.list_state.select(None),
+            None => {
+                let i = self.list_state.selected().unwrap_or_default();
+                self.list_state
+                    .select(Some(i.min(self.queue.files().len() - 1)));
+            }
+        }
+
+        self.library.start_scan();
+        true
+    }
+
+    /// Collect the results the background scan produced so far.
+    fn poll_scan(&mut self) {
+        let update = self.library.poll_scan();
+        if update.scanned > 0 {
+            self.listing.invalidate();
+        }
+        if !update.finished {
+            return;
+        }
+
+        self.startup.finish("tag load");
+        self.player.notify_task(self.library.scan_progress());
+
+        // Smart playlists matching on tags only see the ones scanned when they were built.
+        if let View::Smart(i) = self.view {
+            if self.args.smart_playlists[i].rule.uses_tags() {
+                self.refresh_files();
+                let last = self.queue.files().len().checked_sub(1);
+                let selected = self.list_state.selected();
+                self.list_state
+                    .select(selected.zip(last).map(|(i, last)| i.min(last)));
+            }
+        }
+    }
+
+    /// Read the size and tags of every file again, e.g. after they were edited externally.
+    fn rescan(&mut self) {
+        self.library.forget_sizes();
+        self.listing.invalidate();
+        if self.reload_library() {
+            self.player.notify_task(self.library.scan_progress());
+        }
+    }
+
+    /// Decode the files that haven't been verified yet in the background
+    /// to find damaged ones, unless that is already running.
+    fn start_verify(&mut self) {
+        if self.verification.is_some() {
+            return;
+        }
+
+        let mut verifications = Verifications::load();
+        let files: Vec<PathBuf> = self
+            .library
+            .files()
+            .iter()
+            .filter(|file| file.is_file() && verifications.get(&file_uri(file)).is_none())
+            .cloned()
+            .collect();
+
+        let progress = Progress {
+            task: Task::Verify,
+            done: 0,
+            total: files.len(),
+        };
+        tracing::info!("verifying {} files", progress.total);
+        self.player.notify_task(progress);
+
+        if progress.is_done() {
+            self.show(Level::Info, "All files have been verified");
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (i, file) in files.into_iter().enumerate() {
+                let uri = file_uri(&file);
+                let damaged = match verifications.verify(&file, &uri) {
+                    Ok(verification) => verification.error.is_some(),
+                    Err(e) => {
+                        tracing::warn!("can't verify {}: {e}", file.display());
+                        false
+                    }
+                };
+
+                // Save as we go so that an interrupted run isn't lost.
+                if (i + 1) % verify::SAVE_BATCH == 0 {
+                    if let Err(e) = verifications.save() {
+                        tracing::warn!("can't save verification results: {e}");
+                    }
+                }
+
+                if tx.send((uri, damaged)).is_err() {
+                    break;
+                }
+            }
+
+            // Also reached once the results are no longer wanted, e.g. on exit.
+            if let Err(e) = verifications.save() {
+                tracing::warn!("can't save verification results: {e}");
+            }
+        });
+
+        self.verification = Some((rx, progress));
+    }
+
+    /// Collect the results the background verification produced so far.
+    fn poll_verify(&mut self) {
+        let (rx, progress) = match &mut self.verification {
+            Some(verification) => verification,
+            None => return,
+        };
+
+        for (uri, damaged) in rx.try_iter() {
+            progress.done += 1;
+            if damaged {
+                self.damaged.insert(uri);
+            } else {
+                self.damaged.remove(&uri);
+            }
+        }
+
+        if progress.is_done() {
+            let progress = *progress;
+            self.verification = None;
+            self.player.notify_task(progress);
+        }
+    }
+
+    /// Get the progress of the library scan and of the running tasks.
+    fn task_progress(&self) -> Vec<Progress> {
+        let mut tasks = vec![self.library.scan_progress()];
+        if let Some((_, progress)) = &self.verification {
+            tasks.push(*progress);
+        }
+
+        tasks
+    }
+
+    /// Send the progress of the running tasks to the extensions now and then.
+    fn report_tasks(&mut self) {
+        if self.tasks_reported.elapsed() < TASK_REPORT_INTERVAL {
+            return;
+        }
+
+        self.tasks_reported = Instant::now();
+        for progress in self.task_progress() {
+            if !progress.is_done() {
+                self.player.notify_task(progress);
+            }
+        }
+    }
+
+    /// Summarize the current list, e.g. "1,284 tracks · 7.3 GB · 82 h 14 m".
+    /// Size and duration only include files that have already been scanned.
+    fn list_summary(&self) -> String {
+        let mut size = 0;
+        let mut seconds = 0;
+
+        for file in self.queue.files() {
+            size += self.library.size(file).unwrap_or_default();
+            seconds += self
+                .library
+                .cached_tags(&file_uri(file))
+                .and_then(|tags| tags.duration)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+        }
+
+        format!(
+            "{} tracks · {:.1} GB · {} h {} m",
+            group_thousands(self.queue.files().len()),
+            size as f64 / 1e9,
+            seconds / 3600,
+            seconds / 60 % 60
+        )
+    }
+
+    /// Describe the stream of a track, e.g. "FLAC · ~912 kbps · 44.1 kHz · stereo · 31.2 MB".
+    /// Bitrates marked with ~ are averages computed from the file size,
+    /// for formats that don't state one.
+    fn technical_info(&mut self, uri: &str) -> String {
+        let path = uri_to_path(uri);
+        let size = path.as_ref().and_then(|path| {
+            self.library
+                .size(path)
+                .or_else(|| fs::metadata(path).ok().map(|metadata| metadata.len()))
+        });
+
+        let tags = self.library.tags(uri);
+
+        let mut info = Vec::new();
+
+        if let Some(codec) = tags.codec {
+            info.push(codec);
+        }
+
+        let average = size
+            .zip(tags.duration.filter(|duration| !duration.is_zero()))
+            .map(|(size, duration)| (size * 8) as f64 / duration.as_secs_f64());
+        match (tags.bitrate, average) {
+            (Some(bitrate), _) => info.push(format!("{} kbps", bitrate / 1000)),
+            (None, Some(average)) => info.push(format!("~{:.0} kbps", average / 1000.0)),
+            (None, None) => {}
+        }
+
+        if let Some(rate) = tags.sample_rate {
+            info.push(format!("{} kHz", rate as f64 / 1000.0));
+        }
+
+        match tags.channels {
+            Some(1) => info.push(String::from("mono")),
+            Some(2) => info.push(String::from("stereo")),
+            Some(6) => info.push(String::from("5.1")),
+            Some(8) => info.push(String::from("7.1")),
+            Some(n) => info.push(format!("{n} channels")),
+            None => {}
+        }
+
+        if let Some(size) = size {
+            info.push(format!("{:.1} MB", size as f64 / 1e6));
+        }
+
+        info.join(" · ")
+    }
+
+    /// Account the time since the last call to the current track's listening time.
+    /// A track counts as played once half of it (or four minutes) has been listened to.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64();
+        self.last_tick = now;
+
+        let uri = match &self.track_uri {
+            Some(uri) if self.play_state == PlayState::Playing && !self.player.is_previewing() => {
+                uri.clone()
+            }
+            _ => return,
+        };
+
+        self.listened += elapsed;
+        self.stats.add_listened(&uri, elapsed);
+
+        let threshold = self.player.backend.duration().map_or(240.0, |duration| {
+            (duration.as_secs() as f64 / 2.0).min(240.0)
+        });
+
+        if !self.counted && self.listened >= threshold {
+            self.stats.record_play(&uri);
+            self.counted = true;
+        }
+    }
+
+    fn new(args: Args) -> anyhow::Result<Self> {
+        let mut startup = Profile::new();
+        let fade = Fade {
+            curve: args.fade_curve,
+            fade_in: Duration::from_secs_f64(args.fade_in.max(0.0)),
+            fade_out: Duration::from_secs_f64(args.fade_out.max(0.0)),
+        };
+        for output in &args.eq_outputs {
+            if !args.eq_profiles.iter().any(|p| p.name == output.profile) {
+                anyhow::bail!("--eq-output {}: unknown EQ profile", output.profile);
+            }
+        }
+
+        let backend = args.backend.open(
+            args.replaygain,
+            fade,
+            !args.eq_profiles.is_empty(),
+            &args.output,
+        )?;
+        let volume = args.volume.or_else(load_volume);
+        let mut player = Player::new(backend, volume);
+        player.set_ramp(Duration::from_millis(args.ramp));
+        player.set_fade_policy(fade.is_enabled().then_some(args.fade_policy));
+        register_extensions(&mut player, &args)?;
+
+        // Zones only play queues, so they skip the EQ and the extensions.
+        let mut zones = Vec::new();
+        for output in &args.zones {
+            let backend = args
+                .backend
+                .open(args.replaygain, fade, false, &output.output)
+                .map_err(|e| anyhow::anyhow!("--zone {}: {e}", output.name))?;
+            let mut player = Player::new(backend, None);
+            player.set_ramp(Duration::from_millis(args.ramp));
+
+            zones.push(Zone::new(output.name.clone(), player));
+        }
+
+        #[cfg(feature = "cover-art")]
+        let cover_art = args.cover_art.then(|| Arc::new(CoverArt::new()));
+
+        let (stats, snapshots, ratings, damaged) = startup.time("database open", || {
+            let damaged = Verifications::load().damaged().map(String::from).collect();
+            (Stats::load(), Snapshots::load(), Ratings::load(), damaged)
+        });
+
+        let mut instance = Self {
+            args,
+            cursor_state: CursorState::default(),
+            player,
+            library: Library::new()?,
+            queue: Queue::default(),
+            view: View::default(),
+            list_state: ListState::default(),
+            grouping: None,
+            collapsed: HashSet::new(),
+            listing: Listing::default(),
+            selections: HashMap::new(),
+            search: String::new(),
+            search_regex: false,
+            track_uri: None,
+            history: History::default(),
+            history_state: ListState::default(),
+            chapter_state: ListState::default(),
+            playlists: Vec::new(),
+            playlist_state: ListState::default(),
+            playlist_dialog: None,
+            mix: Vec::new(),
+            schedule: Schedule::default(),
+            timer_state: ListState::default(),
+            timer_prompt: None,
+            hold_for_timers: false,
+            stop_after_current: false,
+            sleep: None,
+            silence_volume: None,
+            muted: None,
+            preview_mode: false,
+            preview_selected: None,
+            auto_paused: false,
+            stats,
+            listened: 0.0,
+            counted: false,
+            skipped: Vec::new(),
+            undoing_skip: false,
+            last_tick: Instant::now(),
+            source_filter: None,
+            url_prompt: None,
+            resolving: None,
+            play_state: PlayState::Stopped,
+            buffering: None,
+            notice: None,
+            credential_prompt: None,
+            help: None,
+            track_stats: false,
+            track_position: None,
+            watchdog_position: None,
+            watchdog_since: Instant::now(),
+            watchdog_retries: 0,
+            passthrough: false,
+            passthrough_pinned: false,
+            snapshots,
+            ratings,
+            damaged,
+            failed: HashSet::new(),
+            failed_in_row: 0,
+            verification: None,
+            tasks_reported: Instant::now(),
+            rating_dialog: None,
+            track_file: None,
+            reload_prompt: None,
+            rating_undo: Vec::new(),
+            seek_prompt: None,
+            tag_editor: None,
+            tag_journal: TagJournal::load(),
+            delete_dialog: None,
+            marked: HashSet::new(),
+            #[cfg(feature = "cover-art")]
+            cover_art,
+            #[cfg(feature = "listen-along")]
+            listen_along_host: None,
+            #[cfg(feature = "listen-along")]
+            listen_along_missing: None,
+            snapshot_dialog: None,
+            zones,
+            zone: 0,
+            startup,
+            detected_glyphs: Glyphs::detect(),
+            areas: Areas::default(),
+            saved_queue: None,
+            queue_saved: Instant::now(),
+        };
+
+        if !instance.args.no_listing {
+            let dir = instance.dir();
+            let tracks = instance.args.tracks.clone();
+            let library = &mut instance.library;
+            instance.startup.time("directory scan", || {
+                let files = if tracks.is_empty() {
+                    library::read_dir(&dir)?
+                } else {
+                    tracks
+                };
+                library.set_files(files, &Mounts::load());
+
+                io::Result::Ok(())
+            })?;
+
+            instance.queue.set_files(instance.library.files().to_vec());
+        }
+
+        instance.playlists = playlist::find(&instance.dir());
+        instance.list_state.select(Some(0));
+
+        if instance.args.remember_selection {
+            instance.load_selections();
+            instance.restore_selection();
+        }
+
+        if instance.args.persist_history {
+            instance.history = History::load();
+        }
+
+        instance.grouping = instance.args.group_by;
+        instance.queue.autoplay.repeat_list = instance.args.repeat_list;
+        instance.queue.autoplay.repeat = instance.args.repeat;
+        // Tracks given on the command line are played in order unless shuffled.
+        instance.queue.autoplay.sequential =
+            instance.args.sequential || !instance.args.tracks.is_empty() && !instance.args.shuffle;
+        instance.queue.autoplay.shuffle = instance.args.shuffle;
+        instance.queue.autoplay.consume = instance.args.consume;
+
+        Ok(instance)
+    }
+
+    /// Handle a notification from the playback thread.
+    /// Returns false if the player should exit.
+    fn handle_playback_event(&mut self, event: PlaybackEvent) -> bool {
+        tracing::debug!("playback event {event:?}");
+        if !self.player.handle_event(&event) {
+            return true;
+        }
+
+        match event {
+            PlaybackEvent::EndOfStream => return self.autoplay(),
+            PlaybackEvent::Error(err) => {
+                self.player.backend.stop();
+
+                // Unmounted network shares fail with unhelpful errors.
+                match self.player.backend.uri() {
+                    Some(uri) if remote::is_remote(&uri) && !remote::is_mounted(&uri) => {
+                        self.request_credentials(uri);
+                    }
+                    // Damaged or vanished files of the list are skipped,
+                    // unless it looks like every track fails.
+                    Some(uri)
+                        if self.queue.index_of(&uri).is_some()
+                            && self.failed_in_row < MAX_FAILED_IN_ROW =>
+                    {
+                        tracing::warn!("skipping {uri}: {err}");
+                        self.show(Level::Warning, format!("Skipped {}: {err}", uri_name(&uri)));
+                        self.failed.insert(uri);
+                        self.failed_in_row += 1;
+
+                        return self.skip_failed();
+                    }
+                    uri => {
+                        self.failed.extend(uri);
+                        self.show(Level::Error, err);
+                        self.player.notify("stop");
+                    }
+                }
+            }
+            PlaybackEvent::Warning(warning) => self.show(Level::Warning, warning),
+            PlaybackEvent::Buffering(percent) => {
+                self.buffering = if percent < 100 { Some(percent) } else { None };
+            }
+            PlaybackEvent::StateChanged(state) => {
+                if state == PlayState::Playing {
+                    self.failed_in_row = 0;
+                    self.auto_paused = false;
+
+                    if self.notice.as_ref().map(|notice| notice.level) == Some(Level::Error) {
+                        self.notice = None;
+                    }
+                }
+
+                self.play_state = state;
+            }
+        }
+
+        true
+    }
+
+    /// Whether the screen changes without any input or playback event,
+    /// e.g. the progress while playing or the countdown of a timer.
+    /// Ticks only redraw while this is the case.
+    fn is_changing(&self) -> bool {
+        self.play_state == PlayState::Playing
+            || self
+                .zones
+                .iter()
+                .any(|zone| zone.state == PlayState::Playing)
+            || self.notice.is_some()
+            || self.library.is_scanning()
+            || self.verification.is_some()
+            || self.resolving.is_some()
+            || self.sleep.is_some()
+            || !self.schedule.is_empty()
+    }
+
+    /// Update the state that isn't driven by events,
+    /// e.g. the listening statistics and gapless transitions.
+    fn update(&mut self) {
+        self.tick();
+        self.run_timers();
+        self.fade_to_silence();
+        self.poll_scan();
+        self.poll_verify();
+        self.report_tasks();
+        self.poll_resolve();
+        self.watchdog();
+
+        if self.notice.as_ref().is_some_and(Notice::is_expired) {
+            self.notice = None;
+        }
+
+        // Previews aren't playback, the track they interrupted stays the current one.
+        self.update_preview();
+        if self.player.is_previewing() {
+            return;
+        }
+
+        // Setting the volume in any other way, e.g. through the web interface, unmutes.
+        if self.muted.is_some() && self.player.volume() > 0.0 {
+            self.muted = None;
+        }
+
+        if self.play_state == PlayState::Playing {
+            self.player.notify_position();
+        }
+
+        #[cfg(feature = "listen-along")]
+        if let Some(host) = &self.listen_along_host {
+            host.publish(SyncState {
+                uri: self.player.current_uri().unwrap_or_default(),
+                position: self.player.backend.position().unwrap_or_default(),
+                playing: self.play_state == PlayState::Playing,
+            });
+        }
+
+        let uri = self.player.current_uri();
+        if uri != self.track_uri {
+            if let Some(previous) = &self.track_uri {
+                if !self.counted {
+                    self.stats.record_skip(previous, self.track_position);
+
+                    if !self.undoing_skip {
+                        let position = self.track_position.unwrap_or_default();
+                        self.skipped.push((previous.clone(), position));
+                        if self.skipped.len() > SKIPPED_TRACKS {
+                            self.skipped.remove(0);
+                        }
+                    }
+                }
+            }
+            self.undoing_skip = false;
+
+            if let Some(i) = self.queue.leave(self.track_uri.as_deref()) {
+                self.keep_selection(i);
+            }
+
+            self.listened = 0.0;
+            self.counted = false;
+            self.player.track_changed(uri.as_deref());
+
+            if self.passthrough && !self.passthrough_pinned {
+                self.passthrough = false;
+                self.player.set_passthrough(false);
+            }
+
+            if let Some(uri) = &uri {
+                tracing::info!("playing {uri}");
+                self.history.push(uri.clone());
+
+                #[cfg(feature = "cover-art")]
+                self.fetch_cover(uri);
+            }
+
+            if self.args.replaygain == ReplayGain::Auto {
+                if let Some(uri) = &uri {
+                    let album = self.in_album(uri);
+                    self.player.set_album_gain(album);
+                }
+            }
+
+            self.track_file = uri.as_deref().and_then(file_identity);
+            self.reload_prompt = None;
+            self.track_uri = uri;
+        }
+
+        self.check_track_file();
+        self.track_position = self.player.backend.position();
+
+        self.prepare_gapless();
+        self.prepare_fades();
+
+        // Keep the upcoming order visible in the queue pane.
+        if self.queue.autoplay.shuffle && self.queue.shuffle.is_empty() {
+            let weights = self.args.shuffle_weights;
+            self.queue.reshuffle(self.track_uri.as_deref(), |file| {
+                shuffle_weight(weights, &self.stats, file)
+            });
+        }
+
+        if self.args.restore_queue {
+            self.autosave_queue();
+        }
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, accent: Color) {
+        let main_style = Style::default().bg(Color::Reset).fg(accent);
+        let focused_style = main_style.fg(Color::Cyan);
+
+        // The notice line takes up the bottom row while there is a message.
+        let notice_height = u16::from(self.notice.is_some());
+        let screen = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(notice_height)])
+            .split(f.size());
+
+        // The chapters pane is only shown for tracks that have chapters
+        // and the timers pane only while timers are pending.
+        let chapters = self.chapters();
+        self.areas = Areas::new(
+            screen[0],
+            self.args.listing_size,
+            &self.args.hidden_panes,
+            !self.schedule.is_empty(),
+            !chapters.is_empty(),
+        );
+        let areas = self.areas.clone();
+
+        // The focused pane can disappear when the terminal is resized.
+        if !self.is_shown(&self.cursor_state) {
+            self.cursor_state = CursorState::MusicList;
+        }
+
+        let listing_size = areas.listing;
+        let status_size = areas.status;
+
+        // Only the visible rows are rendered, so that huge lists draw as fast as short ones.
+        // Collapsed groups are only a header, which is highlighted for all their files.
+        let cache = self.take_listing();
+        let row_count = cache.rows();
+        let row_selected = self.list_state.selected().map(|i| cache.row_of(i));
+        let height = listing_size.height.saturating_sub(2) as usize;
+        let visible = self.listing.scroll(row_selected, row_count, height);
+
+        let rows: Vec<ListItem> = visible
+            .clone()
+            .map(|row| match cache.row(row) {
+                Row::Header(i) => {
+                    let group = &cache.groups[i];
+                    let marker = self.glyphs().get(if cache.is_collapsed(i) {
+                        Symbol::CollapsedGroup
+                    } else {
+                        Symbol::ExpandedGroup
+                    });
+
+                    ListItem::new(format!("{marker} {} ({})", group.title, group.len))
+                        .style(Style::default().add_modifier(Modifier::BOLD))
+                }
+                Row::File(i) => {
+                    let file = &self.queue.files()[i];
+                    let label = self.file_label(file, cache.badges);
+                    if self.marked.contains(file) {
+                        ListItem::new(format!("{} {label}", self.glyphs().get(Symbol::Marked)))
+                    } else {
+                        ListItem::new(label)
+                    }
+                }
+            })
+            .collect();
+
+        let mut visible_state = ListState::default();
+        visible_state.select(
+            row_selected
+                .filter(|row| visible.contains(row))
+                .map(|row| row - visible.start),
+        );
+
+        // The background scan reads the visible files first.
+        if self.library.is_scanning() {
+            self.library
+                .scan_first(visible.rev().filter_map(|row| match cache.row(row) {
+                    Row::File(i) => Some(self.queue.files()[i].clone()),
+                    Row::Header(_) => None,
+                }));
+        }
+
+        let highlight_base_style = match self.cursor_state {
+            CursorState::MusicList => focused_style,
+            _ => main_style,
+        };
+
+        let mut list_name = match self.view {
+            View::All => None,
+            _ => Some(self.playlist_name().to_string()),
+        };
+
+        if let Some(filter) = self.source_filter {
+            let only = match filter {
+                Source::Local => "local only",
+                Source::Network => "NAS only",
+            };
+
+            list_name = Some(match list_name {
+                Some(name) => format!("{name}, {only}"),
+                None => String::from(only),
+            });
+        }
+
+        let summary = if self.library.is_scanning() {
+            format!("{} …", cache.summary)
+        } else {
+            cache.summary.clone()
+        };
+        let summary = match self.marked.len() {
+            0 => summary,
+            n => format!("{summary}, {n} marked"),
+        };
+        let list_title = match list_name {
+            None => format!("Select music — {summary}"),
+            Some(name) => format!("Select music ({name}) — {summary}"),
+        };
+        self.listing.set(cache);
+
+        let block = Block::default().title(list_title).borders(Borders::ALL);
+        let listing = List::new(rows)
+            .block(block)
+            .style(match self.cursor_state {
+                CursorState::MusicList => focused_style,
+                _ => main_style,
+            })
+            .highlight_style(
+                highlight_base_style
+                    .bg(highlight_base_style.fg.unwrap())
+                    .fg(Color::Black),
+            )
+            .highlight_symbol("> ");
+
+        let mut status_title = match &self.track_uri {
+            Some(uri) => String::from("Now playing: ") + &uri_name(uri),
+            None => String::from("Idle"),
+        };
+
+        for status in self.player.extension_status() {
+            status_title += " · ";
+            status_title += &status;
+        }
+
+        if let Some(zone) = self.zone.checked_sub(1).map(|i| &self.zones[i]) {
+            let track = zone.current.as_deref().map(display_name);
+            status_title += &match (zone.state, track) {
+                (PlayState::Paused, Some(track)) => format!(" · {}: paused {track}", zone.name),
+                (_, Some(track)) => format!(" · {}: {track}", zone.name),
+                (_, None) => format!(" · {}: idle", zone.name),
+            };
+        }
+
+        if let Some((_, progress)) = &self.verification {
+            status_title += &format!(" · Verifying {}/{}", progress.done, progress.total);
+        }
+
+        if let Some(remaining) = self.silence_in() {
+            let label = if self.sleep.is_some() {
+                Some("Sleep")
+            } else if remaining <= SILENCE_COUNTDOWN {
+                Some("End of queue")
+            } else {
+                None
+            };
+
+            if let Some(label) = label {
+                let seconds = remaining.as_secs();
+                status_title += &format!(" · {label} in {}:{:02}", seconds / 60, seconds % 60);
+            }
+        }
+
+        let status_block = Block::default()
+            .title(status_title)
+            .borders(Borders::ALL)
+            .style(main_style);
+
+        let (volume_title, volume) = match self.zone.checked_sub(1).map(|i| &self.zones[i]) {
+            Some(zone) => (format!("Volume ({})", zone.name), zone.player.volume()),
+            None => (String::from("Volume"), self.player.volume()),
+        };
+        let volume_label = match self.muted.filter(|_| self.zone == 0) {
+            Some(volume) => format!("Muted ({})", self.volume_label(volume)),
+            None => self.volume_label(volume),
+        };
+
+        let block = Block::default().title(volume_title).borders(Borders::ALL);
+        let volume_gauge = Gauge::default()
+            .block(block)
+            .style(match self.cursor_state {
+                CursorState::Volume => focused_style,
+                _ => main_style,
+            })
+            .gauge_style(main_style.fg(Color::Blue))
+            .label(volume_label)
+            .ratio(self.args.volume_scale.position(volume).clamp(0.0, 1.0));
+
+        let progress_label = match self.player.backend.position() {
+            _ if self.buffering.is_some() => {
+                format!("Buffering {}%", self.buffering.unwrap())
+            }
+            _ if self.play_state == PlayState::Stopped => match self.track_uri {
+                Some(_) => String::from("Stopped"),
+                None => String::from("Idle"),
+            },
+            Some(position) => match self.player.backend.duration() {
+                Some(duration) => {
+                    let pos_m = position.as_secs() / 60;
+                    let pos_s = position.as_secs() - pos_m * 60;
+                    let total_m = duration.as_secs() / 60;
+                    let total_s = duration.as_secs() - total_m * 60;
+
+                    format!("{pos_m}:{pos_s:0>2} / {total_m}:{total_s:0>2}")
+                }
+                None => String::from("-:-- / -:--"),
+            },
+            None => String::from("-:-- / -:--"),
+        };
+
+        let technical_info = match self.track_uri.clone() {
+            Some(uri) => self.technical_info(&uri),
+            None => String::new(),
+        };
+
+        let block = Block::default().title(technical_info).borders(Borders::ALL);
+        let progress_gauge = Gauge::default()
+            .block(block)
+            .style(main_style)
+            .label(progress_label)
+            .gauge_style(main_style.fg(Color::Blue))
+            .ratio(self.player.current_progress());
+
+        let glyphs = self.glyphs();
+        let control_buttons = glyphs.controls(self.is_paused()) + "\n\n";
+
+        let mut control_indicators = String::new();
+        for symbol in self.modes() {
+            control_indicators += &format!(" {} ", glyphs.get(symbol));
+        }
+        if self.queue.repeat_count > 0 {
+            control_indicators += &format!(
+                " {} x{} ",
+                glyphs.get(Symbol::Repeat),
+                self.queue.repeat_count
+            );
+        }
+        if self.muted.is_some() {
+            control_indicators += &format!(" {} ", glyphs.get(Symbol::Muted));
+        }
+        if self.player.gain() != 0.0 {
+            control_indicators += &format!(" {:+.1} dB ", self.player.gain());
+        }
+        if self.passthrough {
+            control_indicators += &if self.passthrough_pinned {
+                format!(" BYPASS {} ", glyphs.get(Symbol::Pinned))
+            } else {
+                String::from(" BYPASS ")
+            };
+        }
+
+        let block = Block::default().borders(Borders::ALL);
+        let control_paragraph = Paragraph::new(control_buttons + &control_indicators)
+            .block(block)
+            .alignment(Alignment::Center)
+            .style(match self.cursor_state {
+                CursorState::Control => focused_style,
+                _ => main_style,
+            });
+
+        let block = Block::default().borders(Borders::ALL);
+        let search_label = if self.search_regex {
+            "Search (regex)"
+        } else {
+            "Search"
+        };
+        let search_paragraph = Paragraph::new(format!("{search_label}: {}", self.search))
+            .block(block)
+            .alignment(Alignment::Left)
+            .style(match self.cursor_state {
+                CursorState::Search => focused_style,
+                _ => main_style,
+            });
+
+        let history: Vec<ListItem> = self
+            .history
+            .iter()
+            .rev()
+            .map(|uri| ListItem::new(uri_name(uri)))
+            .collect();
+
+        let history_base_style = match self.cursor_state {
+            CursorState::History => focused_style,
+            _ => main_style,
+        };
+
+        let block = Block::default().title("History").borders(Borders::ALL);
+        let history_list = List::new(history)
+            .block(block)
+            .style(history_base_style)
+            .highlight_style(
+                history_base_style
+                    .bg(history_base_style.fg.unwrap())
+                    .fg(Color::Black),
+            )
+            .highlight_symbol("> ");
+
+        let listing_scrollbar =
+            Scrollbar::new(row_selected.unwrap_or_default(), row_count).style(highlight_base_style);
+
+        let zone = self.zone.checked_sub(1).map(|i| &self.zones[i]);
+        // Queued files play before the shuffle order goes on.
+        let queue: Vec<ListItem> = match zone {
+            Some(zone) => zone
+                .queue
+                .iter()
+                .map(|file| ListItem::new(display_name(file)))
+                .collect(),
+            None if self.queue.autoplay.shuffle => self
+                .queue
+                .up_next
+                .iter()
+                .chain(self.queue.shuffle.upcoming())
+                .map(|file| ListItem::new(display_name(file)))
+                .collect(),
+            None => self
+                .queue
+                .up_next
+                .iter()
+                .map(|file| ListItem::new(display_name(file)))
+                .collect(),
+        };
+        let queue_length = queue.len();
+
+        let queue_title = match zone {
+            Some(zone) => format!("Queue ({})", zone.name),
+            None => String::from("Queue"),
+        };
+        let block = Block::default().title(queue_title).borders(Borders::ALL);
+        let queue_list = List::new(queue).block(block).style(main_style);
+        let queue_scrollbar = Scrollbar::new(0, queue_length).style(main_style);
+
+        let playlists_base_style = match self.cursor_state {
+            CursorState::Playlists => focused_style,
+            _ => main_style,
+        };
+
+        let playlists: Vec<ListItem> = self
+            .playlists
+            .iter()
+            .enumerate()
+            .map(|(i, playlist)| {
+                let marker = if self.view == View::Playlist(i) {
+                    "*"
+                } else {
+                    " "
+                };
+                let mix = match self.mix.iter().find(|(path, _)| *path == playlist.path) {
+                    Some((_, weight)) => format!(" ×{weight}"),
+                    None => String::new(),
+                };
+
+                ListItem::new(format!(
+                    "{marker} {} ({}){mix}",
+                    playlist.name,
+                    playlist.entries.len()
+                ))
+            })
+            .collect();
+
+        let block = Block::default().title("Playlists").borders(Borders::ALL);
+        let playlists_list = List::new(playlists)
+            .block(block)
+            .style(playlists_base_style)
+            .highlight_style(
+                playlists_base_style
+                    .bg(playlists_base_style.fg.unwrap())
+                    .fg(Color::Black),
+            )
+            .highlight_symbol("> ");
+        let playlists_scrollbar = Scrollbar::new(
+            self.playlist_state.selected().unwrap_or_default(),
+            self.playlists.len(),
+        )
+        .style(playlists_base_style);
+
+        let history_scrollbar = Scrollbar::new(
+            self.history_state.selected().unwrap_or_default(),
+            self.history.len(),
+        )
+        .style(history_base_style);
+
+        f.render_stateful_widget(listing, listing_size, &mut visible_state);
+        f.render_widget(listing_scrollbar, listing_size);
+
+        if let Some(uri) = self.track_uri.as_ref().filter(|_| self.track_stats) {
+            let stats = self.stats.get(uri).cloned().unwrap_or_default();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+
+            let plays = stats.plays;
+            let played = match stats.last_played.map(|t| now.saturating_sub(t) / 86400) {
+                None => String::from("Never played before"),
+                Some(0) => format!("Played {plays} times, last today"),
+                Some(1) => format!("Played {plays} times, last yesterday"),
+                Some(days) => format!("Played {plays} times, last {days} days ago"),
+            };
+            let skips = match stats.usual_skip_point() {
+                Some(at) => format!(
+                    "Skipped {} times, usually at {}:{:02}",
+                    stats.skips,
+                    at.as_secs() / 60,
+                    at.as_secs() % 60
+                ),
+                None => format!("Skipped {} times", stats.skips),
+            };
+
+            let text = format!("{played}\n{skips}");
+            let block = Block::default()
+                .title("Track stats (I to hide)")
+                .borders(Borders::ALL);
+            let stats_paragraph = Paragraph::new(text).block(block).style(main_style);
+
+            // Bottom right corner of the listing, inside its border.
+            let width = 44.min(listing_size.width.saturating_sub(2));
+            let height = 4.min(listing_size.height.saturating_sub(2));
+            let stats_size = Rect::new(
+                listing_size.right().saturating_sub(width + 1),
+                listing_size.bottom().saturating_sub(height + 1),
+                width,
+                height,
+            );
+
+            f.render_widget(Clear, stats_size);
+            f.render_widget(stats_paragraph, stats_size);
+        }
+        f.render_widget(status_block, status_size);
+        if let Some(area) = areas.volume {
+            f.render_widget(volume_gauge, area);
+        }
+        if let Some(progress_size) = areas.progress {
+            f.render_widget(progress_gauge, progress_size);
+        }
+        let duration = self.player.backend.duration().filter(|d| !d.is_zero());
+        if let (Some(duration), Some(progress_size)) = (duration, areas.progress) {
+            // Mark the chapter starts on the gauge, except the one at the very beginning.
+            let inner = Block::default().borders(Borders::ALL).inner(progress_size);
+            for chapter in chapters.iter().filter(|chapter| !chapter.start.is_zero()) {
+                let ratio = chapter.start.as_secs_f64() / duration.as_secs_f64();
+                if ratio < 1.0 {
+                    let x = inner.x + (ratio * f64::from(inner.width)) as u16;
+                    let tick = Rect::new(x, inner.y, 1, inner.height.min(1));
+                    f.render_widget(Paragraph::new("|").style(main_style), tick);
+                }
+            }
+        }
+        if let Some(area) = areas.controls {
+            f.render_widget(control_paragraph, area);
+        }
+        if let Some(area) = areas.search {
+            f.render_widget(search_paragraph, area);
+        }
+        if let Some(area) = areas.queue {
+            f.render_widget(queue_list, area);
+            f.render_widget(queue_scrollbar, area);
+        }
+        if let Some(area) = areas.playlists {
+            f.render_stateful_widget(playlists_list, area, &mut self.playlist_state);
+            f.render_widget(playlists_scrollbar, area);
+        }
+
+        if let Some(area) = areas.timers {
+            let timers_base_style = match self.cursor_state {
+                CursorState::Timers => focused_style,
+                _ => main_style,
+            };
+
+            let timers: Vec<ListItem> = self
+                .schedule
+                .iter()
+                .map(|timer| {
+                    ListItem::new(format!(
+                        "{} {}",
+                        schedule::format_time(timer.at),
+                        display_name(&timer.path)
+                    ))
+                })
+                .collect();
+
+            if self.timer_state.selected() >= Some(timers.len()) {
+                self.timer_state.select(timers.len().checked_sub(1));
+            }
+
+            let title = if self.hold_for_timers {
+                "Timers (holding)"
+            } else {
+                "Timers"
+            };
+
+            let block = Block::default().title(title).borders(Borders::ALL);
+            let timers_list = List::new(timers)
+                .block(block)
+                .style(timers_base_style)
+                .highlight_style(
+                    timers_base_style
+                        .bg(timers_base_style.fg.unwrap())
+                        .fg(Color::Black),
+                )
+                .highlight_symbol("> ");
+
+            f.render_stateful_widget(timers_list, area, &mut self.timer_state);
+        }
+        if let Some(area) = areas.history {
+            f.render_stateful_widget(history_list, area, &mut self.history_state);
+            f.render_widget(history_scrollbar, area);
+        }
+
+        if let Some(area) = areas.chapters {
+            let chapter_base_style = match self.cursor_state {
+                CursorState::Chapters => focused_style,
+                _ => main_style,
+            };
+
+            let playing = self
+                .player
+                .backend
+                .position()
+                .and_then(|position| current_chapter(&chapters, position));
+
+            let items: Vec<ListItem> = chapters
+                .iter()
+                .enumerate()
+                .map(|(i, chapter)| {
+                    let start = chapter.start.as_secs();
+                    let marker = if playing == Some(i) { "*" } else { " " };
+                    let title = match &chapter.title {
+                        Some(title) => title.clone(),
+                        None => format!("Chapter {}", i + 1),
+                    };
+
+                    ListItem::new(format!(
+                        "{marker} {}:{:0>2}:{:0>2} {title}",
+                        start / 3600,
+                        start / 60 % 60,
+                        start % 60
+                    ))
+                })
+                .collect();
+
+            if self.chapter_state.selected() >= Some(chapters.len()) {
+                self.chapter_state.select(None);
+            }
+
+            let block = Block::default().title("Chapters").borders(Borders::ALL);
+            let chapter_list = List::new(items)
+                .block(block)
+                .style(chapter_base_style)
+                .highlight_style(
+                    chapter_base_style
+                        .bg(chapter_base_style.fg.unwrap())
+                        .fg(Color::Black),
+                )
+                .highlight_symbol("> ");
+            let chapter_scrollbar = Scrollbar::new(
+                self.chapter_state.selected().unwrap_or_default(),
+                chapters.len(),
+            )
+            .style(chapter_base_style);
+
+            f.render_stateful_widget(chapter_list, area, &mut self.chapter_state);
+            f.render_widget(chapter_scrollbar, area);
+        }
+
+        if let Some(notice) = &self.notice {
+            let notice_paragraph = Paragraph::new(notice.text()).style(notice.style(main_style));
+            f.render_widget(notice_paragraph, screen[1]);
+        }
+
+        if let Some(prompt) = &self.credential_prompt {
+            let field = |i: usize| if prompt.field == i { "> " } else { "  " };
+            let text = format!(
+                "{}User: {}\n{}Password: {}\n{}[{}] Save in keyring",
+                field(0),
+                prompt.username,
+                field(1),
+                "*".repeat(prompt.password.chars().count()),
+                field(2),
+                if prompt.save { "x" } else { " " },
+            );
+
+            let block = Block::default()
+                .title(format!("Log in to {}", remote::share(&prompt.uri)))
+                .borders(Borders::ALL);
+            let prompt_paragraph = Paragraph::new(text).block(block).style(focused_style);
+
+            let prompt_size = centered(f.size(), 50, 5);
+            f.render_widget(Clear, prompt_size);
+            f.render_widget(prompt_paragraph, prompt_size);
+        }
+
+        if let Some(editor) = &self.tag_editor {
+            let mut text: Vec<String> = TAG_FIELDS
+                .iter()
+                .zip(&editor.fields)
+                .enumerate()
+                .map(|(i, (label, value))| {
+                    let cursor = if editor.field == i { "> " } else { "  " };
+                    format!("{cursor}{label}: {value}")
+                })
+                .collect();
+
+            let block = Block::default()
+                .title(match editor.paths.as_slice() {
+                    [path] => format!("Edit {} (Enter to save, Esc to cancel)", display_name(path)),
+                    paths => format!(
+                        "Edit {} tracks, empty fields are kept (Enter to save, Esc to cancel)",
+                        paths.len()
+                    ),
+                })
+                .borders(Borders::ALL);
+            if let Some(template) = &self.args.rename_template {
+                text.push(editor.rename_preview(template));
+            }
+            let height = text.len() as u16 + 2;
+            let editor_paragraph = Paragraph::new(text.join("\n"))
+                .block(block)
+                .style(focused_style);
+
+            let area = f.size();
+            let editor_size = centered(area, area.width.saturating_sub(8).min(80), height);
+            f.render_widget(Clear, editor_size);
+            f.render_widget(editor_paragraph, editor_size);
+        }
+
+        if let Some((path, time)) = &self.timer_prompt {
+            let block = Block::default()
+                .title(format!(
+                    "Start {} at (Enter to schedule, Esc to cancel)",
+                    display_name(path)
+                ))
+                .borders(Borders::ALL);
+            let time_paragraph = Paragraph::new(format!("Time: {time}"))
+                .block(block)
+                .style(focused_style);
+
+            let area = f.size();
+            let prompt_size = centered(area, area.width.saturating_sub(8).min(80), 3);
+            f.render_widget(Clear, prompt_size);
+            f.render_widget(time_paragraph, prompt_size);
+        }
+
+        if let Some(url) = &self.url_prompt {
+            let block = Block::default()
+                .title("Open URL (Enter to play, Esc to cancel)")
+                .borders(Borders::ALL);
+            let url_paragraph = Paragraph::new(format!("URL: {url}"))
+                .block(block)
+                .style(focused_style);
+
+            let area = f.size();
+            let prompt_size = centered(area, area.width.saturating_sub(8).min(80), 3);
+            f.render_widget(Clear, prompt_size);
+            f.render_widget(url_paragraph, prompt_size);
+        }
+
+        if let Some(dialog) = &self.playlist_dialog {
+            let (title, name) = match dialog {
+                PlaylistDialog::Create { name } => {
+                    ("New playlist (Enter to create, Esc to cancel)", name)
+                }
+                PlaylistDialog::Rename { name } => {
+                    ("Rename playlist (Enter to rename, Esc to cancel)", name)
+                }
+                PlaylistDialog::Mix { name } => {
+                    ("Mix playlists (Enter to save, Esc to cancel)", name)
+                }
+            };
+
+            let block = Block::default().title(title).borders(Borders::ALL);
+            let name_paragraph = Paragraph::new(format!("Name: {name}"))
+                .block(block)
+                .style(focused_style);
+
+            let dialog_size = centered(f.size(), 50, 3);
+            f.render_widget(Clear, dialog_size);
+            f.render_widget(name_paragraph, dialog_size);
+        }
+
+        match &mut self.snapshot_dialog {
+            Some(SnapshotDialog::Save { name }) => {
+                let block = Block::default()
+                    .title("Save snapshot (Enter to save, Esc to cancel)")
+                    .borders(Borders::ALL);
+                let name_paragraph = Paragraph::new(format!("Name: {name}"))
+                    .block(block)
+                    .style(focused_style);
+
+                let dialog_size = centered(f.size(), 50, 3);
+                f.render_widget(Clear, dialog_size);
+                f.render_widget(name_paragraph, dialog_size);
+            }
+            Some(SnapshotDialog::Restore { state }) => {
+                let snapshots: Vec<ListItem> = self
+                    .snapshots
+                    .iter()
+                    .map(|snapshot| {
+                        let track = snapshot.uri.as_deref().map(uri_name).unwrap_or_default();
+                        let position = snapshot.position.as_secs();
+                        ListItem::new(format!(
+                            "{} — {track} {}:{:0>2}",
+                            snapshot.name,
+                            position / 60,
+                            position % 60
+                        ))
+                    })
+                    .collect();
+
+                let block = Block::default()
+                    .title("Restore snapshot (Enter to restore, Delete to remove)")
+                    .borders(Borders::ALL);
+                let snapshot_list = List::new(snapshots)
+                    .block(block)
+                    .style(focused_style)
+                    .highlight_style(focused_style.bg(Color::Cyan).fg(Color::Black))
+                    .highlight_symbol("> ");
+
+                let area = f.size();
+                let dialog_size = centered(
+                    area,
+                    area.width.saturating_sub(8).min(80),
+                    (self.snapshots.len() as u16 + 2).clamp(3, area.height.saturating_sub(2)),
+                );
+                f.render_widget(Clear, dialog_size);
+                f.render_stateful_widget(snapshot_list, dialog_size, state);
+            }
+            None => {}
+        }
+
+        if let Some(target) = &self.seek_prompt {
+            let block = Block::default()
+                .title("Seek to (e.g. 12:34 or 45%, Enter to seek, Esc to cancel)")
+                .borders(Borders::ALL);
+            let prompt_paragraph = Paragraph::new(format!("Position: {target}"))
+                .block(block)
+                .style(focused_style);
+
+            let prompt_size = centered(f.size(), 64, 3);
+            f.render_widget(Clear, prompt_size);
+            f.render_widget(prompt_paragraph, prompt_size);
+        }
+
+        if let Some(dialog) = &self.rating_dialog {
+            let block = Block::default()
+                .title("Rate matching tracks (Enter to apply, Esc to cancel)")
+                .borders(Borders::ALL);
+            let action = match dialog.rating {
+                0 => String::from("Remove the ratings of"),
+                rating => format!("Rate {} for", ratings::stars(rating)),
+            };
+            let matching = match self.search.as_str() {
+                "" => String::from("in the list"),
+                search => format!("matching \"{search}\""),
+            };
+            let text = format!(
+                "{action} {} tracks {matching}?\n0-{}: change the rating",
+                dialog.files.len(),
+                ratings::MAX
+            );
+            let dialog_paragraph = Paragraph::new(text).block(block).style(focused_style);
+
+            let dialog_size = centered(f.size(), 60, 4);
+            f.render_widget(Clear, dialog_size);
+            f.render_widget(dialog_paragraph, dialog_size);
+        }
+
+        if let Some(dialog) = &self.delete_dialog {
+            let block = Block::default()
+                .title("Delete marked files (Enter to delete, Esc to cancel)")
+                .borders(Borders::ALL);
+            let text = format!(
+                "Delete {} files from the disk? This can't be undone.",
+                dialog.files.len()
+            );
+            let dialog_paragraph = Paragraph::new(text).block(block).style(focused_style);
+
+            let dialog_size = centered(f.size(), 60, 3);
+            f.render_widget(Clear, dialog_size);
+            f.render_widget(dialog_paragraph, dialog_size);
+        }
+
+        if let Some(uri) = &self.reload_prompt {
+            let block = Block::default()
+                .title("File changed (Enter to reload, Esc to keep playing)")
+                .borders(Borders::ALL);
+            let text = format!(
+                "{} was changed on disk.\nReload it at the current position?",
+                uri_name(uri)
+            );
+            let prompt_paragraph = Paragraph::new(text).block(block).style(focused_style);
+
+            let prompt_size = centered(f.size(), 60, 4);
+            f.render_widget(Clear, prompt_size);
+            f.render_widget(prompt_paragraph, prompt_size);
+        }
+
+        if let Some(help) = &self.help {
+            let title = if help.search.is_empty() {
+                String::from("Manual (F1 to close)")
+            } else {
+                format!("Manual: /{}", help.search)
+            };
+
+            let block = Block::default().title(title).borders(Borders::ALL);
+            let help_paragraph = Paragraph::new(help.text(main_style))
+                .block(block)
+                .style(focused_style);
+            let help_scrollbar = Scrollbar::new(help.scroll, Help::len()).style(focused_style);
+
+            let area = f.size();
+            let help_size = centered(
+                area,
+                area.width.saturating_sub(8).min(80),
+                area.height.saturating_sub(2),
+            );
+            f.render_widget(Clear, help_size);
+            f.render_widget(help_paragraph, help_size);
+            f.render_widget(help_scrollbar, help_size);
+        }
+    }
+
+    /// Open the tag editor for files, filled in with the tags they have in common.
+    fn edit_tags(&mut self, paths: Vec<PathBuf>) {
+        let mut fields: Option<[String; 5]> = None;
+        let mut current = Vec::new();
+        for path in &paths {
+            let tags = self.library.read_tags(&file_uri(path)).clone();
+            let values = [
+                tags.title.clone().unwrap_or_default(),
+                tags.artist.clone().unwrap_or_default(),
+                tags.album.clone().unwrap_or_default(),
+                tags.track_number.map(|n| n.to_string()).unwrap_or_default(),
+                tags.genre.clone().unwrap_or_default(),
+            ];
+            current.push(tags);
+
+            match &mut fields {
+                Some(fields) => {
+                    for (field, value) in fields.iter_mut().zip(values) {
+                        if *field != value {
+                            field.clear();
+                        }
+                    }
+                }
+                None => fields = Some(values),
+            }
+        }
+
+        if let Some(fields) = fields {
+            self.tag_editor = Some(TagEditor {
+                paths,
+                current,
+                fields,
+                field: 0,
+            });
+        }
+    }
+
+    /// Handle a key press while the tag editor is open.
+    fn tag_editor_key(&mut self, code: KeyCode) {
+        let editor = match &mut self.tag_editor {
+            Some(editor) => editor,
+            None => return,
+        };
+
+        let n = TAG_FIELDS.len();
+        match code {
+            KeyCode::Esc => self.tag_editor = None,
+            KeyCode::Tab | KeyCode::Down => editor.field = (editor.field + 1) % n,
+            KeyCode::BackTab | KeyCode::Up => editor.field = (editor.field + n - 1) % n,
+            KeyCode::Char(c) => editor.fields[editor.field].push(c),
+            KeyCode::Backspace => {
+                editor.fields[editor.field].pop();
+            }
+            KeyCode::Enter => {
+                let tags = match editor.tags() {
+                    Ok(tags) => tags,
+                    Err(e) => {
+                        self.show(Level::Warning, e);
+                        return;
+                    }
+                };
+
+                // Files that can't be named after their tags keep their name.
+                let names: HashMap<PathBuf, String> = match &self.args.rename_template {
+                    Some(template) => (0..editor.paths.len())
+                        .filter_map(|i| {
+                            let name = editor.file_name(i, template).ok()?;
+                            Some((editor.paths[i].clone(), name))
+                        })
+                        .collect(),
+                    None => HashMap::new(),
+                };
+
+                let paths = std::mem::take(&mut editor.paths);
+                self.tag_editor = None;
+
+                let writes = paths.iter().map(|path| (path.clone(), tags.clone()));
+                let edit = self.write_tags(writes.collect());
+                let saved = edit.len();
+                let renames: Vec<(PathBuf, &String)> = edit
+                    .iter()
+                    .filter_map(|file| Some((file.path.clone(), names.get(&file.path)?)))
+                    .collect();
+                self.record_tag_edit(edit);
+
+                let renamed = renames
+                    .into_iter()
+                    .filter(|(path, name)| self.rename_file(path, name))
+                    .count();
+
+                if renamed > 0 {
+                    self.reload_library();
+                }
+
+                if saved == paths.len() {
+                    let text = match paths.as_slice() {
+                        [path] => format!("Saved tags of {}", display_name(path)),
+                        paths => format!("Saved tags of {} tracks", paths.len()),
+                    };
+                    let text = match renamed {
+                        0 => format!("{text}, Ctrl+Z to undo"),
+                        n => format!("{text} and renamed {n}, Ctrl+Z to undo the tags"),
+                    };
+                    self.show(Level::Info, text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Write tags to files, reporting the ones that fail.
+    /// Returns what was written to the others along with the tags they had before.
+    fn write_tags(&mut self, writes: Vec<(PathBuf, Tags)>) -> Vec<FileEdit> {
+        let mut edit = Vec::new();
+        for (path, tags) in writes {
+            let uri = file_uri(&path);
+            let before = self.library.read_tags(&uri).clone();
+
+            match tags::write(&path, &tags) {
+                Ok(()) => {
+                    // Read the tags again, they may have been normalized.
+                    self.library.forget_tags(&uri);
+                    edit.push(FileEdit::new(path, &before, tags));
+                }
+                Err(e) => {
+                    let text = format!("can't save tags of {}: {e}", display_name(&path));
+                    self.show(Level::Error, text);
+                }
+            }
+        }
+
+        if !edit.is_empty() {
+            self.listing.invalidate();
+            self.marked.clear();
+            // Saving the current track isn't a change worth reloading for.
+            if let Some(uri) = self.track_uri.as_deref() {
+                self.track_file = file_identity(uri);
+            }
+        }
+
+        edit
+    }
+
+    /// Rename a file within its directory, unless it's playing or the name is taken.
+    /// Returns whether it was renamed.
+    fn rename_file(&mut self, path: &Path, name: &str) -> bool {
+        let to = path.with_file_name(name);
+        if to == path {
+            return false;
+        }
+
+        // The current track would go missing, as if it had been replaced.
+        if self.player.current_uri().as_deref() == Some(file_uri(path).as_str()) {
+            let text = format!("kept the name of {}, it's playing", display_name(path));
+            self.show(Level::Warning, text);
+            return false;
+        }
+
+        if to.exists() {
+            let text = format!("can't rename {}: {name} exists", display_name(path));
+            self.show(Level::Warning, text);
+            return false;
+        }
+
+        match fs::rename(path, &to) {
+            Ok(()) => {
+                tracing::info!("renamed {} to {name}", path.display());
+                self.library.forget_tags(&file_uri(path));
+                self.tag_journal.rename(path, &to);
+                self.save_tag_journal();
+                true
+            }
+            Err(e) => {
+                let text = format!("can't rename {}: {e}", display_name(path));
+                self.show(Level::Warning, text);
+                false
+            }
+        }
+    }
+
+    /// Add written tags to the journal so that they can be undone.
+    fn record_tag_edit(&mut self, edit: Vec<FileEdit>) {
+        if edit.is_empty() {
+            return;
+        }
+
+        self.tag_journal.record(edit);
+        self.save_tag_journal();
+    }
+
+    fn save_tag_journal(&mut self) {
+        if let Err(e) = self.tag_journal.save() {
+            self.show(Level::Warning, format!("can't save tag journal: {e}"));
+        }
+    }
+
+    /// Restore the tags the files of the last tag edit had before.
+    fn undo_tag_edit(&mut self) {
+        let edit = match self.tag_journal.undo() {
+            Some(edit) => edit.to_vec(),
+            None => {
+                self.show(Level::Info, "No tag edit to undo");
+                return;
+            }
+        };
+        self.save_tag_journal();
+
+        let writes = edit
+            .iter()
+            .map(|file| (file.path.clone(), tag_journal::restoring(&file.before)));
+        let restored = self.write_tags(writes.collect()).len();
+
+        if restored == edit.len() {
+            let text = format!("Undid tag edit of {restored} tracks, Ctrl+Y to redo");
+            self.show(Level::Info, text);
+        }
+    }
+
+    /// Write the tags of the last undone tag edit again.
+    fn redo_tag_edit(&mut self) {
+        let edit = match self.tag_journal.redo() {
+            Some(edit) => edit.to_vec(),
+            None => {
+                self.show(Level::Info, "No tag edit to redo");
+                return;
+            }
+        };
+        self.save_tag_journal();
+
+        let writes = edit.into_iter().map(|file| (file.path, file.after));
+        let total = writes.len();
+        let redone = self.write_tags(writes.collect()).len();
+
+        if redone == total {
+            self.show(Level::Info, format!("Redid tag edit of {redone} tracks"));
+        }
+    }
+
+    /// Give the marked files, or the selected one, the tags they had before they were first edited.
+    /// Reverting is a tag edit itself, so it can be undone as well.
+    fn revert_tags(&mut self) {
+        let files = self.marked_or_selected();
+        let writes: Vec<(PathBuf, Tags)> = files
+            .iter()
+            .filter_map(|file| {
+                let original = self.tag_journal.original(file)?;
+                Some((file.clone(), tag_journal::restoring(original)))
+            })
+            .collect();
+
+        if writes.is_empty() {
+            self.show(
+                Level::Info,
+                "No original tags recorded, the tracks weren't edited",
+            );
+            return;
+        }
+
+        let total = writes.len();
+        let edit = self.write_tags(writes);
+        let reverted = edit.len();
+        self.record_tag_edit(edit);
+
+        if reverted == total {
+            let text = format!("Reverted {reverted} tracks to their original tags");
+            self.show(Level::Info, text);
+        }
+    }
+
+    /// Offer to reload the current track if its file was modified or replaced,
+    /// e.g. downloaded again in a better quality. The decoder would go on
+    /// reading stale data, or fail when seeking.
+    fn check_track_file(&mut self) {
+        let uri = match &self.track_uri {
+            Some(uri) => uri,
+            None => return,
+        };
+
+        // Missing while it is replaced, or still being written.
+        let identity = match file_identity(uri) {
+            Some((ino, modified)) if modified.elapsed().unwrap_or_default() >= FILE_SETTLE_TIME => {
+                (ino, modified)
+            }
+            _ => return,
+        };
+
+        if self.track_file != Some(identity) {
+            if self.track_file.is_some() {
+                tracing::info!("{uri} changed during playback");
+                self.reload_prompt = Some(uri.clone());
+            }
+
+            self.track_file = Some(identity);
+        }
+    }
+
+    /// Handle a key press while the reload of a changed track is offered.
+    fn reload_prompt_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.reload_prompt = None,
+            KeyCode::Enter => {
+                if let Some(uri) = self.reload_prompt.take() {
+                    let position = self.player.backend.position().unwrap_or_default();
+                    self.library.forget_tags(&uri);
+                    self.listing.invalidate();
+                    self.player.play_uri_at(&uri, position);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle a key press while the snapshot dialog is open.
+    fn snapshot_key(&mut self, code: KeyCode) {
+        let dialog = match &mut self.snapshot_dialog {
+            Some(dialog) => dialog,
+            None => return,
+        };
+
+        match (dialog, code) {
+            (_, KeyCode::Esc) => self.snapshot_dialog = None,
+            (SnapshotDialog::Save { name }, KeyCode::Char(c)) => name.push(c),
+            (SnapshotDialog::Save { name }, KeyCode::Backspace) => {
+                name.pop();
+            }
+            (SnapshotDialog::Save { name }, KeyCode::Enter) if !name.trim().is_empty() => {
+                let name = name.trim().to_string();
+                self.snapshot_dialog = None;
+                self.save_snapshot(name);
+            }
+            (SnapshotDialog::Restore { state }, KeyCode::Up) => {
+                let i = state.selected().unwrap_or_default();
+                state.select(Some(i.saturating_sub(1)));
+            }
+            (SnapshotDialog::Restore { state }, KeyCode::Down) => {
+                let i = state.selected().unwrap_or_default();
+                state.select(Some((i + 1).min(self.snapshots.len().saturating_sub(1))));
+            }
+            (SnapshotDialog::Restore { state }, KeyCode::Enter) => {
+                if let Some(snapshot) = state.selected().and_then(|i| self.snapshots.get(i)) {
+                    let snapshot = snapshot.clone();
+                    self.snapshot_dialog = None;
+                    self.restore_snapshot(&snapshot);
+                    self.show(Level::Info, format!("Restored snapshot {}", snapshot.name));
+                }
+            }
+            (SnapshotDialog::Restore { state }, KeyCode::Delete) => {
+                if let Some(snapshot) = state.selected().and_then(|i| self.snapshots.get(i)) {
+                    let name = snapshot.name.clone();
+                    self.snapshots.remove(&name);
+                    state.select(self.snapshots.len().checked_sub(1));
+
+                    if let Err(e) = self.snapshots.save() {
+                        self.show(Level::Warning, format!("can't save snapshots: {e}"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle a key press while the rating confirmation is open.
+    fn rating_key(&mut self, code: KeyCode) {
+        let dialog = match &mut self.rating_dialog {
+            Some(dialog) => dialog,
+            None => return,
+        };
+
+        match code {
+            KeyCode::Esc => self.rating_dialog = None,
+            KeyCode::Char(c) => {
+                if let Some(rating) = c.to_digit(10).filter(|&n| n <= u32::from(ratings::MAX)) {
+                    dialog.rating = rating as u8;
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(dialog) = self.rating_dialog.take() {
+                    self.rate(&dialog.files, dialog.rating);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Rate all files at once, remembering their previous ratings for undo.
+    fn rate(&mut self, files: &[PathBuf], rating: u8) {
+        self.rating_undo = files
+            .iter()
+            .map(|file| {
+                let uri = file_uri(file);
+                let previous = self.ratings.set(&uri, Some(rating));
+                (uri, previous)
+            })
+            .collect();
+
+        if let Err(e) = self.ratings.save() {
+            self.show(Level::Warning, format!("can't save ratings: {e}"));
+            return;
+        }
+
+        self.show(
+            Level::Info,
+            format!("Rated {} tracks, u to undo", self.rating_undo.len()),
+        );
+    }
+
+    /// Restore the ratings from before the last batch rating.
+    fn undo_rating(&mut self) {
+        if self.rating_undo.is_empty() {
+            self.show(Level::Info, "Nothing to undo");
+            return;
+        }
+
+        let undo = std::mem::take(&mut self.rating_undo);
+        for (uri, rating) in &undo {
+            self.ratings.set(uri, *rating);
+        }
+
+        match self.ratings.save() {
+            Ok(()) => self.show(Level::Info, format!("Restored {} ratings", undo.len())),
+            Err(e) => self.show(Level::Warning, format!("can't save ratings: {e}")),
+        }
+    }
+
+    /// Get the files of the list that match the search, or all of them if it's empty.
+    /// An invalid regular expression is reported and matches nothing.
+    fn search_matches(&mut self) -> Vec<PathBuf> {
+        let matcher = match self.search_matcher() {
+            Ok(matcher) => matcher,
+            Err(()) => return Vec::new(),
+        };
+
+        self.queue
+            .files()
+            .to_vec()
+            .into_iter()
+            .filter(|file| self.matches_search(file, &matcher))
+            .collect()
+    }
+
+    /// Compile the search in regex mode, reporting invalid patterns in the status bar,
+    /// or look the files matching a plain search up in the index.
+    fn search_matcher(&mut self) -> Result<SearchMatcher, ()> {
+        if !self.search_regex {
+            if self.search.trim().is_empty() {
+                return Ok(SearchMatcher::All);
+            }
+
+            return match self.library.search(self.queue.files(), &self.search) {
+                Ok(uris) => Ok(SearchMatcher::Uris(uris)),
+                Err(e) => {
+                    self.show(Level::Warning, format!("Search failed: {e}"));
+                    Err(())
+                }
+            };
+        }
+
+        match search::pattern(&self.search) {
+            Ok(regex) => Ok(SearchMatcher::Regex(regex)),
+            Err(e) => {
+                self.show(Level::Warning, format!("Invalid search pattern: {e}"));
+                Err(())
+            }
+        }
+    }
+
+    /// Check whether a file matches the search. A regular expression is matched
+    /// against the path and the title, artist, album and genre.
+    fn matches_search(&mut self, file: &Path, matcher: &SearchMatcher) -> bool {
+        let regex = match matcher {
+            SearchMatcher::All => return true,
+            SearchMatcher::Uris(uris) => return uris.contains(&file_uri(file)),
+            SearchMatcher::Regex(regex) => regex,
+        };
+
+        let path = file.to_string_lossy();
+        if regex.is_match(&path) {
+            return true;
+        }
+
+        let tags = self.library.tags(&file_uri(file));
+        [tags.title, tags.artist, tags.album, tags.genre]
+            .into_iter()
+            .flatten()
+            .any(|field| regex.is_match(&field))
+    }
+
+    /// Save the current track, position, modes, volume and queue as a named snapshot.
+    fn snapshot(&self, name: String) -> Snapshot {
+        Snapshot {
+            name,
+            uri: self.track_uri.clone(),
+            position: self.player.backend.position().unwrap_or_default(),
+            volume: self.player.volume(),
+            autoplay: self.queue.autoplay.clone(),
+            playlist: self.playlist_name().to_string(),
+            upcoming: self.queue.shuffle.upcoming().cloned().collect(),
+        }
+    }
+
+    fn save_snapshot(&mut self, name: String) {
+        self.snapshots.insert(self.snapshot(name.clone()));
+        match self.snapshots.save() {
+            Ok(()) => self.show(Level::Info, format!("Saved snapshot {name}")),
+            Err(e) => self.show(Level::Warning, format!("can't save snapshots: {e}")),
+        }
+    }
+
+    /// Return to the state saved in a snapshot.
+    fn restore_snapshot(&mut self, snapshot: &Snapshot) {
+        // Smart playlists may have been renamed since, the directory is the fallback.
+        let n = self.args.smart_playlists.len();
+        let view = [
+            View::All,
+            View::MostPlayed,
+            View::RecentlyAdded,
+            View::NeverPlayed,
+        ]
+        .into_iter()
+        .chain((0..n).map(View::Smart))
+        .chain((0..self.playlists.len()).map(View::Playlist))
+        .find(|&view| {
+            let current = std::mem::replace(&mut self.view, view);
+            let found = self.playlist_name() == snapshot.playlist;
+            self.view = current;
+            found
+        })
+        .unwrap_or_default();
+
+        if view != self.view {
+            self.store_selection();
+            self.view = view;
+            self.refresh_files();
+            self.restore_selection();
+        }
+
+        self.queue.autoplay = snapshot.autoplay.clone();
+        self.queue.shuffle.restore(snapshot.upcoming.clone());
+        self.player.set_volume(snapshot.volume);
+
+        match &snapshot.uri {
+            Some(uri) => self.player.play_uri_at(uri, snapshot.position),
+            None => {
+                self.player.backend.stop();
+                self.player.notify("stop");
+            }
+        }
+    }
+
+    /// Save the state for --restore-queue if the track, the queue or the
+    /// autoplay modes changed, or the position changed for a while.
+    fn autosave_queue(&mut self) {
+        let queue = (
+            self.track_uri.clone(),
+            self.queue.autoplay.clone(),
+            self.queue.shuffle.upcoming().count(),
+        );
+        let moved_on = self.play_state == PlayState::Playing
+            && self.queue_saved.elapsed() >= QUEUE_SAVE_INTERVAL;
+
+        if self.saved_queue.as_ref() != Some(&queue) || moved_on {
+            self.save_queue();
+            self.saved_queue = Some(queue);
+        }
+    }
+
+    fn save_queue(&mut self) {
+        self.queue_saved = Instant::now();
+        if let Err(e) = self.snapshot(String::from("queue")).save_last() {
+            tracing::warn!("can't save queue: {e}");
+        }
+    }
+
+    /// Handle a key press while the manual is open.
+    fn help_key(&mut self, code: KeyCode) {
+        let help = match &mut self.help {
+            Some(help) => help,
+            None => return,
+        };
+
+        match code {
+            KeyCode::F(1) | KeyCode::Esc => self.help = None,
+            KeyCode::Down => help.scroll_by(1),
+            KeyCode::Up => help.scroll_by(-1),
+            KeyCode::PageDown => help.scroll_by(10),
+            KeyCode::PageUp => help.scroll_by(-10),
+            KeyCode::Home => help.scroll = 0,
+            KeyCode::End => help.scroll_by(isize::MAX),
+            KeyCode::Enter => help.find(true),
+            KeyCode::Char(c) => {
+                help.search.push(c);
+                help.find(false);
+            }
+            KeyCode::Backspace => {
+                help.search.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle a key press.
+    /// Returns false if the player should exit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.credential_prompt.is_some() {
+            self.credential_key(key.code);
+            return true;
+        }
+
+        if self.help.is_some() {
+            self.help_key(key.code);
+            return true;
+        }
+
+        if self.snapshot_dialog.is_some() {
+            self.snapshot_key(key.code);
+            return true;
+        }
+
+        if self.playlist_dialog.is_some() {
+            self.playlist_dialog_key(key.code);
+            return true;
+        }
+
+        if self.timer_prompt.is_some() {
+            self.timer_prompt_key(key.code);
+            return true;
+        }
+
+        if self.url_prompt.is_some() {
+            self.url_prompt_key(key.code);
+            return true;
+        }
+
+        if self.rating_dialog.is_some() {
+            self.rating_key(key.code);
+            return true;
+        }
+
+        if self.delete_dialog.is_some() {
+            self.delete_key(key.code);
+            return true;
+        }
+
+        if self.seek_prompt.is_some() {
+            self.seek_prompt_key(key.code);
+            return true;
+        }
+
+        if self.reload_prompt.is_some() {
+            self.reload_prompt_key(key.code);
+            return true;
+        }
+
+        if self.tag_editor.is_some() {
+            self.tag_editor_key(key.code);
+            return true;
+        }
+
+        // Keys typed into the credential prompt are never logged.
+        tracing::debug!("key {:?} in {:?}", key.code, self.cursor_state);
+
+        // Shift makes seeking and volume steps larger.
+        let multiplier = if key.modifiers.contains(KeyModifiers::SHIFT) {
+            10.0
+        } else {
+            1.0
+        };
+
+        // Anything but moving through the listing ends a preview,
+        // Enter plays the previewed file instead of going back.
+        if self.player.is_previewing() {
+            let listing = self.cursor_state == CursorState::MusicList;
+            let browsing = listing
+                && (matches!(
+                    key.code,
+                    KeyCode::Up
+                        | KeyCode::Down
+                        | KeyCode::Left
+                        | KeyCode::Right
+                        | KeyCode::Home
+                        | KeyCode::End
+                ) || key.modifiers.contains(KeyModifiers::ALT));
+
+            if !browsing {
+                self.player
+                    .end_preview(!(listing && key.code == KeyCode::Enter));
+            }
+        }
+
+        if self.zone > 0 && self.zone_key(key.code, multiplier) {
+            return true;
+        }
+
+        // Letters are taken by commands, so jumping like in file managers needs Alt.
+        if self.cursor_state == CursorState::MusicList && key.modifiers.contains(KeyModifiers::ALT)
+        {
+            if let KeyCode::Char(c) = key.code {
+                self.jump_to_letter(c);
+                return true;
+            }
+        }
+
+        match key.code {
+            KeyCode::Esc => {
+                return false;
+            }
+            KeyCode::Char('q') if self.cursor_state != CursorState::Search => {
+                return false;
+            }
+            KeyCode::Tab => {
+                // Skip the panes that aren't shown.
+                self.cursor_state.overflowing_next();
+                while !self.is_shown(&self.cursor_state) {
+                    self.cursor_state.overflowing_next();
+                }
+            }
+            KeyCode::F(1) => {
+                self.help = Some(Help::default());
+            }
+            KeyCode::F(5) if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.rescan();
+                self.show(Level::Info, "Reading all tags again");
+            }
+            KeyCode::F(5) => {
+                if self.reload_library() {
+                    let text = format!("Refreshed, {} files", self.library.files().len());
+                    self.show(Level::Info, text);
+                }
+            }
+            KeyCode::Char('n') if self.cursor_state != CursorState::Search => {
+                self.skip_next();
+            }
+            KeyCode::Char('p') if self.cursor_state != CursorState::Search => {
+                self.skip_previous();
+            }
+            KeyCode::Char('S') if self.cursor_state != CursorState::Search => {
+                self.snapshot_dialog = Some(SnapshotDialog::Save {
+                    name: String::new(),
+                });
+            }
+            KeyCode::Char('L') if self.cursor_state != CursorState::Search => {
+                let mut state = ListState::default();
+                state.select(self.snapshots.len().checked_sub(1));
+                self.snapshot_dialog = Some(SnapshotDialog::Restore { state });
+            }
+            // m picks a playlist for a mix in the playlists pane.
+            KeyCode::Char('m')
+                if !matches!(
+                    self.cursor_state,
+                    CursorState::Search | CursorState::Playlists
+                ) =>
+            {
+                self.toggle_mute();
+            }
+            KeyCode::Char('[') if self.cursor_state != CursorState::Search => {
+                self.seek_chapter(false);
+            }
+            KeyCode::Char(']') if self.cursor_state != CursorState::Search => {
+                self.seek_chapter(true);
+            }
+            KeyCode::Char('u') if self.cursor_state != CursorState::Search => {
+                self.undo_rating();
+            }
+            KeyCode::Char('=') if self.cursor_state != CursorState::Search => {
+                self.match_loudness();
+            }
+            KeyCode::Char('~') if self.cursor_state != CursorState::Search => {
+                self.player.set_gain(0.0);
+            }
+            KeyCode::Char('Z') if self.cursor_state != CursorState::Search => {
+                self.next_zone();
+            }
+            KeyCode::Char('U') if self.cursor_state != CursorState::Search => {
+                self.undo_skip();
+            }
+            KeyCode::Char('I') if self.cursor_state != CursorState::Search => {
+                self.track_stats = !self.track_stats;
+            }
+            KeyCode::Char('o') if self.cursor_state != CursorState::Search => {
+                self.url_prompt = Some(String::new());
+            }
+            KeyCode::Char('b') if self.cursor_state != CursorState::Search => {
+                self.passthrough = !self.passthrough;
+                self.passthrough_pinned = false;
+                self.player.set_passthrough(self.passthrough);
+            }
+            KeyCode::Char('B') if self.cursor_state != CursorState::Search => {
+                self.passthrough_pinned = !self.passthrough_pinned;
+                self.passthrough = self.passthrough_pinned;
+                self.player.set_passthrough(self.passthrough);
+            }
+            KeyCode::Char(' ') if self.cursor_state != CursorState::Search => {
+                if self.is_paused() {
+                    self.player.resume();
+                    self.player.notify("resume");
+                } else {
+                    self.player.pause();
+                    self.player.notify("pause");
+                }
+            }
+            _ => match self.cursor_state {
+                CursorState::MusicList => match key.code {
+                    KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        self.move_playlist_entry(true);
+                    }
+                    KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        self.move_playlist_entry(false);
+                    }
+                    KeyCode::Down => self.move_selection(1),
+                    KeyCode::Up => self.move_selection(-1),
+                    KeyCode::Left => self.move_selection(-5),
+                    KeyCode::Right => self.move_selection(5),
+                    KeyCode::Home => self.list_state.select(Some(0)),
+                    KeyCode::End => self.list_state.select(Some(self.queue.files().len() - 1)),
+                    KeyCode::Char('r') => {
+                        let track = rand::random::<usize>() % self.queue.files().len();
+                        self.list_state.select(Some(track));
+                    }
+                    KeyCode::Char('R') => {
+                        if let Some(track) = self.next_shuffled() {
+                            let i = self.queue.files().iter().position(|file| *file == track);
+                            self.list_state.select(i);
+
+                            self.player.play_path(&track);
+                        }
+                    }
+                    KeyCode::Char('v') => self.next_playlist(),
+                    KeyCode::Char('G') => {
+                        self.grouping = Grouping::next(self.grouping);
+                        self.listing.invalidate();
+                        let text = match self.grouping {
+                            Some(grouping) => format!("Grouped by {grouping}"),
+                            None => String::from("Not grouped"),
+                        };
+                        self.show(Level::Info, text);
+                    }
+                    KeyCode::Char('C') => self.toggle_group(),
+                    KeyCode::Char('f') => self.next_source_filter(),
+                    KeyCode::Char('a') => self.add_to_playlist(),
+                    KeyCode::Char('V') => self.start_verify(),
+                    KeyCode::Char('P') => self.toggle_preview_mode(),
+                    KeyCode::Char('t') => {
+                        if let Some(file) = self
+                            .list_state
+                            .selected()
+                            .and_then(|i| self.queue.files().get(i))
+                        {
+                            self.timer_prompt = Some((file.clone(), String::new()));
+                        }
+                    }
+                    KeyCode::Delete => self.delete_marked(),
+                    KeyCode::Char('e') => {
+                        let files = self.marked_or_selected();
+                        self.edit_tags(files);
+                    }
+                    KeyCode::Char('E') => self.revert_tags(),
+                    KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.undo_tag_edit();
+                    }
+                    KeyCode::Char('y') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.redo_tag_edit();
+                    }
+                    KeyCode::Char('x') => self.toggle_mark(),
+                    KeyCode::Char('X') => self.marked.clear(),
+                    KeyCode::Char('Q') => self.enqueue(),
+                    KeyCode::Enter => {
+                        let track = match self.list_state.selected() {
+                            Some(i) => i,
+                            None => {
+                                return true;
+                            }
+                        };
+
+                        self.player.play_path(&self.queue.files()[track]);
+                    }
+                    _ => {}
+                },
+                CursorState::Volume => match key.code {
+                    KeyCode::Left => self.change_volume(-self.args.volume_step * multiplier),
+                    KeyCode::Right => self.change_volume(self.args.volume_step * multiplier),
+                    KeyCode::Home => self.player.set_volume(0.0),
+                    KeyCode::End => self.player.set_volume(1.0),
+                    KeyCode::Down => self.change_volume(-self.args.volume_step_large * multiplier),
+                    KeyCode::Up => self.change_volume(self.args.volume_step_large * multiplier),
+                    _ => {}
+                },
+                CursorState::Control => match key.code {
+                    KeyCode::Left => self.seek_by(-self.args.seek_step * multiplier),
+                    KeyCode::Right => self.seek_by(self.args.seek_step * multiplier),
+                    KeyCode::Down => self.seek_by(-self.args.seek_step_large * multiplier),
+                    KeyCode::Up => self.seek_by(self.args.seek_step_large * multiplier),
+                    KeyCode::Char('g') | KeyCode::Char(':') => {
+                        self.seek_prompt = Some(String::new());
+                    }
+                    KeyCode::Char(c @ '0'..='9') => {
+                        if let Some(duration) = self.player.backend.duration() {
+                            let tenths = c.to_digit(10).unwrap_or_default();
+                            self.player.seek(duration * tenths / 10);
+                        }
+                    }
+                    KeyCode::Home => {
+                        // There is no list order to go back to when shuffling.
+                        let went_back = self.queue.autoplay.shuffle && self.play_previous();
+                        if !went_back {
+                            self.player.seek(Duration::ZERO);
+                        }
+                    }
+                    KeyCode::End => {
+                        if let Some(duration) = self.player.backend.duration() {
+                            self.player.seek(duration);
+                        }
+                    }
+                    KeyCode::Char('r') => {
+                        self.queue.autoplay.repeat = !self.queue.autoplay.repeat;
+                    }
+                    KeyCode::Char('R') => self.queue.add_repeat(),
+                    KeyCode::Char('s') => {
+                        self.queue.autoplay.shuffle = !self.queue.autoplay.shuffle;
+                    }
+                    KeyCode::Char('l') => {
+                        self.queue.autoplay.sequential = !self.queue.autoplay.sequential;
+                    }
+                    KeyCode::Char('i') => {
+                        self.queue.autoplay.repeat_list = !self.queue.autoplay.repeat_list;
+                    }
+                    KeyCode::Char('c') => {
+                        self.queue.autoplay.consume = !self.queue.autoplay.consume;
+                    }
+                    KeyCode::Char('x') => self.stop_after_current = !self.stop_after_current,
+                    KeyCode::Char('z') => self.next_sleep_step(),
+                    _ => {}
+                },
+                CursorState::History => match key.code {
+                    KeyCode::Down => {
+                        let next = self.history_state.selected().map_or(0, |i| i + 1);
+                        if next < self.history.len() {
+                            self.history_state.select(Some(next));
+                        }
+                    }
+                    KeyCode::Up => {
+                        if let Some(i) = self.history_state.selected() {
+                            self.history_state.select(Some(i.saturating_sub(1)));
+                        }
+                    }
+                    KeyCode::Enter => {
+                        // The history is displayed most recent first.
+                        if let Some(i) = self.history_state.selected() {
+                            if let Some(uri) = self.history.iter().rev().nth(i).cloned() {
+                                self.player.play_uri(&uri);
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                CursorState::Playlists => match key.code {
+                    KeyCode::Down => {
+                        let next = self.playlist_state.selected().map_or(0, |i| i + 1);
+                        if next < self.playlists.len() {
+                            self.playlist_state.select(Some(next));
+                        }
+                    }
+                    KeyCode::Up => {
+                        if let Some(i) = self.playlist_state.selected() {
+                            self.playlist_state.select(Some(i.saturating_sub(1)));
+                        }
+                    }
+                    KeyCode::Enter => {
+                        if let Some(i) = self.playlist_state.selected() {
+                            if i < self.playlists.len() {
+                                self.open_playlist(i);
+                            }
+                        }
+                    }
+                    KeyCode::Char('c') => {
+                        self.playlist_dialog = Some(PlaylistDialog::Create {
+                            name: String::new(),
+                        });
+                    }
+                    KeyCode::Char('r') => {
+                        if let Some(playlist) = self
+                            .playlist_state
+                            .selected()
+                            .and_then(|i| self.playlists.get(i))
+                        {
+                            self.playlist_dialog = Some(PlaylistDialog::Rename {
+                                name: playlist.name.clone(),
+                            });
+                        }
+                    }
+                    KeyCode::Char('m') => self.pick_for_mix(None),
+                    KeyCode::Char(c @ '1'..='9') => {
+                        self.pick_for_mix(c.to_digit(10).map(|weight| weight as usize));
+                    }
+                    KeyCode::Char('M') => {
+                        if self.mix.len() < 2 {
+                            self.show(Level::Info, "Pick at least two playlists with m first");
+                        } else {
+                            self.playlist_dialog = Some(PlaylistDialog::Mix {
+                                name: String::new(),
+                            });
+                        }
+                    }
+                    KeyCode::Delete => self.delete_playlist(),
+                    _ => {}
+                },
+                CursorState::Timers => match key.code {
+                    KeyCode::Down => {
+                        let next = self.timer_state.selected().map_or(0, |i| i + 1);
+                        if next < self.schedule.len() {
+                            self.timer_state.select(Some(next));
+                        }
+                    }
+                    KeyCode::Up => {
+                        if let Some(i) = self.timer_state.selected() {
+                            self.timer_state.select(Some(i.saturating_sub(1)));
+                        }
+                    }
+                    KeyCode::Delete => {
+                        if let Some(i) = self.timer_state.selected() {
+                            self.schedule.remove(i);
+                        }
+                    }
+                    KeyCode::Char('h') => self.hold_for_timers = !self.hold_for_timers,
+                    _ => {}
+                },
+                CursorState::Chapters => match key.code {
+                    KeyCode::Down => {
+                        let next = self.chapter_state.selected().map_or(0, |i| i + 1);
+                        if next < self.chapters().len() {
+                            self.chapter_state.select(Some(next));
+                        }
+                    }
+                    KeyCode::Up => {
+                        if let Some(i) = self.chapter_state.selected() {
+                            self.chapter_state.select(Some(i.saturating_sub(1)));
+                        }
+                    }
+                    KeyCode::Enter => {
+                        if let Some(i) = self.chapter_state.selected() {
+                            if let Some(chapter) = self.chapters().get(i) {
+                                self.player.seek(chapter.start);
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                CursorState::Search => match key.code {
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        let files = self.search_matches();
+                        if files.is_empty() {
+                            self.show(Level::Info, "No matching tracks to rate");
+                        } else {
+                            self.rating_dialog = Some(RatingDialog {
+                                files,
+                                rating: ratings::MAX,
+                            });
+                        }
+                    }
+                    KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.search_regex = !self.search_regex;
+                    }
+                    KeyCode::Char(c) => self.search.push(c),
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                    }
+                    KeyCode::Delete => self.search.clear(),
+                    KeyCode::Enter => {
+                        if let Some(selected) = self.list_state.selected() {
+                            let matcher = match self.search_matcher() {
+                                Ok(matcher) => matcher,
+                                Err(()) => return true,
+                            };
+
+                            // Start after the selection and wrap around.
+                            let fmatch = (selected + 1..self.queue.files().len())
+                                .chain(0..=selected)
+                                .find(|&i| {
+                                    let file = self.queue.files()[i].clone();
+                                    self.matches_search(&file, &matcher)
+                                });
+
+                            if let Some(fmatch) = fmatch {
+                                self.list_state.select(Some(fmatch));
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+            },
+        }
+
+        true
+    }
+
+    /// Catch up with the state of the listen-along host.
+    #[cfg(feature = "listen-along")]
+    fn follow_host(&mut self, state: SyncState) {
+        if state.uri.is_empty() {
+            if self.track_uri.is_some() {
+                self.player.stop();
+                self.player.notify("stop");
+            }
+
+            return;
+        }
+
+        let uri = match self.resolve_host_uri(&state.uri) {
+            Some(uri) => uri,
+            None => {
+                if self.listen_along_missing.as_ref() != Some(&state.uri) {
+                    let name = uri_name(&state.uri);
+                    self.show(Level::Warning, format!("{name} isn't in the library"));
+                    self.listen_along_missing = Some(state.uri);
+                }
+
+                return;
+            }
+        };
+
+        if self.player.current_uri().as_ref() != Some(&uri) {
+            self.player.play_uri_at(&uri, state.position);
+            return;
+        }
+
+        match (state.playing, self.is_paused()) {
+            (true, true) => {
+                self.player.resume();
+                self.player.notify("resume");
+            }
+            (false, false) => {
+                self.player.pause();
+                self.player.notify("pause");
+            }
+            _ => {}
+        }
+
+        if let Some(position) = self.player.backend.position() {
+            let tolerance = Duration::from_millis(self.args.listen_along_tolerance);
+            if position.abs_diff(state.position) > tolerance {
+                self.player.seek(state.position);
+            }
+        }
+    }
+
+    /// Find the local copy of a track the listen-along host plays.
+    /// Local files are matched by name if they don't exist at the same path,
+    /// other URIs are played as they are.
+    #[cfg(feature = "listen-along")]
+    fn resolve_host_uri(&self, uri: &str) -> Option<String> {
+        let path = match uri_to_path(uri) {
+            Some(path) => path,
+            None => return Some(uri.to_string()),
+        };
+
+        if path.exists() {
+            return Some(uri.to_string());
+        }
+
+        self.library
+            .files()
+            .iter()
+            .find(|file| file.file_name() == path.file_name())
+            .map(|file| file_uri(file))
+    }
+
+    /// Handle a media key pressed anywhere.
+    #[cfg(feature = "media-keys")]
+    fn media_key(&mut self, key: MediaKey) {
+        tracing::debug!("media key {key:?}");
+
+        match key {
+            MediaKey::PlayPause if self.is_paused() => {
+                self.player.resume();
+                self.player.notify("resume");
+            }
+            MediaKey::PlayPause | MediaKey::Pause => {
+                self.player.pause();
+                self.player.notify("pause");
+            }
+            MediaKey::Play => {
+                self.player.resume();
+                self.player.notify("resume");
+            }
+            MediaKey::Stop => {
+                self.player.stop();
+                self.player.notify("stop");
+            }
+            MediaKey::Next => self.skip_next(),
+            MediaKey::Previous => self.skip_previous(),
+        }
+    }
+
+    /// Run a command typed at the --no-tui prompt, printing the answer.
+    /// Returns false if the player should exit.
+    fn command(&mut self, line: &str) -> bool {
+        let command = match line_mode::Command::parse(line) {
+            Ok(Some(command)) => command,
+            Ok(None) => return true,
+            Err(e) => {
+                println!("{e}");
+                return true;
+            }
+        };
+
+        match command {
+            line_mode::Command::Resume => {
+                self.player.resume();
+                self.player.notify("resume");
+            }
+            line_mode::Command::Play(track) => match track.find(self.queue.files()).cloned() {
+                Some(file) => self.player.play_path(&file),
+                None => match track {
+                    line_mode::Track::Number(n) => println!("No file number {n}"),
+                    line_mode::Track::Name(name) => println!("No file matches {name}"),
+                },
+            },
+            line_mode::Command::Pause => {
+                self.player.pause();
+                self.player.notify("pause");
+            }
+            line_mode::Command::Stop => {
+                self.player.stop();
+                self.player.notify("stop");
+            }
+            line_mode::Command::Next => self.skip_next(),
+            line_mode::Command::Previous => self.skip_previous(),
+            line_mode::Command::Seek(target) => {
+                let duration = self.player.backend.duration();
+                match target.position(duration) {
+                    Some(position) => match duration {
+                        Some(duration) => self.player.seek(position.min(duration)),
+                        None => self.player.seek(position),
+                    },
+                    None => println!("The duration is unknown, seek to a time instead"),
+                }
+            }
+            line_mode::Command::Volume(percent) => {
+                if let Some(percent) = percent {
+                    let position = (percent / 100.0).clamp(0.0, 1.0);
+                    self.muted = None;
+                    self.player
+                        .set_volume(self.args.volume_scale.amplitude(position));
+                }
+
+                println!("Volume {}", self.volume_label(self.player.volume()));
+            }
+            line_mode::Command::Mute => {
+                self.toggle_mute();
+                println!(
+                    "{}",
+                    if self.muted.is_some() {
+                        "Muted"
+                    } else {
+                        "Unmuted"
+                    }
+                );
+            }
+            line_mode::Command::List(name) => {
+                for (n, file) in line_mode::list(self.queue.files(), &name) {
+                    println!("{n:>5}  {}", display_name(file));
+                }
+            }
+            line_mode::Command::Status => {
+                let mut status = self.status_line();
+                if let (Some(position), Some(duration)) = (
+                    self.player.backend.position(),
+                    self.player.backend.duration(),
+                ) {
+                    status += &format!(
+                        ", {}:{:02} of {}:{:02}",
+                        position.as_secs() / 60,
+                        position.as_secs() % 60,
+                        duration.as_secs() / 60,
+                        duration.as_secs() % 60
+                    );
+                }
+                match self.muted {
+                    Some(volume) => println!("{status}, muted at {}", self.volume_label(volume)),
+                    None => println!(
+                        "{status}, volume {}",
+                        self.volume_label(self.player.volume())
+                    ),
+                }
+
+                let modes: Vec<&str> = self
+                    .modes()
+                    .map(|symbol| Glyphs::Labels.get(symbol))
+                    .collect();
+                if !modes.is_empty() {
+                    println!("Modes: {}", modes.join(", "));
+                }
+            }
+            line_mode::Command::Toggle(mode) => {
+                let symbol = match mode {
+                    Mode::Repeat => Symbol::Repeat,
+                    Mode::RepeatList => Symbol::RepeatList,
+                    Mode::Sequential => Symbol::Sequential,
+                    Mode::Shuffle => Symbol::Shuffle,
+                    Mode::Consume => Symbol::Consume,
+                };
+
+                let state = if mode.toggle(&mut self.queue.autoplay) {
+                    "on"
+                } else {
+                    "off"
+                };
+                println!("{} {state}", Glyphs::Labels.get(symbol));
+            }
+            line_mode::Command::Help => println!("{}", line_mode::HELP),
+            line_mode::Command::Quit => {
+                self.player.stop();
+                self.player.notify("stop");
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Answer a request from the web remote.
+    #[cfg(feature = "web")]
+    fn web_request(&mut self, request: web::Request) -> web::Response {
+        match request {
+            web::Request::Status => {}
+            web::Request::Play => {
+                self.player.resume();
+                self.player.notify("resume");
+            }
+            web::Request::Pause => {
+                self.player.pause();
+                self.player.notify("pause");
+            }
+            web::Request::Next => self.skip_next(),
+            web::Request::Previous => self.skip_previous(),
+            web::Request::Volume(level) => {
+                if let Some(level) = level {
+                    self.player.set_volume(level);
+                }
+
+                return web::Response::Json(format!("{{\"volume\":{}}}", self.player.volume()));
+            }
+            web::Request::Queue => {
+                let tracks: Vec<String> = if self.queue.autoplay.shuffle {
+                    self.queue
+                        .shuffle
+                        .upcoming()
+                        .map(|file| {
+                            format!(
+                                "{{\"uri\":\"{}\",\"file\":\"{}\"}}",
+                                json::escape(&file_uri(file)),
+                                json::escape(&display_name(file))
+                            )
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+
+                return web::Response::Json(format!("[{}]", tracks.join(",")));
+            }
+            web::Request::Tasks | web::Request::StartTask(_) => {
+                match request {
+                    web::Request::StartTask(Task::Scan) => self.rescan(),
+                    web::Request::StartTask(Task::Verify) => self.start_verify(),
+                    _ => {}
+                }
+
+                let tasks: Vec<String> =
+                    self.task_progress().iter().map(Progress::to_json).collect();
+
+                return web::Response::Json(format!("[{}]", tasks.join(",")));
+            }
+            web::Request::Cover => {
+                #[cfg(feature = "cover-art")]
+                if let (Some(cover_art), Some(uri)) =
+                    (self.cover_art.clone(), self.track_uri.clone())
+                {
+                    let cover = cover_art
+                        .cached(&self.library.tags(&uri))
+                        .and_then(|path| fs::read(path).ok());
+
+                    if let Some(cover) = cover {
+                        return web::Response::Image(cover);
+                    }
+                }
+
+                return web::Response::NotFound;
+            }
+        }
+
+        let state = self.state_name();
+        let uri = self.track_uri.clone().unwrap_or_default();
+        let seconds = |duration: Option<Duration>| match duration {
+            Some(duration) => duration.as_secs().to_string(),
+            None => String::from("null"),
+        };
+
+        web::Response::Json(format!(
+            "{{\"state\":\"{state}\",\"uri\":\"{}\",\"file\":\"{}\",\"position\":{},\"duration\":{},\"volume\":{}}}",
+            json::escape(&uri),
+            json::escape(&uri_name(&uri)),
+            seconds(self.player.backend.position()),
+            seconds(self.player.backend.duration()),
+            self.player.volume(),
+        ))
+    }
+
+    /// Describe the playback state in a line of plain text for headless mode.
+    fn status_line(&self) -> String {
+        let track = self.track_uri.as_deref().map(uri_name).unwrap_or_default();
+
+        match self.play_state {
+            PlayState::Stopped if self.track_uri.is_none() => String::from("Idle"),
+            PlayState::Stopped => format!("Stopped: {track}"),
+            PlayState::Buffering => format!("Buffering: {track}"),
+            PlayState::Paused => format!("Paused: {track}"),
+            PlayState::Playing => format!("Playing: {track}"),
+        }
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        // Raw mode and escape codes would end up in the pipe or log file.
+        let headless = self.args.headless || self.args.no_tui || !io::stdout().is_terminal();
+
+        // Input, playback events and the redraw ticker all feed this channel.
+        let (message_tx, messages) = mpsc::channel();
+
+        // Handled before raw mode is enabled, so that a kill always leaves the terminal usable.
+        let mut unix_signals = Signals::new([SIGUSR1, SIGHUP, SIGINT, SIGTERM])?;
+        let tx = message_tx.clone();
+        thread::spawn(move || {
+            for signal in unix_signals.forever() {
+                if tx.send(Message::Signal(signal)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let raw_mode = if headless {
+            None
+        } else {
+            Some(RawMode::enable()?)
+        };
+
+        let tx = message_tx.clone();
+        self.player.subscribe(move |event| {
+            let _ = tx.send(Message::Playback(event));
+        });
+
+        for (i, zone) in self.zones.iter().enumerate() {
+            let tx = message_tx.clone();
+            zone.player.subscribe(move |event| {
+                let _ = tx.send(Message::ZonePlayback(i, event));
+            });
+        }
+
+        let mut terminal = if headless {
+            None
+        } else {
+            Some(Terminal::new(CrosstermBackend::new(io::stdout()))?)
+        };
+
+        if let Some(initial) = &self.args.play {
+            // A URI is passed on, e.g. to play a network share or a Bandcamp page.
+            match initial.to_str() {
+                Some(uri) if uri.contains("://") => self.open_url(uri.to_string()),
+                _ => self.player.play_path(initial),
+            }
+        } else if !self.args.tracks.is_empty() {
+            let track = if self.queue.autoplay.shuffle {
+                self.next_shuffled()
+            } else {
+                self.queue.files().first().cloned()
+            };
+
+            if let Some(track) = track {
+                self.player.play_path(&track);
+            }
+        } else if let Some(queue) = Snapshot::load_last().filter(|_| self.args.restore_queue) {
+            self.restore_snapshot(&queue);
+        } else if self.args.random {
+            if let Some(track) = self.next_shuffled() {
+                self.player.play_path(&track);
+            }
+        }
+
+        if let Some(addr) = self.args.web {
+            #[cfg(feature = "web")]
+            {
+                let tx = message_tx.clone();
+                web::serve(addr, move |request| {
+                    let (reply_tx, reply) = mpsc::channel();
+                    if tx.send(Message::Web(request, reply_tx)).is_err() {
+                        return web::Response::Error(String::from("player exited"));
+                    }
+
+                    reply.recv_timeout(WEB_TIMEOUT).unwrap_or_else(|_| {
+                        web::Response::Error(String::from("player didn't respond"))
+                    })
+                })?;
+            }
+            #[cfg(not(feature = "web"))]
+            anyhow::bail!("--web {addr} requires musikbox to be built with the web feature");
+        }
+
+        #[cfg(feature = "listen-along")]
+        {
+            if let Some(addr) = self.args.listen_along_host {
+                self.listen_along_host = Some(listen_along::Host::bind(addr)?);
+                tracing::info!("hosting listen-along session on {addr}");
+            }
+
+            if let Some(addr) = self.args.listen_along {
+                let tx = message_tx.clone();
+                listen_along::follow(addr, move |state| {
+                    let _ = tx.send(Message::ListenAlong(state));
+                });
+            }
+        }
+        #[cfg(not(feature = "listen-along"))]
+        if self.args.listen_along_host.is_some() || self.args.listen_along.is_some() {
+            anyhow::bail!(
+                "--listen-along and --listen-along-host require musikbox to be built with the listen-along feature"
+            );
+        }
+
+        if self.args.media_keys {
+            #[cfg(feature = "media-keys")]
+            {
+                let tx = message_tx.clone();
+                let devices = media_keys::listen(move |key| {
+                    let _ = tx.send(Message::MediaKey(key));
+                })?;
+
+                tracing::info!("listening for media keys on {devices} input devices");
+            }
+            #[cfg(not(feature = "media-keys"))]
+            anyhow::bail!("--media-keys requires musikbox to be built with the media-keys feature");
+        }
+
+        if !self.args.eq_outputs.is_empty() {
+            let tx = message_tx.clone();
+            eq::watch_sink(move |sink| {
+                let _ = tx.send(Message::Sink(sink));
+            });
+        }
+
+        if self.args.auto_pause {
+            let tx = message_tx.clone();
+            interruption::watch_suspend(move |interruption| {
+                let _ = tx.send(Message::Interruption(interruption));
+            });
+
+            let tx = message_tx.clone();
+            interruption::watch_headphones(move |interruption| {
+                let _ = tx.send(Message::Interruption(interruption));
+            });
+        }
+
+        if !self.args.no_listing && self.args.tracks.is_empty() {
+            let tx = message_tx.clone();
+            let watched = watch::watch_dir(&self.dir(), move || {
+                let _ = tx.send(Message::DirChanged);
+            });
+
+            if let Err(e) = watched {
+                tracing::warn!(
+                    "can't watch {}, press F5 to refresh: {e}",
+                    self.dir().display()
+                );
+            }
+        }
+
+        if self.args.no_tui {
+            // Standard input is taken by the track list with --stdin.
+            let tty = if self.args.stdin {
+                Some(fs::File::open("/dev/tty")?)
+            } else {
+                None
+            };
+
+            let tx = message_tx.clone();
+            thread::spawn(move || {
+                let lines: Box<dyn Iterator<Item = io::Result<String>>> = match tty {
+                    Some(tty) => Box::new(io::BufReader::new(tty).lines()),
+                    None => Box::new(io::stdin().lines()),
+                };
+
+                for line in lines {
+                    let line = line.unwrap_or_default();
+                    if tx.send(Message::Line(line)).is_err() {
+                        return;
+                    }
+                }
+
+                // End of input, e.g. Ctrl+D.
+                let _ = tx.send(Message::Line(String::from("quit")));
+            });
+        } else if !headless {
+            let tx = message_tx.clone();
+            thread::spawn(move || {
+                while let Ok(event) = event::read() {
+                    if tx.send(Message::Input(event)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let tx = message_tx;
+        thread::spawn(move || loop {
+            thread::sleep(TICK_INTERVAL);
+            if tx.send(Message::Tick).is_err() {
+                break;
+            }
+        });
+
+        self.startup.start("tag load");
+        self.library.start_scan();
+
+        let mut status = String::new();
+        let mut notice = String::new();
+        // Whether to show the --no-tui prompt again after printing something.
+        let mut prompt = true;
+        // Whether the last message may have changed what is shown.
+        let mut changed = true;
+
+        loop {
+            // Checked before the update as well, so that e.g. an expired notice is cleared.
+            let changing = self.is_changing();
+            self.update();
+
+            match &mut terminal {
+                _ if !changed && !changing && !self.is_changing() => {}
+                Some(terminal) => {
+                    let accent = self.accent_color();
+                    let start = Instant::now();
+                    terminal.draw(|f| self.draw(f, accent))?;
+
+                    if !self.startup.is_ready() {
+                        self.startup.record("first frame render", start.elapsed());
+                        self.startup.ready();
+                    }
+                }
+                None => {
+                    let line = self.status_line();
+                    if line != status {
+                        println!("{line}");
+                        status = line;
+                        prompt = true;
+                    }
+
+                    let line = self.notice.as_ref().map(Notice::text).unwrap_or_default();
+                    if line != notice {
+                        if !line.is_empty() {
+                            eprintln!("{line}");
+                            prompt = true;
+                        }
+
+                        notice = line;
+                    }
+
+                    if self.args.no_tui && prompt {
+                        print!("> ");
+                        io::stdout().flush()?;
+                        prompt = false;
+                    }
+
+                    self.startup.ready();
+                }
+            }
+
+            if self.args.profile_startup
+                && self.startup.is_ready()
+                && !self.startup.is_running("tag load")
+            {
+                break;
+            }
+
+            let message = messages.recv()?;
+            if let Message::Signal(signal) = message {
+                tracing::info!("received signal {signal}");
+            }
+            changed = !matches!(message, Message::Tick);
+
+            let running = match message {
+                Message::Input(Event::Key(key)) => self.handle_key(key),
+                Message::Input(_) | Message::Tick => true,
+                Message::Line(line) => {
+                    tracing::debug!("command {line}");
+                    prompt = true;
+                    self.command(&line)
+                }
+                #[cfg(feature = "media-keys")]
+                Message::MediaKey(key) => {
+                    self.media_key(key);
+                    true
+                }
+                #[cfg(feature = "listen-along")]
+                Message::ListenAlong(state) => {
+                    self.follow_host(state);
+                    true
+                }
+                #[cfg(feature = "web")]
+                Message::Web(request, reply) => {
+                    let _ = reply.send(self.web_request(request));
+                    true
+                }
+                Message::Sink(sink) => {
+                    self.apply_eq(&sink);
+                    true
+                }
+                Message::Interruption(interruption) => {
+                    self.interrupt(interruption);
+                    true
+                }
+                Message::DirChanged => {
+                    self.reload_library();
+                    true
+                }
+                Message::Playback(event) => self.handle_playback_event(event),
+                Message::ZonePlayback(i, event) => {
+                    tracing::debug!("playback event {event:?} in zone {i}");
+                    if let Some(e) = self.zones[i].handle_event(&event) {
+                        self.show(Level::Warning, e);
+                    }
+
+                    true
+                }
+                Message::Signal(SIGUSR1) => {
+                    self.player.resume();
+                    self.player.notify("resume");
+                    true
+                }
+                Message::Signal(SIGHUP) => {
+                    self.player.pause();
+                    self.player.notify("pause");
+                    true
+                }
+                Message::Signal(_) => {
+                    // SIGINT or SIGTERM
+                    self.player.stop();
+                    self.player.notify("stop");
+                    false
+                }
+            };
+
+            if !running {
+                break;
+            }
+        }
+
+        drop(raw_mode);
+        if let Some(mut terminal) = terminal {
+            terminal.clear()?;
+            terminal.set_cursor(0, 0)?;
+        }
+
+        if self.args.profile_startup {
+            eprintln!("{}", self.startup.report());
+        }
+
+        Ok(())
+    }
+
+    /// Stop playback and save the persistent state. Also runs after `run`
+    /// failed or panicked, so that nothing keeps playing and no state is lost.
+    fn shutdown(&mut self) -> anyhow::Result<()> {
+        // Fade out instead of cutting off when quitting.
+        self.player.stop();
+        for zone in &self.zones {
+            zone.player.stop();
+        }
+        self.player.finish_fade();
+        for zone in &self.zones {
+            zone.player.finish_fade();
+        }
+
+        if self.args.remember_selection {
+            self.save_selections()?;
+        }
+
+        if self.args.persist_history {
+            self.history.save()?;
+        }
+
+        if self.args.restore_queue {
+            self.save_queue();
+        }
+
+        self.stats.save()?;
+        let volume = self.muted.unwrap_or_else(|| self.player.volume());
+        state::write("volume", &[vec![volume.to_string()]])?;
+
+        Ok(())
+    }
+}
+
+/// Format a number with comma thousands separators.
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+
+        grouped.push(c);
+    }
+
+    grouped
+}
+
+/// Interval of the redraw ticker that keeps the progress up to date.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks that may fail to play in a row before autoplay stops skipping them.
+const MAX_FAILED_IN_ROW: usize = 5;
+
+/// Position within a chapter after which going back restarts it instead of
+/// going to the previous one.
+const CHAPTER_RESTART: Duration = Duration::from_secs(3);
+
+/// Interval in which the progress of running tasks is sent to the extensions.
+const TASK_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time a file must stay unchanged before it counts as completely written.
+const FILE_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Interval in which the position is saved for --restore-queue while playing.
+const QUEUE_SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Number of skipped tracks that can be returned to.
+const SKIPPED_TRACKS: usize = 20;
+
+/// Durations of the sleep timer in minutes, cycled through with z.
+const SLEEP_STEPS: [u64; 4] = [15, 30, 60, 90];
+
+/// Time before the end of the queue at which it's counted down.
+const SILENCE_COUNTDOWN: Duration = Duration::from_secs(60);
+
+/// Time without position updates after which playback is considered stalled.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of stalls after which playback is no longer reloaded.
+const WATCHDOG_RETRIES: u32 = 3;
+
+/// Time after which a web remote request fails if the main loop doesn't answer.
+#[cfg(feature = "web")]
+const WEB_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Get the key selections of a directory are saved under.
+fn selection_key(dir: &Path) -> String {
+    match fs::canonicalize(dir) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => dir.to_string_lossy().into_owned(),
+    }
+}
+
+/// Get the weight of a track in the shuffled order, the same for all tracks
+/// unless the shuffle is weighted.
+fn shuffle_weight(weights: Option<ShuffleWeights>, stats: &Stats, file: &Path) -> f64 {
+    let weights = match weights {
+        Some(weights) => weights,
+        None => return 1.0,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    weights.weight(stats.get(&file_uri(file)), now)
+}
+
+/// Get the inode and modification time of the local file of a URI,
+/// which change when the file is modified or replaced.
+fn file_identity(uri: &str) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(uri_to_path(uri)?).ok()?;
+    Some((metadata.ino(), metadata.modified().ok()?))
+}
+
+/// Get the name of a file for display.
+/// Names that aren't valid UTF-8 are shown with replacement characters.
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Get a rectangle of the given size centered in an area.
+/// It is shrunk to fit if the area is too small.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Get the volume saved at the end of the previous session.
+fn load_volume() -> Option<f64> {
+    state::read("volume")
+        .first()?
+        .first()?
+        .parse()
+        .ok()
+        .filter(|volume| (0.0..=1.0).contains(volume))
+}
+
+/// Register the optional subsystems that are enabled on the command line.
+/// Using one that wasn't compiled in is an error rather than silently ignored.
+fn register_extensions(
+    player: &mut Player<Box<dyn PlaybackBackend>>,
+    args: &Args,
+) -> anyhow::Result<()> {
+    if !args.webhooks.is_empty() {
+        #[cfg(feature = "webhook")]
+        player.register(Box::new(Webhooks::new(args.webhooks.clone())));
+        #[cfg(not(feature = "webhook"))]
+        anyhow::bail!("--webhook requires musikbox to be built with the webhook feature");
+    }
+
+    let hooks = [
+        &args.on_track_change,
+        &args.on_pause,
+        &args.on_stop,
+        &args.on_queue_empty,
+    ];
+    if hooks.iter().any(|hook| hook.is_some()) {
+        #[cfg(feature = "hooks")]
+        player.register(Box::new(Hooks::new(HookCommands {
+            track_change: args.on_track_change.clone(),
+            pause: args.on_pause.clone(),
+            stop: args.on_stop.clone(),
+            queue_empty: args.on_queue_empty.clone(),
+        })));
+        #[cfg(not(feature = "hooks"))]
+        anyhow::bail!("--on-* hooks require musikbox to be built with the hooks feature");
+    }
+
+    if let Some(path) = &args.now_playing {
+        #[cfg(feature = "now-playing")]
+        player.register(Box::new(NowPlaying::new(
+            path.clone(),
+            &args.now_playing_format,
+        )));
+        #[cfg(not(feature = "now-playing"))]
+        anyhow::bail!(
+            "--now-playing {} requires musikbox to be built with the now-playing feature",
+            path.display()
+        );
+    }
+
+    #[cfg(feature = "listenbrainz")]
+    if let Some(token) = &args.listenbrainz_token {
+        player.register(Box::new(ListenBrainz::new(token.clone())));
+    }
+    #[cfg(not(feature = "listenbrainz"))]
+    if args.listenbrainz_token.is_some() {
+        anyhow::bail!(
+            "--listenbrainz-token requires musikbox to be built with the listenbrainz feature"
+        );
+    }
+
+    let extensions: Vec<&str> = player.extensions().collect();
+    if !extensions.is_empty() {
+        tracing::info!("extensions: {}", extensions.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Get the index of the chapter playing at a position.
+fn current_chapter(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|chapter| chapter.start <= position)
+}
+
+fn main() -> anyhow::Result<()> {
+    // Tags are read through GStreamer even if playback uses another backend.
+    #[cfg(feature = "gstreamer")]
+    gstreamer::init()?;
+
+    let mut args = Args::parse();
+    if let Some(path) = &args.log_file {
+        logger::init(path, args.log_level)?;
+    }
+
+    // Shares are read through the file system gvfs exposes them in.
+    if let Some(uri) = args
+        .dir
+        .as_ref()
+        .and_then(|dir| dir.to_str())
+        .filter(|dir| remote::is_remote(dir))
+    {
+        let path = LocalPaths::default().get(uri)?;
+        tracing::info!("reading {uri} from {}", path.display());
+        args.dir = Some(path);
+    }
+
+    if args.stdin {
+        // Keyboard input is read from the terminal instead.
+        for line in io::stdin().lock().split(b'\n') {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            if !line.is_empty() {
+                args.tracks.push(PathBuf::from(OsString::from_vec(line)));
+            }
+        }
+
+        if args.tracks.is_empty() {
+            anyhow::bail!("--stdin: no tracks were read");
+        }
+    }
+
+    // Tracks are listed as files, except for URLs that aren't on shares.
+    let mut local_paths = LocalPaths::default();
+    for track in &mut args.tracks {
+        let uri = match track.to_str() {
+            Some(uri) if uri.starts_with("file://") || remote::is_remote(uri) => uri.to_string(),
+            _ => continue,
+        };
+
+        *track = match uri_to_path(&uri) {
+            Some(path) => path,
+            None => local_paths.get(&uri)?,
+        };
+    }
+    match args.command {
+        Some(Command::Stats {
+            action: StatsCommand::Export { format, by },
+        }) => export::export(&Stats::load(), by, format, &mut io::stdout().lock())?,
+        Some(Command::Verify { all }) => {
+            let dir = args.dir.unwrap_or_else(|| PathBuf::from("."));
+            verify_library(&dir, all)?;
+        }
+        None => {
+            let mut instance = Instance::new(args)?;
+
+            terminal::install_panic_hook();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| instance.run()));
+            let saved = instance.shutdown();
+
+            match result {
+                Ok(result) => result.and(saved)?,
+                Err(panic) => panic::resume_unwind(panic),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify the files of a directory, printing a line per file.
+/// Fails if any file is damaged.
+fn verify_library(dir: &Path, all: bool) -> anyhow::Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    let mut verifications = Verifications::load();
+    let mut damaged = 0;
+    let mut verified = 0;
+
+    for file in files {
+        let uri = file_uri(&file);
+        if !all && verifications.get(&uri).is_some() {
+            damaged += usize::from(verifications.is_damaged(&uri));
+            continue;
+        }
+
+        match verifications.verify(&file, &uri) {
+            Ok(verification) => match &verification.error {
+                None => println!("ok       {}", file.display()),
+                Some(err) => {
+                    println!("damaged  {}: {err}", file.display());
+                    damaged += 1;
+                }
+            },
+            Err(e) => eprintln!("skipped  {}: {e}", file.display()),
+        }
+
+        // Save as we go so that an interrupted run isn't lost.
+        verified += 1;
+        if verified % verify::SAVE_BATCH == 0 {
+            verifications.save()?;
+        }
+    }
+
+    verifications.save()?;
+
+    if damaged > 0 {
+        anyhow::bail!("{damaged} damaged files");
+    }
 
     Ok(())
 }
@@ -0,0 +1,97 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::thread;
+
+/// Size of a `struct input_event`: a `struct timeval`, the type, code and value.
+const EVENT_SIZE: usize = 2 * size_of::<usize>() + 8;
+
+const EV_KEY: u16 = 0x01;
+
+/// Media key reported by an input device, regardless of which window is focused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKey {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+}
+
+impl MediaKey {
+    /// Map a key code from `linux/input-event-codes.h`.
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            163 => Some(Self::Next),
+            164 => Some(Self::PlayPause),
+            165 => Some(Self::Previous),
+            166 => Some(Self::Stop),
+            200 => Some(Self::Play),
+            201 => Some(Self::Pause),
+            _ => None,
+        }
+    }
+}
+
+/// Call a function for every media key press on any input device in the background.
+/// Reading `/dev/input` usually requires membership in the `input` group.
+/// Other keys are discarded without being looked at.
+/// Returns the number of devices that are watched.
+pub fn listen<F>(f: F) -> io::Result<usize>
+where
+    F: Fn(MediaKey) + Clone + Send + 'static,
+{
+    let mut devices = 0;
+
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+        let is_event_device = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("event"));
+
+        if !is_event_device {
+            continue;
+        }
+
+        // Devices without permission are skipped, others may still work.
+        let mut device = match File::open(&path) {
+            Ok(device) => device,
+            Err(_) => continue,
+        };
+
+        let f = f.clone();
+        thread::spawn(move || {
+            let mut event = [0; EVENT_SIZE];
+            while device.read_exact(&mut event).is_ok() {
+                let kind = u16::from_ne_bytes([event[EVENT_SIZE - 8], event[EVENT_SIZE - 7]]);
+                let code = u16::from_ne_bytes([event[EVENT_SIZE - 6], event[EVENT_SIZE - 5]]);
+                let value = i32::from_ne_bytes([
+                    event[EVENT_SIZE - 4],
+                    event[EVENT_SIZE - 3],
+                    event[EVENT_SIZE - 2],
+                    event[EVENT_SIZE - 1],
+                ]);
+
+                // 1 is a press, 0 a release and 2 a repeat.
+                if kind == EV_KEY && value == 1 {
+                    if let Some(key) = MediaKey::from_code(code) {
+                        f(key);
+                    }
+                }
+            }
+        });
+
+        devices += 1;
+    }
+
+    if devices == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "no readable input devices, is the user in the input group?",
+        ));
+    }
+
+    Ok(devices)
+}
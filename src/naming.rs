@@ -0,0 +1,181 @@
+use crate::tags::Tags;
+
+use std::path::Path;
+use std::str::FromStr;
+
+/// Template for naming files after their tags, e.g. `{artist} - {title}`.
+/// Fields: title, artist, album, albumartist, genre, track and disc.
+/// Track and disc numbers have two digits, the extension of the file is kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamingTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Track,
+    Disc,
+}
+
+impl FromStr for NamingTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('/') {
+            return Err(String::from(
+                "files are renamed within their directory, / isn't allowed",
+            ));
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| String::from("unclosed {"))?;
+            let field = match &rest[start + 1..start + end] {
+                "title" => Field::Title,
+                "artist" => Field::Artist,
+                "album" => Field::Album,
+                "albumartist" => Field::AlbumArtist,
+                "genre" => Field::Genre,
+                "track" => Field::Track,
+                "disc" => Field::Disc,
+                field => {
+                    return Err(format!(
+                        "unknown field {field:?}, expected title, artist, album, albumartist, genre, track or disc"
+                    ))
+                }
+            };
+            parts.push(Part::Field(field));
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Field(_))) {
+            return Err(String::from("expected at least one field, e.g. {title}"));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl NamingTemplate {
+    /// Get the name for a file with these tags, keeping its extension.
+    /// Fails with the name of a field the tags don't have.
+    pub fn file_name(&self, tags: &Tags, path: &Path) -> Result<String, String> {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::Field(field) => name.push_str(&value(tags, *field)?),
+            }
+        }
+
+        if let Some(ext) = path.extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+
+        Ok(name)
+    }
+}
+
+/// Get the value of a field usable in a file name.
+fn value(tags: &Tags, field: Field) -> Result<String, String> {
+    let (value, name) = match field {
+        Field::Title => (tags.title.clone(), "title"),
+        Field::Artist => (tags.artist.clone(), "artist"),
+        Field::Album => (tags.album.clone(), "album"),
+        Field::AlbumArtist => (tags.album_artist.clone(), "album artist"),
+        Field::Genre => (tags.genre.clone(), "genre"),
+        Field::Track => (tags.track_number.map(|n| format!("{n:02}")), "track number"),
+        Field::Disc => (tags.disc_number.map(|n| format!("{n:02}")), "disc number"),
+    };
+
+    // Slashes would move the file elsewhere, e.g. in "AC/DC".
+    let value: String = value
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c == '/' || c.is_control() { '-' } else { c })
+        .collect();
+
+    match value.trim() {
+        "" => Err(format!("no {name}")),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Tags {
+        Tags {
+            title: Some(String::from("Thunderstruck")),
+            artist: Some(String::from("AC/DC")),
+            album: Some(String::from("The Razors Edge")),
+            track_number: Some(1),
+            ..Tags::default()
+        }
+    }
+
+    #[test]
+    fn file_name() {
+        let template: NamingTemplate = "{track} {artist} - {title}".parse().unwrap();
+        assert_eq!(
+            template.file_name(&tags(), Path::new("/music/01.flac")),
+            Ok(String::from("01 AC-DC - Thunderstruck.flac"))
+        );
+
+        let template: NamingTemplate = "{title}".parse().unwrap();
+        assert_eq!(
+            template.file_name(&tags(), Path::new("/music/track")),
+            Ok(String::from("Thunderstruck"))
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        let template: NamingTemplate = "{albumartist} - {title}".parse().unwrap();
+        assert_eq!(
+            template.file_name(&tags(), Path::new("a.mp3")),
+            Err(String::from("no album artist"))
+        );
+
+        let mut tags = tags();
+        tags.title = Some(String::from("  "));
+        let template: NamingTemplate = "{title}".parse().unwrap();
+        assert_eq!(
+            template.file_name(&tags, Path::new("a.mp3")),
+            Err(String::from("no title"))
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!("{artist}/{title}".parse::<NamingTemplate>().is_err());
+        assert!("{artist".parse::<NamingTemplate>().is_err());
+        assert!("{year}".parse::<NamingTemplate>().is_err());
+        assert!("plain".parse::<NamingTemplate>().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+use tui::style::{Color, Style};
+
+/// Time after which informational messages and warnings disappear.
+const NOTICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Message shown in the line at the bottom of the screen.
+/// Errors stay until they are replaced or playback resumes.
+#[derive(Clone, Debug)]
+pub struct Notice {
+    pub level: Level,
+    pub text: String,
+    since: Instant,
+}
+
+impl Notice {
+    pub fn new(level: Level, text: impl Into<String>) -> Self {
+        Self {
+            level,
+            text: text.into(),
+            since: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.level != Level::Error && self.since.elapsed() >= NOTICE_TIMEOUT
+    }
+
+    pub fn text(&self) -> String {
+        match self.level {
+            Level::Info => self.text.clone(),
+            Level::Warning => format!("Warning: {}", self.text),
+            Level::Error => format!("Error: {}", self.text),
+        }
+    }
+
+    pub fn style(&self, base: Style) -> Style {
+        match self.level {
+            Level::Info => base,
+            Level::Warning => base.fg(Color::Yellow),
+            Level::Error => base.fg(Color::Red),
+        }
+    }
+}
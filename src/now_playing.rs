@@ -0,0 +1,271 @@
+use crate::extension::{Extension, Notification};
+use crate::json;
+use crate::player::uri_name;
+use crate::tags::{self, Tags};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Contents of the now playing file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Format {
+    /// JSON object with the status, the track metadata, the position and the volume.
+    Json,
+    /// Line of text with placeholders such as `{artist} - {title}`, see `render`.
+    Template(String),
+}
+
+/// Extension keeping a file up to date with the current track and position,
+/// e.g. for status bars like polybar and waybar or stream overlays.
+pub struct NowPlaying {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    path: PathBuf,
+    format: Format,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// playing, paused or stopped.
+    status: &'static str,
+    uri: String,
+    tags: Tags,
+    /// Cached cover of the album.
+    art: Option<PathBuf>,
+    position: Option<Duration>,
+    duration: Option<Duration>,
+    volume: f64,
+    /// Contents last written, to skip writes that change nothing.
+    written: Option<String>,
+}
+
+impl NowPlaying {
+    /// Write to a file in a format, json or a template, see `render`.
+    pub fn new(path: PathBuf, format: &str) -> Self {
+        let format = match format {
+            "json" => Format::Json,
+            template => Format::Template(template.to_string()),
+        };
+
+        let shared = Arc::new(Shared {
+            path,
+            format,
+            state: Mutex::new(State {
+                status: "stopped",
+                ..Default::default()
+            }),
+        });
+        shared.update(|_| {});
+
+        Self { shared }
+    }
+}
+
+impl Extension for NowPlaying {
+    fn name(&self) -> &'static str {
+        "now-playing"
+    }
+
+    fn notify(&self, notification: &Notification) {
+        let status = match notification.event.as_str() {
+            "start" | "resume" => "playing",
+            "pause" => "paused",
+            "stop" | "queue-empty" => "stopped",
+            _ => return,
+        };
+
+        let started = notification.event == "start" && !notification.uri.is_empty();
+        self.shared.update(|state| {
+            if started {
+                state.uri = notification.uri.clone();
+                state.tags = Tags::default();
+                state.art = None;
+            }
+
+            state.status = status;
+            state.position = notification.position;
+            state.duration = notification.duration;
+            state.volume = notification.volume;
+        });
+
+        // Reading the tags prerolls the file, which is why it's done in a thread.
+        if started {
+            let shared = Arc::clone(&self.shared);
+            let uri = notification.uri.clone();
+
+            thread::spawn(move || {
+                let tags = tags::read(&uri).unwrap_or_default();
+                #[cfg(feature = "cover-art")]
+                let art = crate::cover_art::CoverArt::new().cached(&tags);
+                #[cfg(not(feature = "cover-art"))]
+                let art = None;
+
+                shared.update(|state| {
+                    if state.uri == uri {
+                        state.tags = tags;
+                        state.art = art;
+                    }
+                });
+            });
+        }
+    }
+
+    fn position(&self, notification: &Notification) {
+        self.shared.update(|state| {
+            state.position = notification.position;
+            state.duration = notification.duration;
+            state.volume = notification.volume;
+        });
+    }
+}
+
+impl Shared {
+    /// Change the state and write the file if its contents changed.
+    /// Failures are only logged, a broken file must never interrupt playback.
+    fn update<F: FnOnce(&mut State)>(&self, f: F) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state);
+
+        let contents = match &self.format {
+            Format::Json => json(&state),
+            Format::Template(template) => render(template, &state) + "\n",
+        };
+        if state.written.as_ref() == Some(&contents) {
+            return;
+        }
+
+        match write(&self.path, &contents) {
+            Ok(()) => state.written = Some(contents),
+            Err(e) => tracing::warn!("can't write {}: {e}", self.path.display()),
+        }
+    }
+}
+
+/// Replace a file at once, so that readers never see a partial one.
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+/// Fill in the placeholders of a template: `{status}`, `{title}`, `{artist}`,
+/// `{album}`, `{file}`, `{uri}`, `{art}`, `{position}`, `{duration}` and `{volume}`.
+/// The title falls back to the file name, unknown values are empty
+/// and everything but the status is empty while stopped.
+/// Other text in braces is kept as is.
+fn render(template: &str, state: &State) -> String {
+    let tag = |tag: &Option<String>| tag.clone().unwrap_or_default();
+    let value = |name: &str| {
+        let value = match name {
+            "status" => return Some(state.status.to_string()),
+            "title" => state
+                .tags
+                .title
+                .clone()
+                .unwrap_or_else(|| uri_name(&state.uri)),
+            "artist" => tag(&state.tags.artist),
+            "album" => tag(&state.tags.album),
+            "file" => uri_name(&state.uri),
+            "uri" => state.uri.clone(),
+            "art" => state
+                .art
+                .as_ref()
+                .map(|art| art.display().to_string())
+                .unwrap_or_default(),
+            "position" => clock(state.position),
+            "duration" => clock(state.duration),
+            "volume" => format!("{:.0}", state.volume * 100.0),
+            _ => return None,
+        };
+
+        Some(if state.status == "stopped" {
+            String::new()
+        } else {
+            value
+        })
+    };
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match rest
+            .find('}')
+            .and_then(|end| Some((value(&rest[1..end])?, end)))
+        {
+            Some((value, end)) => {
+                rendered.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Serialize the state as a JSON object.
+/// Unknown values are null, position and duration are in seconds.
+fn json(state: &State) -> String {
+    let text = |value: Option<&str>| match value {
+        Some(value) => format!("\"{}\"", json::escape(value)),
+        None => String::from("null"),
+    };
+    let seconds = |duration: Option<Duration>| match duration {
+        Some(duration) => duration.as_secs().to_string(),
+        None => String::from("null"),
+    };
+
+    if state.status == "stopped" {
+        return String::from("{\"status\":\"stopped\"}\n");
+    }
+
+    format!(
+        "{{\"status\":\"{}\",\"uri\":{},\"file\":{},\"title\":{},\"artist\":{},\"album\":{},\"art\":{},\"position\":{},\"duration\":{},\"volume\":{}}}\n",
+        state.status,
+        text(Some(&state.uri)),
+        text(Some(&uri_name(&state.uri))),
+        text(state.tags.title.as_deref()),
+        text(state.tags.artist.as_deref()),
+        text(state.tags.album.as_deref()),
+        text(state.art.as_deref().and_then(Path::to_str)),
+        seconds(state.position),
+        seconds(state.duration),
+        state.volume,
+    )
+}
+
+/// Format a duration as M:SS, or H:MM:SS from an hour on.
+fn clock(duration: Option<Duration>) -> String {
+    let seconds = match duration {
+        Some(duration) => duration.as_secs(),
+        None => return String::new(),
+    };
+
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,1085 @@
+use crate::backend::PlaybackBackend;
+use crate::eq;
+use crate::extension::{Extension, Notification};
+use crate::fade::FadePolicy;
+use crate::queue::Advance;
+use crate::task::Progress;
+
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Interval in which the volume is changed during a ramp.
+const RAMP_STEP: Duration = Duration::from_millis(10);
+
+/// How long a track is previewed for, from how far into it and at which
+/// fraction of the volume.
+const PREVIEW_LENGTH: Duration = Duration::from_secs(10);
+const PREVIEW_START: f64 = 0.25;
+const PREVIEW_VOLUME: f64 = 0.5;
+
+/// Largest gain in dB matching the loudness of tracks may apply, either way.
+pub const MAX_LEVEL_GAIN: f64 = 20.0;
+
+/// State of a playback backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayState {
+    Stopped,
+    Buffering,
+    Paused,
+    Playing,
+}
+
+/// Notification from the playback thread.
+#[derive(Clone, Debug)]
+pub enum PlaybackEvent {
+    EndOfStream,
+    Error(String),
+    /// Problem that doesn't stop playback, e.g. a corrupt frame that was skipped.
+    Warning(String),
+    Buffering(i32),
+    StateChanged(PlayState),
+}
+
+/// What to play after the current track ends.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AutoplayState {
+    pub repeat_list: bool,
+    pub repeat: bool,
+    pub sequential: bool,
+    pub shuffle: bool,
+    /// Remove tracks from the list once playback moves on from them.
+    pub consume: bool,
+}
+
+/// Source of the ReplayGain adjustment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayGain {
+    #[default]
+    Off,
+    Track,
+    Album,
+    /// Album gain while playing an album, track gain otherwise.
+    Auto,
+}
+
+impl FromStr for ReplayGain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "track" => Ok(Self::Track),
+            "album" => Ok(Self::Album),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "unknown ReplayGain mode {s}, expected off, track, album or auto"
+            )),
+        }
+    }
+}
+
+/// How the volume gauge and steps map to the amplitude.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VolumeScale {
+    #[default]
+    Linear,
+    /// Cube of the gauge position, whose steps sound more even, like most system mixers.
+    Cubic,
+}
+
+impl VolumeScale {
+    /// Get the amplitude of a gauge position from 0 to 1.
+    pub fn amplitude(self, position: f64) -> f64 {
+        match self {
+            Self::Linear => position,
+            Self::Cubic => position.powi(3),
+        }
+    }
+
+    /// Get the gauge position of an amplitude.
+    pub fn position(self, amplitude: f64) -> f64 {
+        match self {
+            Self::Linear => amplitude,
+            Self::Cubic => amplitude.cbrt(),
+        }
+    }
+}
+
+impl FromStr for VolumeScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "cubic" => Ok(Self::Cubic),
+            _ => Err(format!(
+                "unknown volume scale {s}, expected linear or cubic"
+            )),
+        }
+    }
+}
+
+/// What happens around a volume ramp.
+#[derive(Clone, Copy, Debug)]
+enum Ramp {
+    /// Fade in after playback has started.
+    Resume,
+    /// Fade out, then pause.
+    Pause,
+    /// Fade out, seek and fade in again.
+    Seek(Duration),
+    /// Fade out, then stop.
+    Stop,
+}
+
+/// Volume ramp running in the background.
+struct Fader {
+    thread: JoinHandle<()>,
+    /// Set to skip the rest of the ramp, what follows it still happens.
+    skip: Arc<AtomicBool>,
+    /// Volume to return to once the ramp is done.
+    volume: f64,
+}
+
+/// State of the player before a preview, which is restored once it ends.
+struct Preview {
+    /// Track that was loaded, its position and whether it was playing.
+    track: Option<String>,
+    position: Duration,
+    state: PlayState,
+    volume: f64,
+    /// Loudness in dBFS the previews are matched to, that of the interrupted track
+    /// or, if it wasn't heard long enough, of the first preview.
+    reference: Option<f64>,
+    /// Whether the gain of the current preview matches the reference.
+    matched: bool,
+    /// When the current preview started.
+    started: Instant,
+}
+
+/// Playback engine driving a backend.
+/// The backend is exposed for direct control, e.g. without volume ramps.
+pub struct Player<B: PlaybackBackend + 'static> {
+    pub backend: Arc<B>,
+    /// Volume to apply once playback has started.
+    /// Setting it earlier is overridden by some sinks restoring their own volume.
+    pending_volume: Mutex<Option<f64>>,
+    /// Position to seek to once playback has started.
+    pending_seek: Mutex<Option<Duration>>,
+    /// Duration of the volume ramps when pausing, resuming, seeking and stopping.
+    ramp: Duration,
+    fader: Mutex<Option<Fader>>,
+    state: Mutex<PlayState>,
+    /// URI the backend switches to seamlessly when the current track ends.
+    gapless_next: Mutex<Option<String>>,
+    /// Policy of the fades between tracks, `None` without fades.
+    fade_policy: Option<FadePolicy>,
+    /// Gain in dB matching the loudness of the tracks.
+    gain: Mutex<f64>,
+    preview: Mutex<Option<Preview>>,
+    extensions: Vec<Box<dyn Extension>>,
+}
+
+impl<B: PlaybackBackend + 'static> Player<B> {
+    pub fn new(backend: B, initial_volume: Option<f64>) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            pending_volume: Mutex::new(initial_volume),
+            pending_seek: Mutex::new(None),
+            ramp: Duration::ZERO,
+            fader: Mutex::new(None),
+            state: Mutex::new(PlayState::Stopped),
+            gapless_next: Mutex::new(None),
+            fade_policy: None,
+            gain: Mutex::new(0.0),
+            preview: Mutex::new(None),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Ramp the volume over this duration to avoid clicks when pausing,
+    /// resuming, seeking and stopping. Zero switches immediately.
+    pub fn set_ramp(&mut self, ramp: Duration) {
+        self.ramp = ramp;
+    }
+
+    /// Set which transitions between tracks fade, `None` if there are no fades.
+    pub fn set_fade_policy(&mut self, policy: Option<FadePolicy>) {
+        self.fade_policy = policy;
+    }
+
+    /// Add an extension to notify of playback events.
+    pub fn register(&mut self, extension: Box<dyn Extension>) {
+        self.extensions.push(extension);
+    }
+
+    /// Get the names of the registered extensions.
+    pub fn extensions(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.extensions.iter().map(|extension| extension.name())
+    }
+
+    /// Call a function for every playback event.
+    /// It is called from the playback thread.
+    pub fn subscribe<F: Fn(PlaybackEvent) + Send + Sync + 'static>(&self, f: F) {
+        self.backend.subscribe(Arc::new(f));
+    }
+
+    /// Play a local file.
+    pub fn play_path(&self, path: &Path) {
+        self.play_uri(&file_uri(path));
+    }
+
+    pub fn play_uri(&self, uri: &str) {
+        self.settle();
+        self.backend.set_uri(Some(uri));
+        self.backend.play();
+        self.notify("start");
+    }
+
+    /// Play a URI starting at a position.
+    pub fn play_uri_at(&self, uri: &str, position: Duration) {
+        *self.pending_seek.lock().unwrap() = Some(position).filter(|p| !p.is_zero());
+        self.play_uri(uri);
+    }
+
+    /// Play a URI starting at a position without notifying the extensions,
+    /// e.g. for a preview that isn't part of the listening history.
+    pub fn audition(&self, uri: &str, position: Duration) {
+        self.settle();
+        *self.pending_seek.lock().unwrap() = Some(position).filter(|p| !p.is_zero());
+        self.backend.set_uri(Some(uri));
+        self.backend.play();
+    }
+
+    /// Load a URI paused at a position, e.g. to go back to a paused track.
+    pub fn load_paused_at(&self, uri: &str, position: Duration) {
+        self.settle();
+        *self.pending_seek.lock().unwrap() = Some(position).filter(|p| !p.is_zero());
+        self.backend.set_uri(Some(uri));
+        self.backend.pause();
+    }
+
+    /// Update the player's state from a playback event.
+    /// Must be called for every event received through `subscribe`.
+    /// Returns false if the event only ended a preview and needs no further handling.
+    pub fn handle_event(&self, event: &PlaybackEvent) -> bool {
+        if let PlaybackEvent::StateChanged(state) = event {
+            *self.state.lock().unwrap() = *state;
+        }
+
+        // Seeks only work once the track has been loaded, paused or playing.
+        if let PlaybackEvent::StateChanged(PlayState::Paused | PlayState::Playing) = event {
+            if let Some(position) = self.pending_seek.lock().unwrap().take() {
+                self.backend.seek(position);
+            }
+        }
+
+        if let PlaybackEvent::StateChanged(PlayState::Playing) = event {
+            if let Some(volume) = self.pending_volume.lock().unwrap().take() {
+                self.backend.set_volume(volume);
+            }
+        }
+
+        // Previews that end early go back to the interrupted track.
+        if self.is_previewing()
+            && matches!(event, PlaybackEvent::EndOfStream | PlaybackEvent::Error(_))
+        {
+            self.end_preview(true);
+            return false;
+        }
+
+        true
+    }
+
+    /// Carry out what autoplay decided after the current track ended.
+    /// `continues_album` is set if the next track is from the same album,
+    /// it then starts without a fade-in if the fade policy wants none in between.
+    pub fn advance(&self, advance: &Advance, continues_album: bool) {
+        match advance {
+            Advance::Stop => {
+                self.backend.stop();
+                self.notify("stop");
+            }
+            Advance::Repeat => {
+                self.backend.seek(Duration::ZERO);
+                self.backend.play();
+                self.notify("start");
+            }
+            Advance::Play(track) => {
+                if continues_album && self.joins_albums() {
+                    self.skip_fade_in();
+                }
+
+                self.play_path(track);
+            }
+            Advance::Exhausted => self.notify("queue-empty"),
+            Advance::Idle => {
+                self.notify("queue-empty");
+                self.backend.stop();
+                self.notify("stop");
+            }
+        }
+    }
+
+    /// Note that the current track changed, sending a start event
+    /// if the backend switched to it on its own in a gapless transition.
+    pub fn track_changed(&self, uri: Option<&str>) {
+        let gapless = uri.is_some() && self.gapless_next.lock().unwrap().as_deref() == uri;
+        if gapless {
+            self.notify("start");
+        }
+    }
+
+    /// Preview a track without notifying the extensions, from a quarter into it,
+    /// past most intros, and at a reduced volume. The first preview interrupts
+    /// the current track, which `end_preview` returns to.
+    pub fn preview(&self, uri: &str, duration: Option<Duration>) {
+        let mut preview = self.preview.lock().unwrap();
+        match &mut *preview {
+            Some(preview) => {
+                preview.started = Instant::now();
+                preview.matched = false;
+            }
+            None => {
+                let state = *self.state.lock().unwrap();
+                let volume = self.volume();
+                let reference = match state {
+                    PlayState::Stopped => None,
+                    _ => self.backend.loudness().current,
+                };
+
+                *preview = Some(Preview {
+                    track: self.current_uri(),
+                    position: self.backend.position().unwrap_or_default(),
+                    state,
+                    volume,
+                    reference,
+                    matched: false,
+                    started: Instant::now(),
+                });
+                self.set_volume(volume * PREVIEW_VOLUME);
+            }
+        }
+        drop(preview);
+
+        tracing::debug!("previewing {uri}");
+        let position = duration.map(|duration| duration.mul_f64(PREVIEW_START));
+        self.audition(uri, position.unwrap_or_default());
+    }
+
+    /// End a preview that has played long enough, otherwise match its loudness
+    /// to the interrupted track once enough of it has been heard to measure it.
+    pub fn update_preview(&self) {
+        let expired = self
+            .preview
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|preview| preview.started.elapsed() >= PREVIEW_LENGTH);
+
+        if expired {
+            self.end_preview(true);
+        } else {
+            self.match_preview();
+        }
+    }
+
+    /// Amplify the current preview to the loudness of the interrupted track,
+    /// like `match_loudness`.
+    fn match_preview(&self) {
+        let mut preview = self.preview.lock().unwrap();
+        let preview = match &mut *preview {
+            Some(preview) if !preview.matched => preview,
+            _ => return,
+        };
+
+        let current = match self.backend.loudness().current {
+            Some(current) => current,
+            None => return,
+        };
+        preview.matched = true;
+
+        // Both are measured before the gain, the interrupted track was heard with `gain`.
+        let reference = *preview.reference.get_or_insert(current);
+        let gain = (self.gain() + reference - current).clamp(-MAX_LEVEL_GAIN, MAX_LEVEL_GAIN);
+        tracing::debug!("preview matched at {gain:+.1} dB");
+        self.backend.set_gain(gain);
+    }
+
+    /// Stop previewing and, if `restore` is set, go back to the track
+    /// that was interrupted, where it was and paused if it was.
+    pub fn end_preview(&self, restore: bool) {
+        let preview = match self.preview.lock().unwrap().take() {
+            Some(preview) => preview,
+            None => return,
+        };
+
+        self.set_volume(preview.volume);
+        self.backend.set_gain(self.gain());
+        if !restore {
+            return;
+        }
+
+        match (preview.track, preview.state) {
+            (Some(track), PlayState::Playing) => self.audition(&track, preview.position),
+            (Some(track), PlayState::Paused) => self.load_paused_at(&track, preview.position),
+            (track, _) => {
+                self.backend.stop();
+                self.backend.set_uri(track.as_deref());
+            }
+        }
+    }
+
+    /// Whether a preview is playing instead of the current track.
+    pub fn is_previewing(&self) -> bool {
+        self.preview.lock().unwrap().is_some()
+    }
+
+    fn is_playing(&self) -> bool {
+        *self.state.lock().unwrap() == PlayState::Playing
+    }
+
+    /// Ramp the volume in a background task and pause, seek or stop around it.
+    /// A ramp that is still running is finished first.
+    fn fade(&self, ramp: Ramp) {
+        self.settle();
+
+        let volume = self.backend.volume();
+        if let Ramp::Resume = ramp {
+            self.backend.set_volume(0.0);
+            self.backend.play();
+        }
+
+        let backend = Arc::clone(&self.backend);
+        let skip = Arc::new(AtomicBool::new(false));
+        let skipped = Arc::clone(&skip);
+        let steps = (self.ramp.as_millis() / RAMP_STEP.as_millis()).max(1) as u32;
+
+        let thread = thread::spawn(move || {
+            let change = |from: f64, to: f64| {
+                for step in 1..=steps {
+                    if skipped.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let t = step as f64 / steps as f64;
+                    backend.set_volume(from + (to - from) * t);
+                    thread::sleep(RAMP_STEP);
+                }
+                backend.set_volume(to);
+            };
+
+            match ramp {
+                Ramp::Resume => change(0.0, volume),
+                Ramp::Pause => {
+                    change(volume, 0.0);
+                    backend.pause();
+                    backend.set_volume(volume);
+                }
+                Ramp::Seek(position) => {
+                    change(volume, 0.0);
+                    backend.seek(position);
+                    change(0.0, volume);
+                }
+                Ramp::Stop => {
+                    change(volume, 0.0);
+                    backend.stop();
+                    backend.set_volume(volume);
+                }
+            }
+        });
+
+        *self.fader.lock().unwrap() = Some(Fader {
+            thread,
+            skip,
+            volume,
+        });
+    }
+
+    /// Finish the running ramp at once, e.g. before another one starts.
+    fn settle(&self) {
+        if let Some(fader) = self.fader.lock().unwrap().take() {
+            fader.skip.store(true, Ordering::SeqCst);
+            let _ = fader.thread.join();
+        }
+    }
+
+    /// Wait for the running ramp to end, e.g. to fade out completely before exiting.
+    pub fn finish_fade(&self) {
+        if let Some(fader) = self.fader.lock().unwrap().take() {
+            let _ = fader.thread.join();
+        }
+    }
+
+    pub fn pause(&self) {
+        if self.ramp.is_zero() || !self.is_playing() {
+            self.settle();
+            self.backend.pause();
+        } else {
+            self.fade(Ramp::Pause);
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.ramp.is_zero() || self.is_playing() {
+            self.settle();
+            self.backend.play();
+        } else {
+            self.fade(Ramp::Resume);
+        }
+    }
+
+    pub fn seek(&self, position: Duration) {
+        if self.ramp.is_zero() || !self.is_playing() {
+            self.settle();
+            self.backend.seek(position);
+        } else {
+            self.fade(Ramp::Seek(position));
+        }
+    }
+
+    pub fn stop(&self) {
+        if self.ramp.is_zero() || !self.is_playing() {
+            self.settle();
+            self.backend.stop();
+        } else {
+            self.fade(Ramp::Stop);
+        }
+    }
+
+    /// Get the volume, including one that hasn't been applied yet
+    /// and the one a running ramp returns to.
+    pub fn volume(&self) -> f64 {
+        if let Some(fader) = &*self.fader.lock().unwrap() {
+            if !fader.thread.is_finished() {
+                return fader.volume;
+            }
+        }
+
+        self.pending_volume
+            .lock()
+            .unwrap()
+            .unwrap_or_else(|| self.backend.volume())
+    }
+
+    pub fn set_volume(&self, volume: f64) {
+        self.settle();
+        *self.pending_volume.lock().unwrap() = None;
+        self.backend.set_volume(volume);
+    }
+
+    /// Get the URI of the track that is currently playing.
+    /// Unlike `Play::uri` this follows gapless transitions.
+    pub fn current_uri(&self) -> Option<String> {
+        self.backend.current_uri()
+    }
+
+    /// Set the URI to switch to seamlessly when the current track is about to end.
+    pub fn set_gapless_next(&self, uri: Option<String>) {
+        let mut next = self.gapless_next.lock().unwrap();
+        if *next != uri {
+            next.clone_from(&uri);
+            self.backend.set_next_uri(uri);
+        }
+    }
+
+    /// Use the album gain instead of the track gain.
+    /// Has no effect if ReplayGain is disabled.
+    pub fn set_album_gain(&self, album: bool) {
+        self.backend.set_album_gain(album);
+    }
+
+    /// Whether tracks of the same album follow each other without fades,
+    /// so that it matters whether the upcoming track continues the album.
+    pub fn joins_albums(&self) -> bool {
+        self.fade_policy == Some(FadePolicy::BetweenAlbums)
+    }
+
+    /// Fade out the current track unless the upcoming one continues its album
+    /// and the fade policy wants no fades in between.
+    pub fn prepare_fades(&self, continues_album: bool) {
+        self.backend
+            .set_fade_out(!(continues_album && self.joins_albums()));
+    }
+
+    /// Start the next track without a fade-in.
+    pub fn skip_fade_in(&self) {
+        self.backend.skip_fade_in();
+    }
+
+    /// Apply equalizer gains in dB, `None` for a flat response.
+    pub fn set_eq(&self, gains: Option<[f64; eq::BANDS]>) {
+        self.backend.set_eq(gains);
+    }
+
+    pub fn gain(&self) -> f64 {
+        *self.gain.lock().unwrap()
+    }
+
+    /// Amplify every track by a gain in dB, e.g. the one found by `match_loudness`.
+    pub fn set_gain(&self, gain: f64) {
+        *self.gain.lock().unwrap() = gain;
+        self.backend.set_gain(gain);
+    }
+
+    /// Amplify the current track to the loudness of the previous one as both were heard,
+    /// and keep the gain for the following tracks.
+    /// Returns the new gain, `None` if either loudness is unknown.
+    pub fn match_loudness(&self) -> Option<f64> {
+        let loudness = self.backend.loudness();
+        let (previous, current) = (loudness.previous?, loudness.current?);
+
+        // The previous track was heard with the gain, and so is the current one.
+        let gain = (self.gain() + previous - current).clamp(-MAX_LEVEL_GAIN, MAX_LEVEL_GAIN);
+        self.set_gain(gain);
+
+        Some(gain)
+    }
+
+    /// Play the signal without fades, the EQ or other processing.
+    /// ReplayGain still applies.
+    pub fn set_passthrough(&self, passthrough: bool) {
+        self.backend.set_passthrough(passthrough);
+    }
+
+    /// Get the progress ratio of the current song.
+    /// Returns 0.0 if no song is selected.
+    pub fn current_progress(&self) -> f64 {
+        if let Some(position) = self.backend.position() {
+            if let Some(duration) = self.backend.duration() {
+                if duration.as_secs() > 0 {
+                    position.as_secs() as f64 / duration.as_secs() as f64
+                } else {
+                    position.as_millis() as f64 / duration.as_millis() as f64
+                }
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the status bar descriptions of the extensions that have one.
+    pub fn extension_status(&self) -> impl Iterator<Item = String> + '_ {
+        self.extensions
+            .iter()
+            .filter_map(|extension| extension.status())
+    }
+
+    /// Send a playback event and the current track metadata to all extensions.
+    pub fn notify(&self, event: &str) {
+        self.notify_with(event, None);
+    }
+
+    /// Send the progress of a background task to all extensions,
+    /// as task-progress while it runs and task-done once it finished.
+    pub fn notify_task(&self, progress: Progress) {
+        let event = if progress.is_done() {
+            "task-done"
+        } else {
+            "task-progress"
+        };
+
+        self.notify_with(event, Some(progress));
+    }
+
+    /// Send the position of the current track to all extensions, as a position event.
+    pub fn notify_position(&self) {
+        if self.extensions.is_empty() {
+            return;
+        }
+
+        let notification = self.notification("position", None);
+        for extension in &self.extensions {
+            extension.position(&notification);
+        }
+    }
+
+    fn notify_with(&self, event: &str, task: Option<Progress>) {
+        if self.extensions.is_empty() {
+            return;
+        }
+
+        let notification = self.notification(event, task);
+        for extension in &self.extensions {
+            extension.notify(&notification);
+        }
+    }
+
+    fn notification(&self, event: &str, task: Option<Progress>) -> Notification {
+        Notification {
+            event: event.to_string(),
+            uri: self.current_uri().unwrap_or_default(),
+            position: self.backend.position(),
+            duration: self.backend.duration(),
+            volume: self.volume(),
+            task,
+        }
+    }
+}
+
+/// Get the URI of a local file.
+/// Relative paths are resolved against the working directory.
+/// Everything but unreserved characters is percent-encoded,
+/// so spaces, `#`, `?` and non-UTF-8 names survive the round trip.
+/// URLs listed as tracks, e.g. from the command line, are returned as they are.
+pub fn file_uri(path: &Path) -> String {
+    if let Some(url) = path.to_str().filter(|path| is_url(path)) {
+        return url.to_string();
+    }
+
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    uri
+}
+
+/// Check whether a track is a URL such as `https://example.com/a.mp3` rather than a path.
+pub fn is_url(track: &str) -> bool {
+    match track.split_once("://") {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Get the local path of a `file://` URI.
+/// Returns `None` for other schemes and malformed escapes.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let encoded = encoded.strip_prefix("localhost").unwrap_or(encoded);
+
+    percent_decode(encoded)
+}
+
+/// Decode the percent escapes of a URI path.
+/// Returns `None` for malformed escapes.
+pub fn percent_decode(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+/// Get the file name a URI refers to for display.
+/// Local names are decoded, lossily if they aren't valid UTF-8.
+pub fn uri_name(uri: &str) -> String {
+    match uri_to_path(uri) {
+        Some(path) => path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+        None => uri.split('/').next_back().unwrap_or_default().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::queue::Queue;
+    use crate::tags::Tags;
+    use std::collections::HashSet;
+    use std::sync::mpsc::{self, Receiver};
+
+    /// Extension recording the events it is sent.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Extension for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn notify(&self, notification: &Notification) {
+            self.0.lock().unwrap().push(notification.event.clone());
+        }
+    }
+
+    fn player() -> (Player<MockBackend>, Arc<Mutex<Vec<String>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut player = Player::new(MockBackend::default(), None);
+        player.register(Box::new(Recorder(Arc::clone(&events))));
+
+        (player, events)
+    }
+
+    fn take(events: &Mutex<Vec<String>>) -> Vec<String> {
+        std::mem::take(&mut *events.lock().unwrap())
+    }
+
+    #[test]
+    fn advance_notifies() {
+        let (player, events) = player();
+
+        player.advance(&Advance::Play("/music/a.flac".into()), false);
+        assert_eq!(
+            player.current_uri(),
+            Some(file_uri(Path::new("/music/a.flac")))
+        );
+        assert_eq!(player.backend.play_state(), PlayState::Playing);
+        assert_eq!(take(&events), ["start"]);
+
+        player.backend.seek(Duration::from_secs(30));
+        player.advance(&Advance::Repeat, false);
+        assert_eq!(player.backend.position(), Some(Duration::ZERO));
+        assert_eq!(take(&events), ["start"]);
+
+        player.advance(&Advance::Exhausted, false);
+        assert_eq!(player.backend.play_state(), PlayState::Playing);
+        assert_eq!(take(&events), ["queue-empty"]);
+
+        player.advance(&Advance::Idle, false);
+        assert_eq!(player.backend.play_state(), PlayState::Stopped);
+        assert_eq!(take(&events), ["queue-empty", "stop"]);
+
+        player.advance(&Advance::Stop, false);
+        assert_eq!(take(&events), ["stop"]);
+    }
+
+    #[test]
+    fn gapless_transition_starts_track() {
+        let (player, events) = player();
+        let next = file_uri(Path::new("/music/b.flac"));
+
+        player.set_gapless_next(Some(next.clone()));
+        player.track_changed(Some(&file_uri(Path::new("/music/c.flac"))));
+        assert!(take(&events).is_empty());
+
+        player.track_changed(Some(&next));
+        assert_eq!(take(&events), ["start"]);
+    }
+
+    #[test]
+    fn preview_returns_to_track() {
+        let (player, events) = player();
+        let track = file_uri(Path::new("/music/a.flac"));
+
+        player.play_uri(&track);
+        player.handle_event(&PlaybackEvent::StateChanged(PlayState::Playing));
+        player.backend.seek(Duration::from_secs(30));
+        take(&events);
+
+        player.preview(
+            &file_uri(Path::new("/music/b.flac")),
+            Some(Duration::from_secs(200)),
+        );
+        assert!(player.is_previewing());
+        assert_eq!(player.backend.volume(), PREVIEW_VOLUME);
+        player.handle_event(&PlaybackEvent::StateChanged(PlayState::Playing));
+        assert_eq!(player.backend.position(), Some(Duration::from_secs(50)));
+
+        assert!(!player.handle_event(&PlaybackEvent::EndOfStream));
+        assert!(!player.is_previewing());
+        assert_eq!(player.current_uri(), Some(track));
+        assert_eq!(player.backend.volume(), 1.0);
+
+        player.handle_event(&PlaybackEvent::StateChanged(PlayState::Playing));
+        assert_eq!(player.backend.position(), Some(Duration::from_secs(30)));
+        assert!(take(&events).is_empty());
+        assert!(player.handle_event(&PlaybackEvent::EndOfStream));
+    }
+
+    /// Player and queue driven by the events of the mock backend like the UI drives them.
+    struct Session {
+        player: Player<MockBackend>,
+        queue: Queue,
+        receiver: Receiver<PlaybackEvent>,
+        events: Arc<Mutex<Vec<String>>>,
+        track: Option<String>,
+    }
+
+    impl Session {
+        fn new(autoplay: AutoplayState) -> Self {
+            let (player, events) = player();
+            let (sender, receiver) = mpsc::channel();
+            player.subscribe(move |event| sender.send(event).unwrap());
+
+            let mut queue = Queue::default();
+            queue.autoplay = autoplay;
+            queue.set_files(
+                ["/music/a.flac", "/music/b.flac", "/music/c.flac"]
+                    .map(PathBuf::from)
+                    .into(),
+            );
+
+            Self {
+                player,
+                queue,
+                receiver,
+                events,
+                track: None,
+            }
+        }
+
+        /// Handle the pending events, then note a track change.
+        fn update(&mut self) {
+            while let Ok(event) = self.receiver.try_recv() {
+                if !self.player.handle_event(&event) {
+                    continue;
+                }
+
+                if let PlaybackEvent::EndOfStream = event {
+                    let current = self.player.current_uri();
+                    let advance = self.queue.advance(current.as_deref(), false, |_| 1.0);
+                    self.player.advance(&advance, false);
+                }
+            }
+
+            let uri = self.player.current_uri();
+            if uri != self.track {
+                self.queue.leave(self.track.as_deref());
+                self.player.track_changed(uri.as_deref());
+                self.track = uri;
+            }
+
+            let next = self.track.as_deref().and_then(|track| {
+                self.queue.gapless_next(track, false, |_| Tags {
+                    album: Some("Album".to_string()),
+                    ..Tags::default()
+                })
+            });
+            self.player.set_gapless_next(next);
+        }
+
+        /// Play the current track to its end.
+        fn finish(&mut self) {
+            self.player.backend.finish();
+            self.update();
+        }
+
+        fn current(&self) -> Option<PathBuf> {
+            self.player.current_uri().as_deref().and_then(uri_to_path)
+        }
+    }
+
+    #[test]
+    fn end_of_track_advances() {
+        let mut session = Session::new(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+        session.player.play_path(Path::new("/music/b.flac"));
+        session.update();
+        take(&session.events);
+
+        session.finish();
+        assert_eq!(session.current(), Some("/music/c.flac".into()));
+        assert_eq!(session.player.backend.play_state(), PlayState::Playing);
+        assert_eq!(take(&session.events), ["start"]);
+
+        session.finish();
+        assert_eq!(session.current(), Some("/music/c.flac".into()));
+        assert_eq!(take(&session.events), ["queue-empty"]);
+
+        session.queue.autoplay.sequential = false;
+        session.finish();
+        assert_eq!(session.player.backend.play_state(), PlayState::Stopped);
+        assert_eq!(take(&session.events), ["queue-empty", "stop"]);
+    }
+
+    #[test]
+    fn end_of_track_shuffles() {
+        let mut session = Session::new(AutoplayState {
+            shuffle: true,
+            ..AutoplayState::default()
+        });
+        session.player.play_path(Path::new("/music/a.flac"));
+        session.update();
+        take(&session.events);
+
+        let mut played = HashSet::new();
+        for _ in 0..3 {
+            let previous = session.current();
+            session.finish();
+
+            let current = session.current().unwrap();
+            assert_ne!(Some(&current), previous.as_ref());
+            assert!(played.insert(current));
+        }
+
+        assert_eq!(played.len(), 3);
+        assert_eq!(take(&session.events), ["start", "start", "start"]);
+    }
+
+    #[test]
+    fn end_of_track_consumes() {
+        let mut session = Session::new(AutoplayState {
+            sequential: true,
+            consume: true,
+            ..AutoplayState::default()
+        });
+        session.player.play_path(Path::new("/music/a.flac"));
+        session.update();
+
+        session.finish();
+        assert_eq!(session.current(), Some("/music/b.flac".into()));
+        assert_eq!(
+            session.queue.files(),
+            ["/music/b.flac", "/music/c.flac"].map(PathBuf::from)
+        );
+
+        session.finish();
+        session.finish();
+        assert_eq!(session.current(), Some("/music/c.flac".into()));
+        assert_eq!(session.queue.files(), [PathBuf::from("/music/c.flac")]);
+    }
+
+    #[test]
+    fn gapless_handoff() {
+        let mut session = Session::new(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+        session.player.play_path(Path::new("/music/a.flac"));
+        session.update();
+        take(&session.events);
+
+        // The backend switches on its own, without ending the stream.
+        session.finish();
+        assert_eq!(session.current(), Some("/music/b.flac".into()));
+        assert_eq!(
+            session.player.backend.uri(),
+            Some(file_uri(Path::new("/music/a.flac")))
+        );
+        assert_eq!(take(&session.events), ["start"]);
+
+        session.finish();
+        assert_eq!(session.current(), Some("/music/c.flac".into()));
+        assert_eq!(take(&session.events), ["start"]);
+
+        // Nothing follows the last track, so its stream ends.
+        session.finish();
+        assert_eq!(session.current(), Some("/music/c.flac".into()));
+        assert_eq!(take(&session.events), ["queue-empty"]);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::player::uri_to_path;
+use crate::remote::{self, LocalPaths};
+use crate::state;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Ordered list of local files, stored as an M3U file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playlist {
+    pub name: String,
+    pub path: PathBuf,
+    pub entries: Vec<PathBuf>,
+    /// URIs of the entries on network shares, which are written back instead of their paths.
+    pub uris: HashMap<PathBuf, String>,
+}
+
+impl Playlist {
+    /// Read an M3U file. Relative entries are resolved against its directory.
+    /// Entries on network shares such as `smb://nas/music/a.flac` are accessed through gvfs,
+    /// mounting the share if necessary.
+    /// Comments such as `#EXTINF` and entries that can't be accessed as files are skipped.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut local_paths = LocalPaths::default();
+        let mut uris = HashMap::new();
+
+        let entries = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                if line.starts_with("file://") {
+                    uri_to_path(line)
+                } else if remote::is_remote(line) {
+                    match local_paths.get(line) {
+                        Ok(entry) => {
+                            uris.insert(entry.clone(), line.to_string());
+                            Some(entry)
+                        }
+                        Err(e) => {
+                            tracing::warn!("skipping {line} in {}: {e}", path.display());
+                            None
+                        }
+                    }
+                } else if line.contains("://") {
+                    None
+                } else {
+                    Some(base.join(line))
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            name: playlist_name(path),
+            path: path.to_path_buf(),
+            entries,
+            uris,
+        })
+    }
+
+    /// Write the playlist back to its file.
+    /// Entries below its directory are written as relative paths, others as absolute ones.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let base = self.path.parent().unwrap_or(Path::new(""));
+        let tmp = self.path.with_file_name(format!(
+            ".{}.tmp",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+
+        let mut file = fs::File::create(&tmp)?;
+        writeln!(file, "#EXTM3U")?;
+        for entry in &self.entries {
+            match self.uris.get(entry) {
+                Some(uri) => writeln!(file, "{uri}")?,
+                None => {
+                    let entry = entry.strip_prefix(base).unwrap_or(entry);
+                    writeln!(file, "{}", entry.display())?;
+                }
+            }
+        }
+
+        file.sync_all()?;
+        fs::rename(tmp, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Rename the playlist and its file, which stays in the same directory.
+    pub fn rename(&mut self, name: &str) -> anyhow::Result<()> {
+        check_name(name)?;
+
+        let ext = self
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("m3u"));
+        let path = self.path.with_file_name(format!("{name}.{ext}"));
+
+        if path.exists() {
+            anyhow::bail!("{} already exists", path.display());
+        }
+
+        fs::rename(&self.path, &path)?;
+        self.name = name.to_string();
+        self.path = path;
+
+        Ok(())
+    }
+
+    /// Delete the playlist file.
+    pub fn delete(&self) -> anyhow::Result<()> {
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    /// Move an entry to another position, shifting the ones in between.
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        if from < self.entries.len() && to < self.entries.len() {
+            let entry = self.entries.remove(from);
+            self.entries.insert(to, entry);
+        }
+    }
+}
+
+/// Get the directory playlists created in musikbox are saved in.
+/// This is the `playlists` directory in the state directory.
+pub fn dir() -> Option<PathBuf> {
+    Some(state::dir()?.join("playlists"))
+}
+
+/// Find the saved playlists and the M3U files of a music directory, sorted by name.
+/// Unreadable files are skipped.
+pub fn find(music_dir: &Path) -> Vec<Playlist> {
+    let mut playlists: Vec<Playlist> = dir()
+        .into_iter()
+        .chain([music_dir.to_path_buf()])
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_playlist(path))
+        .filter_map(|path| Playlist::load(&path).ok())
+        .collect();
+
+    playlists.sort_by_key(|playlist| playlist.name.to_lowercase());
+    playlists
+}
+
+/// Create an empty playlist in the playlist directory.
+pub fn create(name: &str) -> anyhow::Result<Playlist> {
+    check_name(name)?;
+
+    let dir = dir().ok_or_else(|| anyhow::anyhow!("can't determine state directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("{name}.m3u"));
+    if path.exists() {
+        anyhow::bail!("playlist {name} already exists");
+    }
+
+    let playlist = Playlist {
+        name: name.to_string(),
+        path,
+        entries: Vec::new(),
+        uris: HashMap::new(),
+    };
+
+    playlist.save()?;
+    Ok(playlist)
+}
+
+/// Check whether a file is an M3U playlist by its extension.
+pub fn is_playlist(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+
+    matches!(ext.as_deref(), Some("m3u" | "m3u8")) && !playlist_name(path).starts_with('.')
+}
+
+fn playlist_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Check that a name can be used as a file name.
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        anyhow::bail!("invalid playlist name {name:?}");
+    }
+
+    Ok(())
+}
+
+/// Interleave lists, taking as many entries from each per round as its weight,
+/// e.g. two songs, then a podcast segment for weights 2 and 1.
+/// Lists that run out are skipped, so the longer ones continue alone.
+pub fn interleave(lists: &[(&[PathBuf], usize)]) -> Vec<PathBuf> {
+    let total = lists.iter().map(|(entries, _)| entries.len()).sum();
+    let mut positions = vec![0; lists.len()];
+    let mut mixed = Vec::with_capacity(total);
+
+    while mixed.len() < total {
+        for ((entries, weight), position) in lists.iter().zip(&mut positions) {
+            let end = (*position + (*weight).max(1)).min(entries.len());
+            mixed.extend_from_slice(&entries[*position..end]);
+            *position = end;
+        }
+    }
+
+    mixed
+}
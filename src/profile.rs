@@ -0,0 +1,99 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Durations of the phases of startup, e.g. to find out what makes a large
+/// library slow to open.
+#[derive(Debug)]
+pub struct Profile {
+    started: Instant,
+    /// Finished phases in the order they finished.
+    phases: Vec<(&'static str, Duration)>,
+    /// Phases that run in the background and when they started.
+    running: Vec<(&'static str, Instant)>,
+    /// Time from the start until the player was ready to use.
+    ready: Option<Duration>,
+}
+
+impl Profile {
+    /// Start measuring now.
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            phases: Vec::new(),
+            running: Vec::new(),
+            ready: None,
+        }
+    }
+
+    /// Run a phase and record its duration.
+    pub fn time<T, F: FnOnce() -> T>(&mut self, phase: &'static str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+
+        result
+    }
+
+    /// Record the duration of a phase that was measured elsewhere.
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        self.phases.push((phase, duration));
+    }
+
+    /// Start a phase that finishes later, e.g. in the background.
+    pub fn start(&mut self, phase: &'static str) {
+        self.running.push((phase, Instant::now()));
+    }
+
+    /// Record the duration of a started phase.
+    /// Has no effect if it isn't running, so it can be called on every completion.
+    pub fn finish(&mut self, phase: &'static str) {
+        if let Some(i) = self.running.iter().position(|(name, _)| *name == phase) {
+            let (name, start) = self.running.remove(i);
+            self.record(name, start.elapsed());
+        }
+    }
+
+    pub fn is_running(&self, phase: &'static str) -> bool {
+        self.running.iter().any(|(name, _)| *name == phase)
+    }
+
+    /// Record that the player is ready to use. Only the first call counts.
+    pub fn ready(&mut self) {
+        self.ready.get_or_insert_with(|| self.started.elapsed());
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.is_some()
+    }
+
+    /// Get a line per phase and the total, with unfinished phases marked as such.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        for (phase, duration) in &self.phases {
+            let _ = writeln!(report, "{phase:<20} {}", millis(*duration));
+        }
+        for (phase, start) in &self.running {
+            let _ = writeln!(
+                report,
+                "{phase:<20} not finished after {}",
+                millis(start.elapsed())
+            );
+        }
+
+        let total = self.ready.unwrap_or_else(|| self.started.elapsed());
+        let _ = write!(report, "{:<20} {}", "total", millis(total));
+
+        report
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:>9.1} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,768 @@
+use crate::player::{file_uri, uri_to_path, AutoplayState};
+use crate::state;
+use crate::stats::TrackStats;
+use crate::tags::Tags;
+
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Maximum number of tracks kept in the playback history.
+const HISTORY_LENGTH: usize = 1000;
+
+/// Most times a track can be set to repeat before the count starts over.
+pub const MAX_REPEAT_COUNT: u32 = 9;
+
+/// Days since the last play after which a track counts as not played recently at all.
+const RECENCY_DAYS: u64 = 365;
+
+/// Index files by their URIs, so that the file a URI refers to is found
+/// without encoding every path again. Duplicates map to their first index.
+pub fn uri_index(files: &[PathBuf]) -> HashMap<String, usize> {
+    let mut index = HashMap::with_capacity(files.len());
+    for (i, file) in files.iter().enumerate() {
+        index.entry(file_uri(file)).or_insert(i);
+    }
+
+    index
+}
+
+/// Get the index of the track after `current` in list order,
+/// wrapping around if the list is repeated.
+/// Playback starts at the beginning if there is no current track.
+pub fn sequential_next(len: usize, current: Option<usize>, repeat_list: bool) -> Option<usize> {
+    let mut track = current.map_or(0, |i| i + 1);
+
+    if track >= len && repeat_list {
+        track = 0
+    }
+
+    if track < len {
+        Some(track)
+    } else {
+        None
+    }
+}
+
+/// Get the index of the track before `current` in list order,
+/// wrapping around if the list is repeated.
+pub fn sequential_previous(len: usize, current: Option<usize>, repeat_list: bool) -> Option<usize> {
+    match current {
+        Some(0) if repeat_list && len > 0 => Some(len - 1),
+        Some(0) | None => None,
+        Some(i) => Some(i - 1),
+    }
+}
+
+/// What autoplay does after the current track ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Advance {
+    /// Stop, e.g. because playback was to stop after the track.
+    Stop,
+    /// Play the current track again.
+    Repeat,
+    /// Continue with another track.
+    Play(PathBuf),
+    /// The autoplay mode has nothing left to play.
+    Exhausted,
+    /// No autoplay mode is enabled.
+    Idle,
+}
+
+/// Where going back from the current track leads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Previous {
+    /// The track before it in list order.
+    Track(PathBuf),
+    /// The track played before it, shuffled playback goes back in the history.
+    History,
+    /// The start of the current track, there is nothing before it.
+    Restart,
+}
+
+/// Tracks autoplay continues with: the list, the tracks queued to play next
+/// and the shuffled order. Consumed tracks are left out of the list
+/// for the rest of the session.
+#[derive(Debug, Default)]
+pub struct Queue {
+    pub autoplay: AutoplayState,
+    /// Number of times the current track is played again besides `AutoplayState::repeat`.
+    pub repeat_count: u32,
+    /// Tracks played before the list continues, next first.
+    pub up_next: VecDeque<PathBuf>,
+    pub shuffle: Shuffle,
+    files: Vec<PathBuf>,
+    index: HashMap<String, usize>,
+    /// URIs of the consumed tracks.
+    consumed: HashSet<String>,
+}
+
+impl Queue {
+    /// Replace the list, leaving out the consumed tracks.
+    pub fn set_files(&mut self, mut files: Vec<PathBuf>) {
+        files.retain(|file| !self.consumed.contains(&file_uri(file)));
+
+        self.index = uri_index(&files);
+        self.files = files;
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Get the index of the file of the list a URI refers to.
+    pub fn index_of(&self, uri: &str) -> Option<usize> {
+        self.index.get(uri).copied()
+    }
+
+    /// Get the track after `current` in list order,
+    /// wrapping around if the list is repeated.
+    pub fn sequential_next(&self, current: Option<&str>) -> Option<PathBuf> {
+        let current = current.and_then(|uri| self.index_of(uri));
+        let track = sequential_next(self.files.len(), current, self.autoplay.repeat_list)?;
+
+        self.files.get(track).cloned()
+    }
+
+    /// Take the next track of the shuffled order.
+    pub fn next_shuffled<F>(&mut self, current: Option<&str>, weight: F) -> Option<PathBuf>
+    where
+        F: Fn(&Path) -> f64,
+    {
+        self.shuffle.next(&self.files, current, weight)
+    }
+
+    /// Start a new shuffled order of the list, e.g. to show the upcoming tracks.
+    pub fn reshuffle<F>(&mut self, current: Option<&str>, weight: F)
+    where
+        F: Fn(&Path) -> f64,
+    {
+        self.shuffle.reshuffle(&self.files, current, weight);
+    }
+
+    /// Whether the current track is played again when it ends,
+    /// indefinitely or for a number of times.
+    pub fn repeats(&self) -> bool {
+        self.autoplay.repeat || self.repeat_count > 0
+    }
+
+    /// Repeat the current track once more, up to `MAX_REPEAT_COUNT` times,
+    /// after which the count starts over at none.
+    pub fn add_repeat(&mut self) {
+        self.repeat_count = (self.repeat_count + 1) % (MAX_REPEAT_COUNT + 1);
+    }
+
+    /// Decide how to continue after the current track ended according to the autoplay mode,
+    /// taking the track from the queue or the shuffled order.
+    /// `stop` is set if playback stops after the track.
+    pub fn advance<F>(&mut self, current: Option<&str>, stop: bool, weight: F) -> Advance
+    where
+        F: Fn(&Path) -> f64,
+    {
+        if stop {
+            Advance::Stop
+        } else if self.repeats() {
+            if !self.autoplay.repeat {
+                self.repeat_count -= 1;
+            }
+
+            Advance::Repeat
+        } else if let Some(track) = self.up_next.pop_front() {
+            Advance::Play(track)
+        } else if self.autoplay.sequential {
+            self.sequential_next(current)
+                .map_or(Advance::Exhausted, Advance::Play)
+        } else if self.autoplay.shuffle {
+            self.next_shuffled(current, weight)
+                .map_or(Advance::Exhausted, Advance::Play)
+        } else {
+            Advance::Idle
+        }
+    }
+
+    /// Decide how to go on after the current track failed to play,
+    /// as if it had ended, except that it isn't repeated.
+    pub fn skip_failed<F>(&mut self, current: Option<&str>, stop: bool, weight: F) -> Advance
+    where
+        F: Fn(&Path) -> f64,
+    {
+        let repeat = std::mem::replace(&mut self.autoplay.repeat, false);
+        self.repeat_count = 0;
+        let advance = self.advance(current, stop, weight);
+        self.autoplay.repeat = repeat;
+
+        advance
+    }
+
+    /// Take the track to skip to according to the autoplay mode.
+    /// Unlike `advance` this ignores the repeat modes.
+    pub fn skip_next<F>(&mut self, current: Option<&str>, weight: F) -> Option<PathBuf>
+    where
+        F: Fn(&Path) -> f64,
+    {
+        if let Some(track) = self.up_next.pop_front() {
+            Some(track)
+        } else if self.autoplay.shuffle && !self.autoplay.sequential {
+            self.next_shuffled(current, weight)
+        } else {
+            self.sequential_next(current)
+        }
+    }
+
+    /// Decide where going back from the current track leads according to the autoplay mode.
+    pub fn skip_previous(&self, current: Option<&str>) -> Previous {
+        if self.autoplay.shuffle && !self.autoplay.sequential {
+            return Previous::History;
+        }
+
+        let current = current.and_then(|uri| self.index_of(uri));
+        match sequential_previous(self.files.len(), current, self.autoplay.repeat_list) {
+            Some(track) => Previous::Track(self.files[track].clone()),
+            None => Previous::Restart,
+        }
+    }
+
+    /// Get the track autoplay will continue with, if it's known in advance.
+    /// `stop` is set if playback stops after the track.
+    pub fn upcoming(&self, current: Option<&str>, stop: bool) -> Option<PathBuf> {
+        if stop {
+            None
+        } else if self.repeats() {
+            current.and_then(uri_to_path)
+        } else if let Some(track) = self.up_next.front() {
+            Some(track.clone())
+        } else if self.autoplay.sequential {
+            self.sequential_next(current)
+        } else if self.autoplay.shuffle {
+            self.shuffle.upcoming().next().cloned()
+        } else {
+            None
+        }
+    }
+
+    /// Get the URI of the track to switch to seamlessly once the current one ends:
+    /// the next one in sequential mode if it continues the album in the same format.
+    /// `stop` is set if playback stops after the track.
+    pub fn gapless_next<F>(&self, current: &str, stop: bool, mut tags: F) -> Option<String>
+    where
+        F: FnMut(&str) -> Tags,
+    {
+        if stop || !self.autoplay.sequential || !self.up_next.is_empty() || self.repeats() {
+            return None;
+        }
+
+        let next = file_uri(&self.sequential_next(Some(current))?);
+        let current = tags(current);
+        let following = tags(&next);
+
+        // A format change would make the sink renegotiate mid-stream,
+        // which glitches or fails, so those tracks start from scratch.
+        if !current.same_format(&following) {
+            tracing::debug!("not gapless, {next} has a different format");
+            None
+        } else if current.same_album(&following) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Move on from a track that stopped being the current one:
+    /// a counted repeat only applies to the track it was set for,
+    /// and in consume mode the track is removed from the list.
+    /// Returns the index the track had if it was removed.
+    pub fn leave(&mut self, track: Option<&str>) -> Option<usize> {
+        self.repeat_count = 0;
+
+        match track {
+            Some(uri) if self.autoplay.consume => self.consume(uri),
+            _ => None,
+        }
+    }
+
+    /// Remove the track a URI refers to from the list for the rest of the session.
+    /// Returns the index it had if it was part of the list.
+    pub fn consume(&mut self, uri: &str) -> Option<usize> {
+        // The list may hold relative paths, so the track is looked up by its URI.
+        let i = self.index_of(uri);
+        if let Some(i) = i {
+            let file = self.files.remove(i);
+            self.shuffle.remove(&file);
+            self.index = uri_index(&self.files);
+        }
+
+        self.consumed.insert(uri.to_string());
+        i
+    }
+}
+
+/// Bias of the weighted shuffle against tracks that were played often or recently,
+/// given as PLAYS,RECENCY, e.g. `1,1`. 0 ignores the play count or recency,
+/// larger values make the bias stronger.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShuffleWeights {
+    pub plays: f64,
+    pub recency: f64,
+}
+
+impl ShuffleWeights {
+    /// Get the weight of a track with these statistics at a Unix timestamp.
+    /// Tracks that were never played have the highest weight, 1.
+    pub fn weight(&self, stats: Option<&TrackStats>, now: u64) -> f64 {
+        let plays = stats.map_or(0, |stats| stats.plays);
+        let days = stats
+            .and_then(|stats| stats.last_played)
+            .map_or(RECENCY_DAYS, |last| {
+                (now.saturating_sub(last) / 86400).min(RECENCY_DAYS)
+            });
+
+        let plays = (1.0 + plays as f64).powf(-self.plays);
+        let recency = ((days + 1) as f64 / (RECENCY_DAYS + 1) as f64).powf(self.recency);
+
+        plays * recency
+    }
+}
+
+impl FromStr for ShuffleWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid weights {s:?}, expected PLAYS,RECENCY, e.g. 1,1");
+
+        let (plays, recency) = s.split_once(',').ok_or_else(invalid)?;
+        let plays: f64 = plays.trim().parse().map_err(|_| invalid())?;
+        let recency: f64 = recency.trim().parse().map_err(|_| invalid())?;
+
+        if !(plays >= 0.0 && recency >= 0.0) {
+            return Err(String::from("shuffle weights can't be negative"));
+        }
+
+        Ok(Self { plays, recency })
+    }
+}
+
+/// Order in which shuffled tracks are played.
+/// Every track of the list is played once before the order is reshuffled
+/// and the same track is never played twice in a row.
+#[derive(Debug, Default)]
+pub struct Shuffle {
+    upcoming: VecDeque<PathBuf>,
+}
+
+impl Shuffle {
+    /// Take the next track, reshuffling the list if all tracks have been played.
+    pub fn next<F>(
+        &mut self,
+        files: &[PathBuf],
+        current: Option<&str>,
+        weight: F,
+    ) -> Option<PathBuf>
+    where
+        F: Fn(&Path) -> f64,
+    {
+        if self.upcoming.is_empty() {
+            self.reshuffle(files, current, weight);
+        }
+
+        self.upcoming.pop_front()
+    }
+
+    /// Start a new random order that doesn't begin with the current track.
+    /// Tracks with a higher weight tend to come earlier, equal weights are a uniform shuffle.
+    pub fn reshuffle<F>(&mut self, files: &[PathBuf], current: Option<&str>, weight: F)
+    where
+        F: Fn(&Path) -> f64,
+    {
+        let mut rng = rand::thread_rng();
+
+        // Sorting by u^(1/w) draws the tracks one after another with probabilities
+        // proportional to their weights (Efraimidis and Spirakis).
+        // Its logarithm sorts the same without underflowing for small weights.
+        let mut keyed: Vec<(f64, &PathBuf)> = files
+            .iter()
+            .map(|file| {
+                let weight = weight(file).max(f64::MIN_POSITIVE);
+                (rng.gen::<f64>().ln() / weight, file)
+            })
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let mut order: Vec<PathBuf> = keyed.into_iter().map(|(_, file)| file.clone()).collect();
+
+        let first = order.first().map(|file| file_uri(file));
+        if order.len() > 1 && first.is_some() && first.as_deref() == current {
+            let last = order.len() - 1;
+            order.swap(0, last);
+        }
+
+        self.upcoming = order.into();
+    }
+
+    pub fn clear(&mut self) {
+        self.upcoming.clear();
+    }
+
+    /// Drop a track from the order, e.g. because it was removed from the list.
+    pub fn remove(&mut self, file: &Path) {
+        self.upcoming.retain(|upcoming| upcoming != file);
+    }
+
+    /// Continue a previously saved order.
+    pub fn restore(&mut self, upcoming: Vec<PathBuf>) {
+        self.upcoming = upcoming.into();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upcoming.is_empty()
+    }
+
+    /// Iterate over the tracks that haven't been played yet, next first.
+    pub fn upcoming(&self) -> impl Iterator<Item = &PathBuf> {
+        self.upcoming.iter()
+    }
+}
+
+/// URIs of the played tracks, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    /// Load the playback history of the previous session.
+    pub fn load() -> Self {
+        Self {
+            entries: state::read("history")
+                .into_iter()
+                .filter_map(|row| row.into_iter().next())
+                .collect(),
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let rows: Vec<Vec<String>> = self.entries.iter().map(|uri| vec![uri.clone()]).collect();
+
+        state::write("history", &rows)
+    }
+
+    /// Record a played track, forgetting the oldest one if the history is full.
+    pub fn push(&mut self, uri: String) {
+        if self.entries.len() >= HISTORY_LENGTH {
+            self.entries.remove(0);
+        }
+
+        self.entries.push(uri);
+    }
+
+    /// Forget the current track and return the one played before it.
+    /// Returns `None` and keeps the history if there is no previous track.
+    pub fn previous(&mut self) -> Option<String> {
+        if self.entries.len() < 2 {
+            return None;
+        }
+
+        self.entries.pop();
+        self.entries.last().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the played tracks, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &String> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(autoplay: AutoplayState) -> Queue {
+        let mut queue = Queue {
+            autoplay,
+            ..Queue::default()
+        };
+        queue.set_files(
+            ["/music/a.flac", "/music/b.flac", "/music/c.flac"]
+                .map(PathBuf::from)
+                .into(),
+        );
+
+        queue
+    }
+
+    fn uri(path: &str) -> String {
+        file_uri(Path::new(path))
+    }
+
+    fn uniform(_: &Path) -> f64 {
+        1.0
+    }
+
+    fn album(album: &str, sample_rate: u32) -> Tags {
+        Tags {
+            album: Some(album.to_string()),
+            sample_rate: Some(sample_rate),
+            ..Tags::default()
+        }
+    }
+
+    #[test]
+    fn sequential_advance() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+
+        let a = uri("/music/a.flac");
+        let c = uri("/music/c.flac");
+        assert_eq!(
+            queue.advance(Some(&a), false, uniform),
+            Advance::Play("/music/b.flac".into())
+        );
+        assert_eq!(queue.advance(Some(&c), false, uniform), Advance::Exhausted);
+        assert_eq!(
+            queue.advance(None, false, uniform),
+            Advance::Play("/music/a.flac".into())
+        );
+        assert_eq!(queue.advance(Some(&a), true, uniform), Advance::Stop);
+
+        queue.autoplay.repeat_list = true;
+        assert_eq!(
+            queue.advance(Some(&c), false, uniform),
+            Advance::Play("/music/a.flac".into())
+        );
+    }
+
+    #[test]
+    fn idle_without_mode() {
+        let mut queue = queue(AutoplayState::default());
+
+        let a = uri("/music/a.flac");
+        assert_eq!(queue.advance(Some(&a), false, uniform), Advance::Idle);
+        assert_eq!(queue.upcoming(Some(&a), false), None);
+    }
+
+    #[test]
+    fn up_next_first() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+        queue.up_next.push_back("/music/c.flac".into());
+
+        let a = uri("/music/a.flac");
+        assert_eq!(
+            queue.upcoming(Some(&a), false),
+            Some("/music/c.flac".into())
+        );
+        assert_eq!(
+            queue.advance(Some(&a), false, uniform),
+            Advance::Play("/music/c.flac".into())
+        );
+        assert_eq!(
+            queue.advance(Some(&a), false, uniform),
+            Advance::Play("/music/b.flac".into())
+        );
+    }
+
+    #[test]
+    fn counted_repeat() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+        queue.add_repeat();
+        queue.add_repeat();
+
+        let a = uri("/music/a.flac");
+        assert_eq!(
+            queue.upcoming(Some(&a), false),
+            Some("/music/a.flac".into())
+        );
+        assert_eq!(queue.advance(Some(&a), false, uniform), Advance::Repeat);
+        assert_eq!(queue.advance(Some(&a), false, uniform), Advance::Repeat);
+        assert_eq!(
+            queue.advance(Some(&a), false, uniform),
+            Advance::Play("/music/b.flac".into())
+        );
+
+        queue.repeat_count = MAX_REPEAT_COUNT;
+        queue.add_repeat();
+        assert_eq!(queue.repeat_count, 0);
+    }
+
+    #[test]
+    fn failed_track_not_repeated() {
+        let mut queue = queue(AutoplayState {
+            repeat: true,
+            sequential: true,
+            ..AutoplayState::default()
+        });
+
+        let a = uri("/music/a.flac");
+        assert_eq!(
+            queue.skip_failed(Some(&a), false, uniform),
+            Advance::Play("/music/b.flac".into())
+        );
+        assert!(queue.autoplay.repeat);
+        assert_eq!(queue.advance(Some(&a), false, uniform), Advance::Repeat);
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once() {
+        let mut queue = queue(AutoplayState {
+            shuffle: true,
+            ..AutoplayState::default()
+        });
+
+        let mut current = None;
+        let mut played = HashSet::new();
+        for _ in 0..3 {
+            let Advance::Play(track) = queue.advance(current.as_deref(), false, uniform) else {
+                panic!("shuffle ran out of tracks");
+            };
+
+            assert!(played.insert(track.clone()));
+            current = Some(file_uri(&track));
+        }
+
+        assert_eq!(played.len(), 3);
+        assert!(queue.shuffle.is_empty());
+
+        let last = current.unwrap();
+        let Advance::Play(track) = queue.advance(Some(&last), false, uniform) else {
+            panic!("shuffle didn't start over");
+        };
+        assert_ne!(file_uri(&track), last);
+    }
+
+    #[test]
+    fn skip_previous_by_mode() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+
+        let a = uri("/music/a.flac");
+        let b = uri("/music/b.flac");
+        assert_eq!(
+            queue.skip_previous(Some(&b)),
+            Previous::Track("/music/a.flac".into())
+        );
+        assert_eq!(queue.skip_previous(Some(&a)), Previous::Restart);
+
+        queue.autoplay.repeat_list = true;
+        assert_eq!(
+            queue.skip_previous(Some(&a)),
+            Previous::Track("/music/c.flac".into())
+        );
+
+        queue.autoplay = AutoplayState {
+            shuffle: true,
+            ..AutoplayState::default()
+        };
+        assert_eq!(queue.skip_previous(Some(&b)), Previous::History);
+    }
+
+    #[test]
+    fn gapless_within_album() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+
+        let a = uri("/music/a.flac");
+        let b = uri("/music/b.flac");
+        let tags = |uri: &str| match uri.ends_with("c.flac") {
+            true => album("Other", 44100),
+            false => album("Album", 44100),
+        };
+
+        assert_eq!(queue.gapless_next(&a, false, tags), Some(b.clone()));
+        assert_eq!(queue.gapless_next(&b, false, tags), None);
+        assert_eq!(queue.gapless_next(&a, true, tags), None);
+
+        queue.up_next.push_back("/music/c.flac".into());
+        assert_eq!(queue.gapless_next(&a, false, tags), None);
+    }
+
+    #[test]
+    fn gapless_needs_same_format() {
+        let queue = queue(AutoplayState {
+            sequential: true,
+            ..AutoplayState::default()
+        });
+
+        let a = uri("/music/a.flac");
+        let tags = |uri: &str| match uri.ends_with("a.flac") {
+            true => album("Album", 44100),
+            false => album("Album", 48000),
+        };
+
+        assert_eq!(queue.gapless_next(&a, false, tags), None);
+    }
+
+    #[test]
+    fn consume_on_leave() {
+        let mut queue = queue(AutoplayState {
+            sequential: true,
+            consume: true,
+            ..AutoplayState::default()
+        });
+        queue.repeat_count = 3;
+
+        let a = uri("/music/a.flac");
+        let b = uri("/music/b.flac");
+        let c = uri("/music/c.flac");
+        assert_eq!(queue.leave(Some(&b)), Some(1));
+        assert_eq!(queue.repeat_count, 0);
+        assert_eq!(queue.files().len(), 2);
+        assert_eq!(queue.index_of(&c), Some(1));
+        assert_eq!(queue.index_of(&b), None);
+        assert_eq!(
+            queue.advance(Some(&a), false, uniform),
+            Advance::Play("/music/c.flac".into())
+        );
+
+        // Consumed tracks stay out when the list is replaced.
+        queue.set_files(["/music/a.flac", "/music/b.flac"].map(PathBuf::from).into());
+        assert_eq!(queue.files(), [PathBuf::from("/music/a.flac")]);
+
+        queue.autoplay.consume = false;
+        assert_eq!(queue.leave(Some(&a)), None);
+        assert_eq!(queue.files().len(), 1);
+    }
+
+    #[test]
+    fn consume_relative_paths() {
+        // Libraries read from a directory hold paths relative to the working directory,
+        // while the player reports absolute URIs.
+        let mut queue = Queue::default();
+        queue.autoplay.consume = true;
+        queue.set_files(["./a.mp3", "./b.mp3"].map(PathBuf::from).into());
+        queue
+            .shuffle
+            .restore(vec!["./a.mp3".into(), "./b.mp3".into()]);
+
+        let a = uri("./a.mp3");
+        assert!(a.starts_with("file:///"));
+        assert_eq!(queue.leave(Some(&a)), Some(0));
+        assert_eq!(queue.files(), [PathBuf::from("./b.mp3")]);
+        assert_eq!(queue.index_of(&uri("./b.mp3")), Some(0));
+        assert_eq!(
+            queue.shuffle.upcoming().collect::<Vec<_>>(),
+            [&PathBuf::from("./b.mp3")]
+        );
+
+        queue.set_files(["./a.mp3", "./b.mp3"].map(PathBuf::from).into());
+        assert_eq!(queue.files(), [PathBuf::from("./b.mp3")]);
+    }
+}
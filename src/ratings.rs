@@ -0,0 +1,58 @@
+use crate::state;
+
+use std::collections::HashMap;
+
+/// Highest rating a track can have.
+pub const MAX: u8 = 5;
+
+/// Star ratings of tracks by URI, persisted in the state directory.
+/// Unrated tracks aren't stored.
+#[derive(Debug, Default)]
+pub struct Ratings {
+    ratings: HashMap<String, u8>,
+}
+
+impl Ratings {
+    pub fn load() -> Self {
+        let ratings = state::read("ratings")
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [uri, rating] => Some((uri.clone(), rating.parse().ok()?)),
+                _ => None,
+            })
+            .filter(|(_, rating)| (1..=MAX).contains(rating))
+            .collect();
+
+        Self { ratings }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let rows: Vec<Vec<String>> = self
+            .ratings
+            .iter()
+            .map(|(uri, rating)| vec![uri.clone(), rating.to_string()])
+            .collect();
+
+        state::write("ratings", &rows)
+    }
+
+    pub fn get(&self, uri: &str) -> Option<u8> {
+        self.ratings.get(uri).copied()
+    }
+
+    /// Rate a track, or remove its rating if `None` or 0 is given.
+    /// Ratings above the maximum are capped.
+    /// Returns the previous rating.
+    pub fn set(&mut self, uri: &str, rating: Option<u8>) -> Option<u8> {
+        match rating.filter(|&rating| rating > 0) {
+            Some(rating) => self.ratings.insert(uri.to_string(), rating.min(MAX)),
+            None => self.ratings.remove(uri),
+        }
+    }
+}
+
+/// Format a rating as stars, e.g. ★★★☆☆.
+pub fn stars(rating: u8) -> String {
+    let rating = rating.min(MAX);
+    "★".repeat(rating.into()) + &"☆".repeat((MAX - rating).into())
+}
@@ -0,0 +1,297 @@
+use crate::player::percent_decode;
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// URI schemes that are accessed through gvfs and may require a mount.
+const GVFS_SCHEMES: &[&str] = &["smb", "sftp", "ftp", "ftps", "dav", "davs", "afp", "nfs"];
+
+/// File systems that access the network, as listed in `/proc/self/mounts`.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.gvfsd-fuse",
+    "fuse.rclone",
+];
+
+/// Where a file is stored, i.e. whether playing it requires network access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    Local,
+    /// A network share mounted into the file system, e.g. a NAS.
+    Network,
+}
+
+/// Mount points and their file system types.
+#[derive(Debug, Default)]
+pub struct Mounts {
+    mounts: Vec<(PathBuf, String)>,
+}
+
+impl Mounts {
+    /// Read the mount table. Everything is considered local if it can't be read.
+    pub fn load() -> Self {
+        let table = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        let mounts = table
+            .lines()
+            .filter_map(|line| {
+                // Device, mount point, type, options, ...
+                let mut fields = line.split(' ');
+                let mount_point = unescape_mount_point(fields.nth(1)?);
+                let fs_type = fields.next()?;
+
+                Some((mount_point, fs_type.to_string()))
+            })
+            .collect();
+
+        Self { mounts }
+    }
+
+    /// Get the source of a file by the mount it resolves to, following symlinks.
+    pub fn source(&self, path: &Path) -> Source {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        // The innermost mount point wins, later mounts shadow earlier ones.
+        let fs_type = self
+            .mounts
+            .iter()
+            .filter(|(mount_point, _)| path.starts_with(mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.components().count())
+            .map(|(_, fs_type)| fs_type.as_str());
+
+        match fs_type {
+            Some(fs_type) if NETWORK_FILESYSTEMS.contains(&fs_type) => Source::Network,
+            _ => Source::Local,
+        }
+    }
+}
+
+/// Decode the octal escapes of spaces and other special characters in a mount point.
+fn unescape_mount_point(s: &str) -> PathBuf {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+
+        match escape {
+            Some(byte) if bytes[i] == b'\\' => {
+                decoded.push(byte);
+                i += 4;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+/// Check whether a URI refers to a gvfs network location.
+pub fn is_remote(uri: &str) -> bool {
+    match uri.split_once("://") {
+        Some((scheme, _)) => GVFS_SCHEMES.contains(&scheme),
+        None => false,
+    }
+}
+
+/// Check whether the share containing a URI is already mounted.
+pub fn is_mounted(uri: &str) -> bool {
+    Command::new("gio")
+        .args(["info", "--attributes=standard::type", uri])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Get the share a URI belongs to, e.g. `smb://nas/music` for `smb://nas/music/a.flac`.
+/// Credentials are stored per share.
+pub fn share(uri: &str) -> String {
+    match uri.split_once("://") {
+        Some((scheme, rest)) => {
+            let parts: Vec<&str> = rest.splitn(3, '/').collect();
+            format!("{scheme}://{}", parts[..parts.len().min(2)].join("/"))
+        }
+        None => uri.to_string(),
+    }
+}
+
+/// Mount the share containing a URI unless it already is,
+/// using the credentials from the keyring if there are any.
+pub fn ensure_mounted(uri: &str) -> anyhow::Result<()> {
+    if is_mounted(uri) {
+        return Ok(());
+    }
+
+    match lookup_credentials(uri) {
+        Some((username, password)) => mount(uri, &username, &password),
+        None => {
+            // Anonymous shares and sftp with keys don't ask for anything.
+            let output = Command::new("gio")
+                .args(["mount", uri])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()?;
+
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "can't mount {}, mount it with \"gio mount {}\" first: {}",
+                    share(uri),
+                    share(uri),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+            }
+        }
+    }
+}
+
+/// Paths of gvfs URIs in the file system, where gvfsd-fuse exposes mounted shares,
+/// e.g. `/run/user/1000/gvfs/smb-share:server=nas,share=music/a.flac` for
+/// `smb://nas/music/a.flac`. Shares are mounted on first access and looked up once.
+#[derive(Debug, Default)]
+pub struct LocalPaths {
+    roots: HashMap<String, Result<PathBuf, String>>,
+}
+
+impl LocalPaths {
+    pub fn get(&mut self, uri: &str) -> anyhow::Result<PathBuf> {
+        let share = share(uri);
+        let root = self
+            .roots
+            .entry(share.clone())
+            .or_insert_with(|| share_root(&share).map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+        let rest = uri[share.len()..].trim_start_matches('/');
+        let rest = percent_decode(rest).ok_or_else(|| anyhow::anyhow!("invalid URI {uri}"))?;
+
+        Ok(root.join(rest))
+    }
+}
+
+/// Mount a share and get its path in the file system.
+fn share_root(share: &str) -> anyhow::Result<PathBuf> {
+    ensure_mounted(share)?;
+
+    let output = Command::new("gio")
+        .args(["info", "--attributes=standard::name", share])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("local path: "))
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("{share} has no local path, is gvfsd-fuse running?"))
+}
+
+/// Mount the share containing a URI using gio,
+/// answering its authentication questions with the given credentials.
+pub fn mount(uri: &str, username: &str, password: &str) -> anyhow::Result<()> {
+    let mut child = Command::new("gio")
+        .args(["mount", uri])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // SMB additionally asks for a domain, an empty answer keeps the default.
+    let answers = if uri.starts_with("smb://") {
+        format!("{username}\n\n{password}\n")
+    } else {
+        format!("{username}\n{password}\n")
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(answers.as_bytes());
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let err = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow::anyhow!(
+            "can't mount {}: {}",
+            share(uri),
+            err.trim()
+        ))
+    }
+}
+
+/// Look up the credentials of a share in the keyring using secret-tool.
+pub fn lookup_credentials(uri: &str) -> Option<(String, String)> {
+    let output = Command::new("secret-tool")
+        .args(["search", "--unlock", "service", "musikbox", "share"])
+        .arg(share(uri))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut username = None;
+    let mut password = None;
+
+    for line in output.lines() {
+        if let Some((key, value)) = line.split_once(" = ") {
+            match key.trim() {
+                "attribute.username" => username = Some(value.to_string()),
+                "secret" => password = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    Some((username?, password?))
+}
+
+/// Save the credentials of a share to the keyring using secret-tool.
+pub fn store_credentials(uri: &str, username: &str, password: &str) -> anyhow::Result<()> {
+    let share = share(uri);
+    let mut child = Command::new("secret-tool")
+        .arg("store")
+        .arg(format!("--label=musikbox: {share}"))
+        .args(["service", "musikbox", "share", &share, "username", username])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(password.as_bytes())?;
+    }
+
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("can't save credentials for {share}"))
+    }
+}
@@ -0,0 +1,292 @@
+use crate::json::{self, Value};
+use crate::tags::Tags;
+
+use std::io;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const SOUNDCLOUD_RESOLVE_URL: &str = "https://api.soundcloud.com/resolve";
+
+/// Site whose page URLs are resolved into an audio stream before playing.
+/// Pages are resolved with yt-dlp if it is installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Site {
+    /// Track and album pages, otherwise played from the stream of their embedded player.
+    Bandcamp,
+    /// Track pages, otherwise resolved through the public API. Requires a client ID.
+    SoundCloud,
+    /// Video pages, only supported through yt-dlp.
+    YouTube,
+}
+
+impl Site {
+    /// Get the site of a page URL, `None` if the URL is played as is.
+    pub fn of(url: &str) -> Option<Self> {
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?;
+        let host = rest.split(['/', '?', '#']).next()?.to_lowercase();
+
+        if host == "bandcamp.com" || host.ends_with(".bandcamp.com") {
+            Some(Self::Bandcamp)
+        } else if matches!(
+            host.as_str(),
+            "soundcloud.com" | "www.soundcloud.com" | "m.soundcloud.com"
+        ) {
+            Some(Self::SoundCloud)
+        } else if matches!(
+            host.as_str(),
+            "youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com" | "youtu.be"
+        ) {
+            Some(Self::YouTube)
+        } else {
+            None
+        }
+    }
+}
+
+/// Audio stream of a page and the metadata the site provides for it.
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub uri: String,
+    pub tags: Tags,
+}
+
+/// Resolve a page URL of a supported site into its audio stream.
+/// Only streams the site offers to anyone visiting the page are used,
+/// downloads that require a purchase or login aren't.
+/// Blocks on network requests, which are delegated to curl.
+pub fn resolve(url: &str, soundcloud_client_id: Option<&str>) -> anyhow::Result<Stream> {
+    let site = Site::of(url);
+    if site.is_some() {
+        if let Some(stream) = yt_dlp(url) {
+            return stream;
+        }
+    }
+
+    match site {
+        Some(Site::Bandcamp) => bandcamp(url),
+        Some(Site::YouTube) => Err(anyhow::anyhow!("YouTube URLs require yt-dlp")),
+        Some(Site::SoundCloud) => {
+            let client_id = soundcloud_client_id
+                .ok_or_else(|| anyhow::anyhow!("SoundCloud URLs require --soundcloud-client-id"))?;
+
+            soundcloud(url, client_id)
+        }
+        None => Ok(Stream {
+            uri: url.to_string(),
+            tags: Tags::default(),
+        }),
+    }
+}
+
+/// Get the best audio stream of a page using yt-dlp.
+/// Returns `None` if yt-dlp isn't installed.
+fn yt_dlp(url: &str) -> Option<anyhow::Result<Stream>> {
+    let output = Command::new("yt-dlp")
+        .args([
+            "--no-playlist",
+            "--no-warnings",
+            "-f",
+            "bestaudio/best",
+            "-J",
+        ])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => return Some(Err(e.into())),
+    };
+
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        return Some(Err(anyhow::anyhow!("yt-dlp failed: {}", err.trim())));
+    }
+
+    let info = match json::parse(&String::from_utf8_lossy(&output.stdout)) {
+        Ok(info) => info,
+        Err(e) => return Some(Err(anyhow::anyhow!("unexpected yt-dlp output: {e}"))),
+    };
+
+    let uri = match text(&info, "url") {
+        Some(uri) => uri,
+        None => return Some(Err(anyhow::anyhow!("yt-dlp found no audio stream"))),
+    };
+
+    Some(Ok(Stream {
+        uri,
+        tags: Tags {
+            // Music sites have track metadata, video sites only titles and channels.
+            title: text(&info, "track").or_else(|| text(&info, "title")),
+            artist: text(&info, "artist").or_else(|| text(&info, "uploader")),
+            album: text(&info, "album"),
+            genre: text(&info, "genre"),
+            duration: info
+                .get("duration")
+                .and_then(Value::as_f64)
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+            ..Default::default()
+        },
+    }))
+}
+
+/// Get the stream of the first playable track of a Bandcamp page,
+/// which is the track itself on a track page.
+fn bandcamp(url: &str) -> anyhow::Result<Stream> {
+    let page = fetch(Command::new("curl").arg("-L").arg(url))?;
+    let data = attribute(&page, "data-tralbum")
+        .ok_or_else(|| anyhow::anyhow!("no track on this Bandcamp page"))?;
+    let data = json::parse(&html_unescape(data))
+        .map_err(|e| anyhow::anyhow!("unexpected Bandcamp page: {e}"))?;
+
+    let (uri, track) = data
+        .get("trackinfo")
+        .map(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .find_map(|track| {
+            let uri = track.get("file")?.get("mp3-128")?.as_str()?;
+            Some((uri.to_string(), track))
+        })
+        .ok_or_else(|| anyhow::anyhow!("nothing on this Bandcamp page can be streamed"))?;
+
+    let album = data
+        .get("current")
+        .filter(|_| data.get("item_type").and_then(Value::as_str) == Some("album"))
+        .and_then(|current| text(current, "title"));
+
+    Ok(Stream {
+        uri,
+        tags: Tags {
+            title: text(track, "title"),
+            artist: text(track, "artist").or_else(|| text(&data, "artist")),
+            album,
+            duration: track
+                .get("duration")
+                .and_then(Value::as_f64)
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+            ..Default::default()
+        },
+    })
+}
+
+/// Get the stream of a SoundCloud track page using the public API.
+fn soundcloud(url: &str, client_id: &str) -> anyhow::Result<Stream> {
+    let body = fetch(
+        Command::new("curl")
+            .args(["-L", "-G"])
+            .arg("--data-urlencode")
+            .arg(format!("url={url}"))
+            .arg("--data-urlencode")
+            .arg(format!("client_id={client_id}"))
+            .arg(SOUNDCLOUD_RESOLVE_URL),
+    )?;
+    let track =
+        json::parse(&body).map_err(|e| anyhow::anyhow!("unexpected SoundCloud response: {e}"))?;
+
+    if track.get("kind").and_then(Value::as_str) != Some("track") {
+        anyhow::bail!("only SoundCloud track pages can be played");
+    }
+
+    let stream_url = track
+        .get("stream_url")
+        .and_then(Value::as_str)
+        .filter(|_| track.get("streamable") != Some(&Value::Bool(false)))
+        .ok_or_else(|| anyhow::anyhow!("this SoundCloud track can't be streamed"))?;
+
+    let separator = if stream_url.contains('?') { '&' } else { '?' };
+
+    Ok(Stream {
+        uri: format!("{stream_url}{separator}client_id={client_id}"),
+        tags: Tags {
+            title: text(&track, "title"),
+            artist: track.get("user").and_then(|user| text(user, "username")),
+            genre: text(&track, "genre"),
+            duration: track
+                .get("duration")
+                .and_then(Value::as_f64)
+                .map(|ms| Duration::from_millis(ms as u64)),
+            ..Default::default()
+        },
+    })
+}
+
+/// Run a prepared curl command and return what it downloaded.
+fn fetch(curl: &mut Command) -> anyhow::Result<String> {
+    let output = curl
+        .args(["-fsS", "--max-time", "10"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}", err.trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Get the non-empty text of a member of a JSON object.
+fn text(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Get the raw value of the first double-quoted HTML attribute with a name.
+fn attribute<'a>(html: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = html.split_once(&format!("{name}=\""))?;
+    rest.split_once('"').map(|(value, _)| value)
+}
+
+/// Decode the character references that occur in attribute values.
+fn html_unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "quot" => '"',
+                "amp" => '&',
+                "apos" => '\'',
+                "lt" => '<',
+                "gt" => '>',
+                entity => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => entity.strip_prefix('#')?.parse().ok()?,
+                    };
+
+                    char::from_u32(code)?
+                }
+            };
+
+            Some((c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
@@ -0,0 +1,560 @@
+use crate::player::file_uri;
+use crate::ratings::{self, Ratings};
+use crate::stats::{Stats, TrackStats};
+use crate::tags::Tags;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::{Chars, FromStr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A named list of files matching a rule expression,
+/// e.g. `recent=ext = flac AND modified < 30d`.
+#[derive(Clone, Debug)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub rule: Rule,
+}
+
+impl FromStr for SmartPlaylist {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rule) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected NAME=RULE"))?;
+
+        Ok(Self {
+            name: name.trim().to_string(),
+            rule: rule.parse()?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// File name including the extension.
+    Name,
+    /// Lowercase file extension.
+    Ext,
+    /// File size in bytes.
+    Size,
+    /// Time since the last modification in seconds.
+    Modified,
+    /// Genre tag.
+    Genre,
+    /// Star rating, 0 if the track is unrated.
+    Rating,
+    /// Time since the track was last played in seconds,
+    /// infinite if it was never played.
+    LastPlayed,
+}
+
+impl Field {
+    /// Check whether the field is read from the tags.
+    fn is_tag(self) -> bool {
+        self == Self::Genre
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "ext" => Ok(Self::Ext),
+            "size" => Ok(Self::Size),
+            "modified" => Ok(Self::Modified),
+            "genre" => Ok(Self::Genre),
+            "rating" => Ok(Self::Rating),
+            "lastplayed" => Ok(Self::LastPlayed),
+            _ => Err(format!("unknown field {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Case-insensitive substring match.
+    Contains,
+}
+
+impl Op {
+    fn eval(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+            Self::Contains => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+}
+
+/// What the player knows about the library besides the files themselves.
+/// Tags are only taken from the cache, files that haven't been scanned yet have none.
+pub struct Context<'a> {
+    pub tags: &'a HashMap<String, Tags>,
+    pub ratings: &'a Ratings,
+    pub stats: &'a Stats,
+}
+
+#[derive(Clone, Debug)]
+pub enum Rule {
+    And(Box<Rule>, Box<Rule>),
+    Or(Box<Rule>, Box<Rule>),
+    Not(Box<Rule>),
+    Compare { field: Field, op: Op, value: Value },
+}
+
+impl Rule {
+    /// Check whether a file satisfies the rule.
+    /// Attributes that can't be read never match.
+    pub fn matches(&self, path: &Path, context: &Context) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.matches(path, context) && rhs.matches(path, context),
+            Self::Or(lhs, rhs) => lhs.matches(path, context) || rhs.matches(path, context),
+            Self::Not(rule) => !rule.matches(path, context),
+            Self::Compare { field, op, value } => match (attribute(path, *field, context), value) {
+                (Some(Value::Text(actual)), Value::Text(expected)) => {
+                    let actual = actual.to_lowercase();
+                    let expected = expected.to_lowercase();
+
+                    match op {
+                        Op::Contains => actual.contains(&expected),
+                        _ => op.eval(actual.cmp(&expected)),
+                    }
+                }
+                (Some(Value::Number(actual)), Value::Number(expected)) => match op {
+                    Op::Contains => false,
+                    _ => actual
+                        .partial_cmp(expected)
+                        .map(|ordering| op.eval(ordering))
+                        .unwrap_or(false),
+                },
+                _ => false,
+            },
+        }
+    }
+
+    /// Check whether the rule refers to tags,
+    /// i.e. its matches can change once the library is scanned.
+    pub fn uses_tags(&self) -> bool {
+        match self {
+            Self::And(lhs, rhs) | Self::Or(lhs, rhs) => lhs.uses_tags() || rhs.uses_tags(),
+            Self::Not(rule) => rule.uses_tags(),
+            Self::Compare { field, .. } => field.is_tag(),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s)?.into_iter().peekable();
+        let rule = parse_or(&mut tokens)?;
+
+        match tokens.next() {
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Ok(rule),
+        }
+    }
+}
+
+fn attribute(path: &Path, field: Field, context: &Context) -> Option<Value> {
+    match field {
+        Field::Name => Some(Value::Text(
+            path.file_name()?.to_string_lossy().into_owned(),
+        )),
+        Field::Ext => Some(Value::Text(
+            path.extension()?.to_string_lossy().to_lowercase(),
+        )),
+        Field::Size => Some(Value::Number(fs::metadata(path).ok()?.len() as f64)),
+        Field::Modified => {
+            let modified = fs::metadata(path).ok()?.modified().ok()?;
+            let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+
+            Some(Value::Number(age.as_secs_f64()))
+        }
+        Field::Genre => {
+            let genre = context.tags.get(&file_uri(path))?.genre.clone()?;
+            Some(Value::Text(genre))
+        }
+        Field::Rating => {
+            let rating = context.ratings.get(&file_uri(path)).unwrap_or_default();
+            Some(Value::Number(rating.into()))
+        }
+        Field::LastPlayed => {
+            // Tracks that were never played are the least recently played of all.
+            let last_played = match context.stats.get(&file_uri(path)) {
+                Some(TrackStats {
+                    last_played: Some(last_played),
+                    ..
+                }) => *last_played,
+                _ => return Some(Value::Number(f64::INFINITY)),
+            };
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+            Some(Value::Number(now.saturating_sub(last_played) as f64))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => quoted.push(c),
+                        None => return Err(String::from("unterminated string")),
+                    }
+                }
+
+                tokens.push(Token::Quoted(quoted));
+            }
+            '=' | '!' | '<' | '>' | '~' => tokens.push(Token::Op(tokenize_op(&mut chars)?)),
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()\"=!<>~".contains(c) {
+                        break;
+                    }
+
+                    word.push(c);
+                    chars.next();
+                }
+
+                tokens.push(match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn tokenize_op(chars: &mut Peekable<Chars>) -> Result<Op, String> {
+    let first = chars.next().unwrap_or_default();
+    let equals = chars.peek() == Some(&'=');
+    if equals {
+        chars.next();
+    }
+
+    match (first, equals) {
+        ('=', _) => Ok(Op::Eq),
+        ('!', true) => Ok(Op::Ne),
+        ('<', false) => Ok(Op::Lt),
+        ('<', true) => Ok(Op::Le),
+        ('>', false) => Ok(Op::Gt),
+        ('>', true) => Ok(Op::Ge),
+        ('~', false) => Ok(Op::Contains),
+        _ => Err(format!("invalid operator near {first}")),
+    }
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Rule, String> {
+    let mut rule = parse_and(tokens)?;
+    while tokens.next_if_eq(&Token::Or).is_some() {
+        rule = Rule::Or(Box::new(rule), Box::new(parse_and(tokens)?));
+    }
+
+    Ok(rule)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Rule, String> {
+    let mut rule = parse_not(tokens)?;
+    while tokens.next_if_eq(&Token::And).is_some() {
+        rule = Rule::And(Box::new(rule), Box::new(parse_not(tokens)?));
+    }
+
+    Ok(rule)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Rule, String> {
+    if tokens.next_if_eq(&Token::Not).is_some() {
+        return Ok(Rule::Not(Box::new(parse_not(tokens)?)));
+    }
+
+    match tokens.next() {
+        Some(Token::Open) => {
+            let rule = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(rule),
+                _ => Err(String::from("missing closing parenthesis")),
+            }
+        }
+        Some(Token::Word(field)) => {
+            let field = field.parse()?;
+            let op = match tokens.next() {
+                Some(Token::Op(op)) => op,
+                token => return Err(format!("expected operator, got {token:?}")),
+            };
+            let value = match (field, tokens.next()) {
+                (
+                    Field::Name | Field::Ext | Field::Genre,
+                    Some(Token::Word(s) | Token::Quoted(s)),
+                ) => Value::Text(s),
+                (Field::Size, Some(Token::Word(s))) => Value::Number(parse_size(&s)?),
+                (Field::Modified | Field::LastPlayed, Some(Token::Word(s))) => {
+                    Value::Number(parse_age(&s)?)
+                }
+                (Field::Rating, Some(Token::Word(s))) => Value::Number(parse_rating(&s)?),
+                (_, token) => return Err(format!("invalid value {token:?}")),
+            };
+
+            Ok(Rule::Compare { field, op, value })
+        }
+        token => Err(format!("expected field, got {token:?}")),
+    }
+}
+
+fn split_suffix(s: &str) -> (&str, &str) {
+    let i = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    s.split_at(i)
+}
+
+/// Parse a size such as `512K` or `1.5G` into bytes.
+fn parse_size(s: &str) -> Result<f64, String> {
+    let (number, suffix) = split_suffix(s);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size {s}"))?;
+    let factor = match suffix.to_uppercase().as_str() {
+        "" | "B" => 1.0,
+        "K" => 1024.0,
+        "M" => 1024.0 * 1024.0,
+        "G" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("invalid size suffix {suffix}")),
+    };
+
+    Ok(number * factor)
+}
+
+/// Parse a rating from 0 (unrated) to the maximum number of stars.
+fn parse_rating(s: &str) -> Result<f64, String> {
+    match s.parse::<u8>() {
+        Ok(rating) if rating <= ratings::MAX => Ok(rating.into()),
+        _ => Err(format!(
+            "invalid rating {s}, expected 0 to {}",
+            ratings::MAX
+        )),
+    }
+}
+
+/// Parse an age such as `90m` or `30d` into seconds.
+fn parse_age(s: &str) -> Result<f64, String> {
+    let (number, suffix) = split_suffix(s);
+    let number: f64 = number.parse().map_err(|_| format!("invalid age {s}"))?;
+    let factor = match suffix {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        "w" => 7.0 * 24.0 * 60.0 * 60.0,
+        _ => return Err(format!("invalid age suffix {suffix}")),
+    };
+
+    Ok(number * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(rule: &str, path: &str, context: &Context) -> bool {
+        rule.parse::<Rule>()
+            .unwrap()
+            .matches(Path::new(path), context)
+    }
+
+    fn with_context<F: FnOnce(&Context)>(f: F) {
+        let mut tags = HashMap::new();
+        tags.insert(
+            file_uri(Path::new("/music/a.flac")),
+            Tags {
+                genre: Some("Synthwave".to_string()),
+                ..Tags::default()
+            },
+        );
+
+        let mut ratings = Ratings::default();
+        ratings.set(&file_uri(Path::new("/music/a.flac")), Some(4));
+
+        let mut stats = Stats::default();
+        stats.record_play(&file_uri(Path::new("/music/a.flac")));
+
+        f(&Context {
+            tags: &tags,
+            ratings: &ratings,
+            stats: &stats,
+        });
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!("Name".parse(), Ok(Field::Name));
+        assert_eq!("LASTPLAYED".parse(), Ok(Field::LastPlayed));
+        assert_eq!(
+            "bitrate".parse::<Field>(),
+            Err(String::from("unknown field bitrate"))
+        );
+        assert!("bitrate = 320".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_size("512"), Ok(512.0));
+        assert_eq!(parse_size("1.5K"), Ok(1536.0));
+        assert_eq!(parse_size("2m"), Ok(2.0 * 1024.0 * 1024.0));
+        assert!(parse_size("1T").is_err());
+
+        assert_eq!(parse_age("90"), Ok(90.0));
+        assert_eq!(parse_age("90m"), Ok(5400.0));
+        assert_eq!(parse_age("2h"), Ok(7200.0));
+        assert_eq!(parse_age("30d"), Ok(30.0 * 86400.0));
+        assert_eq!(parse_age("1w"), Ok(7.0 * 86400.0));
+        assert!(parse_age("1y").is_err());
+
+        assert_eq!(parse_rating("5"), Ok(5.0));
+        assert!(parse_rating("6").is_err());
+        assert!("rating = -1".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!("ext flac".parse::<Rule>().is_err());
+        assert!("(ext = flac".parse::<Rule>().is_err());
+        assert!("name = \"a".parse::<Rule>().is_err());
+        assert!("ext => flac".parse::<Rule>().is_err());
+        assert!("ext = flac ext = mp3".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn operators() {
+        with_context(|context| {
+            assert!(matches("ext = FLAC", "/music/a.flac", context));
+            assert!(matches("ext != mp3", "/music/a.flac", context));
+            assert!(matches("name ~ \"A.F\"", "/music/a.flac", context));
+            assert!(matches("name < b", "/music/a.flac", context));
+            assert!(!matches("name > b", "/music/a.flac", context));
+            assert!(matches("rating >= 4", "/music/a.flac", context));
+            assert!(matches("rating <= 4", "/music/a.flac", context));
+            assert!(!matches("rating > 4", "/music/a.flac", context));
+            assert!(matches("rating = 0", "/music/b.flac", context));
+            assert!(!matches("rating ~ 4", "/music/a.flac", context));
+            assert!(matches("genre ~ wave", "/music/a.flac", context));
+            // Attributes that are unknown never match.
+            assert!(!matches("genre != pop", "/music/b.flac", context));
+            assert!(!matches("size > 0", "/music/missing.flac", context));
+        });
+    }
+
+    #[test]
+    fn precedence() {
+        with_context(|context| {
+            // AND binds tighter than OR.
+            assert!(matches(
+                "ext = mp3 AND rating = 5 OR genre ~ synth",
+                "/music/a.flac",
+                context
+            ));
+            assert!(!matches(
+                "ext = mp3 AND (rating = 5 OR genre ~ synth)",
+                "/music/a.flac",
+                context
+            ));
+            // NOT binds tighter than AND.
+            assert!(matches(
+                "NOT ext = mp3 AND rating = 4",
+                "/music/a.flac",
+                context
+            ));
+            assert!(!matches(
+                "NOT (ext = flac AND rating = 4)",
+                "/music/a.flac",
+                context
+            ));
+            assert!(matches("not not ext = flac", "/music/a.flac", context));
+        });
+    }
+
+    #[test]
+    fn last_played() {
+        with_context(|context| {
+            assert!(matches("lastplayed < 1h", "/music/a.flac", context));
+            assert!(!matches("lastplayed > 30d", "/music/a.flac", context));
+
+            // Never played tracks are the ones not played recently.
+            assert!(matches("lastplayed > 30d", "/music/b.flac", context));
+            assert!(!matches("lastplayed < 30d", "/music/b.flac", context));
+        });
+    }
+
+    #[test]
+    fn uses_tags() {
+        assert!("ext = flac OR NOT genre = pop"
+            .parse::<Rule>()
+            .unwrap()
+            .uses_tags());
+        assert!(!"ext = flac AND rating > 3"
+            .parse::<Rule>()
+            .unwrap()
+            .uses_tags());
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Track that starts at a wall-clock time, e.g. for a timed event program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timer {
+    pub at: SystemTime,
+    pub path: PathBuf,
+}
+
+/// Pending timers, sorted by their start.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    timers: Vec<Timer>,
+}
+
+impl Schedule {
+    pub fn add(&mut self, timer: Timer) {
+        let i = self.timers.partition_point(|other| other.at <= timer.at);
+        self.timers.insert(i, timer);
+    }
+
+    pub fn remove(&mut self, i: usize) -> Option<Timer> {
+        (i < self.timers.len()).then(|| self.timers.remove(i))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Timer> {
+        self.timers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Take the timers that are due and return the latest of them.
+    /// Earlier ones were missed, e.g. while the machine was suspended, and are dropped.
+    pub fn due(&mut self, now: SystemTime) -> Option<Timer> {
+        let n = self.timers.partition_point(|timer| timer.at <= now);
+        self.timers.drain(..n).next_back()
+    }
+}
+
+/// Get the next time a local time of day such as `20:00` or `7:30:15` occurs,
+/// which is tomorrow if it has already passed today.
+/// Days with a daylight saving time change may be off by the shift.
+pub fn next_occurrence(time: &str, now: SystemTime) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid time {time:?}, expected HH:MM or HH:MM:SS");
+
+    let parts: Vec<u64> = time
+        .trim()
+        .split(':')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes] => (*hours, *minutes, 0),
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        _ => return Err(invalid()),
+    };
+
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return Err(invalid());
+    }
+
+    let target = hours * 3600 + minutes * 60 + seconds;
+    let current = time_of_day(now);
+    let wait = (target + DAY - current) % DAY;
+
+    // The current second counts as passed.
+    let wait = if wait == 0 { DAY } else { wait };
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    Ok(UNIX_EPOCH + Duration::from_secs(now + wait))
+}
+
+/// Format the local time of day of a point in time as `HH:MM:SS`.
+pub fn format_time(time: SystemTime) -> String {
+    let seconds = time_of_day(time);
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Get the seconds since local midnight, or UTC midnight if the time zone is unknown.
+fn time_of_day(time: SystemTime) -> u64 {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let timestamp = seconds as libc::time_t;
+    // SAFETY: tm is plain data for which all zeroes are valid.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    // SAFETY: Both pointers are valid for the duration of the call
+    // and localtime_r, unlike localtime, doesn't use shared state.
+    if unsafe { libc::localtime_r(&timestamp, &mut tm) }.is_null() {
+        return seconds % DAY;
+    }
+
+    tm.tm_hour as u64 * 3600 + tm.tm_min as u64 * 60 + tm.tm_sec as u64
+}
@@ -0,0 +1,58 @@
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::Style;
+use tui::widgets::Widget;
+
+/// Vertical scrollbar drawn over the right border of a bordered pane.
+/// Nothing is drawn if the content fits into the pane.
+pub struct Scrollbar {
+    /// Index of the selected or topmost visible item.
+    position: usize,
+    /// Total number of items.
+    length: usize,
+    style: Style,
+}
+
+impl Scrollbar {
+    pub fn new(position: usize, length: usize) -> Self {
+        Self {
+            position,
+            length,
+            style: Style::default(),
+        }
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+impl Widget for Scrollbar {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width < 1 || area.height < 3 {
+            return;
+        }
+
+        let track = (area.height - 2) as usize;
+        if self.length <= track {
+            return;
+        }
+
+        let thumb = (track * track / self.length).max(1);
+        let start = (track - thumb) * self.position.min(self.length - 1) / (self.length - 1);
+
+        let x = area.right() - 1;
+        for i in 0..track {
+            let symbol = if (start..start + thumb).contains(&i) {
+                "█"
+            } else {
+                "│"
+            };
+
+            buf.get_mut(x, area.top() + 1 + i as u16)
+                .set_symbol(symbol)
+                .set_style(self.style);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use regex::{Regex, RegexBuilder};
+
+/// Largest size in bytes a compiled search pattern may take,
+/// which rejects huge repetitions like `a{1000}{1000}` instead of building them.
+const SIZE_LIMIT: usize = 1 << 20;
+
+/// Compile a search in regex mode, a case-insensitive regular expression
+/// matched anywhere in the text. Matching takes time linear in the length of the text
+/// whatever the pattern, so no search can stall the UI.
+pub fn pattern(search: &str) -> anyhow::Result<Regex> {
+    RegexBuilder::new(search)
+        .case_insensitive(true)
+        .size_limit(SIZE_LIMIT)
+        .build()
+        .map_err(|e| anyhow::anyhow!(describe(&e)))
+}
+
+/// Get the one-line description of an invalid pattern.
+/// Syntax errors quote the pattern on several lines before stating the problem.
+fn describe(e: &regex::Error) -> String {
+    let message = e.to_string();
+    let reason = message
+        .lines()
+        .find_map(|line| line.strip_prefix("error: "));
+
+    reason.unwrap_or(&message).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn matches(search: &str, text: &str) -> bool {
+        pattern(search).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches("abba|queen", "Queen - Innuendo.flac"));
+        assert!(matches("^(abba|queen)", "ABBA - Waterloo.mp3"));
+        assert!(!matches("^(abba|queen)", "The Best of ABBA.mp3"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches(r"\d{2} ", "03 Intro.flac"));
+        assert!(matches("[a-c]x", "BX"));
+        assert!(!matches("^[^0-9]", "1 Track.ogg"));
+        assert!(matches(r"\w+\s\w+", "live demo"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches(r"\.flac$", "song.flac"));
+        assert!(!matches(r"\.flac$", "song.flac.part"));
+        assert!(matches("^song", "Song.flac"));
+        assert!(!matches("^song", "A Song.flac"));
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert!(matches("^a*b$", "b"));
+        assert!(matches("^a+b$", "aaab"));
+        assert!(!matches("^a+b$", "b"));
+        assert!(matches("^colou?r$", "color"));
+        assert!(matches("^a{2,3}$", "aaa"));
+        assert!(!matches("^a{2,3}$", "aaaa"));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert_eq!(pattern("(live").unwrap_err().to_string(), "unclosed group");
+        assert!(pattern("a{1000}{1000}").is_err());
+    }
+
+    #[test]
+    fn pathological_pattern() {
+        // Takes exponential time in a backtracking matcher.
+        let text = "x".repeat(10_000);
+        let started = Instant::now();
+        assert!(!matches("(x+x+)+y", &text));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::player::{file_uri, uri_to_path, AutoplayState};
+use crate::state;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Saved player state to return to later, e.g. after an interruption.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    /// Track that was playing, if any.
+    pub uri: Option<String>,
+    pub position: Duration,
+    pub volume: f64,
+    pub autoplay: AutoplayState,
+    /// Name of the list that was shown, empty for the entire directory.
+    pub playlist: String,
+    /// Remaining tracks of the shuffle order, next first.
+    pub upcoming: Vec<PathBuf>,
+}
+
+/// Named snapshots, persisted in the state directory.
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    snapshots: Vec<Snapshot>,
+}
+
+impl Snapshot {
+    /// Load the state the player was left in when it last ran with --restore-queue.
+    pub fn load_last() -> Option<Self> {
+        state::read("queue")
+            .first()
+            .and_then(|row| Self::from_row(row))
+    }
+
+    /// Save the state to continue from after a restart or a crash.
+    pub fn save_last(&self) -> anyhow::Result<()> {
+        state::write("queue", &[self.to_row()])
+    }
+
+    fn from_row(row: &[String]) -> Option<Self> {
+        match row {
+            [name, uri, position, volume, flags, playlist, upcoming] => Some(Self {
+                name: name.clone(),
+                uri: Some(uri.clone()).filter(|uri| !uri.is_empty()),
+                position: Duration::from_millis(position.parse().unwrap_or_default()),
+                volume: volume.parse().unwrap_or(1.0),
+                autoplay: AutoplayState {
+                    repeat_list: flags.contains('i'),
+                    repeat: flags.contains('r'),
+                    sequential: flags.contains('l'),
+                    shuffle: flags.contains('s'),
+                    consume: flags.contains('c'),
+                },
+                playlist: playlist.clone(),
+                // URIs are percent-encoded and never contain spaces.
+                upcoming: upcoming.split(' ').filter_map(uri_to_path).collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn to_row(&self) -> Vec<String> {
+        // Flags use the keys that toggle the modes.
+        let autoplay = &self.autoplay;
+        let flags: String = [
+            (autoplay.repeat_list, 'i'),
+            (autoplay.repeat, 'r'),
+            (autoplay.sequential, 'l'),
+            (autoplay.shuffle, 's'),
+            (autoplay.consume, 'c'),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, flag)| flag)
+        .collect();
+
+        let upcoming: Vec<String> = self.upcoming.iter().map(|file| file_uri(file)).collect();
+
+        vec![
+            self.name.clone(),
+            self.uri.clone().unwrap_or_default(),
+            self.position.as_millis().to_string(),
+            self.volume.to_string(),
+            flags,
+            self.playlist.clone(),
+            upcoming.join(" "),
+        ]
+    }
+}
+
+impl Snapshots {
+    pub fn load() -> Self {
+        let snapshots = state::read("snapshots")
+            .iter()
+            .filter_map(|row| Snapshot::from_row(row))
+            .collect();
+
+        Self { snapshots }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let rows: Vec<Vec<String>> = self.snapshots.iter().map(Snapshot::to_row).collect();
+        state::write("snapshots", &rows)
+    }
+
+    /// Add a snapshot, replacing one of the same name.
+    /// Tabs and line breaks in the name are replaced by spaces.
+    pub fn insert(&mut self, mut snapshot: Snapshot) {
+        snapshot.name = snapshot.name.replace(['\t', '\n', '\r'], " ");
+        self.remove(&snapshot.name);
+        self.snapshots.push(snapshot);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.snapshots.retain(|snapshot| snapshot.name != name);
+    }
+
+    pub fn get(&self, i: usize) -> Option<&Snapshot> {
+        self.snapshots.get(i)
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Iterate over the snapshots, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Snapshot> {
+        self.snapshots.iter()
+    }
+}
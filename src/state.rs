@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Get the directory persistent state is stored in.
+/// This is `$XDG_STATE_HOME/musikbox` or `~/.local/state/musikbox`.
+pub fn dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(state_home) if !state_home.is_empty() => PathBuf::from(state_home),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+
+    Some(base.join("musikbox"))
+}
+
+/// Read a tab-separated state file.
+/// Missing or unreadable files are treated as empty.
+pub fn read(name: &str) -> Vec<Vec<String>> {
+    let contents = match dir().map(|dir| fs::read_to_string(dir.join(name))) {
+        Some(Ok(contents)) => contents,
+        _ => return Vec::new(),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split('\t').map(String::from).collect())
+        .collect()
+}
+
+/// Replace a tab-separated state file.
+/// The new contents are written to a temporary file first
+/// so that an interrupted write never leaves a truncated file behind.
+pub fn write(name: &str, rows: &[Vec<String>]) -> anyhow::Result<()> {
+    let dir = dir().ok_or_else(|| anyhow::anyhow!("can't determine state directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let tmp = dir.join(format!(".{name}.tmp"));
+    let mut file = fs::File::create(&tmp)?;
+
+    for row in rows {
+        writeln!(file, "{}", row.join("\t"))?;
+    }
+
+    file.sync_all()?;
+    fs::rename(tmp, dir.join(name))?;
+
+    Ok(())
+}
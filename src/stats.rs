@@ -0,0 +1,201 @@
+use crate::state;
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Listening statistics of a single track.
+#[derive(Clone, Debug, Default)]
+pub struct TrackStats {
+    pub plays: u64,
+    pub skips: u64,
+    /// Total listening time in seconds.
+    pub listened: f64,
+    /// Unix timestamp of the last play.
+    pub last_played: Option<u64>,
+    /// Skips whose position is known and the sum of these positions in seconds.
+    pub timed_skips: u64,
+    pub skip_positions: f64,
+}
+
+impl TrackStats {
+    /// Get the average position the track is skipped at.
+    pub fn usual_skip_point(&self) -> Option<Duration> {
+        if self.timed_skips == 0 {
+            return None;
+        }
+
+        Duration::try_from_secs_f64(self.skip_positions / self.timed_skips as f64).ok()
+    }
+}
+
+/// Listening statistics of a track on a single day.
+#[derive(Clone, Debug, Default)]
+pub struct DayStats {
+    pub plays: u64,
+    /// Listening time in seconds.
+    pub listened: f64,
+}
+
+/// Listening statistics of all tracks, keyed by URI.
+#[derive(Debug, Default)]
+pub struct Stats {
+    tracks: HashMap<String, TrackStats>,
+    /// Statistics per UTC day (`YYYY-MM-DD`) and URI.
+    days: HashMap<(String, String), DayStats>,
+}
+
+impl Stats {
+    /// Load the statistics from the state directory.
+    pub fn load() -> Self {
+        let mut tracks = HashMap::new();
+
+        for row in state::read("stats") {
+            // Skip positions were added later.
+            if let [uri, plays, skips, listened, last_played, skip_positions @ ..] = row.as_slice()
+            {
+                let (timed_skips, skip_positions) = match skip_positions {
+                    [timed_skips, skip_positions] => (
+                        timed_skips.parse().unwrap_or_default(),
+                        skip_positions.parse().unwrap_or_default(),
+                    ),
+                    _ => (0, 0.0),
+                };
+
+                let stats = TrackStats {
+                    plays: plays.parse().unwrap_or_default(),
+                    skips: skips.parse().unwrap_or_default(),
+                    listened: listened.parse().unwrap_or_default(),
+                    last_played: last_played.parse().ok(),
+                    timed_skips,
+                    skip_positions,
+                };
+
+                tracks.insert(uri.clone(), stats);
+            }
+        }
+
+        let mut days = HashMap::new();
+
+        for row in state::read("stats-daily") {
+            if let [day, uri, plays, listened] = row.as_slice() {
+                let stats = DayStats {
+                    plays: plays.parse().unwrap_or_default(),
+                    listened: listened.parse().unwrap_or_default(),
+                };
+
+                days.insert((day.clone(), uri.clone()), stats);
+            }
+        }
+
+        Self { tracks, days }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let rows: Vec<Vec<String>> = self
+            .tracks
+            .iter()
+            .map(|(uri, stats)| {
+                vec![
+                    uri.clone(),
+                    stats.plays.to_string(),
+                    stats.skips.to_string(),
+                    stats.listened.to_string(),
+                    stats.last_played.map(|t| t.to_string()).unwrap_or_default(),
+                    stats.timed_skips.to_string(),
+                    stats.skip_positions.to_string(),
+                ]
+            })
+            .collect();
+
+        state::write("stats", &rows)?;
+
+        let rows: Vec<Vec<String>> = self
+            .days
+            .iter()
+            .map(|((day, uri), stats)| {
+                vec![
+                    day.clone(),
+                    uri.clone(),
+                    stats.plays.to_string(),
+                    stats.listened.to_string(),
+                ]
+            })
+            .collect();
+
+        state::write("stats-daily", &rows)
+    }
+
+    /// Iterate over the statistics of all tracks.
+    pub fn tracks(&self) -> impl Iterator<Item = (&str, &TrackStats)> {
+        self.tracks.iter().map(|(uri, stats)| (uri.as_str(), stats))
+    }
+
+    /// Iterate over the statistics per day and track.
+    pub fn days(&self) -> impl Iterator<Item = (&str, &str, &DayStats)> {
+        self.days
+            .iter()
+            .map(|((day, uri), stats)| (day.as_str(), uri.as_str(), stats))
+    }
+
+    fn today(&mut self, uri: &str) -> &mut DayStats {
+        self.days.entry((today(), uri.to_string())).or_default()
+    }
+
+    pub fn get(&self, uri: &str) -> Option<&TrackStats> {
+        self.tracks.get(uri)
+    }
+
+    pub fn plays(&self, uri: &str) -> u64 {
+        self.get(uri).map(|stats| stats.plays).unwrap_or_default()
+    }
+
+    pub fn record_play(&mut self, uri: &str) {
+        let stats = self.tracks.entry(uri.to_string()).or_default();
+
+        stats.plays += 1;
+        stats.last_played = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|t| t.as_secs());
+
+        self.today(uri).plays += 1;
+    }
+
+    /// Count a skip, at a position if it is known.
+    pub fn record_skip(&mut self, uri: &str, position: Option<Duration>) {
+        let stats = self.tracks.entry(uri.to_string()).or_default();
+        stats.skips += 1;
+
+        if let Some(position) = position {
+            stats.timed_skips += 1;
+            stats.skip_positions += position.as_secs_f64();
+        }
+    }
+
+    pub fn add_listened(&mut self, uri: &str, seconds: f64) {
+        self.tracks.entry(uri.to_string()).or_default().listened += seconds;
+        self.today(uri).listened += seconds;
+    }
+}
+
+/// Get the current UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs() / 86400)
+        .unwrap_or_default() as i64;
+
+    // Convert days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
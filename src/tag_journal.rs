@@ -0,0 +1,388 @@
+use crate::player::{file_uri, uri_to_path};
+use crate::state;
+use crate::tags::Tags;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Most saves of the tag editor that can be undone, older ones are forgotten.
+const MAX_EDITS: usize = 100;
+
+/// Tags written to a file by one save, and the ones it had before.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    /// Title, artist, album, track number and genre before the save, `None` if absent.
+    pub before: Tags,
+    /// Tags that were written, as passed to `tags::write`.
+    pub after: Tags,
+}
+
+impl FileEdit {
+    /// Note tags written to a file, keeping only the editable fields of the ones it had.
+    pub fn new(path: PathBuf, before: &Tags, after: Tags) -> Self {
+        let before = Tags {
+            title: before.title.clone(),
+            artist: before.artist.clone(),
+            album: before.album.clone(),
+            track_number: before.track_number,
+            genre: before.genre.clone(),
+            ..Tags::default()
+        };
+
+        Self {
+            path,
+            before,
+            after,
+        }
+    }
+}
+
+/// Journal of the tag edits, persisted in the state directory
+/// so that they can be undone and redone in later sessions as well.
+/// The tags a file had before it was first edited are kept to revert it to.
+#[derive(Debug, Default)]
+pub struct TagJournal {
+    /// Saves, oldest first, each with the files it changed.
+    edits: Vec<Vec<FileEdit>>,
+    /// Number of saves that are applied, the ones after them can be redone.
+    applied: usize,
+    /// Tags of files before their first edit by URI.
+    originals: HashMap<String, Tags>,
+}
+
+impl TagJournal {
+    pub fn load() -> Self {
+        Self::from_rows(&state::read("tag-journal"))
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        state::write("tag-journal", &self.to_rows())
+    }
+
+    /// Note a save of the tag editor, which can't be redone anymore
+    /// if it follows an undo.
+    pub fn record(&mut self, edit: Vec<FileEdit>) {
+        if edit.is_empty() {
+            return;
+        }
+
+        for file in &edit {
+            self.originals
+                .entry(file_uri(&file.path))
+                .or_insert_with(|| file.before.clone());
+        }
+
+        self.edits.truncate(self.applied);
+        self.edits.push(edit);
+        if self.edits.len() > MAX_EDITS {
+            self.edits.remove(0);
+        }
+        self.applied = self.edits.len();
+    }
+
+    /// Take the last applied save to undo, its files are to be written with `restoring`
+    /// of the tags they had before.
+    pub fn undo(&mut self) -> Option<&[FileEdit]> {
+        self.applied = self.applied.checked_sub(1)?;
+        Some(&self.edits[self.applied])
+    }
+
+    /// Take the last undone save to apply again.
+    pub fn redo(&mut self) -> Option<&[FileEdit]> {
+        let edit = self.edits.get(self.applied)?;
+        self.applied += 1;
+        Some(edit)
+    }
+
+    /// Get the tags a file had before it was first edited.
+    pub fn original(&self, path: &Path) -> Option<&Tags> {
+        self.originals.get(&file_uri(path))
+    }
+
+    /// Follow a file that was renamed, so that its edits can still be undone.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        for file in self.edits.iter_mut().flatten() {
+            if file.path == from {
+                file.path = to.to_path_buf();
+            }
+        }
+
+        if let Some(original) = self.originals.remove(&file_uri(from)) {
+            self.originals.insert(file_uri(to), original);
+        }
+    }
+
+    fn from_rows(rows: &[Vec<String>]) -> Self {
+        let mut journal = Self::default();
+        let mut applied = None;
+
+        for row in rows {
+            match row.as_slice() {
+                [kind, n] if kind == "applied" => applied = n.parse().ok(),
+                [kind, uri, fields @ ..] if kind == "original" => {
+                    if let Some(tags) = decode_tags(fields) {
+                        journal.originals.insert(uri.clone(), tags);
+                    }
+                }
+                [kind, n, uri, fields @ ..] if kind == "edit" && fields.len() == 10 => {
+                    let (Some(path), Ok(n)) = (uri_to_path(uri), n.parse::<usize>()) else {
+                        continue;
+                    };
+                    let (Some(before), Some(after)) =
+                        (decode_tags(&fields[..5]), decode_tags(&fields[5..]))
+                    else {
+                        continue;
+                    };
+
+                    // Edits are numbered, consecutive rows with a number belong to one save.
+                    if journal.edits.len() <= n {
+                        journal.edits.resize_with(n + 1, Vec::new);
+                    }
+                    journal.edits[n].push(FileEdit {
+                        path,
+                        before,
+                        after,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        journal.edits.retain(|edit| !edit.is_empty());
+        journal.applied = applied
+            .unwrap_or(journal.edits.len())
+            .min(journal.edits.len());
+
+        journal
+    }
+
+    fn to_rows(&self) -> Vec<Vec<String>> {
+        let mut rows = vec![vec![String::from("applied"), self.applied.to_string()]];
+
+        for (uri, tags) in &self.originals {
+            let mut row = vec![String::from("original"), uri.clone()];
+            row.extend(encode_tags(tags));
+            rows.push(row);
+        }
+
+        for (n, edit) in self.edits.iter().enumerate() {
+            for file in edit {
+                let mut row = vec![String::from("edit"), n.to_string(), file_uri(&file.path)];
+                row.extend(encode_tags(&file.before));
+                row.extend(encode_tags(&file.after));
+                rows.push(row);
+            }
+        }
+
+        rows
+    }
+}
+
+/// Get the tags to write to give a file the title, artist, album, track number
+/// and genre it had, clearing those it didn't have.
+pub fn restoring(tags: &Tags) -> Tags {
+    let text = |value: &Option<String>| Some(value.clone().unwrap_or_default());
+
+    Tags {
+        title: text(&tags.title),
+        artist: text(&tags.artist),
+        album: text(&tags.album),
+        track_number: Some(tags.track_number.unwrap_or_default()),
+        genre: text(&tags.genre),
+        ..Tags::default()
+    }
+}
+
+/// Encode the editable fields, absent ones as empty columns
+/// and present ones prefixed with `=` so that empty values survive.
+fn encode_tags(tags: &Tags) -> [String; 5] {
+    let text = |value: &Option<String>| match value {
+        Some(value) => format!("={}", escape(value)),
+        None => String::new(),
+    };
+
+    [
+        text(&tags.title),
+        text(&tags.artist),
+        text(&tags.album),
+        tags.track_number
+            .map(|n| format!("={n}"))
+            .unwrap_or_default(),
+        text(&tags.genre),
+    ]
+}
+
+fn decode_tags(fields: &[String]) -> Option<Tags> {
+    let text = |value: &str| -> Option<Option<String>> {
+        match value {
+            "" => Some(None),
+            value => Some(Some(unescape(value.strip_prefix('=')?))),
+        }
+    };
+
+    match fields {
+        [title, artist, album, track_number, genre] => Some(Tags {
+            title: text(title)?,
+            artist: text(artist)?,
+            album: text(album)?,
+            track_number: match track_number.as_str() {
+                "" => None,
+                n => Some(n.strip_prefix('=')?.parse().ok()?),
+            },
+            genre: text(genre)?,
+            ..Tags::default()
+        }),
+        _ => None,
+    }
+}
+
+/// Escape the characters that separate the columns and rows of a state file.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => unescaped.push('\t'),
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            (c, _) => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+
+        chars.next();
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn title(title: &str) -> Tags {
+        Tags {
+            title: Some(title.to_string()),
+            ..Tags::default()
+        }
+    }
+
+    fn edit(path: &str, before: &str, after: &str) -> Vec<FileEdit> {
+        vec![FileEdit {
+            path: PathBuf::from(path),
+            before: title(before),
+            after: title(after),
+        }]
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut journal = TagJournal::default();
+        journal.record(edit("/music/a.flac", "A", "B"));
+        journal.record(edit("/music/a.flac", "B", "C"));
+
+        assert_eq!(journal.undo(), Some(&edit("/music/a.flac", "B", "C")[..]));
+        assert_eq!(journal.undo(), Some(&edit("/music/a.flac", "A", "B")[..]));
+        assert_eq!(journal.undo(), None);
+
+        assert_eq!(journal.redo(), Some(&edit("/music/a.flac", "A", "B")[..]));
+
+        // A new edit can't be followed by the ones that were undone.
+        journal.record(edit("/music/a.flac", "B", "D"));
+        assert_eq!(journal.redo(), None);
+        assert_eq!(journal.undo(), Some(&edit("/music/a.flac", "B", "D")[..]));
+    }
+
+    #[test]
+    fn originals() {
+        let mut journal = TagJournal::default();
+        journal.record(edit("/music/a.flac", "A", "B"));
+        journal.record(edit("/music/a.flac", "B", "C"));
+
+        assert_eq!(
+            journal.original(Path::new("/music/a.flac")),
+            Some(&title("A"))
+        );
+        assert_eq!(journal.original(Path::new("/music/b.flac")), None);
+    }
+
+    #[test]
+    fn rename() {
+        let mut journal = TagJournal::default();
+        journal.record(edit("/music/a.flac", "A", "B"));
+        journal.rename(Path::new("/music/a.flac"), Path::new("/music/B.flac"));
+
+        assert_eq!(journal.original(Path::new("/music/a.flac")), None);
+        assert_eq!(
+            journal.original(Path::new("/music/B.flac")),
+            Some(&title("A"))
+        );
+        assert_eq!(journal.undo(), Some(&edit("/music/B.flac", "A", "B")[..]));
+    }
+
+    #[test]
+    fn forgets_old_edits() {
+        let mut journal = TagJournal::default();
+        for i in 0..MAX_EDITS + 5 {
+            journal.record(edit("/music/a.flac", &i.to_string(), &(i + 1).to_string()));
+        }
+
+        let mut undone = 0;
+        while journal.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_EDITS);
+        assert_eq!(
+            journal.original(Path::new("/music/a.flac")),
+            Some(&title("0"))
+        );
+    }
+
+    #[test]
+    fn restores_absent_fields() {
+        let tags = restoring(&title("A"));
+        assert_eq!(tags.title.as_deref(), Some("A"));
+        assert_eq!(tags.artist.as_deref(), Some(""));
+        assert_eq!(tags.track_number, Some(0));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut journal = TagJournal::default();
+        journal.record(vec![
+            FileEdit {
+                path: PathBuf::from("/music/a b#?.flac"),
+                before: Tags {
+                    title: Some("Tab\there\\n".to_string()),
+                    track_number: Some(3),
+                    ..Tags::default()
+                },
+                after: restoring(&Tags::default()),
+            },
+            FileEdit {
+                path: PathBuf::from("/music/c.flac"),
+                before: Tags {
+                    artist: Some("Line\nbreak".to_string()),
+                    ..Tags::default()
+                },
+                after: title("C"),
+            },
+        ]);
+        journal.record(edit("/music/c.flac", "C", "D"));
+        journal.undo();
+
+        let loaded = TagJournal::from_rows(&journal.to_rows());
+        assert_eq!(loaded.edits, journal.edits);
+        assert_eq!(loaded.applied, 1);
+        assert_eq!(loaded.originals, journal.originals);
+    }
+}
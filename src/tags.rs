@@ -0,0 +1,554 @@
+#[cfg(feature = "gstreamer")]
+use gstreamer::prelude::*;
+#[cfg(feature = "gstreamer")]
+use gstreamer::{
+    tags, ClockTime, ElementFactory, MessageView, State, TagList, TagMergeMode, TocEntry,
+    TocEntryType,
+};
+use std::collections::BTreeMap;
+#[cfg(feature = "gstreamer")]
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Metadata of a track as reported by its container.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub duration: Option<Duration>,
+    /// Sample rate of the audio stream in Hz.
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+    /// Codec of the audio stream, e.g. "FLAC" or "MPEG-1 Layer 3 (MP3)".
+    pub codec: Option<String>,
+    /// Bitrate of the audio stream in bit/s as stated by the file.
+    /// Lossless formats usually don't state one.
+    pub bitrate: Option<u32>,
+    /// MusicBrainz release ID of the album.
+    pub musicbrainz_album_id: Option<String>,
+    /// Whether the file contains a cover image.
+    pub embedded_art: bool,
+    /// Chapter markers, e.g. of an audiobook, sorted by their start.
+    pub chapters: Vec<Chapter>,
+}
+
+/// Chapter marker within a track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: Option<String>,
+}
+
+impl Tags {
+    /// Fill in fields that are still unknown from a tag list.
+    #[cfg(feature = "gstreamer")]
+    fn merge(&mut self, list: &TagList) {
+        // Empty text and a track number of 0 are how `write` clears a field.
+        let text = |value: Option<tags::TagValue<&str>>| {
+            value.map(|v| v.get().to_string()).filter(|v| !v.is_empty())
+        };
+
+        self.title = self
+            .title
+            .take()
+            .or_else(|| text(list.get::<tags::Title>()));
+        self.artist = self
+            .artist
+            .take()
+            .or_else(|| text(list.get::<tags::Artist>()));
+        self.album = self
+            .album
+            .take()
+            .or_else(|| text(list.get::<tags::Album>()));
+        self.album_artist = self
+            .album_artist
+            .take()
+            .or_else(|| text(list.get::<tags::AlbumArtist>()));
+        self.genre = self
+            .genre
+            .take()
+            .or_else(|| text(list.get::<tags::Genre>()));
+        self.track_number = self
+            .track_number
+            .or_else(|| list.get::<tags::TrackNumber>().map(|v| v.get()))
+            .filter(|&n| n > 0);
+        self.disc_number = self
+            .disc_number
+            .or_else(|| list.get::<tags::AlbumVolumeNumber>().map(|v| v.get()));
+        self.codec = self
+            .codec
+            .take()
+            .or_else(|| text(list.get::<tags::AudioCodec>()));
+        self.bitrate = self
+            .bitrate
+            .or_else(|| list.get::<tags::Bitrate>().map(|v| v.get()))
+            .or_else(|| list.get::<tags::NominalBitrate>().map(|v| v.get()));
+        self.embedded_art |= list.get::<tags::Image>().is_some();
+        // Defined by the tag library of gst-plugins-base, which has no binding here.
+        self.musicbrainz_album_id = self.musicbrainz_album_id.take().or_else(|| {
+            list.generic("musicbrainz-albumid")
+                .and_then(|value| value.get::<String>().ok())
+        });
+    }
+
+    /// Fill in fields that are still unknown from symphonia tags.
+    #[cfg(all(feature = "rodio", not(feature = "gstreamer")))]
+    fn merge(&mut self, list: &[symphonia::core::meta::Tag]) {
+        use symphonia::core::meta::StandardTagKey;
+
+        // Numbers may be stored as "3/12".
+        let number = |value: String| value.split('/').next()?.trim().parse().ok();
+
+        for tag in list {
+            let value = tag.value.to_string();
+            if value.is_empty() {
+                continue;
+            }
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::AlbumArtist) => &mut self.album_artist,
+                Some(StandardTagKey::Genre) => &mut self.genre,
+                Some(StandardTagKey::MusicBrainzAlbumId) => &mut self.musicbrainz_album_id,
+                Some(StandardTagKey::TrackNumber) => {
+                    self.track_number = self
+                        .track_number
+                        .or_else(|| number(value).filter(|&n| n > 0));
+                    continue;
+                }
+                Some(StandardTagKey::DiscNumber) => {
+                    self.disc_number = self.disc_number.or_else(|| number(value));
+                    continue;
+                }
+                _ => continue,
+            };
+
+            field.get_or_insert(value);
+        }
+    }
+
+    /// Get the album title without a disc suffix such as `(Disc 2)` or `CD2`,
+    /// so discs tagged as separate albums are grouped together.
+    pub fn album_title(&self) -> Option<String> {
+        self.album
+            .as_deref()
+            .map(|album| strip_disc_suffix(album).to_lowercase())
+    }
+
+    /// Check whether two tracks can be played back to back without renegotiating the output.
+    /// Tracks of unknown format are assumed to match.
+    pub fn same_format(&self, other: &Self) -> bool {
+        let matches = |a: Option<u32>, b: Option<u32>| a.is_none() || b.is_none() || a == b;
+        matches(self.sample_rate, other.sample_rate) && matches(self.channels, other.channels)
+    }
+
+    /// Check whether two tracks belong to the same album, regardless of their folders.
+    /// Discs of a multi-disc album count as the same album.
+    /// Tracks without an album tag never do.
+    pub fn same_album(&self, other: &Self) -> bool {
+        self.album.is_some()
+            && self.album_title() == other.album_title()
+            && (self.album_artist.is_none()
+                || other.album_artist.is_none()
+                || self.album_artist == other.album_artist)
+    }
+}
+
+/// Remove a trailing disc label like ` (Disc 2)`, ` [CD 1]` or ` - Disk 3`.
+fn strip_disc_suffix(album: &str) -> &str {
+    let album = album.trim_end();
+
+    let (head, label) = match album.chars().last() {
+        Some(close @ (')' | ']')) => {
+            let open = if close == ')' { '(' } else { '[' };
+            match album.rfind(open) {
+                Some(i) => (&album[..i], &album[i + 1..album.len() - 1]),
+                None => return album,
+            }
+        }
+        _ => {
+            let lower = album.to_ascii_lowercase();
+            match ["disc", "disk", "cd"]
+                .iter()
+                .filter_map(|word| lower.rfind(word))
+                .max()
+            {
+                Some(i) => (&album[..i], &album[i..]),
+                None => return album,
+            }
+        }
+    };
+
+    let head = head.trim_end_matches(|c: char| c.is_whitespace() || "-,:".contains(c));
+    if is_disc_label(label) && !head.is_empty() {
+        head
+    } else {
+        album
+    }
+}
+
+/// Check whether a string is a disc label like `Disc 2` or `CD1`.
+fn is_disc_label(label: &str) -> bool {
+    let label = label.trim().to_lowercase();
+    let number = ["disc", "disk", "cd"]
+        .iter()
+        .find_map(|word| label.strip_prefix(word));
+
+    match number {
+        Some(number) => {
+            let number = number.trim_start_matches([' ', '.', '#']);
+            !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Get the chapters of Vorbis comments, which Ogg Vorbis and Opus audiobooks use:
+/// `CHAPTER001=00:00:00.000` marks the start and `CHAPTER001NAME` names it.
+fn comment_chapters(comments: &[(String, String)]) -> Vec<Chapter> {
+    let mut chapters: BTreeMap<u32, (Option<Duration>, Option<String>)> = BTreeMap::new();
+
+    for (key, value) in comments {
+        let key = key.to_ascii_uppercase();
+        let rest = match key.strip_prefix("CHAPTER") {
+            Some(rest) => rest,
+            None => continue,
+        };
+
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        let number = match rest[..digits].parse() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+
+        let chapter = chapters.entry(number).or_default();
+        match &rest[digits..] {
+            "" => chapter.0 = parse_timestamp(value),
+            "NAME" => chapter.1 = Some(value.clone()),
+            _ => {}
+        }
+    }
+
+    chapters
+        .into_values()
+        .filter_map(|(start, title)| {
+            Some(Chapter {
+                start: start?,
+                title,
+            })
+        })
+        .collect()
+}
+
+/// Parse a timestamp of the form `HH:MM:SS.mmm`.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let mut parts = timestamp.trim().rsplit(':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    let hours: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+
+    if parts.next().is_some() || !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(hours * 3600 + minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Read the tags of a URI by prerolling it in a muted pipeline.
+#[cfg(feature = "gstreamer")]
+pub fn read(uri: &str) -> anyhow::Result<Tags> {
+    let playbin = ElementFactory::make("playbin")
+        .property("uri", uri)
+        .build()?;
+    let audio_sink = ElementFactory::make("fakesink").build()?;
+    let video_sink = ElementFactory::make("fakesink").build()?;
+
+    playbin.set_property("audio-sink", &audio_sink);
+    playbin.set_property("video-sink", &video_sink);
+
+    let bus = playbin
+        .bus()
+        .ok_or_else(|| anyhow::anyhow!("pipeline without bus"))?;
+    playbin.set_state(State::Paused)?;
+
+    let mut tags = Tags::default();
+    let mut comments = Vec::new();
+    let mut result = Ok(());
+
+    for msg in bus.iter_timed(ClockTime::from_seconds(2)) {
+        match msg.view() {
+            MessageView::Tag(tag) => {
+                let list = tag.tags();
+                tags.merge(&list);
+
+                // Vorbis comments GStreamer doesn't know, which includes chapters.
+                comments.extend(
+                    list.iter_tag::<tags::ExtendedComment>()
+                        .filter_map(|comment| {
+                            let (key, value) = comment.get().split_once('=')?;
+                            Some((key.to_string(), value.to_string()))
+                        }),
+                );
+            }
+            MessageView::Toc(toc) => {
+                let (toc, _) = toc.toc();
+                tags.chapters.clear();
+                toc_chapters(&toc.entries(), &mut tags.chapters);
+            }
+            MessageView::AsyncDone(_) => {
+                tags.duration = playbin.query_duration::<ClockTime>().map(Duration::from);
+
+                // The sink accepts anything, so it receives the decoded format unchanged.
+                let caps = audio_sink
+                    .static_pad("sink")
+                    .and_then(|pad| pad.current_caps());
+                if let Some(format) = caps.as_ref().and_then(|caps| caps.structure(0)) {
+                    tags.sample_rate = format.get::<i32>("rate").ok().map(|rate| rate as u32);
+                    tags.channels = format.get::<i32>("channels").ok().map(|n| n as u32);
+                }
+
+                break;
+            }
+            MessageView::Error(err) => {
+                result = Err(err.error().into());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    playbin.set_state(State::Null)?;
+
+    if tags.chapters.is_empty() {
+        tags.chapters = comment_chapters(&comments);
+    }
+    tags.chapters.sort_by_key(|chapter| chapter.start);
+
+    result.map(|_| tags)
+}
+
+/// Collect the chapters of a table of contents, which may be nested in editions.
+#[cfg(feature = "gstreamer")]
+fn toc_chapters(entries: &[TocEntry], chapters: &mut Vec<Chapter>) {
+    for entry in entries {
+        if entry.entry_type() == TocEntryType::Chapter {
+            if let Some((start, _)) = entry.start_stop_times().filter(|(start, _)| *start >= 0) {
+                let title = entry
+                    .tags()
+                    .and_then(|list| list.get::<tags::Title>().map(|v| v.get().to_string()));
+
+                chapters.push(Chapter {
+                    start: Duration::from_nanos(start as u64),
+                    title,
+                });
+            }
+        }
+
+        toc_chapters(&entry.sub_entries(), chapters);
+    }
+}
+
+/// Write the title, artist, album, genre and track number of a local file.
+/// The file is remuxed into a temporary file with the new tags, which then replaces it.
+/// Fields that are `None` keep their current value, other tags are preserved.
+/// Empty text and a track number of 0 clear a field.
+/// MP3, FLAC and Ogg Vorbis files are supported.
+#[cfg(feature = "gstreamer")]
+pub fn write(path: &Path, tags: &Tags) -> anyhow::Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    // The element named tagger writes the tags, the audio isn't decoded.
+    let chain = match ext.as_str() {
+        "mp3" => "id3demux ! mpegaudioparse ! id3v2mux name=tagger",
+        "flac" => "flacparse ! flactag name=tagger",
+        "ogg" | "oga" => "oggdemux ! vorbisparse ! vorbistag name=tagger ! oggmux",
+        _ => anyhow::bail!("can't write tags of .{ext} files"),
+    };
+
+    let tmp = path.with_file_name(format!(
+        ".{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+
+    let pipeline =
+        gstreamer::parse_launch(&format!("filesrc name=src ! {chain} ! filesink name=sink"))?
+            .dynamic_cast::<gstreamer::Bin>()
+            .map_err(|_| anyhow::anyhow!("pipeline isn't a bin"))?;
+
+    let element = |name: &str| {
+        pipeline
+            .by_name(name)
+            .ok_or_else(|| anyhow::anyhow!("pipeline without {name}"))
+    };
+    let location = |path: &Path| {
+        path.to_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("{} isn't valid UTF-8", path.display()))
+    };
+    element("src")?.set_property("location", location(path)?);
+    element("sink")?.set_property("location", location(&tmp)?);
+
+    let mut list = TagList::new();
+    {
+        let list = list.get_mut().unwrap();
+        let mode = TagMergeMode::Replace;
+
+        if let Some(title) = &tags.title {
+            list.add::<tags::Title>(&title.as_str(), mode);
+        }
+        if let Some(artist) = &tags.artist {
+            list.add::<tags::Artist>(&artist.as_str(), mode);
+        }
+        if let Some(album) = &tags.album {
+            list.add::<tags::Album>(&album.as_str(), mode);
+        }
+        if let Some(genre) = &tags.genre {
+            list.add::<tags::Genre>(&genre.as_str(), mode);
+        }
+        if let Some(track_number) = tags.track_number {
+            list.add::<tags::TrackNumber>(&track_number, mode);
+        }
+    }
+
+    element("tagger")?
+        .dynamic_cast::<gstreamer::TagSetter>()
+        .map_err(|_| anyhow::anyhow!("tagger can't set tags"))?
+        .merge_tags(&list, TagMergeMode::Replace);
+
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| anyhow::anyhow!("pipeline without bus"))?;
+    pipeline.set_state(State::Playing)?;
+
+    let mut result = Err(anyhow::anyhow!("timed out writing tags"));
+    for msg in bus.iter_timed(ClockTime::from_seconds(30)) {
+        match msg.view() {
+            MessageView::Eos(_) => {
+                result = Ok(());
+                break;
+            }
+            MessageView::Error(err) => {
+                result = Err(err.error().into());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    pipeline.set_state(State::Null)?;
+
+    let result = result.and_then(|_| {
+        fs::set_permissions(&tmp, fs::metadata(path)?.permissions())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Writing tags requires GStreamer.
+#[cfg(not(feature = "gstreamer"))]
+pub fn write(_path: &Path, _tags: &Tags) -> anyhow::Result<()> {
+    anyhow::bail!("writing tags requires musikbox to be built with the gstreamer feature")
+}
+
+/// Read the tags of a local file using symphonia.
+#[cfg(all(feature = "rodio", not(feature = "gstreamer")))]
+pub fn read(uri: &str) -> anyhow::Result<Tags> {
+    use crate::player::uri_to_path;
+    use std::fs::File;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+    use symphonia::core::probe::Hint;
+
+    let path = uri_to_path(uri).ok_or_else(|| anyhow::anyhow!("unsupported URI {uri}"))?;
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let stream = MediaSourceStream::new(Box::new(File::open(&path)?), Default::default());
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut tags = Tags::default();
+    let mut comments = Vec::new();
+
+    // Tags are either in front of the container (e.g. ID3v2) or part of it.
+    let mut merge = |revision: &MetadataRevision| {
+        tags.merge(revision.tags());
+        tags.embedded_art |= !revision.visuals().is_empty();
+        comments.extend(
+            revision
+                .tags()
+                .iter()
+                .map(|tag| (tag.key.clone(), tag.value.to_string())),
+        );
+    };
+
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        merge(revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        merge(revision);
+    }
+
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        if let (Some(frames), Some(rate)) = (params.n_frames, params.sample_rate) {
+            tags.duration = Some(Duration::from_secs_f64(frames as f64 / rate as f64));
+        }
+
+        tags.sample_rate = params.sample_rate;
+        tags.channels = params.channels.map(|channels| channels.count() as u32);
+        tags.codec = symphonia::default::get_codecs()
+            .get_codec(params.codec)
+            .map(|codec| codec.short_name.to_uppercase());
+    }
+
+    // Cue sheets, e.g. of FLAC files, mark chapters in frames.
+    // Symphonia doesn't read MP4 chapters, those need GStreamer.
+    if let Some(rate) = tags.sample_rate.filter(|&rate| rate > 0) {
+        tags.chapters = probed
+            .format
+            .cues()
+            .iter()
+            .map(|cue| Chapter {
+                start: Duration::from_secs_f64(cue.start_ts as f64 / f64::from(rate)),
+                title: cue
+                    .tags
+                    .iter()
+                    .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+                    .map(|tag| tag.value.to_string()),
+            })
+            .collect();
+    }
+
+    if tags.chapters.is_empty() {
+        tags.chapters = comment_chapters(&comments);
+    }
+    tags.chapters.sort_by_key(|chapter| chapter.start);
+
+    Ok(tags)
+}
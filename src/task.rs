@@ -0,0 +1,57 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Long-running background job, e.g. maintenance that can be triggered remotely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Task {
+    /// Reading the size and tags of the files in the library.
+    Scan,
+    /// Decoding the files that haven't been verified yet to find damaged ones.
+    Verify,
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Scan => write!(f, "scan"),
+            Self::Verify => write!(f, "verify"),
+        }
+    }
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scan" => Ok(Self::Scan),
+            "verify" => Ok(Self::Verify),
+            _ => Err(format!("unknown task {s}, expected scan or verify")),
+        }
+    }
+}
+
+/// Number of files a task has processed so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub task: Task,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+
+    /// Serialize the progress as a JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"task\":\"{}\",\"done\":{},\"total\":{},\"running\":{}}}",
+            self.task,
+            self.done,
+            self.total,
+            !self.is_done()
+        )
+    }
+}
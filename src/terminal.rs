@@ -0,0 +1,43 @@
+use crossterm::cursor::Show;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+use std::io;
+use std::panic;
+use std::thread;
+
+/// Raw mode that is left again when dropped, also when returning early
+/// with an error or unwinding from a panic.
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leave raw mode and show the cursor. Has no effect if raw mode isn't enabled.
+pub fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), Show);
+}
+
+/// Restore the terminal before the panic message is printed,
+/// which would be garbled in raw mode. Panics of other threads
+/// are left alone as the interface keeps running.
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() == Some("main") {
+            restore();
+        }
+        default(info);
+    }));
+}
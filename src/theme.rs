@@ -0,0 +1,65 @@
+use std::str::FromStr;
+use tui::style::Color;
+
+/// Accent color used while playing tracks of a genre, e.g. `metal=red`.
+#[derive(Clone, Debug)]
+pub struct GenreColor {
+    pub genre: String,
+    pub color: Color,
+}
+
+impl GenreColor {
+    /// Check whether a genre tag matches, ignoring case.
+    /// Partial matches count, so `metal` also applies to `Death Metal`.
+    pub fn matches(&self, genre: &str) -> bool {
+        genre.to_lowercase().contains(&self.genre.to_lowercase())
+    }
+}
+
+impl FromStr for GenreColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (genre, color) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected GENRE=COLOR"))?;
+
+        Ok(Self {
+            genre: genre.trim().to_string(),
+            color: parse_color(color.trim())?,
+        })
+    }
+}
+
+/// Parse a color name or a `#rrggbb` hex color.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let rgb = u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color {s}"))?;
+        if hex.len() != 6 {
+            return Err(format!("invalid color {s}"));
+        }
+
+        return Ok(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+    }
+
+    match s.to_lowercase().as_str() {
+        "black" => Ok(Color::Black),
+        "red" => Ok(Color::Red),
+        "green" => Ok(Color::Green),
+        "yellow" => Ok(Color::Yellow),
+        "blue" => Ok(Color::Blue),
+        "magenta" => Ok(Color::Magenta),
+        "cyan" => Ok(Color::Cyan),
+        "gray" => Ok(Color::Gray),
+        "darkgray" => Ok(Color::DarkGray),
+        "lightred" => Ok(Color::LightRed),
+        "lightgreen" => Ok(Color::LightGreen),
+        "lightyellow" => Ok(Color::LightYellow),
+        "lightblue" => Ok(Color::LightBlue),
+        "lightmagenta" => Ok(Color::LightMagenta),
+        "lightcyan" => Ok(Color::LightCyan),
+        "white" => Ok(Color::White),
+        "amber" => Ok(Color::Rgb(0xff, 0xbf, 0x00)),
+        _ => Err(format!("unknown color {s}")),
+    }
+}
@@ -0,0 +1,236 @@
+use crate::player::uri_to_path;
+use crate::state;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Decoded audio may end this much before the duration in the headers
+/// without the file being reported as truncated.
+const TRUNCATION_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Number of files verified between saves of the results. An interrupted run
+/// loses at most this many, without rewriting all results after every file.
+pub const SAVE_BATCH: usize = 50;
+
+/// Result of decoding a file completely.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verification {
+    /// Modification time of the file when it was verified, in seconds since the epoch.
+    pub modified: u64,
+    /// Why the file couldn't be decoded, `None` if it's intact.
+    pub error: Option<String>,
+}
+
+/// Verification results of all files, keyed by URI, persisted in the state directory.
+#[derive(Debug, Default)]
+pub struct Verifications {
+    results: HashMap<String, Verification>,
+}
+
+impl Verifications {
+    pub fn load() -> Self {
+        let results = state::read("verify")
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [uri, modified, error] => Some((
+                    uri.clone(),
+                    Verification {
+                        modified: modified.parse().ok()?,
+                        error: Some(error.clone()).filter(|error| !error.is_empty()),
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+
+        Self { results }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let rows: Vec<Vec<String>> = self
+            .results
+            .iter()
+            .map(|(uri, verification)| {
+                vec![
+                    uri.clone(),
+                    verification.modified.to_string(),
+                    verification.error.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+
+        state::write("verify", &rows)
+    }
+
+    /// Get the result of a file unless it has been modified since it was verified.
+    pub fn get(&self, uri: &str) -> Option<&Verification> {
+        let verification = self.results.get(uri)?;
+        let modified = uri_to_path(uri).and_then(|path| modified(&path))?;
+
+        (verification.modified == modified).then_some(verification)
+    }
+
+    /// Check whether a file failed its last verification and hasn't changed since.
+    pub fn is_damaged(&self, uri: &str) -> bool {
+        self.get(uri)
+            .is_some_and(|verification| verification.error.is_some())
+    }
+
+    /// Iterate over the URIs of the damaged files that haven't changed since.
+    pub fn damaged(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .keys()
+            .filter(|uri| self.is_damaged(uri))
+            .map(String::as_str)
+    }
+
+    /// Decode a file and record the result.
+    /// Returns the result, or an error if the file can't be accessed at all.
+    pub fn verify(&mut self, path: &Path, uri: &str) -> anyhow::Result<&Verification> {
+        let modified =
+            modified(path).ok_or_else(|| anyhow::anyhow!("can't read {}", path.display()))?;
+        let error = decode(uri).err().map(|err| {
+            // Results are stored as tab-separated lines.
+            err.to_string().replace(['\t', '\n', '\r'], " ")
+        });
+
+        self.results
+            .insert(uri.to_string(), Verification { modified, error });
+        Ok(&self.results[uri])
+    }
+}
+
+/// Get the modification time of a file in seconds since the epoch.
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Check whether decoded audio that ended at `position` is shorter than the file claims.
+fn check_truncation(position: Duration, duration: Option<Duration>) -> anyhow::Result<()> {
+    match duration {
+        Some(duration) if position + TRUNCATION_TOLERANCE < duration => Err(anyhow::anyhow!(
+            "truncated, audio ends at {}s of {}s",
+            position.as_secs(),
+            duration.as_secs()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Decode a URI completely as fast as possible, without playing it.
+#[cfg(feature = "gstreamer")]
+pub fn decode(uri: &str) -> anyhow::Result<()> {
+    use gstreamer::prelude::*;
+    use gstreamer::{ClockTime, ElementFactory, MessageView, State};
+
+    let playbin = ElementFactory::make("playbin")
+        .property("uri", uri)
+        .build()?;
+    // Fake sinks don't synchronize to the clock, so decoding isn't throttled.
+    let audio_sink = ElementFactory::make("fakesink").build()?;
+    let video_sink = ElementFactory::make("fakesink").build()?;
+
+    playbin.set_property("audio-sink", &audio_sink);
+    playbin.set_property("video-sink", &video_sink);
+
+    let bus = playbin
+        .bus()
+        .ok_or_else(|| anyhow::anyhow!("pipeline without bus"))?;
+    playbin.set_state(State::Playing)?;
+
+    let mut duration = None;
+    let mut result = Err(anyhow::anyhow!("pipeline ended without EOS"));
+
+    for msg in bus.iter_timed(ClockTime::NONE) {
+        match msg.view() {
+            MessageView::AsyncDone(_) if duration.is_none() => {
+                duration = playbin.query_duration::<ClockTime>().map(Duration::from);
+            }
+            MessageView::Eos(_) => {
+                let position = playbin
+                    .query_position::<ClockTime>()
+                    .map(Duration::from)
+                    .unwrap_or_default();
+
+                result = check_truncation(position, duration);
+                break;
+            }
+            MessageView::Error(err) => {
+                result = Err(err.error().into());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    playbin.set_state(State::Null)?;
+    result
+}
+
+/// Decode a local file completely using symphonia.
+#[cfg(all(feature = "rodio", not(feature = "gstreamer")))]
+pub fn decode(uri: &str) -> anyhow::Result<()> {
+    use std::fs::File;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let path = uri_to_path(uri).ok_or_else(|| anyhow::anyhow!("unsupported URI {uri}"))?;
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let stream = MediaSourceStream::new(Box::new(File::open(&path)?), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow::anyhow!("no audio track"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let mut decoder =
+        symphonia::default::get_codecs().make(&params, &DecoderOptions { verify: true })?;
+
+    let mut frames = 0;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        if packet.track_id() == track_id {
+            frames += decoder.decode(&packet)?.frames() as u64;
+        }
+    }
+
+    // Decoders that store a checksum, e.g. FLAC's MD5, check it at the end.
+    if decoder.finalize().verify_ok == Some(false) {
+        anyhow::bail!("checksum mismatch");
+    }
+
+    let seconds = |frames: u64| {
+        params
+            .sample_rate
+            .map(|rate| Duration::from_secs_f64(frames as f64 / rate as f64))
+    };
+    check_truncation(
+        seconds(frames).unwrap_or_default(),
+        params.n_frames.and_then(seconds),
+    )
+}
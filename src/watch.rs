@@ -0,0 +1,90 @@
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Time without further changes after which a batch of changes is reported.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Watch a directory for files that are added, removed or renamed in the background.
+/// Calls a function once the changes have settled, so copying an album is reported once.
+/// Files count as added once they are completely written.
+pub fn watch_dir<F>(dir: &Path, f: F) -> notify::Result<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    thread::spawn(move || {
+        // Watching stops once the watcher is dropped.
+        let _watcher = watcher;
+
+        loop {
+            // The events themselves don't matter, the directory is read again anyway.
+            match rx.recv() {
+                Ok(Ok(event)) if changes_listing(&event) => {}
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => {
+                    tracing::warn!("stopped watching directory: {e}");
+                    return;
+                }
+                Err(_) => return,
+            }
+
+            loop {
+                match rx.recv_timeout(SETTLE_TIME) {
+                    Ok(_) => {}
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            f();
+        }
+    });
+
+    Ok(())
+}
+
+/// Check whether an event adds, removes or renames a file.
+/// Created files are only listed once they are closed after writing.
+fn changes_listing(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn reports_settled_changes() {
+        let dir = std::env::temp_dir().join(format!("musikbox-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        watch_dir(&dir, move || tx.send(()).unwrap()).unwrap();
+
+        fs::write(dir.join("a.flac"), b"a").unwrap();
+        fs::write(dir.join("b.flac"), b"b").unwrap();
+        fs::rename(dir.join("b.flac"), dir.join("c.flac")).unwrap();
+
+        // The changes are reported together once they settled.
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(rx.recv_timeout(SETTLE_TIME * 2).is_err());
+
+        fs::remove_file(dir.join("a.flac")).unwrap();
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
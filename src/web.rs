@@ -0,0 +1,162 @@
+use crate::json;
+use crate::task::Task;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// Remote control page served at `/`.
+const INDEX: &str = include_str!("web/index.html");
+
+/// Requests are dropped if the client doesn't send them within this time.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Command received through the HTTP API.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Status,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    /// Get the volume, or set it if a level from 0.0 to 1.0 is given.
+    Volume(Option<f64>),
+    Queue,
+    /// Cover of the current track's album, if it has been fetched.
+    Cover,
+    /// Progress of the background tasks.
+    Tasks,
+    /// Start a background task unless it is already running.
+    StartTask(Task),
+}
+
+/// Response to a request, sent as JSON.
+#[derive(Clone, Debug)]
+pub enum Response {
+    Json(String),
+    /// JPEG image.
+    Image(Vec<u8>),
+    NotFound,
+    Error(String),
+}
+
+/// Serve the remote control page and the JSON API in the background.
+/// Every request is answered by the handler, which is called from the connection's thread.
+pub fn serve<F>(addr: SocketAddr, handler: F) -> io::Result<()>
+where
+    F: Fn(Request) -> Response + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, handler) {
+                    tracing::debug!("web remote connection failed: {err}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle<F: Fn(Request) -> Response>(stream: TcpStream, handler: F) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // The headers aren't needed, but have to be read before responding.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", INDEX.into()),
+        (method, path) => match parse(method, path, query) {
+            Ok(request) => {
+                tracing::debug!("web remote request {request:?}");
+
+                match handler(request) {
+                    Response::Json(json) => ("200 OK", "application/json", json.into_bytes()),
+                    Response::Image(image) => ("200 OK", "image/jpeg", image),
+                    Response::NotFound => (
+                        "404 Not Found",
+                        "application/json",
+                        error("404 Not Found").into_bytes(),
+                    ),
+                    Response::Error(err) => (
+                        "503 Service Unavailable",
+                        "application/json",
+                        error(&err).into_bytes(),
+                    ),
+                }
+            }
+            Err(status) => (status, "application/json", error(status).into_bytes()),
+        },
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+/// Map an HTTP request to a command.
+/// Commands that change the state require POST.
+fn parse(method: &str, path: &str, query: &str) -> Result<Request, &'static str> {
+    let request = match path {
+        "/status" => Request::Status,
+        "/queue" => Request::Queue,
+        "/cover" => Request::Cover,
+        "/volume" if method == "GET" => Request::Volume(None),
+        "/volume" => {
+            let level = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("level="))
+                .and_then(|level| level.parse::<f64>().ok())
+                .filter(|level| (0.0..=1.0).contains(level))
+                .ok_or("400 Bad Request")?;
+
+            Request::Volume(Some(level))
+        }
+        "/play" => Request::Play,
+        "/pause" => Request::Pause,
+        "/next" => Request::Next,
+        "/previous" => Request::Previous,
+        "/tasks" => Request::Tasks,
+        path => match path.strip_prefix("/tasks/").map(str::parse) {
+            Some(Ok(task)) => Request::StartTask(task),
+            _ => return Err("404 Not Found"),
+        },
+    };
+
+    let read_only = matches!(
+        request,
+        Request::Status | Request::Queue | Request::Cover | Request::Volume(None) | Request::Tasks
+    );
+
+    match method {
+        "GET" if read_only => Ok(request),
+        "POST" if !read_only => Ok(request),
+        _ => Err("405 Method Not Allowed"),
+    }
+}
+
+fn error(message: &str) -> String {
+    format!("{{\"error\":\"{}\"}}", json::escape(message))
+}
@@ -0,0 +1,79 @@
+use crate::extension::{Extension, Notification};
+use crate::tags::{self, Tags};
+
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Extension posting every notification as JSON to a list of URLs.
+/// A single worker delivers them, so they arrive in the order they happened.
+pub struct Webhooks {
+    sender: Sender<Notification>,
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<String>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || deliver(&urls, receiver));
+
+        Self { sender }
+    }
+}
+
+impl Extension for Webhooks {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn notify(&self, notification: &Notification) {
+        let _ = self.sender.send(notification.clone());
+    }
+}
+
+/// Post the notifications one after another until the extension is dropped.
+/// Reading the tags prerolls the file, which is why it's done here
+/// and only once the track changes.
+fn deliver(urls: &[String], receiver: Receiver<Notification>) {
+    let mut track: Option<(String, Tags)> = None;
+
+    for notification in receiver {
+        let tags = match &track {
+            Some((uri, tags)) if *uri == notification.uri => tags,
+            _ => {
+                let tags = match notification.uri.as_str() {
+                    "" => Tags::default(),
+                    uri => tags::read(uri).unwrap_or_default(),
+                };
+
+                &track.insert((notification.uri.clone(), tags)).1
+            }
+        };
+
+        let body = notification.to_json(tags);
+        for url in urls {
+            post(url, &body);
+        }
+    }
+}
+
+/// POST a JSON body to a webhook URL, waiting at most a few seconds.
+/// Delivery is delegated to curl so that https endpoints work.
+/// Failures are only logged, a broken webhook must never interrupt playback.
+fn post(url: &str, body: &str) {
+    let status = Command::new("curl")
+        .args(["-fsS", "--max-time", "5", "-X", "POST"])
+        .args(["-H", "Content-Type: application/json"])
+        .arg("--data-binary")
+        .arg(body)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!("webhook {url} failed: curl {status}"),
+        Err(err) => tracing::warn!("webhook {url} failed: {err}"),
+    }
+}
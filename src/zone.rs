@@ -0,0 +1,107 @@
+use crate::backend::{Output, PlaybackBackend};
+use crate::player::{PlayState, PlaybackEvent, Player};
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Additional playback zone as NAME=OUTPUT, where OUTPUT is an output device
+/// as named by `pactl list short sinks` or a stream, see `Output`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZoneOutput {
+    pub name: String,
+    pub output: Output,
+}
+
+impl FromStr for ZoneOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, output) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected NAME=OUTPUT"))?;
+
+        Ok(Self {
+            name: name.trim().to_string(),
+            output: output.trim().parse()?,
+        })
+    }
+}
+
+/// Playback zone next to the main one, with its own player and queue,
+/// e.g. for another room. Plays its queue in order and stops at its end.
+pub struct Zone {
+    pub name: String,
+    pub player: Player<Box<dyn PlaybackBackend>>,
+    /// Tracks to play after the current one.
+    pub queue: VecDeque<PathBuf>,
+    /// Track that is playing or paused.
+    pub current: Option<PathBuf>,
+    pub state: PlayState,
+}
+
+impl Zone {
+    pub fn new(name: String, player: Player<Box<dyn PlaybackBackend>>) -> Self {
+        Self {
+            name,
+            player,
+            queue: VecDeque::new(),
+            current: None,
+            state: PlayState::Stopped,
+        }
+    }
+
+    /// Play a track now and the given ones after it, replacing the queue.
+    pub fn play(&mut self, track: PathBuf, following: impl IntoIterator<Item = PathBuf>) {
+        self.queue = following.into_iter().collect();
+        self.player.play_path(&track);
+        self.current = Some(track);
+    }
+
+    /// Play the next track of the queue, or stop if it's empty.
+    pub fn advance(&mut self) {
+        match self.queue.pop_front() {
+            Some(track) => {
+                self.player.play_path(&track);
+                self.current = Some(track);
+            }
+            None => {
+                self.player.stop();
+                self.current = None;
+            }
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.state == PlayState::Playing {
+            self.player.pause();
+        } else if self.current.is_some() {
+            self.player.resume();
+        }
+    }
+
+    /// Update the zone from an event of its player.
+    /// Returns the error message if the track couldn't be played, it is skipped.
+    pub fn handle_event(&mut self, event: &PlaybackEvent) -> Option<String> {
+        self.player.handle_event(event);
+
+        match event {
+            PlaybackEvent::StateChanged(state) => self.state = *state,
+            PlaybackEvent::EndOfStream => self.advance(),
+            PlaybackEvent::Error(e) => {
+                let track = self
+                    .current
+                    .as_deref()
+                    .and_then(|track| track.file_name())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.advance();
+
+                return Some(format!("{}: can't play {track}: {e}", self.name));
+            }
+            PlaybackEvent::Warning(_) | PlaybackEvent::Buffering(_) => {}
+        }
+
+        None
+    }
+}